@@ -22,6 +22,9 @@ use darkfi::rpc::jsonrpc::ErrorCode;
 pub enum RpcError {
     InvalidWorkerLogin = -32110,
     UnsupportedMiningAlgo = -32111,
+    MiningJobUnavailable = -32112,
+    UnknownMiningJob = -32113,
+    RejectedSolution = -32114,
 }
 
 impl From<RpcError> for ErrorCode {
@@ -46,7 +46,9 @@ use uuid::Uuid;
 
 mod error;
 mod monero;
+use monero::{MergeMiningJob, MONEROD_PASSTHROUGH};
 mod stratum;
+use stratum::StratumJob;
 
 const CONFIG_FILE: &str = "darkfi_mmproxy.toml";
 const CONFIG_FILE_CONTENTS: &str = include_str!("../darkfi_mmproxy.toml");
@@ -71,6 +73,14 @@ struct Args {
     /// monerod JSON-RPC server listen URL
     monerod_rpc: Url,
 
+    #[structopt(long, default_value = "tcp://127.0.0.1:8340")]
+    /// darkfid JSON-RPC server listen URL, serving block templates
+    darkfid_rpc: Url,
+
+    #[structopt(long)]
+    /// Monero wallet address receiving the rewards of blocks mined by stratum workers
+    monero_address: Option<String>,
+
     #[structopt(long)]
     /// List of worker logins
     workers: Vec<String>,
@@ -102,6 +112,14 @@ impl Worker {
 struct MiningProxy {
     /// monerod RPC endpoint
     monerod_rpc: Url,
+    /// darkfid RPC endpoint
+    darkfid_rpc: Url,
+    /// Monero wallet address used in stratum block templates
+    monero_address: Option<String>,
+    /// DarkFi jobs committed to in handed out Monero block templates
+    merge_jobs: Mutex<Vec<MergeMiningJob>>,
+    /// Monero block templates handed out to stratum workers
+    stratum_jobs: Mutex<Vec<StratumJob>>,
     /// Worker logins
     logins: HashMap<String, String>,
     /// Workers UUIDs
@@ -115,11 +133,17 @@ struct MiningProxy {
 impl MiningProxy {
     fn new(
         monerod_rpc: Url,
+        darkfid_rpc: Url,
+        monero_address: Option<String>,
         logins: HashMap<String, String>,
         executor: Arc<Executor<'static>>,
     ) -> Self {
         Self {
             monerod_rpc,
+            darkfid_rpc,
+            monero_address,
+            merge_jobs: Mutex::new(vec![]),
+            stratum_jobs: Mutex::new(vec![]),
             logins,
             workers: Arc::new(RwLock::new(HashMap::new())),
             rpc_connections: Mutex::new(HashSet::new()),
@@ -143,41 +167,9 @@ impl RequestHandler for MiningProxy {
             "keepalived" => self.stratum_keepalived(req.id, req.params).await,
 
             // Monero daemon methods
-            "get_block_count" => self.monero_get_block_count(req.id, req.params).await,
-            "getblockcount" => self.monero_get_block_count(req.id, req.params).await,
-
-            /*
-            "on_get_block_hash" => self.monero_on_get_block_hash(req.id, req.params).await,
-            "get_block_template" => self.monero_get_block_template(req.id, req.params).await,
-            "submit_block" => self.monero_submit_block(req.id, req.params).await,
-            "generateblocks" => self.monero_generateblocks(req.id, req.params).await,
-            "get_last_block_header" => self.monero_get_last_block_header(req.id, req.params).await,
-            "get_block_header_by_hash" => self.monero_get_block_header_by_hash(req.id, req.params).await,
-            "get_block_header_by_height" => self.monero_get_block_header_by_height(req.id, req.params).await,
-            "get_block_headers_range" => self.monero_get_block_headers_range(req.id, req.params).await,
-            "get_block" => self.monero_get_block(req.id, req.params).await,
-            "get_connections" => self.monero_get_connections(req.id, req.params).await,
-            "get_info" => self.monero_get_info(req.id, req.params).await,
-            "hard_fork_info" => self.monero_hard_fork_info(req.id, req.params).await,
-            "set_bans" => self.monero_set_bans(req.id, req.params).await,
-            "get_bans" => self.monero_get_bans(req.id, req.params).await,
-            "banned" => self.monero_banned(req.id, req.params).await,
-            "flush_txpool" => self.monero_flush_txpool(req.id, req.params).await,
-            "get_output_histogram" => self.monero_get_output_histogram(req.id, req.params).await,
-            "get_version" => self.monero_get_version(req.id, req.params).await,
-            "get_coinbase_tx_sum" => self.monero_get_coinbase_tx_sum(req.id, req.params).await,
-            "get_fee_estimate" => self.monero_get_fee_estimate(req.id, req.params).await,
-            "get_alternate_chains" => self.monero_get_alternate_chains(req.id, req.params).await,
-            "relay_tx" => self.monero_relay_tx(req.id, req.params).await,
-            "sync_info" => self.monero_sync_info(req.id, req.params).await,
-            "get_txpool_backlog" => self.monero_get_txpool_backlog(req.id, req.params).await,
-            "get_output_distribution" => self.monero_get_output_distribution(req.id, req.params).await,
-            "get_miner_data" => self.monero_get_miner_data(req.id, req.params).await,
-            "prune_blockchain" => self.monero_prune_blockchain(req.id, req.params).await,
-            "calc_pow" => self.monero_calc_pow(req.id, req.params).await,
-            "flush_cache" => self.monero_flush_cache(req.id, req.params).await,
-            "add_aux_pow" => self.monero_add_aux_pow(req.id, req.params).await,
-            */
+            "get_block_template" | "getblocktemplate" => self.monero_get_block_template(req.id, req.params).await,
+            "submit_block" | "submitblock" => self.monero_submit_block(req.id, req.params).await,
+            method if MONEROD_PASSTHROUGH.contains(&method) => self.monero_passthrough(req.id, method, req.params).await,
 
             _ => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
@@ -201,7 +193,13 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
         logins.insert(user, pass);
     }
 
-    let mmproxy = Arc::new(MiningProxy::new(args.monerod_rpc, logins, ex.clone()));
+    let mmproxy = Arc::new(MiningProxy::new(
+        args.monerod_rpc,
+        args.darkfid_rpc,
+        args.monero_address,
+        logins,
+        ex.clone(),
+    ));
     let mmproxy_ = Arc::clone(&mmproxy);
 
     info!("Starting JSON-RPC server");
@@ -16,185 +16,611 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi::rpc::{
-    jsonrpc::{ErrorCode::InternalError, JsonError, JsonRequest, JsonResponse, JsonResult},
-    util::JsonValue,
+use std::collections::HashMap;
+
+use darkfi::{
+    blockchain::monero::{insert_merge_mining_tag, MoneroPowData},
+    rpc::{
+        client::RpcClient,
+        jsonrpc::{
+            ErrorCode::{InternalError, InvalidParams, ServerError},
+            JsonError, JsonRequest, JsonResponse, JsonResult,
+        },
+        util::JsonValue,
+    },
+    util::encoding::base64,
+    Error, Result,
 };
-use log::{debug, error};
+use darkfi_serial::serialize;
+use log::{debug, error, info, warn};
 
 use super::MiningProxy;
 
-impl MiningProxy {
-    pub async fn monero_get_block_count(&self, id: u16, _params: JsonValue) -> JsonResult {
-        debug!(target: "rpc::monero", "get_block_count()");
+/// monerod JSON-RPC methods which are proxied as they are
+pub const MONEROD_PASSTHROUGH: &[&str] = &[
+    "get_block_count",
+    "getblockcount",
+    "on_get_block_hash",
+    "on_getblockhash",
+    "generateblocks",
+    "get_last_block_header",
+    "get_block_header_by_hash",
+    "get_block_header_by_height",
+    "get_block_headers_range",
+    "get_block",
+    "get_connections",
+    "get_info",
+    "hard_fork_info",
+    "set_bans",
+    "get_bans",
+    "banned",
+    "flush_txpool",
+    "get_output_histogram",
+    "get_version",
+    "get_coinbase_tx_sum",
+    "get_fee_estimate",
+    "get_alternate_chains",
+    "relay_tx",
+    "sync_info",
+    "get_txpool_backlog",
+    "get_output_distribution",
+    "get_miner_data",
+    "prune_blockchain",
+    "calc_pow",
+    "flush_cache",
+    "add_aux_pow",
+];
+
+/// Maximum number of DarkFi jobs we keep around for merge mining submissions
+const MAX_MERGE_JOBS: usize = 16;
+
+/// DarkFi block template committed to in a Monero block template
+#[derive(Clone)]
+pub struct MergeMiningJob {
+    /// DarkFi block header hash, committed to in the coinbase transaction
+    pub hash: blake3::Hash,
+    /// `darkfid` job ID of the block template
+    pub job_id: String,
+    /// RandomX key of the Monero block template
+    pub seed_hash: [u8; 32],
+    /// DarkFi mine target, as big-endian bytes the RandomX output
+    /// must not exceed
+    pub target: [u8; 32],
+}
 
-        let req_body = JsonRequest::new("get_block_count", vec![].into()).stringify().unwrap();
+impl MiningProxy {
+    /// Perform a JSON-RPC request to monerod, returning its result or
+    /// a `JsonRpcError` with the monerod error.
+    pub async fn monerod_request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let req_body = JsonRequest::new(method, params).stringify()?;
 
         let client = surf::Client::new();
-        let mut response = match client
-            .get(&self.monerod_rpc)
+        let mut response = client
+            .post(&self.monerod_rpc)
             .header("Content-Type", "application/json")
             .body(req_body)
             .send()
             .await
-        {
-            Ok(v) => v,
-            Err(e) => {
-                error!(target: "rpc::monero::get_block_count", "Error sending RPC request to monerod: {}", e);
-                return JsonError::new(InternalError, None, id).into()
-            }
-        };
+            .map_err(|e| Error::Custom(format!("Error sending RPC request to monerod: {}", e)))?;
 
-        let response_bytes = match response.body_bytes().await {
-            Ok(v) => v,
-            Err(e) => {
-                error!(target: "rpc::monero::get_block_count", "Error reading monerod RPC response: {}", e);
-                return JsonError::new(InternalError, None, id).into()
-            }
-        };
-
-        let response_string = match String::from_utf8(response_bytes) {
-            Ok(v) => v,
-            Err(e) => {
-                error!(target: "rpc::monero::get_block_count", "Error parsing monerod RPC response: {}", e);
-                return JsonError::new(InternalError, None, id).into()
-            }
-        };
+        let response_bytes = response
+            .body_bytes()
+            .await
+            .map_err(|e| Error::Custom(format!("Error reading monerod RPC response: {}", e)))?;
 
-        let response_json: JsonValue = match response_string.parse() {
-            Ok(v) => v,
-            Err(e) => {
-                error!(target: "rpc::monero::get_block_count", "Error parsing monerod RPC response: {}", e);
-                return JsonError::new(InternalError, None, id).into()
-            }
+        let response_json: JsonValue = String::from_utf8(response_bytes)?.parse()?;
+        let Some(response) = response_json.get::<HashMap<String, JsonValue>>() else {
+            return Err(Error::UnexpectedJsonRpc(response_json.stringify()?))
         };
 
-        JsonResponse::new(response_json, id).into()
-    }
-
-    /*
-    pub async fn monero_on_get_block_hash(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
-
-    pub async fn monero_get_block_template(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
-
-    pub async fn monero_submit_block(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+        if let Some(error) =
+            response.get("error").and_then(|v| v.get::<HashMap<String, JsonValue>>())
+        {
+            let code = error.get("code").and_then(|v| v.get::<f64>()).copied();
+            let message = error.get("message").and_then(|v| v.get::<String>()).cloned();
+            return Err(Error::JsonRpcError((
+                code.unwrap_or(-1.0) as i32,
+                message.unwrap_or_default(),
+            )))
+        }
+
+        match response.get("result") {
+            Some(result) => Ok(result.clone()),
+            None => Err(Error::UnexpectedJsonRpc(response_json.stringify()?)),
+        }
     }
 
-    pub async fn monero_generateblocks(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Perform a JSON-RPC request to darkfid
+    async fn darkfid_request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let rpc_client = RpcClient::new(self.darkfid_rpc.clone(), self.executor.clone()).await?;
+        rpc_client.oneshot_request(JsonRequest::new(method, params)).await
     }
 
-    pub async fn monero_get_last_block_header(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Map a monerod request result to a JSON-RPC result for given ID
+    fn monerod_result(id: u16, result: Result<JsonValue>) -> JsonResult {
+        match result {
+            Ok(v) => JsonResponse::new(v, id).into(),
+            Err(Error::JsonRpcError((code, message))) => {
+                JsonError::new(ServerError(code), Some(message), id).into()
+            }
+            Err(e) => {
+                error!(target: "rpc::monero", "monerod request failed: {}", e);
+                JsonError::new(InternalError, None, id).into()
+            }
+        }
     }
 
-    pub async fn monero_get_block_header_by_hash(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Proxy a monerod JSON-RPC method call as it is
+    pub async fn monero_passthrough(&self, id: u16, method: &str, params: JsonValue) -> JsonResult {
+        debug!(target: "rpc::monero", "{}()", method);
+        Self::monerod_result(id, self.monerod_request(method, params).await)
     }
 
-    pub async fn monero_get_block_header_by_height(
-        &self,
-        id: u16,
-        params: JsonValue,
-    ) -> JsonResult {
-        todo!()
-    }
+    /// Proxy monerod's `get_block_template`, committing to the current DarkFi
+    /// block template with a merge mining tag in the coinbase transaction
+    /// extra, so that solving the Monero block also solves the DarkFi one.
+    /// If darkfid is unreachable, the Monero template is returned as it is.
+    pub async fn monero_get_block_template(&self, id: u16, params: JsonValue) -> JsonResult {
+        debug!(target: "rpc::monero", "get_block_template()");
 
-    pub async fn monero_get_block_headers_range(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        if !params.is_object() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
 
-    pub async fn monero_get_block(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        let mut template = match self.monerod_request("get_block_template", params).await {
+            Ok(v) => v,
+            Err(e) => return Self::monerod_result(id, Err(e)),
+        };
 
-    pub async fn monero_get_connections(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        if let Err(e) = self.inject_darkfi_job(&mut template).await {
+            warn!(target: "rpc::monero::get_block_template", "Not merge mining: {}", e);
+        }
 
-    pub async fn monero_get_info(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+        JsonResponse::new(template, id).into()
     }
 
-    pub async fn monero_hard_fork_info(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Fetch a DarkFi block template and commit to it in the Monero one.
+    /// The merge mining tag is inserted as the first coinbase extra field,
+    /// moving the reserved space, so `reserved_offset` gets updated too.
+    /// The job is kept around for merge mining submissions.
+    pub async fn inject_darkfi_job(&self, template: &mut JsonValue) -> Result<MergeMiningJob> {
+        let darkfi_job = self.darkfid_request("miner.get_block_template", vec![].into()).await?;
+        let parse_err = || Error::UnexpectedJsonRpc(String::from("Invalid block template"));
+
+        let darkfi_job = darkfi_job.get::<HashMap<String, JsonValue>>().ok_or_else(parse_err)?;
+        let job_id = darkfi_job.get("job_id").and_then(|v| v.get::<String>());
+        let job_id = job_id.ok_or_else(parse_err)?.clone();
+        let hash = darkfi_job.get("hash").and_then(|v| v.get::<String>());
+        let hash = blake3::Hash::from_hex(hash.ok_or_else(parse_err)?).map_err(|_| parse_err())?;
+        let target = darkfi_job.get("target").and_then(|v| v.get::<String>());
+        let target = target.and_then(|v| hex_decode(v)).ok_or_else(parse_err)?;
+        let target: [u8; 32] = target.try_into().map_err(|_| parse_err())?;
+
+        let Some(template) = template.get_mut::<HashMap<String, JsonValue>>() else {
+            return Err(parse_err())
+        };
+        let blob = template.get("blocktemplate_blob").and_then(|v| v.get::<String>());
+        let blob = blob.and_then(|v| hex_decode(v)).ok_or_else(parse_err)?;
+        let seed_hash = template.get("seed_hash").and_then(|v| v.get::<String>());
+        let seed_hash = seed_hash.and_then(|v| hex_decode(v)).ok_or_else(parse_err)?;
+        let seed_hash: [u8; 32] = seed_hash.try_into().map_err(|_| parse_err())?;
+        let offset = template.get("reserved_offset").and_then(|v| v.get::<f64>()).copied();
+
+        let (blob, growth) = insert_merge_mining_tag(&blob, &hash).ok_or_else(parse_err)?;
+
+        // Miners hash the hashing blob, which commits to the coinbase transaction
+        let pow_data = MoneroPowData::from_block_blob(&blob, seed_hash).ok_or_else(parse_err)?;
+        let hashing_blob = pow_data.hashing_blob().ok_or_else(parse_err)?;
+
+        template.insert("blocktemplate_blob".to_string(), JsonValue::String(hex_encode(&blob)));
+        template
+            .insert("blockhashing_blob".to_string(), JsonValue::String(hex_encode(&hashing_blob)));
+        if let Some(offset) = offset {
+            template
+                .insert("reserved_offset".to_string(), JsonValue::Number(offset + growth as f64));
+        }
+
+        let job = MergeMiningJob { hash, job_id, seed_hash, target };
+        info!(target: "rpc::monero::get_block_template", "Merge mining DarkFi job {}", job.job_id);
+        let mut merge_jobs = self.merge_jobs.lock().await;
+        if merge_jobs.len() >= MAX_MERGE_JOBS {
+            merge_jobs.remove(0);
+        }
+        merge_jobs.push(job.clone());
+
+        Ok(job)
     }
 
-    pub async fn monero_set_bans(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+    /// Proxy monerod's `submit_block`, and submit the merge mining proof of
+    /// any DarkFi job the Monero block commits to, to darkfid. The monerod
+    /// result is returned, since DarkFi submissions are best effort and their
+    /// target is usually different.
+    pub async fn monero_submit_block(&self, id: u16, params: JsonValue) -> JsonResult {
+        debug!(target: "rpc::monero", "submit_block()");
 
-    pub async fn monero_get_bans(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        let Some(blobs) = params.get::<Vec<JsonValue>>() else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
 
-    pub async fn monero_banned(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        let result = self.monerod_request("submit_block", params.clone()).await;
+        if let Err(e) = &result {
+            info!(target: "rpc::monero::submit_block", "monerod rejected block: {}", e);
+        }
 
-    pub async fn monero_flush_txpool(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        for blob in blobs.iter().filter_map(|v| v.get::<String>()).filter_map(|v| hex_decode(v)) {
+            if let Err(e) = self.submit_merge_mined(&blob).await {
+                error!(target: "rpc::monero::submit_block", "darkfid rejected block: {}", e);
+            }
+        }
 
-    pub async fn monero_get_output_histogram(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+        Self::monerod_result(id, result)
     }
 
-    pub async fn monero_get_version(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+    /// Submit the merge mining proof of given Monero block to darkfid, if it
+    /// commits to one of our DarkFi jobs.
+    async fn submit_merge_mined(&self, blob: &[u8]) -> Result<()> {
+        let Some(pow_data) = MoneroPowData::from_block_blob(blob, [0; 32]) else {
+            return Err(Error::ParseFailed("Invalid Monero block blob"))
+        };
 
-    pub async fn monero_get_coinbase_tx_sum(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+        let merge_jobs = self.merge_jobs.lock().await;
+        let Some(job) = merge_jobs.iter().find(|job| pow_data.commits_to(&job.hash)) else {
+            debug!(target: "rpc::monero::submit_block", "Block doesn't commit to a DarkFi job");
+            return Ok(())
+        };
+        let job = job.clone();
+        drop(merge_jobs);
 
-    pub async fn monero_get_fee_estimate(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+        self.submit_pow_data(&job, pow_data).await
     }
 
-    pub async fn monero_get_alternate_chains(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Submit the merge mining proof of given DarkFi job to darkfid
+    pub async fn submit_pow_data(
+        &self,
+        job: &MergeMiningJob,
+        mut pow_data: MoneroPowData,
+    ) -> Result<()> {
+        pow_data.seed_hash = job.seed_hash;
+
+        let params = vec![
+            JsonValue::String(job.job_id.clone()),
+            JsonValue::String(base64::encode(&serialize(&pow_data))),
+        ];
+        self.darkfid_request("miner.submit_merge_mined", params.into()).await?;
+        info!(target: "rpc::monero::submit_block", "DarkFi job {} solved", job.job_id);
+
+        Ok(())
     }
+}
 
-    pub async fn monero_relay_tx(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+/// Encode given bytes to a lowercase hex string
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-    pub async fn monero_sync_info(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+/// Decode given hex string to bytes
+pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None
     }
 
-    pub async fn monero_get_txpool_backlog(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
-    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
 
-    pub async fn monero_get_output_distribution(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use darkfi::{
+        blockchain::monero::{write_varint, MERGE_MINING_TAG_SIZE},
+        rpc::{
+            jsonrpc::JsonResponse,
+            server::{listen_and_serve, RequestHandler},
+        },
+        system::{msleep, StoppableTask, StoppableTaskPtr},
+    };
+    use darkfi_serial::{async_trait, deserialize};
+    use smol::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        lock::{Mutex, MutexGuard},
+        net::TcpListener,
+        Executor,
+    };
+    use url::Url;
+
+    use super::*;
+
+    /// Size of the reserved space in the mock Monero block template
+    const RESERVE_SIZE: usize = 8;
+
+    /// Offset of the reserved space in the mock Monero block template, which
+    /// is followed by the coinbase RingCT type and the transactions count.
+    fn reserved_offset() -> usize {
+        block_template_blob().len() - RESERVE_SIZE - 2
     }
 
-    pub async fn monero_get_miner_data(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Build a Monero block template blob, with reserved space in the coinbase
+    /// transaction extra, as an extra nonce.
+    fn block_template_blob() -> Vec<u8> {
+        let mut blob = vec![16, 16];
+        write_varint(&mut blob, 1700000000);
+        blob.extend_from_slice(&[1; 32]);
+        blob.extend_from_slice(&[0; 4]);
+
+        blob.extend_from_slice(&[2, 60, 1, 0xff]);
+        write_varint(&mut blob, 3000000);
+        blob.push(1);
+        write_varint(&mut blob, 600000000000);
+        blob.push(0x03);
+        blob.extend_from_slice(&[2; 33]);
+
+        // Transaction public key and extra nonce holding the reserved space
+        let mut extra = vec![0x01];
+        extra.extend_from_slice(&[3; 32]);
+        extra.extend_from_slice(&[0x02, RESERVE_SIZE as u8]);
+        extra.extend_from_slice(&[0; RESERVE_SIZE]);
+        write_varint(&mut blob, extra.len() as u64);
+        blob.extend_from_slice(&extra);
+        blob.push(0);
+
+        blob.push(0);
+        blob
     }
 
-    pub async fn monero_prune_blockchain(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Minimal monerod stand-in, serving JSON-RPC over HTTP
+    async fn mock_monerod(listener: TcpListener, submissions: Arc<Mutex<Vec<JsonValue>>>) {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else { break };
+
+            // Read the HTTP request headers and body
+            let mut buf = vec![];
+            let mut byte = [0; 1];
+            while !buf.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).await.unwrap();
+                buf.push(byte[0]);
+            }
+            let headers = String::from_utf8(buf).unwrap().to_lowercase();
+            let len = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body).await.unwrap();
+
+            let req: JsonValue = String::from_utf8(body).unwrap().parse().unwrap();
+            let req = JsonRequest::try_from(&req).unwrap();
+            let result = match req.method.as_str() {
+                "get_block_template" => {
+                    let params = req.params.get::<HashMap<String, JsonValue>>().unwrap();
+                    assert!(params.contains_key("wallet_address"));
+                    JsonValue::Object(HashMap::from([
+                        (
+                            "blocktemplate_blob".to_string(),
+                            JsonValue::String(hex_encode(&block_template_blob())),
+                        ),
+                        ("blockhashing_blob".to_string(), JsonValue::String("00".to_string())),
+                        (
+                            "reserved_offset".to_string(),
+                            JsonValue::Number(reserved_offset() as f64),
+                        ),
+                        ("seed_hash".to_string(), JsonValue::String(hex_encode(&[4; 32]))),
+                        ("height".to_string(), JsonValue::Number(3000000.0)),
+                        ("difficulty".to_string(), JsonValue::Number(1000.0)),
+                    ]))
+                }
+                "submit_block" => {
+                    submissions.lock().await.push(req.params.clone());
+                    JsonValue::Object(HashMap::from([(
+                        "status".to_string(),
+                        JsonValue::String("OK".to_string()),
+                    )]))
+                }
+                _ => JsonValue::Number(42.0),
+            };
+
+            let body = JsonResponse::new(result, req.id).stringify().unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
     }
 
-    pub async fn monero_calc_pow(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    /// Minimal darkfid stand-in, serving a single block template
+    struct MockDarkfid {
+        hash: blake3::Hash,
+        submissions: Mutex<Vec<JsonValue>>,
+        rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
     }
 
-    pub async fn monero_flush_cache(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    #[async_trait]
+    impl RequestHandler for MockDarkfid {
+        async fn handle_request(&self, req: JsonRequest) -> JsonResult {
+            let result = match req.method.as_str() {
+                "miner.get_block_template" => JsonValue::Object(HashMap::from([
+                    ("job_id".to_string(), JsonValue::String("7".to_string())),
+                    ("hash".to_string(), JsonValue::String(self.hash.to_hex().to_string())),
+                    ("target".to_string(), JsonValue::String(format!("00{}", "ff".repeat(31)))),
+                ])),
+                "miner.submit_merge_mined" => {
+                    self.submissions.lock().await.push(req.params);
+                    JsonValue::Boolean(true)
+                }
+                _ => return JsonError::new(ServerError(-32601), None, req.id).into(),
+            };
+            JsonResponse::new(result, req.id).into()
+        }
+
+        async fn connections_mut(&self) -> MutexGuard<'_, HashSet<StoppableTaskPtr>> {
+            self.rpc_connections.lock().await
+        }
     }
 
-    pub async fn monero_add_aux_pow(&self, id: u16, params: JsonValue) -> JsonResult {
-        todo!()
+    #[test]
+    fn merge_mining_proxy() -> Result<()> {
+        let executor = Arc::new(Executor::new());
+
+        smol::block_on(executor.run(async {
+            // Spawn the monerod stand-in
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let monerod_rpc = Url::parse(&format!(
+                "http://127.0.0.1:{}/json_rpc",
+                listener.local_addr()?.port()
+            ))?;
+            let monerod_submissions = Arc::new(Mutex::new(vec![]));
+            executor.spawn(mock_monerod(listener, monerod_submissions.clone())).detach();
+
+            // Spawn the darkfid stand-in
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let darkfid_rpc =
+                Url::parse(&format!("tcp://127.0.0.1:{}", listener.local_addr()?.port()))?;
+            drop(listener);
+            let darkfid = Arc::new(MockDarkfid {
+                hash: blake3::hash(b"darkfi block header"),
+                submissions: Mutex::new(vec![]),
+                rpc_connections: Mutex::new(HashSet::new()),
+            });
+            let darkfid_task = StoppableTask::new();
+            darkfid_task.clone().start(
+                listen_and_serve(darkfid_rpc.clone(), darkfid.clone(), None, executor.clone()),
+                |_| async {},
+                Error::RpcServerStopped,
+                executor.clone(),
+            );
+            msleep(500).await;
+
+            let mmproxy = MiningProxy::new(
+                monerod_rpc,
+                darkfid_rpc,
+                Some("4".to_string()),
+                HashMap::from([("x".to_string(), "x".to_string())]),
+                executor.clone(),
+            );
+
+            // Calls get proxied to monerod
+            let JsonResult::Response(rep) =
+                mmproxy.monero_passthrough(0, "get_info", vec![].into()).await
+            else {
+                panic!("get_info failed")
+            };
+            assert_eq!(rep.result.get::<f64>(), Some(&42.0));
+
+            // The block template commits to the DarkFi block
+            let params = HashMap::from([
+                ("wallet_address".to_string(), JsonValue::String("4".to_string())),
+                ("reserve_size".to_string(), JsonValue::Number(8.0)),
+            ]);
+            let JsonResult::Response(rep) =
+                mmproxy.monero_get_block_template(1, params.into()).await
+            else {
+                panic!("get_block_template failed")
+            };
+            let template = rep.result.get::<HashMap<String, JsonValue>>().unwrap();
+            let blob = hex_decode(template["blocktemplate_blob"].get::<String>().unwrap()).unwrap();
+            let pow_data = MoneroPowData::from_block_blob(&blob, [4; 32]).unwrap();
+            assert!(pow_data.commits_to(&darkfid.hash));
+
+            // The reserved space moved along with the inserted tag
+            let offset = *template["reserved_offset"].get::<f64>().unwrap() as usize;
+            assert_eq!(offset, reserved_offset() + MERGE_MINING_TAG_SIZE);
+            assert_eq!(
+                blob[offset - 2..offset + RESERVE_SIZE],
+                block_template_blob()[reserved_offset() - 2..reserved_offset() + RESERVE_SIZE]
+            );
+            let hashing_blob = template["blockhashing_blob"].get::<String>().unwrap();
+            assert_eq!(hex_decode(hashing_blob), pow_data.hashing_blob());
+
+            // Solved blocks get submitted to both chains
+            let params = vec![JsonValue::String(hex_encode(&blob))];
+            let JsonResult::Response(rep) = mmproxy.monero_submit_block(2, params.into()).await
+            else {
+                panic!("submit_block failed")
+            };
+            assert!(rep.result.get::<HashMap<String, JsonValue>>().unwrap().contains_key("status"));
+            assert_eq!(monerod_submissions.lock().await.len(), 1);
+
+            let submissions = darkfid.submissions.lock().await;
+            assert_eq!(submissions.len(), 1);
+            let params = submissions[0].get::<Vec<JsonValue>>().unwrap();
+            assert_eq!(params[0].get::<String>().unwrap(), "7");
+            let bytes = base64::decode(params[1].get::<String>().unwrap()).unwrap();
+            let submitted: MoneroPowData = deserialize(&bytes)?;
+            assert_eq!(submitted, pow_data);
+            assert!(submitted.commits_to(&darkfid.hash));
+            drop(submissions);
+
+            // Blocks not committing to DarkFi jobs only go to monerod
+            let params = vec![JsonValue::String(hex_encode(&block_template_blob()))];
+            mmproxy.monero_submit_block(3, params.into()).await;
+            assert_eq!(monerod_submissions.lock().await.len(), 2);
+            assert_eq!(darkfid.submissions.lock().await.len(), 1);
+
+            // Stratum workers get a job on login
+            let params = vec![JsonValue::Object(HashMap::from([
+                ("login".to_string(), JsonValue::String("x".to_string())),
+                ("pass".to_string(), JsonValue::String("x".to_string())),
+                ("agent".to_string(), JsonValue::String("xmrig".to_string())),
+                ("algo".to_string(), JsonValue::Array(vec![JsonValue::String("rx/0".to_string())])),
+            ]))];
+            let JsonResult::Response(rep) = mmproxy.stratum_login(4, params.into()).await else {
+                panic!("login failed")
+            };
+            let login = rep.result.get::<HashMap<String, JsonValue>>().unwrap();
+            let uuid = login["id"].get::<String>().unwrap().clone();
+            let job = login["job"].get::<HashMap<String, JsonValue>>().unwrap();
+            let job_id = job["job_id"].get::<String>().unwrap().clone();
+            let hashing_blob = hex_decode(job["blob"].get::<String>().unwrap());
+            assert_eq!(hashing_blob, pow_data.hashing_blob());
+            let target = hex_decode(job["target"].get::<String>().unwrap()).unwrap();
+            assert_eq!(target, (u64::MAX / 1000).to_le_bytes());
+
+            // Shares meeting the DarkFi target get submitted to both chains
+            let submit = |job_id: &str, result: &str| -> JsonValue {
+                vec![JsonValue::Object(HashMap::from([
+                    ("id".to_string(), JsonValue::String(uuid.clone())),
+                    ("job_id".to_string(), JsonValue::String(job_id.to_string())),
+                    ("nonce".to_string(), JsonValue::String("01020304".to_string())),
+                    ("result".to_string(), JsonValue::String(result.repeat(32))),
+                ]))]
+                .into()
+            };
+            let JsonResult::Response(_) = mmproxy.stratum_submit(5, submit(&job_id, "00")).await
+            else {
+                panic!("submit failed")
+            };
+            let submitted = monerod_submissions.lock().await[2].clone();
+            let submitted = submitted.get::<Vec<JsonValue>>().unwrap()[0].get::<String>().unwrap();
+            let mut solved = blob.clone();
+            solved[pow_data.header.len() - 4..pow_data.header.len()].copy_from_slice(&[1, 2, 3, 4]);
+            assert_eq!(hex_decode(submitted), Some(solved.clone()));
+
+            let submissions = darkfid.submissions.lock().await;
+            assert_eq!(submissions.len(), 2);
+            let params = submissions[1].get::<Vec<JsonValue>>().unwrap();
+            let bytes = base64::decode(params[1].get::<String>().unwrap()).unwrap();
+            let submitted: MoneroPowData = deserialize(&bytes)?;
+            assert_eq!(submitted, MoneroPowData::from_block_blob(&solved, [4; 32]).unwrap());
+            drop(submissions);
+
+            // Shares missing the DarkFi target only go to monerod
+            let JsonResult::Response(_) = mmproxy.stratum_submit(6, submit(&job_id, "ff")).await
+            else {
+                panic!("submit failed")
+            };
+            assert_eq!(monerod_submissions.lock().await.len(), 4);
+            assert_eq!(darkfid.submissions.lock().await.len(), 2);
+
+            // Unknown jobs are refused
+            let JsonResult::Error(_) = mmproxy.stratum_submit(7, submit("0", "00")).await else {
+                panic!("submit of unknown job succeeded")
+            };
+
+            darkfid_task.stop().await;
+            Ok(())
+        }))
     }
-    */
 }
@@ -19,6 +19,7 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use darkfi::{
+    blockchain::monero::MoneroPowData,
     rpc::{
         jsonrpc::{ErrorCode, JsonError, JsonResponse, JsonResult, JsonSubscriber},
         util::JsonValue,
@@ -30,12 +31,95 @@ use log::{debug, error, info, warn};
 use smol::{channel, lock::RwLock};
 use uuid::Uuid;
 
-use super::{error::RpcError, MiningProxy, Worker};
+use super::{
+    error::RpcError,
+    monero::{hex_decode, hex_encode, MergeMiningJob},
+    MiningProxy, Worker,
+};
 
 /// Algo string representing Monero's RandomX
 pub const RANDOMX_ALGO: &str = "rx/0";
 
+/// Maximum number of jobs we keep accepting stratum submissions for
+const MAX_STRATUM_JOBS: usize = 16;
+
+/// Monero block template handed out to stratum workers
+#[derive(Clone)]
+pub struct StratumJob {
+    /// Job ID sent to workers
+    pub job_id: String,
+    /// Monero block template blob
+    pub blob: Vec<u8>,
+    /// Offset of the 4 byte nonce in both the block and hashing blobs
+    pub nonce_offset: usize,
+    /// DarkFi job the block template commits to, if any
+    pub merge_job: Option<MergeMiningJob>,
+}
+
 impl MiningProxy {
+    /// Fetch a Monero block template committing to the current DarkFi job,
+    /// and keep it around for submissions. Returns the stratum job object
+    /// sent to workers. Its share target is the Monero block difficulty,
+    /// so every accepted share is a Monero block candidate.
+    async fn new_stratum_job(&self) -> Result<JsonValue> {
+        let Some(monero_address) = &self.monero_address else {
+            return Err(Error::Custom("No Monero wallet address configured".to_string()))
+        };
+
+        let params = HashMap::from([
+            ("wallet_address".to_string(), JsonValue::String(monero_address.clone())),
+            ("reserve_size".to_string(), JsonValue::Number(0.0)),
+        ]);
+        let mut template = self.monerod_request("get_block_template", params.into()).await?;
+
+        let merge_job = match self.inject_darkfi_job(&mut template).await {
+            Ok(job) => Some(job),
+            Err(e) => {
+                warn!("new_stratum_job: Not merge mining: {}", e);
+                None
+            }
+        };
+
+        let parse_err = || Error::UnexpectedJsonRpc(String::from("Invalid block template"));
+        let Some(template) = template.get::<HashMap<String, JsonValue>>() else {
+            return Err(parse_err())
+        };
+        let blob = template.get("blocktemplate_blob").and_then(|v| v.get::<String>());
+        let blob = blob.and_then(|v| hex_decode(v)).ok_or_else(parse_err)?;
+        let seed_hash = template.get("seed_hash").and_then(|v| v.get::<String>());
+        let seed_hash = seed_hash.ok_or_else(parse_err)?.clone();
+        let height = template.get("height").and_then(|v| v.get::<f64>());
+        let height = *height.ok_or_else(parse_err)?;
+        let difficulty = template.get("difficulty").and_then(|v| v.get::<f64>());
+        let difficulty = (*difficulty.ok_or_else(parse_err)? as u64).max(1);
+
+        let pow_data = MoneroPowData::from_block_blob(&blob, [0; 32]).ok_or_else(parse_err)?;
+        let hashing_blob = pow_data.hashing_blob().ok_or_else(parse_err)?;
+        let nonce_offset = pow_data.header.len() - 4;
+
+        // Workers compare the last 8 bytes of the hash, as a little-endian
+        // integer, with the little-endian encoded target.
+        let target = u64::MAX / difficulty;
+
+        let job_id = Uuid::new_v4().to_string();
+        let job = JsonValue::Object(HashMap::from([
+            ("job_id".to_string(), JsonValue::String(job_id.clone())),
+            ("blob".to_string(), JsonValue::String(hex_encode(&hashing_blob))),
+            ("target".to_string(), JsonValue::String(hex_encode(&target.to_le_bytes()))),
+            ("height".to_string(), JsonValue::Number(height)),
+            ("seed_hash".to_string(), JsonValue::String(seed_hash)),
+            ("algo".to_string(), JsonValue::String(RANDOMX_ALGO.to_string())),
+        ]));
+
+        let mut stratum_jobs = self.stratum_jobs.lock().await;
+        if stratum_jobs.len() >= MAX_STRATUM_JOBS {
+            stratum_jobs.remove(0);
+        }
+        stratum_jobs.push(StratumJob { job_id, blob, nonce_offset, merge_job });
+
+        Ok(job)
+    }
+
     /// Background task listening for keepalives from a worker, if timeout is reached
     /// the worker will be dropped.
     async fn keepalive_task(
@@ -146,6 +230,20 @@ impl MiningProxy {
             .into()
         }
 
+        // Fetch the job to start working on
+        let job = match self.new_stratum_job().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("stratum_login: Failed creating mining job: {}", e);
+                return JsonError::new(
+                    RpcError::MiningJobUnavailable.into(),
+                    Some("Mining job unavailable".to_string()),
+                    id,
+                )
+                .into()
+            }
+        };
+
         // Login success, generate UUID
         let uuid = Uuid::new_v4();
 
@@ -174,17 +272,21 @@ impl MiningProxy {
 
         info!("Added worker {} ({})", login, uuid);
 
-        // TODO: Send current job
         JsonResponse::new(
-            JsonValue::Object(HashMap::from([(
-                "status".to_string(),
-                JsonValue::String("KEEPALIVED".to_string()),
-            )])),
+            JsonValue::Object(HashMap::from([
+                ("id".to_string(), JsonValue::String(uuid.to_string())),
+                ("job".to_string(), job),
+                ("status".to_string(), JsonValue::String("OK".to_string())),
+            ])),
             id,
         )
         .into()
     }
 
+    /// Stratum submit method. The worker's nonce is filled into the Monero
+    /// block of the job, which gets submitted to monerod. If the resulting
+    /// hash also meets the DarkFi target of the job, its merge mining proof
+    /// gets submitted to darkfid.
     pub async fn stratum_submit(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if params.len() != 1 || !params[0].is_object() {
@@ -201,23 +303,90 @@ impl MiningProxy {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         }
 
-        let Some(_uuid) = params["id"].get::<String>() else {
+        let Some(uuid) = params["id"].get::<String>() else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        let Some(job_id) = params["job_id"].get::<String>() else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        let Some(nonce) = params["nonce"].get::<String>() else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        let Some(result) = params["result"].get::<String>() else {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         };
 
-        let Some(_job_id) = params["job_id"].get::<String>() else {
+        let Ok(uuid) = Uuid::try_from(uuid.as_str()) else {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         };
 
-        let Some(_nonce) = params["nonce"].get::<String>() else {
+        let Some(nonce) = hex_decode(nonce).filter(|v| v.len() == 4) else {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         };
 
-        let Some(_result) = params["result"].get::<String>() else {
+        let Some(result) = hex_decode(result).filter(|v| v.len() == 32) else {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         };
 
-        todo!()
+        if !self.workers.read().await.contains_key(&uuid) {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let stratum_jobs = self.stratum_jobs.lock().await;
+        let Some(job) = stratum_jobs.iter().find(|job| &job.job_id == job_id).cloned() else {
+            return JsonError::new(
+                RpcError::UnknownMiningJob.into(),
+                Some("Unknown job ID".to_string()),
+                id,
+            )
+            .into()
+        };
+        drop(stratum_jobs);
+
+        // Rebuild the Monero block with the worker's nonce
+        let mut blob = job.blob;
+        blob[job.nonce_offset..job.nonce_offset + 4].copy_from_slice(&nonce);
+
+        let mut accepted = false;
+        let params = vec![JsonValue::String(hex_encode(&blob))];
+        match self.monerod_request("submit_block", params.into()).await {
+            Ok(_) => {
+                info!("Monero block {} solved by worker {}", job_id, uuid);
+                accepted = true;
+            }
+            Err(e) => info!("stratum_submit: monerod rejected block: {}", e),
+        }
+
+        // The DarkFi target is compared with the hash as a big-endian integer.
+        // The hash is the worker's claim, darkfid verifies it on submission.
+        if let Some(merge_job) = job.merge_job.filter(|v| result[..] <= v.target[..]) {
+            let pow_data = MoneroPowData::from_block_blob(&blob, merge_job.seed_hash).unwrap();
+            match self.submit_pow_data(&merge_job, pow_data).await {
+                Ok(()) => accepted = true,
+                Err(e) => error!("stratum_submit: darkfid rejected block: {}", e),
+            }
+        }
+
+        if !accepted {
+            return JsonError::new(
+                RpcError::RejectedSolution.into(),
+                Some("Rejected solution".to_string()),
+                id,
+            )
+            .into()
+        }
+
+        JsonResponse::new(
+            JsonValue::Object(HashMap::from([(
+                "status".to_string(),
+                JsonValue::String("OK".to_string()),
+            )])),
+            id,
+        )
+        .into()
     }
 
     /// Non standard but widely supported protocol extension. Miner sends `keepalived`
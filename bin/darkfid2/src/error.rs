@@ -28,6 +28,15 @@ pub enum RpcError {
     // State-related errors,
     NotSynced = -32120,
     UnknownSlot = -32121,
+    UnknownBlock = -32122,
+    UnknownTx = -32123,
+    LightNode = -32124,
+
+    // Miner-related errors
+    StratumDisabled = -32130,
+    StaleJob = -32131,
+    InvalidSolution = -32132,
+    BlockAppendFail = -32133,
 
     // Parsing errors
     ParseError = -32190,
@@ -44,6 +53,14 @@ fn to_tuple(e: RpcError) -> (i32, String) {
         // State-related errors
         RpcError::NotSynced => "Blockchain is not synced",
         RpcError::UnknownSlot => "Did not find slot",
+        RpcError::UnknownBlock => "Did not find block",
+        RpcError::UnknownTx => "Did not find transaction",
+        RpcError::LightNode => "Not supported by light nodes",
+        // Miner-related errors
+        RpcError::StratumDisabled => "Node is not serving external miners",
+        RpcError::StaleJob => "Stale job",
+        RpcError::InvalidSolution => "Block does not meet the mine target",
+        RpcError::BlockAppendFail => "Failed appending mined block",
         // Parsing errors
         RpcError::ParseError => "Parse error",
         // Contract-related errors
@@ -0,0 +1,134 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Light node on-demand data retrieval. Light nodes only store the headers
+//! chain, so blocks and transactions are requested from full node peers and
+//! verified against the local headers before being served.
+
+use std::{sync::Arc, time::Duration};
+
+use log::{debug, warn};
+
+use darkfi::{
+    blockchain::{BlockInfo, TxInclusionProof},
+    net::{ChannelPtr, Message},
+    system::timeout::timeout,
+    tx::Transaction,
+    Result,
+};
+
+use crate::{
+    proto::{BlockRangeRequest, SyncResponse, TxInclusionRequest, TxInclusionResponse, BATCH},
+    Darkfid,
+};
+
+/// Time to wait for a peer to respond to a light node request, in seconds
+const LIGHT_TIMEOUT: u64 = 10;
+
+/// Send a request to the given peer and wait for its response.
+/// Returns `None` if the peer didn't respond in time.
+async fn request_peer<Req: Message, Res: Message>(
+    channel: &ChannelPtr,
+    request: Req,
+) -> Option<Arc<Res>> {
+    let sub = channel.subscribe_msg::<Res>().await.ok()?;
+    let response = match channel.send(&request).await {
+        Ok(()) => timeout(Duration::from_secs(LIGHT_TIMEOUT), sub.receive()).await.ok(),
+        Err(_) => None,
+    };
+    sub.unsubscribe().await;
+
+    match response {
+        Some(Ok(response)) => Some(response),
+        _ => None,
+    }
+}
+
+impl Darkfid {
+    /// Fetch the blocks of the given inclusive height range from our peers.
+    /// Only blocks whose headers we know are requested, and each of them must
+    /// match its known header and its transactions the header Merkle root.
+    pub async fn fetch_blocks(&self, start: u64, end: u64) -> Result<Vec<BlockInfo>> {
+        let blockchain = { self.validator.read().await.blockchain.clone() };
+        let headers = blockchain.get_headers_in_range(start, end)?;
+
+        let mut blocks = Vec::with_capacity(headers.len());
+        for chunk in headers.chunks(BATCH as usize) {
+            let (start, end) = (chunk[0].height, chunk[chunk.len() - 1].height);
+
+            let mut retrieved = None;
+            for channel in self.sync_p2p.channels().await {
+                let request = BlockRangeRequest { start, end };
+                let Some(response) = request_peer::<_, SyncResponse>(&channel, request).await
+                else {
+                    debug!(target: "darkfid::light::fetch_blocks", "Peer {} didn't respond", channel.address());
+                    continue
+                };
+
+                let valid = response.blocks.iter().map(|b| &b.header).eq(chunk) &&
+                    response.blocks.iter().all(|b| matches!(b.verify_txs_root(), Ok(true)));
+                if !valid {
+                    warn!(target: "darkfid::light::fetch_blocks", "Peer {} served invalid blocks", channel.address());
+                    continue
+                }
+
+                retrieved = Some(response.blocks.clone());
+                break
+            }
+
+            // Blocks must be served in order, so we stop at the first missing range
+            let Some(retrieved) = retrieved else { break };
+            blocks.extend(retrieved);
+        }
+
+        Ok(blocks)
+    }
+
+    /// Fetch a transaction along with its inclusion proof from our peers.
+    /// The proof must verify against the header of the block containing it.
+    pub async fn fetch_tx(
+        &self,
+        tx_hash: &blake3::Hash,
+    ) -> Result<Option<(Transaction, TxInclusionProof)>> {
+        let blockchain = { self.validator.read().await.blockchain.clone() };
+
+        for channel in self.sync_p2p.channels().await {
+            let request = TxInclusionRequest { tx_hash: *tx_hash };
+            let Some(response) = request_peer::<_, TxInclusionResponse>(&channel, request).await
+            else {
+                debug!(target: "darkfid::light::fetch_tx", "Peer {} didn't respond", channel.address());
+                continue
+            };
+
+            let Some((tx, proof)) = response.proof.clone() else { continue };
+            let Some(header) = blockchain.get_headers_in_range(proof.height, proof.height)?.pop()
+            else {
+                continue
+            };
+
+            if &tx.hash()? != tx_hash || !proof.verify(&header, &tx)? {
+                warn!(target: "darkfid::light::fetch_tx", "Peer {} served an invalid proof", channel.address());
+                continue
+            }
+
+            return Ok(Some((tx, proof)))
+        }
+
+        Ok(None)
+    }
+}
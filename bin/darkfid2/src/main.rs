@@ -18,6 +18,8 @@
 
 use std::{
     collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter},
     str::FromStr,
     sync::Arc,
 };
@@ -29,16 +31,19 @@ use url::Url;
 
 use darkfi::{
     async_daemonize,
-    blockchain::BlockInfo,
+    blockchain::{BlockInfo, Blockchain},
     cli_desc,
     net::{settings::SettingsOpt, P2pPtr},
     rpc::{
         jsonrpc::JsonSubscriber,
         server::{listen_and_serve, RequestHandler},
     },
+    runtime::module_cache::MODULE_CACHE,
     system::{StoppableTask, StoppableTaskPtr},
     util::{path::expand_path, time::TimeKeeper},
-    validator::{utils::genesis_txs_total, Validator, ValidatorConfig, ValidatorPtr},
+    validator::{
+        mempool::MempoolConfig, utils::genesis_txs_total, Validator, ValidatorConfig, ValidatorPtr,
+    },
     Error, Result,
 };
 use darkfi_sdk::crypto::PublicKey;
@@ -53,15 +58,19 @@ use error::{server_error, RpcError};
 /// JSON-RPC requests handler and methods
 mod rpc;
 mod rpc_blockchain;
+mod rpc_miner;
 mod rpc_tx;
 
 /// Validator async tasks
 mod task;
-use task::{miner_task, sync_task};
+use task::{miner_task, stratum::MinerJobs, stratum_task, sync_task};
 
 /// P2P net protocols
 mod proto;
 
+/// Light node on-demand data retrieval
+mod light;
+
 /// Utility functions
 mod utils;
 use utils::{spawn_consensus_p2p, spawn_sync_p2p};
@@ -91,6 +100,23 @@ struct Args {
     /// Blockchain network to use
     network: String,
 
+    #[structopt(long)]
+    /// Roll back the blockchain to given block height before starting
+    rewind: Option<u64>,
+
+    #[structopt(long)]
+    /// Export a blockchain snapshot to given file and exit
+    export_snapshot: Option<String>,
+
+    #[structopt(long)]
+    /// Block height to export the snapshot at (defaults to last block)
+    snapshot_height: Option<u64>,
+
+    #[structopt(long)]
+    /// Bootstrap an empty blockchain from given snapshot file,
+    /// verified against the configured checkpoint
+    import_snapshot: Option<String>,
+
     #[structopt(flatten)]
     /// Localnet blockchain network configuration
     localnet: BlockchainNetwork,
@@ -153,6 +179,39 @@ pub struct BlockchainNetwork {
     /// Wallet address to receive consensus rewards
     pub recipient: Option<String>,
 
+    #[structopt(long)]
+    /// Serve block templates to external miners over JSON-RPC,
+    /// instead of mining blocks in-process
+    pub stratum: bool,
+
+    #[structopt(long)]
+    /// Trusted checkpoint block height, used to verify imported snapshots
+    pub checkpoint_height: Option<u64>,
+
+    #[structopt(long)]
+    /// Trusted checkpoint snapshot hash, used to verify imported snapshots
+    pub checkpoint_hash: Option<String>,
+
+    #[structopt(long, default_value = "1000")]
+    /// Maximum number of pending transactions in the mempool
+    pub mempool_max_txs: usize,
+
+    #[structopt(long, default_value = "16777216")]
+    /// Maximum total size of pending transactions in the mempool, in bytes
+    pub mempool_max_size: usize,
+
+    #[structopt(long, default_value = "3600")]
+    /// Time after which pending transactions expire, in seconds
+    pub mempool_expiry: u64,
+
+    #[structopt(long, default_value = "1000")]
+    /// Number of most recent blocks that can be rolled back
+    pub journal_depth: u64,
+
+    #[structopt(long)]
+    /// Run as a light node, syncing and verifying only block headers
+    pub light: bool,
+
     #[structopt(long)]
     /// Skip syncing process and start node right away
     pub skip_sync: bool,
@@ -182,6 +241,8 @@ pub struct Darkfid {
     subscribers: HashMap<&'static str, JsonSubscriber>,
     /// JSON-RPC connection tracker
     rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
+    /// Block templates served to external miners, if enabled
+    miner_jobs: Mutex<Option<MinerJobs>>,
 }
 
 impl Darkfid {
@@ -197,6 +258,7 @@ impl Darkfid {
             validator,
             subscribers,
             rpc_connections: Mutex::new(HashSet::new()),
+            miner_jobs: Mutex::new(None),
         }
     }
 }
@@ -220,6 +282,18 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         info!(target: "darkfid", "Node is configured to run in testing mode!");
     }
 
+    // Light nodes can't produce blocks
+    if blockchain_config.light && blockchain_config.consensus {
+        error!(target: "darkfid", "Light nodes can't participate in consensus");
+        return Err(Error::ConfigInvalid)
+    }
+
+    // Block templates are only served when participating in consensus
+    if blockchain_config.stratum && !blockchain_config.consensus {
+        error!(target: "darkfid", "Serving external miners requires participating in consensus");
+        return Err(Error::ConfigInvalid)
+    }
+
     // Parse the genesis block
     let bytes = bs58::decode(&genesis_block.trim()).into_vec()?;
     let genesis_block: BlockInfo = deserialize(&bytes)?;
@@ -228,6 +302,47 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     let db_path = expand_path(&blockchain_config.database)?;
     let sled_db = sled::open(&db_path)?;
 
+    // Keep compiled contract modules next to the database. They get loaded
+    // as native code, so the directory must only be writable by the node.
+    MODULE_CACHE.set_path(&db_path.join("wasm_cache"))?;
+
+    // Roll back the blockchain, if requested
+    if let Some(height) = args.rewind {
+        info!(target: "darkfid", "Rewinding blockchain to height {}...", height);
+        Blockchain::new(&sled_db)?.rollback_to(height)?;
+    }
+
+    // Export a blockchain snapshot, if requested
+    if let Some(path) = args.export_snapshot {
+        let blockchain = Blockchain::new(&sled_db)?;
+        let height = match args.snapshot_height {
+            Some(height) => height,
+            None => blockchain.last()?.0,
+        };
+        info!(target: "darkfid", "Exporting blockchain snapshot at height {}...", height);
+        let file = File::create(expand_path(&path)?)?;
+        let hash = blockchain.export_snapshot(height, BufWriter::new(file))?;
+        info!(target: "darkfid", "Snapshot exported to {} with hash: {}", path, hash);
+        return Ok(())
+    }
+
+    // Bootstrap the blockchain from a snapshot, if requested
+    if let Some(path) = args.import_snapshot {
+        let (Some(height), Some(hash)) =
+            (blockchain_config.checkpoint_height, &blockchain_config.checkpoint_hash)
+        else {
+            error!(target: "darkfid", "Importing a snapshot requires a trusted checkpoint");
+            return Err(Error::ConfigInvalid)
+        };
+        let hash = blake3::Hash::from_hex(hash)
+            .map_err(|_| Error::ParseFailed("Invalid checkpoint hash"))?;
+
+        info!(target: "darkfid", "Importing blockchain snapshot from {}...", path);
+        let file = File::open(expand_path(&path)?)?;
+        Blockchain::new(&sled_db)?.import_snapshot(BufReader::new(file), (height, hash))?;
+        info!(target: "darkfid", "Snapshot imported, resuming sync from height {}", height);
+    }
+
     // Initialize validator configuration
     let genesis_txs_total = genesis_txs_total(&genesis_block.txs)?;
     let time_keeper = TimeKeeper::new(
@@ -236,7 +351,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         blockchain_config.slot_time,
         0,
     );
-    let config = ValidatorConfig::new(
+    let mut config = ValidatorConfig::new(
         time_keeper,
         blockchain_config.threshold,
         blockchain_config.pow_threads,
@@ -246,28 +361,47 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         vec![],
         blockchain_config.testing_mode,
     );
+    config.mempool = MempoolConfig {
+        max_txs: blockchain_config.mempool_max_txs,
+        max_size: blockchain_config.mempool_max_size,
+        expiry: blockchain_config.mempool_expiry,
+    };
+    config.journal_depth = blockchain_config.journal_depth;
 
     // Initialize validator
     let validator = Validator::new(&sled_db, config).await?;
 
+    if blockchain_config.light {
+        info!(target: "darkfid", "Node is configured to run as a light node!");
+        validator.write().await.light = true;
+    }
+
     // Here we initialize various subscribers that can export live blockchain/consensus data.
     let mut subscribers = HashMap::new();
     subscribers.insert("blocks", JsonSubscriber::new("blockchain.subscribe_blocks"));
     subscribers.insert("txs", JsonSubscriber::new("blockchain.subscribe_txs"));
+    subscribers.insert("events", JsonSubscriber::new("blockchain.subscribe_events"));
     if blockchain_config.consensus {
         subscribers.insert("proposals", JsonSubscriber::new("blockchain.subscribe_proposals"));
+        if blockchain_config.stratum {
+            subscribers.insert("jobs", JsonSubscriber::new("miner.subscribe_jobs"));
+        }
     }
 
     // Initialize syncing P2P network
-    let sync_p2p =
-        spawn_sync_p2p(&blockchain_config.sync_net.into(), &validator, &subscribers, ex.clone())
-            .await;
+    let sync_p2p = spawn_sync_p2p(
+        &blockchain_config.sync_net.try_into()?,
+        &validator,
+        &subscribers,
+        ex.clone(),
+    )
+    .await;
 
     // Initialize consensus P2P network
     let consensus_p2p = if blockchain_config.consensus {
         Some(
             spawn_consensus_p2p(
-                &blockchain_config.consensus_net.into(),
+                &blockchain_config.consensus_net.try_into()?,
                 &validator,
                 &subscribers,
                 ex.clone(),
@@ -339,10 +473,17 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         };
 
         let (sender, recvr) = smol::channel::bounded(1);
+        let stratum = blockchain_config.stratum;
         let task = StoppableTask::new();
         task.clone().start(
             // Weird hack to prevent lifetimes hell
-            async move { miner_task(&darkfid, &recipient, &recvr).await },
+            async move {
+                if stratum {
+                    stratum_task(&darkfid, &recipient).await
+                } else {
+                    miner_task(&darkfid, &recipient, &recvr).await
+                }
+            },
             |res| async {
                 match res {
                     Ok(()) | Err(Error::MinerTaskStopped) => { /* Do nothing */ }
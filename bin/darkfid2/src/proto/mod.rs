@@ -26,7 +26,11 @@ pub use protocol_proposal::ProtocolProposal;
 
 /// Validator blockchain sync protocol
 mod protocol_sync;
-pub use protocol_sync::{ProtocolSync, SyncRequest, SyncResponse};
+pub use protocol_sync::{
+    BlockRangeRequest, HeaderSyncRequest, HeaderSyncResponse, ProtocolSync, SyncRequest,
+    SyncResponse, TipRequest, TipResponse, TxInclusionRequest, TxInclusionResponse, BATCH,
+    HEADERS_BATCH,
+};
 
 /// Transaction broadcast protocol
 mod protocol_tx;
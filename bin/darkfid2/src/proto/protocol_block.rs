@@ -38,6 +38,8 @@ use darkfi::{
 };
 use darkfi_serial::{serialize, SerialDecodable, SerialEncodable};
 
+use crate::utils::notify_events;
+
 /// Auxiliary [`BlockInfo`] wrapper structure used for messaging.
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
 pub struct BlockInfoMessage(BlockInfo);
@@ -57,6 +59,7 @@ pub struct ProtocolBlock {
     p2p: P2pPtr,
     channel_address: Url,
     subscriber: JsonSubscriber,
+    events_subscriber: JsonSubscriber,
 }
 
 impl ProtocolBlock {
@@ -65,6 +68,7 @@ impl ProtocolBlock {
         validator: ValidatorPtr,
         p2p: P2pPtr,
         subscriber: JsonSubscriber,
+        events_subscriber: JsonSubscriber,
     ) -> Result<ProtocolBasePtr> {
         debug!(
             target: "validator::protocol_block::init",
@@ -82,6 +86,7 @@ impl ProtocolBlock {
             p2p,
             channel_address: channel.address().clone(),
             subscriber,
+            events_subscriber,
         }))
     }
 
@@ -124,11 +129,42 @@ impl ProtocolBlock {
 
             let block_copy = (*block).clone();
 
+            // Light nodes only verify and store the block header, without
+            // relaying the block further since its body can't be verified.
+            if self.validator.read().await.light {
+                if !matches!(block_copy.0.verify_txs_root(), Ok(true)) {
+                    debug!(
+                        target: "validator::protocol_block::handle_receive_block",
+                        "Block transactions don't match its header, skipping..."
+                    );
+                    continue
+                }
+
+                match self.validator.write().await.add_headers(&[block_copy.0.header.clone()]).await
+                {
+                    Ok(()) => {
+                        let encoded_block =
+                            JsonValue::String(base64::encode(&serialize(&block_copy)));
+                        self.subscriber.notify(vec![encoded_block].into()).await;
+                    }
+                    Err(e) => {
+                        debug!(
+                            target: "validator::protocol_block::handle_receive_block",
+                            "add_headers fail: {}",
+                            e
+                        );
+                    }
+                };
+                continue
+            }
+
             match self.validator.write().await.append_block(&block_copy.0).await {
                 Ok(()) => {
                     self.p2p.broadcast_with_exclude(&block_copy, &exclude_list).await;
                     let encoded_block = JsonValue::String(base64::encode(&serialize(&block_copy)));
                     self.subscriber.notify(vec![encoded_block].into()).await;
+                    let blockchain = self.validator.read().await.blockchain.clone();
+                    notify_events(&self.events_subscriber, &blockchain, &[block_copy.0]).await;
                 }
                 Err(e) => {
                     debug!(
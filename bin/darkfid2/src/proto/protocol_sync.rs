@@ -20,22 +20,27 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use log::{debug, error};
+use num_bigint::BigUint;
 use smol::Executor;
 
 use darkfi::{
-    blockchain::BlockInfo,
+    blockchain::{BlockInfo, Header, TxInclusionProof},
     impl_p2p_message,
     net::{
         ChannelPtr, Message, MessageSubscription, ProtocolBase, ProtocolBasePtr,
         ProtocolJobsManager, ProtocolJobsManagerPtr,
     },
-    validator::ValidatorPtr,
+    tx::Transaction,
+    validator::{Validator, ValidatorPtr},
     Result,
 };
 use darkfi_serial::{SerialDecodable, SerialEncodable};
 
 // Constant defining how many blocks we send during syncing.
-const BATCH: u64 = 10;
+pub const BATCH: u64 = 10;
+
+// Constant defining how many headers we send during syncing.
+pub const HEADERS_BATCH: u64 = 100;
 
 /// Auxiliary structure used for blockchain syncing.
 #[derive(Debug, SerialEncodable, SerialDecodable)]
@@ -57,8 +62,84 @@ pub struct SyncResponse {
 
 impl_p2p_message!(SyncResponse, "syncresponse");
 
+/// Auxiliary structure used to request a peer's canonical blockchain tip.
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+pub struct TipRequest;
+
+impl_p2p_message!(TipRequest, "tiprequest");
+
+/// Auxiliary structure used to respond with the canonical blockchain tip.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct TipResponse {
+    /// Last block height
+    pub height: u64,
+    /// Last block headerhash
+    pub hash: blake3::Hash,
+    /// Total blocks cummulative difficulty
+    pub cummulative_difficulty: BigUint,
+}
+
+impl_p2p_message!(TipResponse, "tipresponse");
+
+/// Auxiliary structure used to request the headers of the canonical
+/// blocks in an inclusive height range.
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+pub struct HeaderSyncRequest {
+    /// Range start height
+    pub start: u64,
+    /// Range end height
+    pub end: u64,
+}
+
+impl_p2p_message!(HeaderSyncRequest, "headersyncrequest");
+
+/// Auxiliary structure used for blockchain headers syncing.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct HeaderSyncResponse {
+    /// Response headers
+    pub headers: Vec<Header>,
+}
+
+impl_p2p_message!(HeaderSyncResponse, "headersyncresponse");
+
+/// Auxiliary structure used to request the canonical blocks in an
+/// inclusive height range. Peers respond with a [`SyncResponse`].
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+pub struct BlockRangeRequest {
+    /// Range start height
+    pub start: u64,
+    /// Range end height
+    pub end: u64,
+}
+
+impl_p2p_message!(BlockRangeRequest, "blockrangerequest");
+
+/// Auxiliary structure used by light nodes to request a transaction
+/// along with its inclusion proof.
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+pub struct TxInclusionRequest {
+    /// Requested transaction hash
+    pub tx_hash: blake3::Hash,
+}
+
+impl_p2p_message!(TxInclusionRequest, "txinclusionrequest");
+
+/// Auxiliary structure used to respond with a transaction and its inclusion
+/// proof against its block header. `None` if the transaction is not found.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct TxInclusionResponse {
+    /// Response transaction and its inclusion proof
+    pub proof: Option<(Transaction, TxInclusionProof)>,
+}
+
+impl_p2p_message!(TxInclusionResponse, "txinclusionresponse");
+
 pub struct ProtocolSync {
     request_sub: MessageSubscription<SyncRequest>,
+    tip_request_sub: MessageSubscription<TipRequest>,
+    header_request_sub: MessageSubscription<HeaderSyncRequest>,
+    range_request_sub: MessageSubscription<BlockRangeRequest>,
+    tx_request_sub: MessageSubscription<TxInclusionRequest>,
     jobsman: ProtocolJobsManagerPtr,
     validator: ValidatorPtr,
     channel: ChannelPtr,
@@ -72,11 +153,29 @@ impl ProtocolSync {
         );
         let msg_subsystem = channel.message_subsystem();
         msg_subsystem.add_dispatch::<SyncRequest>().await;
+        msg_subsystem.add_dispatch::<TipRequest>().await;
+        msg_subsystem.add_dispatch::<HeaderSyncRequest>().await;
+        msg_subsystem.add_dispatch::<BlockRangeRequest>().await;
+        msg_subsystem.add_dispatch::<TxInclusionRequest>().await;
+
+        // Responses are consumed by the sync task
+        msg_subsystem.add_dispatch::<SyncResponse>().await;
+        msg_subsystem.add_dispatch::<TipResponse>().await;
+        msg_subsystem.add_dispatch::<HeaderSyncResponse>().await;
+        msg_subsystem.add_dispatch::<TxInclusionResponse>().await;
 
         let request_sub = channel.subscribe_msg::<SyncRequest>().await?;
+        let tip_request_sub = channel.subscribe_msg::<TipRequest>().await?;
+        let header_request_sub = channel.subscribe_msg::<HeaderSyncRequest>().await?;
+        let range_request_sub = channel.subscribe_msg::<BlockRangeRequest>().await?;
+        let tx_request_sub = channel.subscribe_msg::<TxInclusionRequest>().await?;
 
         Ok(Arc::new(Self {
             request_sub,
+            tip_request_sub,
+            header_request_sub,
+            range_request_sub,
+            tx_request_sub,
             jobsman: ProtocolJobsManager::new("SyncProtocol", channel.clone()),
             validator,
             channel,
@@ -107,6 +206,15 @@ impl ProtocolSync {
                 continue
             }
 
+            // Light nodes don't have blocks to serve
+            if self.validator.read().await.light {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_request",
+                    "Node is a light node, skipping..."
+                );
+                continue
+            }
+
             let key = request.slot;
             let blocks = match self.validator.read().await.blockchain.get_blocks_after(key, BATCH) {
                 Ok(v) => v,
@@ -130,6 +238,243 @@ impl ProtocolSync {
             };
         }
     }
+
+    async fn handle_receive_tip_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "validator::protocol_sync::handle_receive_tip_request", "START");
+        loop {
+            if let Err(e) = self.tip_request_sub.receive().await {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_tip_request",
+                    "recv fail: {}",
+                    e
+                );
+                continue
+            }
+
+            // Check if node has finished syncing its blockchain
+            let validator = self.validator.read().await;
+            if !validator.synced {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_tip_request",
+                    "Node still syncing blockchain, skipping..."
+                );
+                continue
+            }
+
+            // Light nodes can't serve the blocks of their tip
+            if validator.light {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_tip_request",
+                    "Node is a light node, skipping..."
+                );
+                continue
+            }
+
+            let (height, hash) = match validator.blockchain.last() {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "validator::protocol_sync::handle_receive_tip_request",
+                        "blockchain last fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+            let cummulative_difficulty = validator.consensus.module.cummulative_difficulty.clone();
+            drop(validator);
+
+            let response = TipResponse { height, hash, cummulative_difficulty };
+            if let Err(e) = self.channel.send(&response).await {
+                error!(
+                    target: "validator::protocol_sync::handle_receive_tip_request",
+                    "channel send fail: {}",
+                    e
+                )
+            };
+        }
+    }
+
+    async fn handle_receive_header_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "validator::protocol_sync::handle_receive_header_request", "START");
+        loop {
+            let request = match self.header_request_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!(
+                        target: "validator::protocol_sync::handle_receive_header_request",
+                        "recv fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            // Check if node has finished syncing its blockchain
+            if !self.validator.read().await.synced {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_header_request",
+                    "Node still syncing blockchain, skipping..."
+                );
+                continue
+            }
+
+            let end = request.end.min(request.start.saturating_add(HEADERS_BATCH - 1));
+            let headers = match self
+                .validator
+                .read()
+                .await
+                .blockchain
+                .get_headers_in_range(request.start, end)
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "validator::protocol_sync::handle_receive_header_request",
+                        "get_headers_in_range fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            let response = HeaderSyncResponse { headers };
+            if let Err(e) = self.channel.send(&response).await {
+                error!(
+                    target: "validator::protocol_sync::handle_receive_header_request",
+                    "channel send fail: {}",
+                    e
+                )
+            };
+        }
+    }
+
+    async fn handle_receive_range_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "validator::protocol_sync::handle_receive_range_request", "START");
+        loop {
+            let request = match self.range_request_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!(
+                        target: "validator::protocol_sync::handle_receive_range_request",
+                        "recv fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            // Check if node has finished syncing its blockchain
+            if !self.validator.read().await.synced {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_range_request",
+                    "Node still syncing blockchain, skipping..."
+                );
+                continue
+            }
+
+            // Light nodes don't have blocks to serve
+            if self.validator.read().await.light {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_range_request",
+                    "Node is a light node, skipping..."
+                );
+                continue
+            }
+
+            let end = request.end.min(request.start.saturating_add(BATCH - 1));
+            let blocks = match self
+                .validator
+                .read()
+                .await
+                .blockchain
+                .get_blocks_in_range(request.start, end)
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "validator::protocol_sync::handle_receive_range_request",
+                        "get_blocks_in_range fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            let response = SyncResponse { blocks };
+            if let Err(e) = self.channel.send(&response).await {
+                error!(
+                    target: "validator::protocol_sync::handle_receive_range_request",
+                    "channel send fail: {}",
+                    e
+                )
+            };
+        }
+    }
+
+    async fn handle_receive_tx_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "validator::protocol_sync::handle_receive_tx_request", "START");
+        loop {
+            let request = match self.tx_request_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!(
+                        target: "validator::protocol_sync::handle_receive_tx_request",
+                        "recv fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            // Check if node has finished syncing its blockchain
+            let validator = self.validator.read().await;
+            if !validator.synced || validator.light {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_tx_request",
+                    "Node can't serve transactions, skipping..."
+                );
+                continue
+            }
+
+            let proof = match tx_inclusion_proof(&validator, &request.tx_hash) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "validator::protocol_sync::handle_receive_tx_request",
+                        "tx_inclusion_proof fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+            drop(validator);
+
+            let response = TxInclusionResponse { proof };
+            if let Err(e) = self.channel.send(&response).await {
+                error!(
+                    target: "validator::protocol_sync::handle_receive_tx_request",
+                    "channel send fail: {}",
+                    e
+                )
+            };
+        }
+    }
+}
+
+/// Retrieve a transaction along with its inclusion proof against its block header.
+fn tx_inclusion_proof(
+    validator: &Validator,
+    tx_hash: &blake3::Hash,
+) -> Result<Option<(Transaction, TxInclusionProof)>> {
+    let Some((height, _)) = validator.blockchain.get_tx_location(tx_hash)? else { return Ok(None) };
+    let Some(block) = validator.blockchain.get_blocks_in_range(height, height)?.pop() else {
+        return Ok(None)
+    };
+    let Some(proof) = block.tx_inclusion_proof(tx_hash)? else { return Ok(None) };
+    let tx = block.txs[proof.position as usize].clone();
+
+    Ok(Some((tx, proof)))
 }
 
 #[async_trait]
@@ -138,6 +483,22 @@ impl ProtocolBase for ProtocolSync {
         debug!(target: "validator::protocol_sync::start", "START");
         self.jobsman.clone().start(executor.clone());
         self.jobsman.clone().spawn(self.clone().handle_receive_request(), executor.clone()).await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_tip_request(), executor.clone())
+            .await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_header_request(), executor.clone())
+            .await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_range_request(), executor.clone())
+            .await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_tx_request(), executor.clone())
+            .await;
         debug!(target: "validator::protocol_sync::start", "END");
         Ok(())
     }
@@ -107,6 +107,15 @@ impl ProtocolTx {
                 continue
             }
 
+            // Light nodes don't keep the state required to verify transactions
+            if self.validator.read().await.light {
+                debug!(
+                    target: "validator::protocol_tx::handle_receive_tx",
+                    "Node is a light node, skipping..."
+                );
+                continue
+            }
+
             let tx_copy = (*tx).clone();
 
             // Nodes use unconfirmed_txs vector as seen_txs pool.
@@ -53,6 +53,13 @@ impl RequestHandler for Darkfid {
             // ==================
             "blockchain.get_slot" => return self.blockchain_get_slot(req.id, req.params).await,
             "blockchain.get_tx" => return self.blockchain_get_tx(req.id, req.params).await,
+            "blockchain.get_block" => return self.blockchain_get_block(req.id, req.params).await,
+            "blockchain.get_tx_location" => {
+                return self.blockchain_get_tx_location(req.id, req.params).await
+            }
+            "blockchain.get_blocks_range" => {
+                return self.blockchain_get_blocks_range(req.id, req.params).await
+            }
             "blockchain.last_known_slot" => {
                 return self.blockchain_last_known_slot(req.id, req.params).await
             }
@@ -68,6 +75,9 @@ impl RequestHandler for Darkfid {
             "blockchain.subscribe_proposals" => {
                 return self.blockchain_subscribe_proposals(req.id, req.params).await
             }
+            "blockchain.subscribe_events" => {
+                return self.blockchain_subscribe_events(req.id, req.params).await
+            }
 
             // ===================
             // Transaction methods
@@ -77,6 +87,18 @@ impl RequestHandler for Darkfid {
             "tx.pending" => return self.tx_pending(req.id, req.params).await,
             "tx.clean_pending" => return self.tx_pending(req.id, req.params).await,
 
+            // =============
+            // Miner methods
+            // =============
+            "miner.get_block_template" => {
+                return self.miner_get_block_template(req.id, req.params).await
+            }
+            "miner.submit_block" => return self.miner_submit_block(req.id, req.params).await,
+            "miner.submit_merge_mined" => {
+                return self.miner_submit_merge_mined(req.id, req.params).await
+            }
+            "miner.subscribe_jobs" => return self.miner_subscribe_jobs(req.id, req.params).await,
+
             // ==============
             // Invalid method
             // ==============
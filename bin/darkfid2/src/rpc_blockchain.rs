@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use darkfi_sdk::crypto::ContractId;
 use darkfi_serial::{deserialize, serialize};
@@ -34,6 +34,9 @@ use darkfi::{
 
 use crate::{server_error, Darkfid, RpcError};
 
+/// Maximum number of blocks returned by `blockchain.get_blocks_range`
+const MAX_BLOCKS_RANGE: u64 = 100;
+
 impl Darkfid {
     // RPCAPI:
     // Queries the blockchain database for a block in the given slot.
@@ -59,7 +62,14 @@ impl Darkfid {
             Err(_) => return JsonError::new(ParseError, None, id).into(),
         };
 
-        let blocks = match self.validator.read().await.blockchain.get_blocks_by_slot(&[slot]) {
+        // Light nodes fetch blocks from their peers
+        let blocks = if self.validator.read().await.light {
+            self.fetch_blocks(slot, slot).await
+        } else {
+            self.validator.read().await.blockchain.get_blocks_by_slot(&[slot])
+        };
+
+        let blocks = match blocks {
             Ok(v) => v,
             Err(e) => {
                 error!(target: "darkfid::rpc::blockchain_get_slot", "Failed fetching block by slot: {}", e);
@@ -100,6 +110,21 @@ impl Darkfid {
             Err(_) => return JsonError::new(ParseError, None, id).into(),
         };
 
+        // Light nodes fetch transactions from their peers
+        if self.validator.read().await.light {
+            let tx = match self.fetch_tx(&tx_hash).await {
+                Ok(Some((tx, _))) => tx,
+                Ok(None) => return server_error(RpcError::UnknownTx, id, None),
+                Err(e) => {
+                    error!(target: "darkfid::rpc::blockchain_get_tx", "Failed fetching tx from peers: {}", e);
+                    return JsonError::new(InternalError, None, id).into()
+                }
+            };
+
+            let tx_enc = base64::encode(&serialize(&tx));
+            return JsonResponse::new(JsonValue::String(tx_enc), id).into()
+        }
+
         let txs = match self.validator.read().await.blockchain.transactions.get(&[tx_hash], true) {
             Ok(txs) => txs,
             Err(e) => {
@@ -116,6 +141,189 @@ impl Darkfid {
         JsonResponse::new(JsonValue::String(tx_enc), id).into()
     }
 
+    // RPCAPI:
+    // Queries the blockchain database for a block, either by its height
+    // or by its hash.
+    // Returns a readable block upon success.
+    //
+    // **Params:**
+    // * `array[0]`: `u64` block height (as string) or hex-encoded block hash string
+    //
+    // **Returns:**
+    // * [`BlockInfo`](https://darkrenaissance.github.io/darkfi/development/darkfi/consensus/block/struct.BlockInfo.html)
+    //   struct serialized into base64.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_block", "params": ["0"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "ABCD...", "id": 1}
+    pub async fn blockchain_get_block(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let param = params[0].get::<String>().unwrap();
+        let (blockchain, light) = {
+            let validator = self.validator.read().await;
+            (validator.blockchain.clone(), validator.light)
+        };
+
+        // Block hashes are 64 hex characters, which no u64 height can be
+        let blocks = if param.len() == 64 {
+            let Ok(hash) = blake3::Hash::from_hex(param) else {
+                return JsonError::new(ParseError, None, id).into()
+            };
+            if light {
+                // Light nodes find the block height from its known header
+                match blockchain.headers.get(&[hash], true) {
+                    Ok(v) => {
+                        let height = v[0].as_ref().unwrap().height;
+                        self.fetch_blocks(height, height).await
+                    }
+                    Err(e) => Err(e),
+                }
+            } else {
+                blockchain.get_blocks_by_hash(&[hash])
+            }
+        } else if light {
+            let Ok(height) = param.parse::<u64>() else {
+                return JsonError::new(ParseError, None, id).into()
+            };
+            self.fetch_blocks(height, height).await
+        } else {
+            let Ok(height) = param.parse::<u64>() else {
+                return JsonError::new(ParseError, None, id).into()
+            };
+            blockchain.get_blocks_by_slot(&[height])
+        };
+
+        // Missing hashes are reported as errors, missing heights as no blocks
+        let Ok(blocks) = blocks else { return server_error(RpcError::UnknownBlock, id, None) };
+        if blocks.is_empty() {
+            return server_error(RpcError::UnknownBlock, id, None)
+        }
+
+        let block = base64::encode(&serialize(&blocks[0]));
+        JsonResponse::new(JsonValue::String(block), id).into()
+    }
+
+    // RPCAPI:
+    // Queries the blockchain database for the location of a given transaction,
+    // i.e. the block containing it and its position in the block's transactions.
+    //
+    // **Params:**
+    // * `array[0]`: Hex-encoded transaction hash string
+    //
+    // **Returns:**
+    // * `block_hash`: Hex-encoded hash string of the block containing the transaction
+    // * `height`: `u64` height of the block (as string)
+    // * `index`: Index of the transaction in the block
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_tx_location", "params": ["TxHash"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"block_hash": "BlockHash", "height": "1", "index": 0}, "id": 1}
+    pub async fn blockchain_get_tx_location(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let tx_hash = params[0].get::<String>().unwrap();
+        let Ok(tx_hash) = blake3::Hash::from_hex(tx_hash) else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+
+        let (blockchain, light) = {
+            let validator = self.validator.read().await;
+            (validator.blockchain.clone(), validator.light)
+        };
+
+        // Light nodes find the location from the transaction inclusion proof
+        let location = if light {
+            self.fetch_tx(&tx_hash)
+                .await
+                .map(|proof| proof.map(|(_, proof)| (proof.height, proof.position)))
+        } else {
+            blockchain.get_tx_location(&tx_hash)
+        };
+
+        let (height, index) = match location {
+            Ok(Some(v)) => v,
+            Ok(None) => return server_error(RpcError::UnknownTx, id, None),
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_tx_location", "Failed fetching tx location: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let block_hash = match blockchain.order.get(&[height], true) {
+            Ok(v) => v[0].unwrap(),
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_tx_location", "Failed fetching block order: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let location = HashMap::from([
+            ("block_hash".to_string(), JsonValue::String(block_hash.to_hex().to_string())),
+            ("height".to_string(), JsonValue::String(height.to_string())),
+            ("index".to_string(), JsonValue::Number(index as f64)),
+        ]);
+
+        JsonResponse::new(JsonValue::Object(location), id).into()
+    }
+
+    // RPCAPI:
+    // Queries the blockchain database for all blocks in the given inclusive
+    // height range. At most 100 blocks can be requested at once.
+    //
+    // **Params:**
+    // * `array[0]`: `u64` start height (as string)
+    // * `array[1]`: `u64` end height (as string)
+    //
+    // **Returns:**
+    // * Array of [`BlockInfo`](https://darkrenaissance.github.io/darkfi/development/darkfi/consensus/block/struct.BlockInfo.html)
+    //   structs serialized into base64, in ascending height order.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_blocks_range", "params": ["0", "10"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["ABCD...", "EFGH..."], "id": 1}
+    pub async fn blockchain_get_blocks_range(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let (Ok(start), Ok(end)) = (
+            params[0].get::<String>().unwrap().parse::<u64>(),
+            params[1].get::<String>().unwrap().parse::<u64>(),
+        ) else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+
+        if start > end || end - start >= MAX_BLOCKS_RANGE {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        // Light nodes fetch blocks from their peers
+        let blocks = if self.validator.read().await.light {
+            self.fetch_blocks(start, end).await
+        } else {
+            self.validator.read().await.blockchain.get_blocks_in_range(start, end)
+        };
+
+        let blocks = match blocks {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_blocks_range", "Failed fetching blocks range: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let blocks = blocks
+            .iter()
+            .map(|block| JsonValue::String(base64::encode(&serialize(block))))
+            .collect();
+        JsonResponse::new(JsonValue::Array(blocks), id).into()
+    }
+
     // RPCAPI:
     // Queries the blockchain database to find the last known slot
     //
@@ -197,6 +405,59 @@ impl Darkfid {
         proposals_subscriber.unwrap().clone().into()
     }
 
+    // RPCAPI:
+    // Initializes a subscription to the events emitted by contracts in new incoming
+    // blocks' transactions. Once a subscription is established, `darkfid` will send
+    // JSON-RPC notifications of the events matching the optional filters to the subscriber.
+    //
+    // **Params:**
+    // * `array[0]`: base58-encoded contract ID string to filter by, or `null` (optional)
+    // * `array[1]`: Event topic string to filter by, or `null` (optional)
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.subscribe_events", "params": ["BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o", "transfer"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "method": "blockchain.subscribe_events", "params": [{"contract_id": "BZHK...", "topic": "transfer", "data": "ABCD...", "tx_hash": "...", "height": 42}]}
+    pub async fn blockchain_subscribe_events(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() > 2 || params.iter().any(|p| !p.is_string() && !p.is_null()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let contract_id = match params.first().and_then(|p| p.get::<String>()) {
+            Some(contract_id) => match ContractId::from_str(contract_id) {
+                Ok(v) => Some(v.to_string()),
+                Err(e) => {
+                    error!(target: "darkfid::rpc::blockchain_subscribe_events", "Error decoding string to ContractId: {}", e);
+                    return JsonError::new(InvalidParams, None, id).into()
+                }
+            },
+            None => None,
+        };
+        let topic = params.get(1).and_then(|p| p.get::<String>()).cloned();
+
+        let events_subscriber = self.subscribers.get("events").unwrap();
+        if contract_id.is_none() && topic.is_none() {
+            return events_subscriber.clone().into()
+        }
+
+        // Each notification carries a single event object
+        events_subscriber
+            .with_filter(Arc::new(move |params: &JsonValue| {
+                let event = &params[0];
+                if let Some(contract_id) = &contract_id {
+                    if event["contract_id"].get::<String>() != Some(contract_id) {
+                        return false
+                    }
+                }
+                if let Some(topic) = &topic {
+                    if event["topic"].get::<String>() != Some(topic) {
+                        return false
+                    }
+                }
+                true
+            }))
+            .into()
+    }
+
     // RPCAPI:
     // Performs a lookup of zkas bincodes for a given contract ID and returns all of
     // them, including their namespace.
@@ -0,0 +1,235 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::pasta::pallas;
+use darkfi_serial::deserialize;
+use log::{error, info};
+use tinyjson::JsonValue;
+
+use darkfi::{
+    blockchain::{Header, MoneroPowData},
+    rpc::jsonrpc::{
+        ErrorCode::{InternalError, InvalidParams},
+        JsonError, JsonResponse, JsonResult,
+    },
+    util::encoding::base64,
+};
+
+use crate::{
+    server_error,
+    task::{
+        miner::append_mined_block,
+        stratum::{best_fork_tip, refresh_jobs, MinerJob},
+    },
+    Darkfid, RpcError,
+};
+
+impl Darkfid {
+    // RPCAPI:
+    // Returns the current block template for external miners, assuming node
+    // serves them. Miners fill in the `nonce_offset` bytes of the base64 encoded
+    // `blob` with their nonce, and hash it with RandomX using `seed_hash` as key,
+    // until the output is below the hex encoded `target`. Merge miners commit to
+    // the header `hash` instead, as documented in `miner.submit_merge_mined`.
+    //
+    // --> {"jsonrpc": "2.0", "method": "miner.get_block_template", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"job_id": "1", "height": "42", "hash": "hex", "blob": "base64encodedBlob", "nonce_offset": 57, "seed_hash": "hex", "target": "hex"}, "id": 1}
+    pub async fn miner_get_block_template(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        // Make sure the template extends the best fork tip
+        if let Err(e) = refresh_jobs(self, false).await {
+            error!(target: "darkfid::rpc::miner_get_block_template", "Failed refreshing jobs: {}", e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+
+        let miner_jobs = self.miner_jobs.lock().await;
+        let Some((job_id, job)) = miner_jobs.as_ref().and_then(|jobs| jobs.current()) else {
+            return server_error(RpcError::StratumDisabled, id, None)
+        };
+
+        match job.to_json(*job_id) {
+            Ok(v) => JsonResponse::new(v, id).into(),
+            Err(e) => {
+                error!(target: "darkfid::rpc::miner_get_block_template", "Failed exporting job: {}", e);
+                JsonError::new(InternalError, None, id).into()
+            }
+        }
+    }
+
+    // RPCAPI:
+    // Submits a solved nonce for given job ID. The nonce is a `u64` formatted as
+    // a string. Returns `true` if the mined block got appended as a proposal,
+    // otherwise, a corresponding error. Solutions for jobs that don't extend the
+    // best fork tip anymore are rejected as stale.
+    //
+    // --> {"jsonrpc": "2.0", "method": "miner.submit_block", "params": ["1", "1337"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn miner_submit_block(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(job_id) = params[0].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let Ok(nonce) = params[1].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        self.submit_job_solution(id, job_id, "miner_submit_block", |header| {
+            header.nonce = pallas::Base::from(nonce)
+        })
+        .await
+    }
+
+    // RPCAPI:
+    // Submits a merge mining solution for given job ID, found by mining a Monero
+    // block whose coinbase transaction commits to the job's header `hash`. The
+    // proof is the base64 encoded serialized `MoneroPowData` of the Monero block.
+    // Returns `true` if the mined block got appended as a proposal, otherwise,
+    // a corresponding error. Solutions for stale jobs are rejected as well.
+    //
+    // --> {"jsonrpc": "2.0", "method": "miner.submit_merge_mined", "params": ["1", "base64encodedPowData"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn miner_submit_merge_mined(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(job_id) = params[0].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let Some(bytes) = base64::decode(params[1].get::<String>().unwrap()) else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let Ok(pow_data) = deserialize::<MoneroPowData>(&bytes) else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        self.submit_job_solution(id, job_id, "miner_submit_merge_mined", |header| {
+            header.pow_data = Some(pow_data)
+        })
+        .await
+    }
+
+    // RPCAPI:
+    // Initializes a subscription to new block templates, assuming node serves
+    // them. Once a subscription is established, `darkfid` will send JSON-RPC
+    // notifications of new jobs to the subscriber, whenever the best fork tip
+    // changes or the template gets refreshed.
+    //
+    // --> {"jsonrpc": "2.0", "method": "miner.subscribe_jobs", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "method": "miner.subscribe_jobs", "params": [`job`]}
+    pub async fn miner_subscribe_jobs(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        // Since jobs subscriber is only active if we serve external miners,
+        // we have to check if it actually exists in the subscribers map.
+        let Some(jobs_subscriber) = self.subscribers.get("jobs") else {
+            return server_error(RpcError::StratumDisabled, id, None)
+        };
+
+        jobs_subscriber.clone().into()
+    }
+
+    /// Auxiliary function to verify a solution for given job ID, applied to its
+    /// block header by `solve`, and append the solved block as a proposal.
+    async fn submit_job_solution(
+        &self,
+        id: u16,
+        job_id: u64,
+        method: &str,
+        solve: impl FnOnce(&mut Header),
+    ) -> JsonResult {
+        // Grab a copy of the job, so the jobs are not locked while verifying
+        let job = {
+            let miner_jobs = self.miner_jobs.lock().await;
+            let Some(jobs) = miner_jobs.as_ref() else {
+                return server_error(RpcError::StratumDisabled, id, None)
+            };
+
+            // Jobs get dropped once their tip is no longer the best one
+            let Some(job) = jobs.jobs.get(&job_id) else {
+                return server_error(RpcError::StaleJob, id, None)
+            };
+            job.clone()
+        };
+        match best_fork_tip(self).await {
+            Ok(tip) if tip == job.tip => {}
+            Ok(_) => return server_error(RpcError::StaleJob, id, None),
+            Err(e) => {
+                error!(target: "darkfid::rpc::miner_submit_job", "Failed grabbing best fork: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        }
+
+        // Verify the solution. RandomX hashing is expensive, so it
+        // runs on a blocking thread.
+        let MinerJob { mut block, secret, module, .. } = job;
+        solve(&mut block.header);
+        let (mut block, verified) = smol::unblock(move || {
+            let verified = module.verify_current_block(&block);
+            (block, verified)
+        })
+        .await;
+        if let Err(e) = verified {
+            error!(target: "darkfid::rpc::miner_submit_job", "Invalid block solution: {}", e);
+            return server_error(RpcError::InvalidSolution, id, None)
+        }
+
+        if let Err(e) = block.sign(&secret) {
+            error!(target: "darkfid::rpc::miner_submit_job", "Failed signing block: {}", e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+
+        // Solutions for this job are no longer needed. If the job is gone,
+        // it was solved or became stale while we were verifying.
+        let removed = match self.miner_jobs.lock().await.as_mut() {
+            Some(jobs) => jobs.jobs.remove(&job_id).is_some(),
+            None => false,
+        };
+        if !removed {
+            return server_error(RpcError::StaleJob, id, None)
+        }
+
+        info!(target: "darkfid::rpc::miner_submit_job", "Job {} solved via {}", job_id, method);
+        if let Err(e) = append_mined_block(self, block).await {
+            error!(target: "darkfid::rpc::miner_submit_job", "Failed appending mined block: {}", e);
+            return server_error(RpcError::BlockAppendFail, id, Some(&e.to_string()))
+        }
+
+        // Hand out a template extending the new tip
+        if let Err(e) = refresh_jobs(self, false).await {
+            error!(target: "darkfid::rpc::miner_submit_job", "Failed refreshing jobs: {}", e);
+        }
+
+        JsonResponse::new(JsonValue::Boolean(true), id).into()
+    }
+}
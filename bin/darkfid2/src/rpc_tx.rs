@@ -16,6 +16,8 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::HashMap;
+
 use darkfi_serial::deserialize;
 use log::error;
 use tinyjson::JsonValue;
@@ -35,11 +37,12 @@ use crate::{server_error, RpcError};
 impl Darkfid {
     // RPCAPI:
     // Simulate a network state transition with the given transaction.
-    // Returns `true` if the transaction is valid, otherwise, a corresponding
-    // error.
+    // Returns the gas consumed by its contract calls and the fee it is
+    // required to pay if the transaction is valid, otherwise, a
+    // corresponding error.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.simulate", "params": ["base64encodedTX"], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"gas_used": 123456, "fee": 78910}, "id": 1}
     pub async fn tx_simulate(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if params.len() != 1 || !params[0].is_string() {
@@ -51,6 +54,11 @@ impl Darkfid {
             return server_error(RpcError::NotSynced, id, None)
         }
 
+        if self.validator.read().await.light {
+            error!(target: "darkfid::rpc::tx_simulate", "Light nodes can't simulate transactions");
+            return server_error(RpcError::LightNode, id, None)
+        }
+
         // Try to deserialize the transaction
         let tx_enc = params[0].get::<String>().unwrap().trim();
         let tx_bytes = match base64::decode(tx_enc) {
@@ -72,16 +80,19 @@ impl Darkfid {
         // Simulate state transition
         let lock = self.validator.read().await;
         let current_slot = lock.consensus.time_keeper.current_slot();
-        let result = lock.add_transactions(&[tx], current_slot, false).await;
-        if result.is_err() {
-            error!(
-                target: "darkfid::rpc::tx_simulate", "Failed to validate state transition: {}",
-                result.err().unwrap()
-            );
-            return server_error(RpcError::TxSimulationFail, id, None)
+        let (gas_used, fee) = match lock.simulate_transaction(&tx, current_slot).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::tx_simulate", "Failed to validate state transition: {}", e);
+                return server_error(RpcError::TxSimulationFail, id, None)
+            }
         };
 
-        JsonResponse::new(JsonValue::Boolean(true), id).into()
+        let result = HashMap::from([
+            ("gas_used".to_string(), JsonValue::Number(gas_used as f64)),
+            ("fee".to_string(), JsonValue::Number(fee as f64)),
+        ]);
+        JsonResponse::new(JsonValue::Object(result), id).into()
     }
 
     // RPCAPI:
@@ -121,26 +132,31 @@ impl Darkfid {
             }
         };
 
-        if self.consensus_p2p.is_some() {
-            // Consensus participants can directly perform
-            // the state transition check and append to their
-            // pending transactions store.
-            if self.validator.write().await.append_tx(&tx).await.is_err() {
-                error!(target: "darkfid::rpc::tx_broadcast", "Failed to append transaction to mempool");
-                return server_error(RpcError::TxSimulationFail, id, None)
+        // Light nodes don't keep the state required to perform
+        // the state transition check, so they only relay it.
+        let light = self.validator.read().await.light;
+        if !light {
+            if self.consensus_p2p.is_some() {
+                // Consensus participants can directly perform
+                // the state transition check and append to their
+                // pending transactions store.
+                if self.validator.write().await.append_tx(&tx).await.is_err() {
+                    error!(target: "darkfid::rpc::tx_broadcast", "Failed to append transaction to mempool");
+                    return server_error(RpcError::TxSimulationFail, id, None)
+                }
+            } else {
+                // We'll perform the state transition check here.
+                let lock = self.validator.read().await;
+                let current_slot = lock.consensus.time_keeper.current_slot();
+                let result = lock.add_transactions(&[tx.clone()], current_slot, false).await;
+                if result.is_err() {
+                    error!(
+                        target: "darkfid::rpc::tx_broadcast", "Failed to validate state transition: {}",
+                        result.err().unwrap()
+                    );
+                    return server_error(RpcError::TxSimulationFail, id, None)
+                };
             }
-        } else {
-            // We'll perform the state transition check here.
-            let lock = self.validator.read().await;
-            let current_slot = lock.consensus.time_keeper.current_slot();
-            let result = lock.add_transactions(&[tx.clone()], current_slot, false).await;
-            if result.is_err() {
-                error!(
-                    target: "darkfid::rpc::tx_broadcast", "Failed to validate state transition: {}",
-                    result.err().unwrap()
-                );
-                return server_error(RpcError::TxSimulationFail, id, None)
-            };
         }
 
         self.sync_p2p.broadcast(&tx).await;
@@ -154,11 +170,13 @@ impl Darkfid {
     }
 
     // RPCAPI:
-    // Queries the node pending transactions store to retrieve all transactions.
-    // Returns a vector of hex-encoded transaction hashes.
+    // Queries the node mempool to retrieve all pending transactions metadata,
+    // in priority order. Returns a vector of objects containing the hex-encoded
+    // transaction hash, its serialized size in bytes, its fee, and the UNIX
+    // timestamp it was received at.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.pending", "params": [], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "[TxHash,...]", "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"hash": "TxHash", "size": 1234, "fee": "0", "received": "1700000000"}, ...], "id": 1}
     pub async fn tx_pending(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if !params.is_empty() {
@@ -170,22 +188,27 @@ impl Darkfid {
             return server_error(RpcError::NotSynced, id, None)
         }
 
-        let pending_txs = match self.validator.read().await.blockchain.get_pending_txs() {
-            Ok(v) => v,
-            Err(e) => {
-                error!(target: "darkfid::rpc::tx_pending", "Failed fetching pending txs: {}", e);
-                return JsonError::new(InternalError, None, id).into()
-            }
-        };
-
-        let pending_txs: Vec<JsonValue> =
-            pending_txs.iter().map(|x| JsonValue::String(x.hash().unwrap().to_string())).collect();
+        let validator = self.validator.read().await;
+        let pending_txs: Vec<JsonValue> = validator
+            .consensus
+            .mempool
+            .entries()
+            .iter()
+            .map(|entry| {
+                JsonValue::Object(HashMap::from([
+                    ("hash".to_string(), JsonValue::String(entry.hash.to_string())),
+                    ("size".to_string(), JsonValue::Number(entry.size as f64)),
+                    ("fee".to_string(), JsonValue::String(entry.fee.to_string())),
+                    ("received".to_string(), JsonValue::String(entry.received.0.to_string())),
+                ]))
+            })
+            .collect();
 
         JsonResponse::new(JsonValue::Array(pending_txs), id).into()
     }
 
     // RPCAPI:
-    // Queries the node pending transactions store to remove all transactions.
+    // Queries the node pending transactions store and mempool to remove all transactions.
     // Returns a vector of hex-encoded transaction hashes.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.clean_pending", "params": [], "id": 1}
@@ -201,7 +224,8 @@ impl Darkfid {
             return server_error(RpcError::NotSynced, id, None)
         }
 
-        let pending_txs = match self.validator.read().await.blockchain.get_pending_txs() {
+        let mut validator = self.validator.write().await;
+        let pending_txs = match validator.blockchain.get_pending_txs() {
             Ok(v) => v,
             Err(e) => {
                 error!(target: "darkfid::rpc::tx_clean_pending", "Failed fetching pending txs: {}", e);
@@ -209,13 +233,14 @@ impl Darkfid {
             }
         };
 
-        if let Err(e) = self.validator.read().await.blockchain.remove_pending_txs(&pending_txs) {
+        let tx_hashes: Vec<blake3::Hash> = pending_txs.iter().map(|x| x.hash().unwrap()).collect();
+        if let Err(e) = validator.remove_pending_txs(&tx_hashes) {
             error!(target: "darkfid::rpc::tx_clean_pending", "Failed fetching pending txs: {}", e);
             return JsonError::new(InternalError, None, id).into()
         };
 
         let pending_txs: Vec<JsonValue> =
-            pending_txs.iter().map(|x| JsonValue::String(x.hash().unwrap().to_string())).collect();
+            tx_hashes.iter().map(|x| JsonValue::String(x.to_string())).collect();
 
         JsonResponse::new(JsonValue::Array(pending_txs), id).into()
     }
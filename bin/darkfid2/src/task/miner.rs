@@ -21,6 +21,7 @@ use darkfi::{
     tx::Transaction,
     validator::{
         consensus::{Fork, Proposal},
+        fees::txs_fees,
         pow::PoWModule,
     },
     zk::{empty_witnesses, ProvingKey, ZkCircuit},
@@ -41,7 +42,7 @@ use log::info;
 use rand::rngs::OsRng;
 use smol::channel::Receiver;
 
-use crate::{proto::BlockInfoMessage, Darkfid};
+use crate::{proto::BlockInfoMessage, utils::notify_events, Darkfid};
 
 // TODO: handle all ? so the task don't stop on errors
 
@@ -84,14 +85,7 @@ async fn miner_loop(
 ) -> Result<()> {
     // Grab zkas proving keys and bin for PoWReward transaction
     info!(target: "darkfid::task::miner_task", "Generating zkas bin and proving keys...");
-    let blockchain = node.validator.read().await.blockchain.clone();
-    let (zkbin, _) = blockchain.contracts.get_zkas(
-        &blockchain.sled_db,
-        &MONEY_CONTRACT_ID,
-        MONEY_CONTRACT_ZKAS_MINT_NS_V1,
-    )?;
-    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
-    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let (zkbin, pk) = pow_reward_zk_setup(node).await?;
 
     // Generate a random master secret key, to derive all signing keys from.
     // This enables us to deanonimize proposals from reward recipient(miner).
@@ -119,23 +113,48 @@ async fn miner_loop(
         node.validator.read().await.consensus.module.verify_current_block(&next_block)?;
 
         // Append the mined block as a proposal
-        let proposal = Proposal::new(next_block)?;
-        let mut lock = node.validator.write().await;
-        lock.consensus.append_proposal(&proposal).await?;
-
-        // Check if we can finalize anything and broadcast them
-        let finalized = lock.finalization().await?;
-        if !finalized.is_empty() {
-            for block in finalized {
-                let message = BlockInfoMessage::from(&block);
-                node.sync_p2p.broadcast(&message).await;
-            }
+        append_mined_block(node, next_block).await?;
+    }
+}
+
+/// Auxiliary function to grab the zkas bin and generate the proving key
+/// used to build `Money::PoWReward` transactions.
+pub async fn pow_reward_zk_setup(node: &Darkfid) -> Result<(ZkBinary, ProvingKey)> {
+    let blockchain = node.validator.read().await.blockchain.clone();
+    let (zkbin, _) = blockchain.contracts.get_zkas(
+        &blockchain.sled_db,
+        &MONEY_CONTRACT_ID,
+        MONEY_CONTRACT_ZKAS_MINT_NS_V1,
+    )?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+
+    Ok((zkbin, pk))
+}
+
+/// Auxiliary function to append a signed mined block as a proposal,
+/// and broadcast any blocks that got finalized because of it.
+pub async fn append_mined_block(node: &Darkfid, block: BlockInfo) -> Result<()> {
+    let proposal = Proposal::new(block)?;
+    let mut lock = node.validator.write().await;
+    lock.consensus.append_proposal(&proposal).await?;
+
+    // Check if we can finalize anything and broadcast them
+    let finalized = lock.finalization().await?;
+    if !finalized.is_empty() {
+        for block in &finalized {
+            let message = BlockInfoMessage::from(block);
+            node.sync_p2p.broadcast(&message).await;
         }
+        let events_sub = node.subscribers.get("events").unwrap();
+        notify_events(events_sub, &lock.blockchain, &finalized).await;
     }
+
+    Ok(())
 }
 
 /// Auxiliary function to generate next block in an atomic manner
-async fn generate_next_block(
+pub async fn generate_next_block(
     node: &Darkfid,
     secret: &mut SecretKey,
     recipient: &PublicKey,
@@ -156,11 +175,15 @@ async fn generate_next_block(
     let next_secret = poseidon_hash([SECRET_KEY_PREFIX, secret.inner(), height.into()]);
     *secret = SecretKey::from(next_secret);
 
-    // Generate reward transaction
-    let tx = generate_pow_transaction(fork, secret, recipient, zkbin, pk)?;
+    // Grab the transactions to include, and their total fees
+    let txs = lock.consensus.unproposed_txs(fork).await?;
+    let fees = txs_fees(&txs)?;
+
+    // Generate reward transaction, claiming the block fees
+    let tx = generate_pow_transaction(fork, secret, recipient, zkbin, pk, fees)?;
 
     // Mine next block proposal
-    let next_block = lock.consensus.generate_unsigned_block(fork, tx).await?;
+    let next_block = lock.consensus.generate_unsigned_block(fork, txs, tx).await?;
     let module = lock.consensus.forks[fork_index].module.clone();
     Ok((next_block, module))
 }
@@ -172,6 +195,7 @@ fn generate_pow_transaction(
     recipient: &PublicKey,
     zkbin: &ZkBinary,
     pk: &ProvingKey,
+    fees: u64,
 ) -> Result<Transaction> {
     // Grab next block height
     let block_height = fork.slots.last().unwrap().id;
@@ -196,6 +220,7 @@ fn generate_pow_transaction(
         fork_previous_hash,
         spend_hook,
         user_data,
+        fees,
         mint_zkbin: zkbin.clone(),
         mint_pk: pk.clone(),
     }
@@ -23,3 +23,6 @@ pub use sync::sync_task;
 
 pub mod miner;
 pub use miner::miner_task;
+
+pub mod stratum;
+pub use stratum::stratum_task;
@@ -0,0 +1,182 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use darkfi::{
+    blockchain::{BlockInfo, Header},
+    system::timeout::timeout,
+    util::encoding::base64,
+    validator::pow::PoWModule,
+    zk::ProvingKey,
+    zkas::ZkBinary,
+    Result,
+};
+use darkfi_sdk::crypto::{PublicKey, SecretKey};
+use log::{debug, info};
+use rand::rngs::OsRng;
+use tinyjson::JsonValue;
+
+use super::miner::{generate_next_block, pow_reward_zk_setup};
+use crate::Darkfid;
+
+/// Interval after which the block template gets refreshed, so it
+/// includes new transactions and its timestamp stays valid.
+const JOB_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of jobs on the current tip we keep accepting
+/// solutions for.
+const MAX_JOBS: usize = 16;
+
+/// Block template handed out to external miners
+#[derive(Clone)]
+pub struct MinerJob {
+    /// Hash of the fork tip the template extends
+    pub tip: blake3::Hash,
+    /// Unsigned block template, waiting for its nonce
+    pub block: BlockInfo,
+    /// Secret key to sign the block with, once mined
+    pub secret: SecretKey,
+    /// PoW module of the fork the template extends
+    pub module: PoWModule,
+}
+
+impl MinerJob {
+    /// Export the job as a JSON object for external miners.
+    /// Miners fill in the `nonce_offset` bytes of the `blob` with
+    /// their nonce, and hash it with RandomX, using `seed_hash` as key,
+    /// until the output is below `target`.
+    pub fn to_json(&self, job_id: u64) -> Result<JsonValue> {
+        let header = &self.block.header;
+        let target = self.module.next_mine_target()?;
+        Ok(JsonValue::Object(HashMap::from([
+            ("job_id".to_string(), JsonValue::String(job_id.to_string())),
+            ("height".to_string(), JsonValue::String(header.height.to_string())),
+            ("hash".to_string(), JsonValue::String(header.hash()?.to_hex().to_string())),
+            ("blob".to_string(), JsonValue::String(base64::encode(&header.hashing_blob()?))),
+            ("nonce_offset".to_string(), JsonValue::Number(Header::NONCE_OFFSET as f64)),
+            ("seed_hash".to_string(), JsonValue::String(header.previous.to_hex().to_string())),
+            ("target".to_string(), JsonValue::String(format!("{:064x}", target))),
+        ])))
+    }
+}
+
+/// Block templates state for external miners
+pub struct MinerJobs {
+    /// `Money::PoWReward` zkas circuit bin
+    zkbin: ZkBinary,
+    /// `Money::PoWReward` zkas circuit proving key
+    pk: ProvingKey,
+    /// Wallet address to receive the block rewards
+    recipient: PublicKey,
+    /// Current signing secret, the next one is derived from it
+    secret: SecretKey,
+    /// Jobs we accept solutions for, by their ID
+    pub jobs: BTreeMap<u64, MinerJob>,
+    /// Next job ID
+    next_id: u64,
+}
+
+impl MinerJobs {
+    pub fn new(zkbin: ZkBinary, pk: ProvingKey, recipient: PublicKey, secret: SecretKey) -> Self {
+        Self { zkbin, pk, recipient, secret, jobs: BTreeMap::new(), next_id: 0 }
+    }
+
+    /// Grab the latest job ID, along with the job itself
+    pub fn current(&self) -> Option<(&u64, &MinerJob)> {
+        self.jobs.last_key_value()
+    }
+}
+
+/// async task serving block templates to external miners. Templates
+/// are refreshed when the best fork tip changes, or periodically.
+pub async fn stratum_task(node: &Darkfid, recipient: &PublicKey) -> Result<()> {
+    info!(target: "darkfid::task::stratum_task", "Starting stratum task...");
+
+    // Grab zkas proving keys and bin for PoWReward transaction
+    info!(target: "darkfid::task::stratum_task", "Generating zkas bin and proving keys...");
+    let (zkbin, pk) = pow_reward_zk_setup(node).await?;
+
+    // Generate a random master secret key, to derive all signing keys from
+    info!(target: "darkfid::task::stratum_task", "Generating signing key...");
+    let secret = SecretKey::random(&mut OsRng);
+    *node.miner_jobs.lock().await = Some(MinerJobs::new(zkbin, pk, *recipient, secret));
+
+    // Generate a new fork to be able to extend
+    info!(target: "darkfid::task::stratum_task", "Generating new empty fork...");
+    node.validator.write().await.consensus.generate_pow_slot()?;
+
+    // Listen for new proposals, so the template follows the best fork tip
+    let subscription = node.subscribers.get("proposals").unwrap().sub.clone().subscribe().await;
+
+    info!(target: "darkfid::task::stratum_task", "Serving block templates to external miners!");
+    let mut force = true;
+    loop {
+        refresh_jobs(node, force).await?;
+
+        // Wait for a new proposal, or the refresh interval to pass
+        force = timeout(JOB_REFRESH_INTERVAL, subscription.receive()).await.is_err();
+    }
+}
+
+/// Generate a new block template if the best fork tip changed, or if `force`
+/// is set, and notify job subscribers. Jobs extending other tips are dropped,
+/// so their solutions get rejected as stale.
+pub async fn refresh_jobs(node: &Darkfid, force: bool) -> Result<()> {
+    let mut miner_jobs = node.miner_jobs.lock().await;
+    let Some(jobs) = miner_jobs.as_mut() else { return Ok(()) };
+
+    if !force {
+        let tip = best_fork_tip(node).await?;
+        if jobs.current().map(|(_, job)| job.tip) == Some(tip) {
+            return Ok(())
+        }
+    }
+
+    // Grab next block template
+    let (block, module) =
+        generate_next_block(node, &mut jobs.secret, &jobs.recipient, &jobs.zkbin, &jobs.pk).await?;
+    let job = MinerJob { tip: block.header.previous, block, secret: jobs.secret, module };
+
+    // Drop stale jobs, keeping a few recent ones on the current tip
+    jobs.jobs.retain(|_, j| j.tip == job.tip);
+    while jobs.jobs.len() >= MAX_JOBS {
+        jobs.jobs.pop_first();
+    }
+
+    let job_id = jobs.next_id;
+    jobs.next_id += 1;
+    let notification = job.to_json(job_id)?;
+    debug!(target: "darkfid::task::stratum_task", "New job {} extending {}", job_id, job.tip);
+    jobs.jobs.insert(job_id, job);
+    drop(miner_jobs);
+
+    node.subscribers.get("jobs").unwrap().notify(vec![notification].into()).await;
+
+    Ok(())
+}
+
+/// Auxiliary function to grab the best fork tip hash
+pub async fn best_fork_tip(node: &Darkfid) -> Result<blake3::Hash> {
+    let lock = node.validator.read().await;
+    let fork_index = lock.consensus.best_forks_indexes()?[0];
+    Ok(lock.consensus.forks[fork_index].last_proposal()?.hash)
+}
@@ -16,77 +16,418 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi::{system::sleep, util::encoding::base64, Result};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use darkfi::{
+    blockchain::{BlockInfo, Header},
+    net::{ChannelPtr, Message, MessageSubscription},
+    system::{sleep, timeout::timeout},
+    util::encoding::base64,
+    validator::{pow::PoWModule, validation::validate_pow_header},
+    Result,
+};
+use darkfi_sdk::blockchain::block_version;
 use darkfi_serial::serialize;
+use futures::future::join_all;
 use log::{debug, info, warn};
+use num_bigint::BigUint;
 use tinyjson::JsonValue;
 
 use crate::{
-    proto::{SyncRequest, SyncResponse},
+    proto::{
+        BlockRangeRequest, HeaderSyncRequest, HeaderSyncResponse, SyncResponse, TipRequest,
+        TipResponse, BATCH,
+    },
+    utils::notify_events,
     Darkfid,
 };
 
-/// async task used for block syncing
+/// Time to wait for a peer to respond to a sync request, in seconds
+const SYNC_TIMEOUT: u64 = 10;
+
+/// Reasons a peer failed to serve a sync request
+enum PeerFailure {
+    /// Peer didn't respond in time, or its channel was stopped
+    Timeout,
+    /// Peer follows a chain that doesn't extend ours
+    Diverged,
+    /// Peer's chain changed since it advertised its tip, e.g. due to a reorg
+    Reorged,
+    /// Peer responded with provably invalid data, like headers not linking
+    /// to each other within a single response
+    Invalid,
+}
+
+/// A connected peer along with its sync responses subscriptions
+struct SyncPeer {
+    channel: ChannelPtr,
+    tip_sub: MessageSubscription<TipResponse>,
+    header_sub: MessageSubscription<HeaderSyncResponse>,
+    block_sub: MessageSubscription<SyncResponse>,
+}
+
+impl SyncPeer {
+    async fn new(channel: ChannelPtr) -> Result<Self> {
+        let tip_sub = channel.subscribe_msg::<TipResponse>().await?;
+        let header_sub = channel.subscribe_msg::<HeaderSyncResponse>().await?;
+        let block_sub = channel.subscribe_msg::<SyncResponse>().await?;
+
+        Ok(Self { channel, tip_sub, header_sub, block_sub })
+    }
+
+    async fn unsubscribe(&self) {
+        self.tip_sub.unsubscribe().await;
+        self.header_sub.unsubscribe().await;
+        self.block_sub.unsubscribe().await;
+    }
+
+    /// Send a request to the peer and wait for its response on the given subscription.
+    async fn request<Req: Message, Res: Message>(
+        &self,
+        request: Req,
+        sub: &MessageSubscription<Res>,
+    ) -> std::result::Result<Arc<Res>, PeerFailure> {
+        if self.channel.send(&request).await.is_err() {
+            return Err(PeerFailure::Timeout)
+        }
+
+        match timeout(Duration::from_secs(SYNC_TIMEOUT), sub.receive()).await {
+            Ok(Ok(response)) => Ok(response),
+            _ => Err(PeerFailure::Timeout),
+        }
+    }
+}
+
+/// async task used for block syncing.
+///
+/// Node asks all its peers for their canonical tip, downloads the headers
+/// chain of each tip better than ours and verifies its proof of work on top
+/// of our last known block. The tip with the highest cummulative difficulty,
+/// as computed from its verified headers rather than as advertised by the
+/// peer, is selected. Block bodies are then downloaded in parallel ranges
+/// from all peers following that head, unless we are a light node, in which
+/// case only the headers chain is verified and stored. Peers that time out or
+/// whose chain changed while syncing, like after a reorg, are skipped for the
+/// rest of the round, while peers serving provably invalid data get banned.
+/// Rounds are repeated against the peers' new tips until no peer has a better
+/// chain than ours.
 pub async fn sync_task(node: &Darkfid) -> Result<()> {
     info!(target: "darkfid::task::sync_task", "Starting blockchain sync...");
-    // Block until at least node is connected to at least one peer
     loop {
-        if !node.sync_p2p.channels().await.is_empty() {
+        // Block until at least node is connected to at least one peer
+        let channels = node.sync_p2p.channels().await;
+        if channels.is_empty() {
+            warn!(target: "darkfid::task::sync_task", "Node is not connected to other nodes, waiting to retry...");
+            sleep(10).await;
+            continue
+        }
+
+        // Communication setup
+        let mut peers = Vec::with_capacity(channels.len());
+        for channel in channels {
+            match SyncPeer::new(channel.clone()).await {
+                Ok(peer) => peers.push(peer),
+                Err(e) => {
+                    debug!(target: "darkfid::task::sync_task", "Skipping peer {}: {}", channel.address(), e)
+                }
+            }
+        }
+
+        let synced = sync_round(node, &peers).await;
+        for peer in &peers {
+            peer.unsubscribe().await;
+        }
+
+        if synced? {
             break
         }
-        warn!(target: "darkfid::task::sync_task", "Node is not connected to other nodes, waiting to retry...");
-        sleep(10).await;
     }
 
-    // Getting a random connected channel to ask from peers
-    let channel = node.sync_p2p.random_channel().await.unwrap();
+    node.validator.write().await.synced = true;
+    info!(target: "darkfid::task::sync_task", "Blockchain synced!");
+    Ok(())
+}
 
-    // Communication setup
-    let msg_subsystem = channel.message_subsystem();
-    msg_subsystem.add_dispatch::<SyncResponse>().await;
-    let block_response_sub = channel.subscribe_msg::<SyncResponse>().await?;
-    let notif_sub = node.subscribers.get("blocks").unwrap();
+/// Perform a sync round with the given peers.
+/// Returns `true` if no peer has a better chain than ours.
+async fn sync_round(node: &Darkfid, peers: &[SyncPeer]) -> Result<bool> {
+    // Ask all peers for their tip
+    let requests = peers.iter().map(|peer| peer.request(TipRequest, &peer.tip_sub));
+    let responses = join_all(requests).await;
+    let mut tips = vec![];
+    for (peer, response) in peers.iter().zip(responses) {
+        if let Ok(tip) = response {
+            tips.push((peer, tip));
+        }
+    }
 
-    // TODO: make this parallel and use a head selection method,
-    // for example use a manual known head and only connect to nodes
-    // that follow that. Also use a random peer on every block range
-    // we sync.
+    if tips.is_empty() {
+        warn!(target: "darkfid::task::sync_task", "No peer responded with its tip, waiting to retry...");
+        sleep(10).await;
+        return Ok(false)
+    }
 
-    // Node sends the last known block hash of the canonical blockchain
-    // and loops until the response is the same block (used to utilize
-    // batch requests).
-    let mut last = node.validator.read().await.blockchain.last()?;
+    let validator = node.validator.read().await;
+    let last = validator.blockchain.last()?;
+    let last_header = validator.blockchain.last_block()?.header;
+    let module = validator.consensus.module.clone();
+    drop(validator);
     info!(target: "darkfid::task::sync_task", "Last known block: {:?} - {:?}", last.0, last.1);
-    loop {
-        // Node creates a `SyncRequest` and sends it
-        let request = SyncRequest { slot: last.0, block: last.1 };
-        channel.send(&request).await?;
 
-        // TODO: add a timeout here to retry
-        // Node waits for response
-        let response = block_response_sub.receive().await?;
+    // Keep the tips claiming to be better than ours, best first, so we
+    // don't download headers from peers not even claiming a better chain
+    let ours = (module.cummulative_difficulty.clone(), last.0);
+    tips.retain(|(_, tip)| (&tip.cummulative_difficulty, tip.height) > (&ours.0, ours.1));
+    if tips.is_empty() {
+        return Ok(true)
+    }
+    tips.sort_by(|(_, a), (_, b)| {
+        (&b.cummulative_difficulty, b.height).cmp(&(&a.cummulative_difficulty, a.height))
+    });
+
+    // Select the best head whose headers chain extends ours, based on the
+    // cummulative difficulty of its verified headers
+    let mut selected: Option<(BigUint, blake3::Hash, Vec<Header>)> = None;
+    for (peer, tip) in &tips {
+        // Peers following an already verified head don't need to be asked again
+        if matches!(&selected, Some((_, head, _)) if *head == tip.hash) {
+            continue
+        }
+
+        match fetch_headers(peer, last, tip).await {
+            Ok(headers) => {
+                let Ok(difficulty) = verify_headers(&last_header, &module, &headers) else {
+                    ban_peer(node, peer).await;
+                    continue
+                };
+
+                let height = headers.last().unwrap().height;
+                if (&difficulty, height) <= (&ours.0, ours.1) {
+                    warn!(target: "darkfid::task::sync_task", "Peer {} advertised a better chain than it has", peer.channel.address());
+                    continue
+                }
 
-        // Verify and store retrieved blocks
-        debug!(target: "darkfid::task::sync_task", "Processing received blocks");
-        node.validator.write().await.add_blocks(&response.blocks).await?;
+                let better = match &selected {
+                    Some((best, _, best_headers)) => {
+                        (&difficulty, height) > (best, best_headers.last().unwrap().height)
+                    }
+                    None => true,
+                };
+                if better {
+                    selected = Some((difficulty, tip.hash, headers));
+                }
+            }
+            Err(PeerFailure::Timeout) => {
+                warn!(target: "darkfid::task::sync_task", "Peer {} timed out serving headers", peer.channel.address())
+            }
+            Err(PeerFailure::Diverged) => {
+                warn!(target: "darkfid::task::sync_task", "Peer {} follows a chain diverging from ours", peer.channel.address())
+            }
+            Err(PeerFailure::Reorged) => {
+                warn!(target: "darkfid::task::sync_task", "Peer {} chain changed while serving headers", peer.channel.address())
+            }
+            Err(PeerFailure::Invalid) => ban_peer(node, peer).await,
+        }
+    }
+
+    let Some((_, head, headers)) = selected else {
+        warn!(target: "darkfid::task::sync_task", "No valid head found, waiting to retry...");
+        sleep(10).await;
+        return Ok(false)
+    };
+    info!(target: "darkfid::task::sync_task", "Selected head: {:?} - {:?}", headers.last().unwrap().height, head);
 
-        // Notify subscriber
-        for block in &response.blocks {
-            let encoded_block = JsonValue::String(base64::encode(&serialize(block)));
-            notif_sub.notify(vec![encoded_block].into()).await;
+    // Light nodes only verify and store the headers chain
+    if node.validator.read().await.light {
+        if let Err(e) = node.validator.write().await.add_headers(&headers).await {
+            warn!(target: "darkfid::task::sync_task", "Received headers failed verification: {}", e);
+            let peer = tips.iter().find(|(_, tip)| tip.hash == head).unwrap().0;
+            ban_peer(node, peer).await;
         }
+        return Ok(false)
+    }
 
-        let last_received = node.validator.read().await.blockchain.last()?;
-        info!(target: "darkfid::task::sync_task", "Last received block: {:?} - {:?}", last_received.0, last_received.1);
+    // Download blocks from all peers following the selected head
+    let peers = tips.iter().filter(|(_, tip)| tip.hash == head).map(|(peer, _)| *peer).collect();
+    download_blocks(node, peers, &headers).await?;
 
-        if last == last_received {
-            break
+    Ok(false)
+}
+
+/// Download the headers chain from our last known block up to the peer's tip,
+/// verifying that each header links to the previous one. Since the peer's
+/// chain might change between requests, only headers not linking to each
+/// other within a single response are considered invalid.
+async fn fetch_headers(
+    peer: &SyncPeer,
+    last: (u64, blake3::Hash),
+    tip: &TipResponse,
+) -> std::result::Result<Vec<Header>, PeerFailure> {
+    let mut headers: Vec<Header> = vec![];
+    let (mut height, mut hash) = last;
+    while hash != tip.hash {
+        if height >= tip.height {
+            return Err(PeerFailure::Reorged)
         }
 
-        last = last_received;
+        let request = HeaderSyncRequest { start: height + 1, end: tip.height };
+        let response = peer.request(request, &peer.header_sub).await?;
+        if response.headers.is_empty() {
+            return Err(PeerFailure::Reorged)
+        }
+
+        for (index, header) in response.headers.iter().enumerate() {
+            if header.previous != hash {
+                if index > 0 {
+                    return Err(PeerFailure::Invalid)
+                }
+                if headers.is_empty() {
+                    return Err(PeerFailure::Diverged)
+                }
+                return Err(PeerFailure::Reorged)
+            }
+
+            if header.height <= height || header.height > tip.height {
+                return Err(PeerFailure::Invalid)
+            }
+
+            // Merge mining data is not hashed, so a peer could tamper with it
+            if header.verify_pow_data().is_err() {
+                return Err(PeerFailure::Invalid)
+            }
+
+            height = header.height;
+            hash = header.hash().map_err(|_| PeerFailure::Invalid)?;
+            headers.push(header.clone());
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Verify the proof of work of the given headers chain on top of our last
+/// known header, using a copy of our PoW module, and return the cummulative
+/// difficulty of the resulting chain. The expected header version is derived
+/// from its height, so a peer can't skip the proof of work by claiming a PoS
+/// header. PoS headers carry no proof of work and don't add difficulty, so
+/// they are only checked to link to each other, which [`fetch_headers`]
+/// already did.
+fn verify_headers(previous: &Header, module: &PoWModule, headers: &[Header]) -> Result<BigUint> {
+    let mut module = module.clone();
+    let mut previous = previous;
+    for header in headers {
+        if block_version(header.height) == 1 {
+            validate_pow_header(header, previous, &module)?;
+            let difficulty = module.next_difficulty()?;
+            module.append(header.timestamp.0, &difficulty);
+        }
+        previous = header;
+    }
+
+    Ok(module.cummulative_difficulty)
+}
+
+/// Download the blocks of the given headers in parallel ranges from the given
+/// peers, and apply them in order. Failed ranges are retried with other peers.
+/// Peers serving blocks of another chain, e.g. after a reorg, are dropped for
+/// the rest of the round, and only banned if their blocks don't link to each
+/// other.
+async fn download_blocks(
+    node: &Darkfid,
+    mut peers: Vec<&SyncPeer>,
+    headers: &[Header],
+) -> Result<()> {
+    let notif_sub = node.subscribers.get("blocks").unwrap();
+    let events_sub = node.subscribers.get("events").unwrap();
+    let ranges: Vec<&[Header]> = headers.chunks(BATCH as usize).collect();
+    let mut pending: VecDeque<usize> = (0..ranges.len()).collect();
+    let mut downloaded: BTreeMap<usize, (&SyncPeer, Vec<BlockInfo>)> = BTreeMap::new();
+    let mut next = 0;
+
+    while next < ranges.len() {
+        if peers.is_empty() {
+            warn!(target: "darkfid::task::sync_task", "No peers left to download blocks from");
+            return Ok(())
+        }
+
+        // Assign a pending range to each peer
+        let count = peers.len().min(pending.len());
+        let assigned: Vec<(usize, &SyncPeer)> =
+            pending.drain(..count).zip(peers.iter().copied()).collect();
+        let requests = assigned.iter().map(|(index, peer)| {
+            let range = ranges[*index];
+            let request =
+                BlockRangeRequest { start: range[0].height, end: range[range.len() - 1].height };
+            peer.request(request, &peer.block_sub)
+        });
+        let responses = join_all(requests).await;
+
+        for ((index, peer), response) in assigned.into_iter().zip(responses) {
+            match response {
+                // Received blocks must be the ones of the headers chain
+                Ok(response) if response.blocks.iter().map(|b| &b.header).eq(ranges[index]) => {
+                    downloaded.insert(index, (peer, response.blocks.clone()));
+                    continue
+                }
+                Ok(response) if !blocks_link(&response.blocks) => ban_peer(node, peer).await,
+                Ok(_) => {
+                    warn!(target: "darkfid::task::sync_task", "Peer {} chain changed while serving blocks", peer.channel.address())
+                }
+                Err(_) => {
+                    warn!(target: "darkfid::task::sync_task", "Peer {} timed out serving blocks", peer.channel.address())
+                }
+            }
+
+            peers.retain(|p| !std::ptr::eq(*p, peer));
+            pending.push_back(index);
+        }
+
+        // Verify and store retrieved blocks, in order
+        while let Some((peer, blocks)) = downloaded.remove(&next) {
+            debug!(target: "darkfid::task::sync_task", "Processing received blocks");
+            if let Err(e) = node.validator.write().await.add_blocks(&blocks).await {
+                warn!(target: "darkfid::task::sync_task", "Received blocks failed verification: {}", e);
+                ban_peer(node, peer).await;
+                return Ok(())
+            }
+
+            // Notify subscriber
+            for block in &blocks {
+                let encoded_block = JsonValue::String(base64::encode(&serialize(block)));
+                notif_sub.notify(vec![encoded_block].into()).await;
+            }
+            let blockchain = node.validator.read().await.blockchain.clone();
+            notify_events(events_sub, &blockchain, &blocks).await;
+
+            let last_received = blocks.last().unwrap();
+            info!(target: "darkfid::task::sync_task", "Last received block: {:?} - {:?}", last_received.header.height, last_received.hash()?);
+            next += 1;
+        }
     }
 
-    node.validator.write().await.synced = true;
-    info!(target: "darkfid::task::sync_task", "Blockchain synced!");
     Ok(())
 }
+
+/// Check that each of the given blocks links to the previous one.
+fn blocks_link(blocks: &[BlockInfo]) -> bool {
+    blocks.windows(2).all(|pair| match pair[0].hash() {
+        Ok(hash) => {
+            pair[1].header.previous == hash && pair[1].header.height > pair[0].header.height
+        }
+        Err(_) => false,
+    })
+}
+
+/// Ban a peer that served invalid data, so we don't connect to it again.
+async fn ban_peer(node: &Darkfid, peer: &SyncPeer) {
+    let address = peer.channel.address();
+    warn!(target: "darkfid::task::sync_task", "Banning peer {} for serving invalid data", address);
+    let hosts = node.sync_p2p.hosts();
+    hosts.remove(address).await;
+    hosts.mark_rejected(address).await;
+    peer.channel.stop().await;
+}
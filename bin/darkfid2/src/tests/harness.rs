@@ -59,38 +59,7 @@ pub struct Harness {
 
 impl Harness {
     pub async fn new(config: HarnessConfig, ex: &Arc<smol::Executor<'static>>) -> Result<Self> {
-        // Use test harness to generate genesis transactions
-        let mut th = TestHarness::new(&["money".to_string(), "consensus".to_string()]).await?;
-        let (genesis_stake_tx, _) = th.genesis_stake(&Holder::Alice, config.alice_initial)?;
-        let (genesis_mint_tx, _) = th.genesis_mint(&Holder::Bob, config.bob_initial)?;
-
-        // Generate default genesis block
-        let mut genesis_block = BlockInfo::default();
-
-        // Retrieve genesis producer transaction
-        let producer_tx = genesis_block.txs.pop().unwrap();
-
-        // Append genesis transactions and calculate their total
-        genesis_block.txs.push(genesis_stake_tx);
-        genesis_block.txs.push(genesis_mint_tx);
-        genesis_block.txs.push(producer_tx);
-        let genesis_txs_total = genesis_txs_total(&genesis_block.txs)?;
-        genesis_block.slots[0].total_tokens = genesis_txs_total;
-
-        // Generate validators configuration
-        // NOTE: we are not using consensus constants here so we
-        // don't get circular dependencies.
-        let time_keeper = TimeKeeper::new(genesis_block.header.timestamp, 10, 90, 0);
-        let validator_config = ValidatorConfig::new(
-            time_keeper,
-            3,
-            config.pow_threads,
-            config.pow_target,
-            genesis_block,
-            genesis_txs_total,
-            vec![],
-            config.testing_node,
-        );
+        let validator_config = generate_validator_config(&config).await?;
 
         // Generate validators using pregenerated vks
         let (_, vks) = vks::read_or_gen_vks_and_pks()?;
@@ -159,6 +128,10 @@ impl Harness {
         assert_eq!(alice_blockchain_len, bob.blockchain.len());
         assert_eq!(alice_blockchain_len, total_blocks);
 
+        // Every stored transaction must have a known location
+        assert_eq!(alice.blockchain.tx_locations.len(), alice.blockchain.txs_len());
+        assert_eq!(bob.blockchain.tx_locations.len(), bob.blockchain.txs_len());
+
         let alice_slots_len = alice.blockchain.slots.len();
         assert_eq!(alice_slots_len, bob.blockchain.slots.len());
         assert_eq!(alice_slots_len, total_slots);
@@ -235,6 +208,43 @@ impl Harness {
     }
 }
 
+// Note: Nodes must share the returned configuration, since every call
+// generates a different genesis block.
+pub async fn generate_validator_config(config: &HarnessConfig) -> Result<ValidatorConfig> {
+    // Use test harness to generate genesis transactions
+    let mut th = TestHarness::new(&["money".to_string(), "consensus".to_string()]).await?;
+    let (genesis_stake_tx, _) = th.genesis_stake(&Holder::Alice, config.alice_initial)?;
+    let (genesis_mint_tx, _) = th.genesis_mint(&Holder::Bob, config.bob_initial)?;
+
+    // Generate default genesis block
+    let mut genesis_block = BlockInfo::default();
+
+    // Retrieve genesis producer transaction
+    let producer_tx = genesis_block.txs.pop().unwrap();
+
+    // Append genesis transactions and calculate their total
+    genesis_block.txs.push(genesis_stake_tx);
+    genesis_block.txs.push(genesis_mint_tx);
+    genesis_block.txs.push(producer_tx);
+    let genesis_txs_total = genesis_txs_total(&genesis_block.txs)?;
+    genesis_block.slots[0].total_tokens = genesis_txs_total;
+
+    // Generate validators configuration
+    // NOTE: we are not using consensus constants here so we
+    // don't get circular dependencies.
+    let time_keeper = TimeKeeper::new(genesis_block.header.timestamp, 10, 90, 0);
+    Ok(ValidatorConfig::new(
+        time_keeper,
+        3,
+        config.pow_threads,
+        config.pow_target,
+        genesis_block,
+        genesis_txs_total,
+        vec![],
+        config.testing_node,
+    ))
+}
+
 // Note: This function should mirror darkfid::main
 pub async fn generate_node(
     vks: &Vec<(Vec<u8>, String, Vec<u8>)>,
@@ -252,6 +262,7 @@ pub async fn generate_node(
     let mut subscribers = HashMap::new();
     subscribers.insert("blocks", JsonSubscriber::new("blockchain.subscribe_blocks"));
     subscribers.insert("txs", JsonSubscriber::new("blockchain.subscribe_txs"));
+    subscribers.insert("events", JsonSubscriber::new("blockchain.subscribe_events"));
     if consensus_settings.is_some() {
         subscribers.insert("proposals", JsonSubscriber::new("blockchain.subscribe_proposals"));
     }
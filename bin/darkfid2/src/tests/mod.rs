@@ -28,6 +28,8 @@ use harness::{generate_node, Harness, HarnessConfig};
 
 mod forks;
 
+mod rpc;
+
 async fn sync_pos_blocks_real(ex: Arc<Executor<'static>>) -> Result<()> {
     init_logger();
 
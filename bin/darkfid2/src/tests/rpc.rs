@@ -0,0 +1,150 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, sync::Arc};
+
+use darkfi::{
+    blockchain::BlockInfo,
+    net::Settings,
+    rpc::jsonrpc::{ErrorCode, JsonResult},
+    util::encoding::base64,
+    Result,
+};
+use darkfi_contract_test_harness::{init_logger, vks};
+use darkfi_serial::deserialize;
+use smol::Executor;
+use tinyjson::JsonValue;
+
+use super::harness::{generate_node, generate_validator_config, HarnessConfig};
+use crate::RpcError;
+
+/// Build the JSON-RPC params array out of the given strings
+fn params(values: &[&str]) -> JsonValue {
+    JsonValue::Array(values.iter().map(|v| JsonValue::String(v.to_string())).collect())
+}
+
+/// Unwrap a successful JSON-RPC result
+fn response(result: JsonResult) -> JsonValue {
+    match result {
+        JsonResult::Response(response) => response.result,
+        _ => panic!("Expected a JSON-RPC response"),
+    }
+}
+
+/// Unwrap the code of an erroneous JSON-RPC result
+fn error_code(result: JsonResult) -> i32 {
+    match result {
+        JsonResult::Error(error) => error.error.code,
+        _ => panic!("Expected a JSON-RPC error"),
+    }
+}
+
+/// Decode a base64-encoded block returned by the RPC
+fn decode_block(value: &JsonValue) -> BlockInfo {
+    deserialize(&base64::decode(value.get::<String>().unwrap()).unwrap()).unwrap()
+}
+
+async fn blockchain_rpc_real(ex: Arc<Executor<'static>>) -> Result<()> {
+    init_logger();
+
+    // Initialize a single node, which only holds the genesis block
+    let config = HarnessConfig {
+        pow_threads: 2,
+        pow_target: 90,
+        testing_node: true,
+        alice_initial: 1000,
+        bob_initial: 500,
+    };
+    let validator_config = generate_validator_config(&config).await?;
+    let (_, vks) = vks::read_or_gen_vks_and_pks()?;
+    let settings = Settings { localnet: true, ..Default::default() };
+    let node = generate_node(&vks, &validator_config, &settings, None, &ex, true).await?;
+    let genesis = node.validator.read().await.blockchain.last_block()?;
+    let genesis_hash = genesis.hash()?.to_hex().to_string();
+    let block_hash = |value: &JsonValue| decode_block(value).hash().unwrap().to_hex().to_string();
+
+    let unknown_block = RpcError::UnknownBlock as i32;
+    let unknown_tx = RpcError::UnknownTx as i32;
+    let invalid_params = ErrorCode::InvalidParams.code();
+
+    // Blocks can be found by height and by hash
+    let block = response(node.blockchain_get_block(1, params(&["0"])).await);
+    assert_eq!(block_hash(&block), genesis_hash);
+    let block = response(node.blockchain_get_block(1, params(&[&genesis_hash])).await);
+    assert_eq!(block_hash(&block), genesis_hash);
+
+    // Unknown heights and hashes are reported as unknown blocks
+    assert_eq!(error_code(node.blockchain_get_block(1, params(&["42"])).await), unknown_block);
+    let unknown_hash = blake3::hash(b"unknown").to_hex().to_string();
+    assert_eq!(
+        error_code(node.blockchain_get_block(1, params(&[&unknown_hash])).await),
+        unknown_block
+    );
+
+    // Every genesis transaction location resolves back to the transaction
+    for tx in genesis.txs.iter() {
+        let tx_hash = tx.hash()?.to_hex().to_string();
+        let location = response(node.blockchain_get_tx_location(1, params(&[&tx_hash])).await);
+        let location = location.get::<HashMap<String, JsonValue>>().unwrap();
+        assert_eq!(location["block_hash"].get::<String>().unwrap(), &genesis_hash);
+        assert_eq!(location["height"].get::<String>().unwrap(), "0");
+
+        let height = location["height"].get::<String>().unwrap();
+        let block = response(node.blockchain_get_block(1, params(&[height])).await);
+        let index = *location["index"].get::<f64>().unwrap() as usize;
+        assert_eq!(&decode_block(&block).txs[index], tx);
+    }
+    assert_eq!(
+        error_code(node.blockchain_get_tx_location(1, params(&[&unknown_hash])).await),
+        unknown_tx
+    );
+
+    // Ranges are inclusive, and must be ordered and bounded
+    let blocks = response(node.blockchain_get_blocks_range(1, params(&["0", "0"])).await);
+    let blocks = blocks.get::<Vec<JsonValue>>().unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(block_hash(&blocks[0]), genesis_hash);
+    assert_eq!(
+        error_code(node.blockchain_get_blocks_range(1, params(&["1", "0"])).await),
+        invalid_params
+    );
+    assert_eq!(
+        error_code(node.blockchain_get_blocks_range(1, params(&["0", "100"])).await),
+        invalid_params
+    );
+
+    // Thanks for reading
+    Ok(())
+}
+
+#[test]
+fn blockchain_rpc() -> Result<()> {
+    let ex = Arc::new(Executor::new());
+    let (signal, shutdown) = smol::channel::unbounded::<()>();
+
+    easy_parallel::Parallel::new().each(0..4, |_| smol::block_on(ex.run(shutdown.recv()))).finish(
+        || {
+            smol::block_on(async {
+                blockchain_rpc_real(ex.clone()).await.unwrap();
+                drop(signal);
+            })
+        },
+    );
+
+    Ok(())
+}
@@ -18,12 +18,15 @@
 
 use std::{collections::HashMap, sync::Arc};
 
-use log::info;
+use log::{error, info};
 use smol::Executor;
+use tinyjson::JsonValue;
 
 use darkfi::{
+    blockchain::{BlockInfo, Blockchain},
     net::{P2p, P2pPtr, Settings, SESSION_ALL},
     rpc::jsonrpc::JsonSubscriber,
+    util::encoding::base64,
     validator::ValidatorPtr,
 };
 
@@ -42,11 +45,17 @@ pub async fn spawn_sync_p2p(
 
     let _validator = validator.clone();
     let _subscriber = subscribers.get("blocks").unwrap().clone();
+    let _events_subscriber = subscribers.get("events").unwrap().clone();
     registry
         .register(SESSION_ALL, move |channel, p2p| {
             let validator = _validator.clone();
             let subscriber = _subscriber.clone();
-            async move { ProtocolBlock::init(channel, validator, p2p, subscriber).await.unwrap() }
+            let events_subscriber = _events_subscriber.clone();
+            async move {
+                ProtocolBlock::init(channel, validator, p2p, subscriber, events_subscriber)
+                    .await
+                    .unwrap()
+            }
         })
         .await;
 
@@ -94,3 +103,43 @@ pub async fn spawn_consensus_p2p(
 
     p2p
 }
+
+/// Auxiliary function to notify the events subscriber of the contract events
+/// emitted by the transactions of the given stored blocks, one event per
+/// notification.
+pub async fn notify_events(
+    subscriber: &JsonSubscriber,
+    blockchain: &Blockchain,
+    blocks: &[BlockInfo],
+) {
+    for block in blocks {
+        let tx_hashes: Vec<blake3::Hash> = match block.txs.iter().map(|tx| tx.hash()).collect() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::utils::notify_events", "Failed hashing block transactions: {}", e);
+                continue
+            }
+        };
+
+        let events = match blockchain.events.get(&tx_hashes) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::utils::notify_events", "Failed retrieving block events: {}", e);
+                continue
+            }
+        };
+
+        for (tx_hash, tx_events) in tx_hashes.iter().zip(events) {
+            for event in tx_events {
+                let event = JsonValue::Object(HashMap::from([
+                    ("contract_id".to_string(), JsonValue::String(event.contract_id.to_string())),
+                    ("topic".to_string(), JsonValue::String(event.topic)),
+                    ("data".to_string(), JsonValue::String(base64::encode(&event.data))),
+                    ("tx_hash".to_string(), JsonValue::String(tx_hash.to_hex().to_string())),
+                    ("height".to_string(), JsonValue::Number(block.header.height as f64)),
+                ]));
+                subscriber.notify(vec![event].into()).await;
+            }
+        }
+    }
+}
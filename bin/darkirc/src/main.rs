@@ -179,7 +179,7 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
 
     info!("Instantiating event DAG");
     let sled_db = sled::open(datastore)?;
-    let p2p = P2p::new(args.net.into(), ex.clone()).await;
+    let p2p = P2p::new(args.net.try_into()?, ex.clone()).await;
     let event_graph =
         EventGraph::new(p2p.clone(), sled_db.clone(), "darkirc_dag", 1, ex.clone()).await?;
 
@@ -0,0 +1,206 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Persistent airdrop bookkeeping. Airdrop history and the cooldowns of
+//! recipients and clients are kept in sled, so a restart of the faucet
+//! does not reset its limits.
+
+use darkfi::Result;
+use darkfi_serial::{async_trait, deserialize, serialize, SerialDecodable, SerialEncodable};
+use sled::Transactional;
+
+const SLED_AIRDROPS_TREE: &[u8] = b"_faucetd_airdrops";
+const SLED_ADDR_COOLDOWN_TREE: &[u8] = b"_faucetd_addr_cooldown";
+const SLED_IP_COOLDOWN_TREE: &[u8] = b"_faucetd_ip_cooldown";
+
+/// A performed airdrop
+#[derive(Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct AirdropRecord {
+    /// Public key of the recipient
+    pub recipient: [u8; 32],
+    pub amount: u64,
+    /// Hex-encoded hash of the airdrop transaction
+    pub tx_hash: String,
+    /// UNIX timestamp of the airdrop
+    pub timestamp: i64,
+}
+
+/// Airdrop history and cooldowns stored in sled
+pub struct AirdropLedger {
+    /// Airdrop records keyed by timestamp and recipient, so they
+    /// are iterated in chronological order
+    airdrops: sled::Tree,
+    /// Timestamp of the last airdrop to each recipient
+    addr_cooldown: sled::Tree,
+    /// Timestamp of the last airdrop requested by each client IP
+    ip_cooldown: sled::Tree,
+}
+
+impl AirdropLedger {
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        Ok(Self {
+            airdrops: db.open_tree(SLED_AIRDROPS_TREE)?,
+            addr_cooldown: db.open_tree(SLED_ADDR_COOLDOWN_TREE)?,
+            ip_cooldown: db.open_tree(SLED_IP_COOLDOWN_TREE)?,
+        })
+    }
+
+    /// Timestamp of the last airdrop to the given recipient
+    pub fn last_addr_airdrop(&self, recipient: &[u8; 32]) -> Result<Option<i64>> {
+        Ok(self.addr_cooldown.get(recipient)?.map(|v| deserialize(&v)).transpose()?)
+    }
+
+    /// Timestamp of the last airdrop requested by the given client IP
+    pub fn last_ip_airdrop(&self, ip: &str) -> Result<Option<i64>> {
+        Ok(self.ip_cooldown.get(ip)?.map(|v| deserialize(&v)).transpose()?)
+    }
+
+    /// Atomically check that the cooldowns of the recipient and of the
+    /// client IP that requested the airdrop are over, and if so, record
+    /// the airdrop and restart both cooldowns. Returns `false` without
+    /// recording anything if either cooldown is still running, so
+    /// concurrent requests can't both pass the check.
+    pub fn insert(
+        &self,
+        record: &AirdropRecord,
+        ip: &str,
+        addr_timeout: i64,
+        ip_timeout: i64,
+    ) -> Result<bool> {
+        let mut key = (record.timestamp as u64).to_be_bytes().to_vec();
+        key.extend_from_slice(&record.recipient);
+
+        let record_bytes = serialize(record);
+        let timestamp = serialize(&record.timestamp);
+        (&self.airdrops, &self.addr_cooldown, &self.ip_cooldown).transaction(
+            |(airdrops, addr_cooldown, ip_cooldown)| {
+                let last_addr = addr_cooldown.get(record.recipient.as_slice())?;
+                let last_ip = ip_cooldown.get(ip)?;
+                let running = match (
+                    cooldown_running(last_addr, record.timestamp, addr_timeout),
+                    cooldown_running(last_ip, record.timestamp, ip_timeout),
+                ) {
+                    (Ok(addr), Ok(ip)) => addr || ip,
+                    (Err(e), _) | (_, Err(e)) => return Ok(Err(e)),
+                };
+
+                if running {
+                    return Ok(Ok(false))
+                }
+
+                airdrops.insert(key.as_slice(), record_bytes.as_slice())?;
+                addr_cooldown.insert(record.recipient.as_slice(), timestamp.as_slice())?;
+                ip_cooldown.insert(ip, timestamp.as_slice())?;
+                Ok::<_, sled::transaction::ConflictableTransactionError<sled::Error>>(Ok(true))
+            },
+        )?
+    }
+
+    /// Up to `limit` most recent airdrops, newest first, optionally
+    /// only the ones to the given recipient.
+    pub fn history(
+        &self,
+        recipient: Option<&[u8; 32]>,
+        limit: usize,
+    ) -> Result<Vec<AirdropRecord>> {
+        let mut ret = vec![];
+
+        for record in self.airdrops.iter().rev() {
+            if ret.len() == limit {
+                break
+            }
+
+            let (key, value) = record?;
+            if let Some(recipient) = recipient {
+                if &key[8..] != recipient {
+                    continue
+                }
+            }
+
+            ret.push(deserialize(&value)?);
+        }
+
+        Ok(ret)
+    }
+
+    /// Remove cooldowns of recipients and client IPs whose last airdrop
+    /// happened before the respective given timestamps.
+    pub fn prune_cooldowns(&self, addr_before: i64, ip_before: i64) -> Result<()> {
+        for (tree, before) in [(&self.addr_cooldown, addr_before), (&self.ip_cooldown, ip_before)] {
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                if deserialize::<i64>(&value)? < before {
+                    tree.remove(key)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check if a cooldown started at the stored timestamp `last` is still
+/// running at the time `now`.
+fn cooldown_running(last: Option<sled::IVec>, now: i64, timeout: i64) -> Result<bool> {
+    let Some(last) = last else { return Ok(false) };
+    Ok(now - deserialize::<i64>(&last)? <= timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn airdrop_ledger() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let ledger = AirdropLedger::new(&db)?;
+
+        let record = |recipient, timestamp| AirdropRecord {
+            recipient: [recipient; 32],
+            amount: 100,
+            tx_hash: format!("tx{}", timestamp),
+            timestamp,
+        };
+
+        assert!(ledger.insert(&record(1, 10), "1.1.1.1", 5, 5)?);
+        assert!(ledger.insert(&record(2, 20), "2.2.2.2", 5, 5)?);
+        assert!(ledger.insert(&record(1, 30), "2.2.2.2", 5, 5)?);
+
+        // Airdrops within a running cooldown are not recorded
+        assert!(!ledger.insert(&record(1, 32), "3.3.3.3", 5, 5)?);
+        assert!(!ledger.insert(&record(3, 33), "2.2.2.2", 5, 5)?);
+
+        assert_eq!(ledger.last_addr_airdrop(&[1; 32])?, Some(30));
+        assert_eq!(ledger.last_addr_airdrop(&[3; 32])?, None);
+        assert_eq!(ledger.last_ip_airdrop("1.1.1.1")?, Some(10));
+        assert_eq!(ledger.last_ip_airdrop("2.2.2.2")?, Some(30));
+
+        assert_eq!(ledger.history(None, 10)?, vec![record(1, 30), record(2, 20), record(1, 10)]);
+        assert_eq!(ledger.history(None, 1)?, vec![record(1, 30)]);
+        assert_eq!(ledger.history(Some(&[1; 32]), 10)?, vec![record(1, 30), record(1, 10)]);
+
+        // History is kept while cooldowns expire
+        ledger.prune_cooldowns(25, 15)?;
+        assert_eq!(ledger.last_addr_airdrop(&[2; 32])?, None);
+        assert_eq!(ledger.last_addr_airdrop(&[1; 32])?, Some(30));
+        assert_eq!(ledger.last_ip_airdrop("1.1.1.1")?, None);
+        assert_eq!(ledger.history(None, 10)?.len(), 3);
+
+        Ok(())
+    }
+}
@@ -17,7 +17,7 @@
  */
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     path::Path,
     str::FromStr,
     sync::Arc,
@@ -79,7 +79,10 @@ use darkfi::{
     },
     system::{sleep, StoppableTask, StoppableTaskPtr},
     tx::Transaction,
-    util::{parse::decode_base10, path::expand_path},
+    util::{
+        parse::{decode_base10, encode_base10},
+        path::expand_path,
+    },
     wallet::{WalletDb, WalletPtr},
     zk::{proof::ProvingKey, vm::ZkCircuit, vm_heap::empty_witnesses},
     zkas::ZkBinary,
@@ -89,9 +92,21 @@ use darkfi::{
 mod error;
 use error::{server_error, RpcError};
 
+mod ledger;
+use ledger::{AirdropLedger, AirdropRecord};
+
 const CONFIG_FILE: &str = "faucetd_config.toml";
 const CONFIG_FILE_CONTENTS: &str = include_str!("../faucetd_config.toml");
 
+/// VDF steps of a challenge when the request rate is below target
+const BASE_VDF_STEPS: u64 = 2_000_000;
+/// Maximum number of times the VDF steps are doubled under load
+const MAX_VDF_DOUBLINGS: usize = 5;
+/// Window in seconds over which the challenge request rate is measured
+const CHALLENGE_RATE_WINDOW: i64 = 600;
+/// Maximum number of airdrops returned by the `history` method
+const MAX_HISTORY_LEN: usize = 1000;
+
 #[derive(Clone, Debug, Deserialize, StructOpt, StructOptToml)]
 #[serde(default)]
 #[structopt(name = "faucetd", about = cli_desc!())]
@@ -160,6 +175,14 @@ struct Args {
     /// Airdrop timeout limit in seconds
     airdrop_timeout: i64,
 
+    #[structopt(long, default_value = "600")]
+    /// Airdrop timeout limit per client IP in seconds
+    airdrop_ip_timeout: i64,
+
+    #[structopt(long, default_value = "20")]
+    /// Challenge requests per 10 minutes after which the VDF difficulty increases
+    challenge_rate: usize,
+
     #[structopt(long, default_value = "10")]
     /// Airdrop amount limit
     airdrop_limit: String, // We convert this to u64 with decode_base10
@@ -174,8 +197,7 @@ struct Args {
 }
 
 type ProvingKeyMap = Arc<RwLock<HashMap<[u8; 32], Vec<(String, ProvingKey, ZkBinary)>>>>;
-type AirdropMap = Arc<Mutex<HashMap<[u8; 32], i64>>>;
-type ChallengeMap = Arc<Mutex<HashMap<[u8; 32], (BigUint, u64)>>>;
+type ChallengeMap = Arc<Mutex<HashMap<[u8; 32], (BigUint, u64, i64)>>>;
 
 pub struct Faucetd {
     synced: Mutex<bool>, // AtomicBool is weird in Arc
@@ -185,9 +207,12 @@ pub struct Faucetd {
     _wallet: WalletPtr,
     merkle_tree: MerkleTree,
     airdrop_timeout: i64,
+    airdrop_ip_timeout: i64,
     airdrop_limit: u64,
-    airdrop_map: AirdropMap,
+    ledger: Arc<AirdropLedger>,
     challenge_map: ChallengeMap,
+    challenge_rate: usize,
+    challenge_times: Mutex<VecDeque<i64>>,
     proving_keys: ProvingKeyMap,
     rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
 }
@@ -196,12 +221,21 @@ pub struct Faucetd {
 impl RequestHandler for Faucetd {
     async fn handle_request(&self, req: JsonRequest) -> JsonResult {
         match req.method.as_str() {
-            "challenge" => return self.challenge(req.id, req.params).await,
-            "airdrop" => return self.airdrop(req.id, req.params).await,
+            "history" => return self.history(req.id, req.params).await,
             _ => return JsonError::new(MethodNotFound, None, req.id).into(),
         }
     }
 
+    async fn handle_request_from(&self, req: JsonRequest, peer: &Url) -> JsonResult {
+        // Rate limited methods need to know the client IP
+        let ip = peer.host_str().unwrap_or_default();
+        match req.method.as_str() {
+            "challenge" => return self.challenge(req.id, req.params, ip).await,
+            "airdrop" => return self.airdrop(req.id, req.params, ip).await,
+            _ => return self.handle_request(req).await,
+        }
+    }
+
     async fn connections_mut(&self) -> MutexGuard<'_, HashSet<StoppableTaskPtr>> {
         self.rpc_connections.lock().await
     }
@@ -212,8 +246,11 @@ impl Faucetd {
         validator_state: ValidatorStatePtr,
         sync_p2p: P2pPtr,
         wallet: WalletPtr,
+        ledger: AirdropLedger,
         timeout: i64,
+        ip_timeout: i64,
         limit: u64,
+        challenge_rate: usize,
     ) -> Result<Self> {
         // Here we initialize the wallet for the money contract.
         let merkle_tree = Self::initialize_wallet(wallet.clone()).await?;
@@ -277,9 +314,12 @@ impl Faucetd {
             _wallet: wallet,
             merkle_tree,
             airdrop_timeout: timeout,
+            airdrop_ip_timeout: ip_timeout,
             airdrop_limit: limit,
-            airdrop_map: Arc::new(Mutex::new(HashMap::new())),
+            ledger: Arc::new(ledger),
             challenge_map: Arc::new(Mutex::new(HashMap::new())),
+            challenge_rate,
+            challenge_times: Mutex::new(VecDeque::new()),
             proving_keys,
             rpc_connections: Mutex::new(HashSet::new()),
         };
@@ -371,10 +411,45 @@ impl Faucetd {
         Ok(keypair)
     }
 
+    /// Check that neither the recipient nor the requesting client IP
+    /// received an airdrop within their respective timeouts.
+    fn cooldown_expired(&self, pubkey: &PublicKey, ip: &str, now: i64) -> Result<bool> {
+        if let Some(last_airdrop) = self.ledger.last_addr_airdrop(&pubkey.to_bytes())? {
+            if now - last_airdrop <= self.airdrop_timeout {
+                return Ok(false)
+            }
+        }
+
+        if let Some(last_airdrop) = self.ledger.last_ip_airdrop(ip)? {
+            if now - last_airdrop <= self.airdrop_ip_timeout {
+                return Ok(false)
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Record a challenge request and return the VDF steps it requires,
+    /// based on the number of requests within [`CHALLENGE_RATE_WINDOW`].
+    async fn next_vdf_steps(&self, now: i64) -> u64 {
+        let mut times = self.challenge_times.lock().await;
+        while let Some(time) = times.front() {
+            if now - time <= CHALLENGE_RATE_WINDOW {
+                break
+            }
+            times.pop_front();
+        }
+
+        let n_steps = vdf_steps(times.len(), self.challenge_rate);
+        times.push_back(now);
+        n_steps
+    }
+
     // RPCAPI:
     // Request a VDF challenge in order to become eligible for an airdrop. It is then
     // necessary to execute the VDF with the challenge as input and pass it to the
-    // `airdrop` call, which the faucet will then verify.
+    // `airdrop` call, which the faucet will then verify. The number of VDF steps
+    // grows with the rate at which challenges are being requested.
     //
     // **Params:**
     // * `array[0]`: base58 encoded address string of the recipient
@@ -385,9 +460,7 @@ impl Faucetd {
     //
     // --> {"jsonrpc": "2.0", "method": "challenge", "params": ["1DarkFi..."], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": ["0x123...", 10000], "id": 1}
-    async fn challenge(&self, id: u16, params: JsonValue) -> JsonResult {
-        const N_STEPS: u64 = 2_000_000;
-
+    async fn challenge(&self, id: u16, params: JsonValue, ip: &str) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if params.len() != 1 || !params[0].is_string() {
             return JsonError::new(InvalidParams, None, id).into()
@@ -413,6 +486,21 @@ impl Faucetd {
         }
         drop(map);
 
+        // No point in solving a challenge that can't be used yet
+        let now = Utc::now().timestamp();
+        match self.cooldown_expired(&pubkey, ip, now) {
+            Ok(true) => {}
+            Ok(false) => {
+                error!(target: "faucetd", "challenge(): Time limit reached for {} ({})", pubkey, ip);
+                return server_error(RpcError::TimeLimitReached, id)
+            }
+            Err(e) => {
+                error!(target: "faucetd", "challenge(): Failed reading airdrop ledger: {}", e);
+                return server_error(RpcError::InternalError, id)
+            }
+        }
+        let n_steps = self.next_vdf_steps(now).await;
+
         // Create a random challenge
         let mut hasher = blake3::Hasher::new();
         hasher.update(&pubkey.to_bytes());
@@ -422,11 +510,11 @@ impl Faucetd {
 
         // Add/Update this airdrop into the hashmap
         let mut map = self.challenge_map.lock().await;
-        map.insert(pubkey.to_bytes(), (c.clone(), N_STEPS));
+        map.insert(pubkey.to_bytes(), (c.clone(), n_steps, now));
         drop(map);
 
         let chall = JsonValue::from(c.to_str_radix(16));
-        let steps = JsonValue::from(N_STEPS as f64);
+        let steps = JsonValue::from(n_steps as f64);
         JsonResponse::new(JsonValue::Array(vec![chall, steps]), id).into()
     }
 
@@ -444,7 +532,7 @@ impl Faucetd {
     //
     // --> {"jsonrpc": "2.0", "method": "airdrop", "params": ["1DarkFi...", 1.42, "0x123..."], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "txID", "id": 1}
-    async fn airdrop(&self, id: u16, params: JsonValue) -> JsonResult {
+    async fn airdrop(&self, id: u16, params: JsonValue, ip: &str) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
 
         if params.len() != 3 ||
@@ -491,20 +579,23 @@ impl Faucetd {
             return server_error(RpcError::ParseError, id)
         };
 
-        // Check if there as a previous airdrop and the timeout has passed.
+        // Check if there as a previous airdrop and the timeouts have passed.
         let now = Utc::now().timestamp();
-        let map = self.airdrop_map.lock().await;
-        if let Some(last_airdrop) = map.get(&pubkey.to_bytes()) {
-            if now - last_airdrop <= self.airdrop_timeout {
-                error!(target: "faucetd", "airdrop(): Time limit reached for {}", pubkey);
+        match self.cooldown_expired(&pubkey, ip, now) {
+            Ok(true) => {}
+            Ok(false) => {
+                error!(target: "faucetd", "airdrop(): Time limit reached for {} ({})", pubkey, ip);
                 return server_error(RpcError::TimeLimitReached, id)
             }
-        };
-        drop(map);
+            Err(e) => {
+                error!(target: "faucetd", "airdrop(): Failed reading airdrop ledger: {}", e);
+                return server_error(RpcError::InternalError, id)
+            }
+        }
 
         // Check if a VDF challenge exists
         let map = self.challenge_map.lock().await;
-        let Some((challenge, n_steps)) = map.get(&pubkey.to_bytes()).cloned() else {
+        let Some((challenge, n_steps, _)) = map.get(&pubkey.to_bytes()).cloned() else {
             error!(target: "faucetd", "airdrop(): No VDF challenge found for {}", pubkey);
             return server_error(RpcError::NoVdfChallenge, id)
         };
@@ -591,50 +682,139 @@ impl Faucetd {
             return JsonError::new(InternalError, None, id).into()
         }
 
+        let tx_hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
+
+        // Record this airdrop in the ledger before broadcasting, so the
+        // cooldowns apply even if we crash right after broadcasting and
+        // the recipient cannot be paid twice. The cooldowns are checked
+        // again atomically with the insertion, since concurrent requests
+        // may have passed the check above.
+        let record = AirdropRecord {
+            recipient: pubkey.to_bytes(),
+            amount,
+            tx_hash: tx_hash.clone(),
+            timestamp: now,
+        };
+        match self.ledger.insert(&record, ip, self.airdrop_timeout, self.airdrop_ip_timeout) {
+            Ok(true) => {}
+            Ok(false) => {
+                error!(target: "faucetd", "airdrop(): Time limit reached for {} ({})", pubkey, ip);
+                return server_error(RpcError::TimeLimitReached, id)
+            }
+            Err(e) => {
+                error!(target: "faucetd", "airdrop(): Failed writing airdrop to ledger: {}", e);
+                return server_error(RpcError::InternalError, id)
+            }
+        }
+
         // Broadcast transaction to the network.
         self.sync_p2p.broadcast(&tx).await;
 
-        // Add/Update this airdrop into the hashmap
-        let mut map = self.airdrop_map.lock().await;
-        map.insert(pubkey.to_bytes(), now);
-        drop(map);
-
-        let tx_hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
         JsonResponse::new(JsonValue::String(tx_hash), id).into()
     }
+
+    // RPCAPI:
+    // Returns the most recent airdrops, newest first, optionally only the ones
+    // to a given address.
+    //
+    // **Params:**
+    // * `array[0]`: Maximum number of airdrops to return (`u64`)
+    // * `array[1]`: (optional) base58 encoded address string of the recipient
+    //
+    // **Returns:**
+    // * `array`: Airdrops as objects with `address`, `amount`, `tx_hash` and `timestamp`
+    //
+    // --> {"jsonrpc": "2.0", "method": "history", "params": [10, "1DarkFi..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"address": "1DarkFi...", "amount": "1.42", "tx_hash": "txID", "timestamp": 1700000000}], "id": 1}
+    async fn history(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+
+        if params.is_empty() ||
+            params.len() > 2 ||
+            !params[0].is_number() ||
+            (params.len() == 2 && !params[1].is_string())
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let limit = (*params[0].get::<f64>().unwrap() as usize).min(MAX_HISTORY_LEN);
+
+        let recipient = match params.get(1) {
+            Some(pubkey) => match PublicKey::from_str(pubkey.get::<String>().unwrap()) {
+                Ok(v) => Some(v.to_bytes()),
+                Err(e) => {
+                    error!(target: "faucetd", "history(): Failed parsing PublicKey from String: {}", e);
+                    return server_error(RpcError::ParseError, id)
+                }
+            },
+            None => None,
+        };
+
+        let records = match self.ledger.history(recipient.as_ref(), limit) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "faucetd", "history(): Failed reading airdrop ledger: {}", e);
+                return server_error(RpcError::InternalError, id)
+            }
+        };
+
+        let mut ret = vec![];
+        for record in records {
+            let Ok(address) = PublicKey::from_bytes(record.recipient) else {
+                error!(target: "faucetd", "history(): Invalid recipient in airdrop ledger");
+                return server_error(RpcError::InternalError, id)
+            };
+
+            ret.push(JsonValue::Object(HashMap::from([
+                ("address".to_string(), JsonValue::String(address.to_string())),
+                ("amount".to_string(), JsonValue::String(encode_base10(record.amount, 8))),
+                ("tx_hash".to_string(), JsonValue::String(record.tx_hash)),
+                ("timestamp".to_string(), JsonValue::Number(record.timestamp as f64)),
+            ])));
+        }
+
+        JsonResponse::new(JsonValue::Array(ret), id).into()
+    }
+}
+
+/// Number of VDF steps for a challenge. The base amount is doubled for
+/// every multiple of the target rate reached by recent requests.
+fn vdf_steps(recent_requests: usize, target_rate: usize) -> u64 {
+    let doublings = (recent_requests / target_rate.max(1)).min(MAX_VDF_DOUBLINGS);
+    BASE_VDF_STEPS << doublings
 }
 
 async fn prune_airdrop_maps(
-    rate_map: AirdropMap,
+    ledger: Arc<AirdropLedger>,
     challenge_map: ChallengeMap,
     timeout: i64,
+    ip_timeout: i64,
 ) -> Result<()> {
     loop {
-        sleep(timeout as u64).await;
+        sleep(timeout.min(ip_timeout).max(1) as u64).await;
         debug!(target: "faucetd", "Pruning airdrop maps");
 
         let now = Utc::now().timestamp();
 
-        let mut prune = vec![];
-
-        let im_map = rate_map.lock().await;
-        for (k, v) in im_map.iter() {
-            if now - *v > timeout {
-                prune.push(*k);
-            }
-        }
-        drop(im_map);
+        // Expire challenges which were not used in time
+        challenge_map.lock().await.retain(|_, (_, _, created)| now - *created <= timeout);
 
-        let mut mut_rate_map = rate_map.lock().await;
-        let mut mut_challenge_map = challenge_map.lock().await;
-
-        for i in prune {
-            mut_rate_map.remove(&i);
-            mut_challenge_map.remove(&i);
-        }
+        ledger.prune_cooldowns(now - timeout, now - ip_timeout)?;
+    }
+}
 
-        drop(mut_rate_map);
-        drop(mut_challenge_map);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vdf_difficulty() {
+        assert_eq!(vdf_steps(0, 20), BASE_VDF_STEPS);
+        assert_eq!(vdf_steps(19, 20), BASE_VDF_STEPS);
+        assert_eq!(vdf_steps(20, 20), BASE_VDF_STEPS * 2);
+        assert_eq!(vdf_steps(45, 20), BASE_VDF_STEPS * 4);
+        assert_eq!(vdf_steps(10_000, 20), BASE_VDF_STEPS << MAX_VDF_DOUBLINGS);
+        assert_eq!(vdf_steps(3, 0), BASE_VDF_STEPS << 3);
     }
 }
 
@@ -729,26 +909,34 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         .await;
 
     let airdrop_timeout = args.airdrop_timeout;
+    let airdrop_ip_timeout = args.airdrop_ip_timeout;
     let airdrop_limit = decode_base10(&args.airdrop_limit, 8, true)?;
 
+    // Airdrop history and cooldowns are kept next to the blockchain
+    let ledger = AirdropLedger::new(&sled_db)?;
+
     // Initialize program state
     let faucetd = Faucetd::new(
         state.clone(),
         sync_p2p.clone(),
         wallet.clone(),
+        ledger,
         airdrop_timeout,
+        airdrop_ip_timeout,
         airdrop_limit,
+        args.challenge_rate,
     )
     .await?;
     let faucetd = Arc::new(faucetd);
 
-    // Task to periodically clean up the airdrop rate/challenge hashmaps
+    // Task to periodically clean up expired challenges and cooldowns
     let airdrop_task = StoppableTask::new();
     airdrop_task.clone().start(
         prune_airdrop_maps(
-            faucetd.airdrop_map.clone(),
+            faucetd.ledger.clone(),
             faucetd.challenge_map.clone(),
             airdrop_timeout,
+            airdrop_ip_timeout,
         ),
         |res| async {
             match res {
@@ -18,6 +18,8 @@
 
 use std::{
     collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::Arc,
 };
 
@@ -36,7 +38,7 @@ use url::Url;
 
 use darkfi::{
     async_daemonize, cli_desc,
-    geode::Geode,
+    geode::{FileKey, Geode},
     net::{
         self, connector::Connector, protocol::ProtocolVersion, session::Session,
         settings::SettingsOpt, P2p, P2pPtr,
@@ -84,6 +86,11 @@ struct Args {
     /// Base directory for filesystem storage
     base_dir: String,
 
+    #[structopt(long)]
+    /// Maximum amount of bytes to use for storage. Files fetched for
+    /// others get evicted when exceeded, while pinned files are kept.
+    storage_quota: Option<u64>,
+
     #[structopt(flatten)]
     /// Network settings
     net: SettingsOpt,
@@ -98,6 +105,8 @@ pub struct Fud {
     p2p: P2pPtr,
     /// The Geode instance
     geode: Geode,
+    /// Path to the directory where fetched files are reassembled
+    downloads_path: PathBuf,
 
     file_fetch_tx: channel::Sender<(blake3::Hash, Result<()>)>,
     file_fetch_rx: channel::Receiver<(blake3::Hash, Result<()>)>,
@@ -115,6 +124,9 @@ impl RequestHandler for Fud {
 
             "put" => return self.put(req.id, req.params).await,
             "get" => return self.get(req.id, req.params).await,
+            "remove" => return self.remove(req.id, req.params).await,
+            "pin" => return self.pin(req.id, req.params, true).await,
+            "unpin" => return self.pin(req.id, req.params, false).await,
 
             "dnet_switch" => return self.dnet_switch(req.id, req.params).await,
             _ => return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
@@ -130,15 +142,27 @@ impl Fud {
     // RPCAPI:
     // Put a file onto the network. Takes a local filesystem path as a parameter.
     // Returns the file hash that serves as a pointer to the uploaded file.
+    // Optionally, `true` can be passed as a second parameter, in which case the
+    // file is encrypted with a random key before insertion, and the key is
+    // returned alongside the file hash.
     //
     // --> {"jsonrpc": "2.0", "method": "put", "params": ["/foo.txt"], "id": 42}
     // <-- {"jsonrpc": "2.0", "result: "df4...3db7", "id": 42}
+    //
+    // --> {"jsonrpc": "2.0", "method": "put", "params": ["/foo.txt", true], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result: ["df4...3db7", "Fz2...8cb"], "id": 42}
     async fn put(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
-        if params.len() != 1 || !params[0].is_string() {
+        if params.is_empty() || params.len() > 2 || !params[0].is_string() {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         }
 
+        let encrypt = match params.get(1) {
+            Some(v) if v.is_bool() => *v.get::<bool>().unwrap(),
+            Some(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+            None => false,
+        };
+
         let path = params[0].get::<String>().unwrap();
         let path = match expand_path(path.as_str()) {
             Ok(v) => v,
@@ -155,7 +179,13 @@ impl Fud {
             }
         };
 
-        let (file_hash, chunk_hashes) = match self.geode.insert(fd).await {
+        let file_key = if encrypt { Some(FileKey::random()) } else { None };
+        let insert_result = match file_key {
+            Some(ref key) => self.geode.insert_encrypted(fd, key).await,
+            None => self.geode.insert(fd).await,
+        };
+
+        let (file_hash, chunk_hashes) = match insert_result {
             Ok(v) => v,
             Err(e) => {
                 error!("Failed inserting file {:?} to geode: {}", path, e);
@@ -163,21 +193,39 @@ impl Fud {
             }
         };
 
+        // Files explicitly added by the user are never evicted
+        if let Err(e) = self.geode.pin(&file_hash).await {
+            error!("Failed pinning file {} in geode: {}", file_hash, e);
+            return JsonError::new(ErrorCode::InternalError, None, id).into()
+        }
+
         let fud_file = FudFilePut { file_hash, chunk_hashes };
         self.p2p.broadcast(&fud_file).await;
 
-        JsonResponse::new(JsonValue::String(file_hash.to_hex().to_string()), id).into()
+        let file_hash = JsonValue::String(file_hash.to_hex().to_string());
+        match file_key {
+            Some(key) => JsonResponse::new(
+                JsonValue::Array(vec![file_hash, JsonValue::String(key.to_string())]),
+                id,
+            )
+            .into(),
+            None => JsonResponse::new(file_hash, id).into(),
+        }
     }
 
     // RPCAPI:
-    // Fetch a file from the network. Takes a file hash as parameter.
-    // Returns the paths to the local chunks of the file, if found/fetched.
+    // Fetch a file from the network. Takes a file hash as parameter, and
+    // optionally a local filesystem path to write the file to, and a key
+    // to decrypt the file with, if it was inserted encrypted. If no path
+    // is given, the file is written into the downloads directory.
+    // Returns the path to the reassembled file, if found/fetched.
+    // Fetched files are pinned, so they are not evicted from local storage.
     //
-    // --> {"jsonrpc": "2.0", "method": "get", "params": ["1211...abfd"], "id": 42}
-    // <-- {"jsonrpc": "2.0", "result: ["~/.local/share/fud/chunks/fab1...2314", ...], "id": 42}
+    // --> {"jsonrpc": "2.0", "method": "get", "params": ["1211...abfd", "/foo.txt", "Fz2...8cb"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result: "/foo.txt", "id": 42}
     async fn get(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
-        if params.len() != 1 || !params[0].is_string() {
+        if params.is_empty() || params.len() > 3 || params.iter().any(|p| !p.is_string()) {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         }
 
@@ -186,6 +234,39 @@ impl Fud {
             Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
         };
 
+        let out_path = match params.get(1) {
+            Some(path) => match expand_path(path.get::<String>().unwrap()) {
+                Ok(v) => v,
+                Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+            },
+            None => self.downloads_path.join(file_hash.to_hex().as_str()),
+        };
+
+        let file_key = match params.get(2) {
+            Some(key) => match FileKey::from_str(key.get::<String>().unwrap()) {
+                Ok(v) => Some(v),
+                Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+            },
+            None => None,
+        };
+
+        // Keep the chunks we fetch from being evicted before the file is complete
+        self.geode.begin_download(&file_hash).await;
+        let result = self.fetch(id, &file_hash, file_key, &out_path).await;
+        self.geode.end_download(&file_hash).await;
+        result
+    }
+
+    /// Fetch the file metadata and any missing chunks from the network,
+    /// then pin the file and reassemble it into `out_path`.
+    async fn fetch(
+        &self,
+        id: u16,
+        file_hash: &blake3::Hash,
+        file_key: Option<FileKey>,
+        out_path: &Path,
+    ) -> JsonResult {
+        let file_hash = *file_hash;
         let chunked_file = match self.geode.get(&file_hash).await {
             Ok(v) => v,
             Err(Error::GeodeNeedsGc) => todo!(),
@@ -221,16 +302,7 @@ impl Fud {
         };
 
         if chunked_file.is_complete() {
-            let chunks: Vec<JsonValue> = chunked_file
-                .iter()
-                .map(|(_, path)| {
-                    JsonValue::String(
-                        path.as_ref().unwrap().clone().into_os_string().into_string().unwrap(),
-                    )
-                })
-                .collect();
-
-            return JsonResponse::new(JsonValue::Array(chunks), id).into()
+            return self.export(id, &file_hash, file_key, out_path).await
         }
 
         // Fetch any missing chunks
@@ -267,16 +339,96 @@ impl Fud {
             // Return JsonError missing chunks
         }
 
-        let chunks: Vec<JsonValue> = chunked_file
-            .iter()
-            .map(|(_, path)| {
-                JsonValue::String(
-                    path.as_ref().unwrap().clone().into_os_string().into_string().unwrap(),
-                )
-            })
-            .collect();
+        self.export(id, &file_hash, file_key, out_path).await
+    }
+
+    /// Pin a locally complete file fetched by the user, so it can't be
+    /// evicted, then reassemble it from Geode into `path` and return the
+    /// according JSON-RPC response.
+    async fn export(
+        &self,
+        id: u16,
+        file_hash: &blake3::Hash,
+        file_key: Option<FileKey>,
+        path: &Path,
+    ) -> JsonResult {
+        if let Err(e) = self.geode.pin(file_hash).await {
+            error!("Failed pinning file {} in geode: {}", file_hash, e);
+            return JsonError::new(ErrorCode::InternalError, None, id).into()
+        }
 
-        JsonResponse::new(JsonValue::Array(chunks), id).into()
+        if let Err(e) = self.geode.export(file_hash, file_key, path).await {
+            error!("Failed exporting file {} to {:?}: {}", file_hash, path, e);
+            return JsonError::new(ErrorCode::InternalError, None, id).into()
+        }
+
+        JsonResponse::new(JsonValue::String(path.to_string_lossy().to_string()), id).into()
+    }
+
+    // RPCAPI:
+    // Remove a file from local storage. Takes a file hash as parameter.
+    // Chunks shared with other stored files are kept.
+    // Returns `true` on success.
+    //
+    // --> {"jsonrpc": "2.0", "method": "remove", "params": ["1211...abfd"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    async fn remove(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let file_hash = match blake3::Hash::from_hex(params[0].get::<String>().unwrap()) {
+            Ok(v) => v,
+            Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        };
+
+        match self.geode.remove(&file_hash).await {
+            Ok(_) => JsonResponse::new(JsonValue::Boolean(true), id).into(),
+            Err(Error::GeodeFileNotFound) => {
+                JsonError::new(ErrorCode::InvalidParams, None, id).into()
+            }
+            Err(e) => {
+                error!("Failed removing file {} from geode: {}", file_hash, e);
+                JsonError::new(ErrorCode::InternalError, None, id).into()
+            }
+        }
+    }
+
+    // RPCAPI:
+    // Pin or unpin a locally stored file. Pinned files are never evicted
+    // when the storage quota is exceeded. Takes a file hash as parameter.
+    // Returns `true` on success.
+    //
+    // --> {"jsonrpc": "2.0", "method": "pin", "params": ["1211...abfd"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    //
+    // --> {"jsonrpc": "2.0", "method": "unpin", "params": ["1211...abfd"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    async fn pin(&self, id: u16, params: JsonValue, pin: bool) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let file_hash = match blake3::Hash::from_hex(params[0].get::<String>().unwrap()) {
+            Ok(v) => v,
+            Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        };
+
+        let res =
+            if pin { self.geode.pin(&file_hash).await } else { self.geode.unpin(&file_hash).await };
+
+        match res {
+            Ok(()) => JsonResponse::new(JsonValue::Boolean(true), id).into(),
+            Err(Error::GeodeFileNotFound) => {
+                JsonError::new(ErrorCode::InvalidParams, None, id).into()
+            }
+            Err(e) => {
+                error!("Failed (un)pinning file {} in geode: {}", file_hash, e);
+                JsonError::new(ErrorCode::InternalError, None, id).into()
+            }
+        }
     }
 
     // RPCAPI:
@@ -537,10 +689,16 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
     let chunks_router = Arc::new(RwLock::new(HashMap::new()));
 
     info!("Instantiating Geode instance");
-    let geode = Geode::new(&basedir).await?;
+    let mut geode = Geode::new(&basedir).await?;
+    geode.set_quota(args.storage_quota);
+    geode.evict().await?;
+
+    // Directory where fetched files are reassembled
+    let downloads_path = basedir.join("downloads");
+    smol::fs::create_dir_all(&downloads_path).await?;
 
     info!("Instantiating P2P network");
-    let p2p = P2p::new(args.net.into(), ex.clone()).await;
+    let p2p = P2p::new(args.net.try_into()?, ex.clone()).await;
 
     // Daemon instantiation
     let (file_fetch_tx, file_fetch_rx) = smol::channel::unbounded();
@@ -550,6 +708,7 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
         chunks_router,
         p2p: p2p.clone(),
         geode,
+        downloads_path,
         file_fetch_tx,
         file_fetch_rx,
         chunk_fetch_tx,
@@ -104,7 +104,7 @@ async fn realmain(args: Args, executor: Arc<smol::Executor<'static>>) -> Result<
     let net_settings = args.net.clone();
 
     // New p2p
-    let p2p = net::P2p::new(net_settings.into(), executor.clone()).await;
+    let p2p = net::P2p::new(net_settings.try_into()?, executor.clone()).await;
     let p2p2 = p2p.clone();
 
     // Register the protocol_event
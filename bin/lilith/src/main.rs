@@ -21,6 +21,7 @@ use std::{
     path::Path,
     process::exit,
     sync::Arc,
+    time::UNIX_EPOCH,
 };
 
 use async_trait::async_trait;
@@ -58,8 +59,12 @@ const CONFIG_FILE_CONTENTS: &str = include_str!("../lilith_config.toml");
 
 /// Period in which the peer purge happens (in seconds)
 const PURGE_PERIOD: u64 = 60;
-/// Amount of hosts to try each purge iteration
-const PROBE_HOSTS_N: u32 = 10;
+/// Amount of hosts to probe concurrently
+const PROBE_HOSTS_N: usize = 10;
+/// Minimum time between two probes of the same host (in seconds)
+const PROBE_INTERVAL: u64 = 600;
+/// Amount of consecutive failed probes after which a host is evicted
+const PROBE_MAX_FAILURES: usize = 3;
 
 #[derive(Clone, Debug, serde::Deserialize, StructOpt, StructOptToml)]
 #[serde(default)]
@@ -90,12 +95,54 @@ struct Args {
     pub verbose: u8,
 }
 
+/// Reachability bookkeeping for the hosts of a spawned network
+#[derive(Default)]
+struct HostsHealth {
+    /// Last time each host was probed
+    last_probe: HashMap<Url, u64>,
+    /// Consecutive failed probes of each host
+    failures: HashMap<Url, usize>,
+    /// Hosts known at the end of the previous purge round
+    previous: HashSet<Url>,
+    /// Hosts which appeared during the last purge round
+    joined: usize,
+    /// Hosts which disappeared during the last purge round
+    left: usize,
+    /// Total amount of hosts which appeared since startup
+    joined_total: u64,
+    /// Total amount of hosts which disappeared since startup
+    left_total: u64,
+    /// Total amount of probes performed since startup
+    probes: u64,
+    /// Total amount of failed probes since startup
+    probes_failed: u64,
+    /// Total amount of hosts evicted as unreachable since startup
+    evicted: u64,
+}
+
+impl HostsHealth {
+    /// Record the current set of hosts at the end of a purge round
+    fn update_churn(&mut self, current: HashSet<Url>) {
+        self.joined = current.difference(&self.previous).count();
+        self.left = self.previous.difference(&current).count();
+        self.joined_total += self.joined as u64;
+        self.left_total += self.left as u64;
+
+        // Forget about hosts which are gone
+        self.last_probe.retain(|host, _| current.contains(host));
+        self.failures.retain(|host, _| current.contains(host));
+        self.previous = current;
+    }
+}
+
 /// Struct representing a spawned P2P network
 struct Spawn {
     /// String identifier,
     pub name: String,
     /// P2P pointer
     pub p2p: P2pPtr,
+    /// Reachability bookkeeping of the network's hosts
+    pub health: Arc<Mutex<HostsHealth>>,
 }
 
 impl Spawn {
@@ -121,6 +168,50 @@ impl Spawn {
             ("hosts".to_string(), JsonValue::Array(self.addresses().await)),
         ]))
     }
+
+    async fn stats(&self) -> JsonValue {
+        let hosts = self.p2p.hosts();
+        let addrs = hosts.fetch_all().await;
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let freshness = self.p2p.settings().hosts_freshness;
+
+        let mut fresh = 0;
+        let mut transports: HashMap<String, JsonValue> = HashMap::new();
+        for addr in &addrs {
+            if let Some(ts) = hosts.last_seen(addr).await {
+                if now.saturating_sub(ts) <= freshness {
+                    fresh += 1;
+                }
+            }
+
+            let count =
+                transports.entry(addr.scheme().to_string()).or_insert(JsonValue::Number(0.0));
+            if let JsonValue::Number(n) = count {
+                *n += 1.0;
+            }
+        }
+
+        let health = self.health.lock().await;
+        let failing = health.failures.len();
+
+        let churn = JsonValue::Object(HashMap::from([
+            ("joined".to_string(), JsonValue::Number(health.joined as f64)),
+            ("left".to_string(), JsonValue::Number(health.left as f64)),
+            ("joined_total".to_string(), JsonValue::Number(health.joined_total as f64)),
+            ("left_total".to_string(), JsonValue::Number(health.left_total as f64)),
+        ]));
+
+        JsonValue::Object(HashMap::from([
+            ("hosts".to_string(), JsonValue::Number(addrs.len() as f64)),
+            ("fresh".to_string(), JsonValue::Number(fresh as f64)),
+            ("failing".to_string(), JsonValue::Number(failing as f64)),
+            ("probes".to_string(), JsonValue::Number(health.probes as f64)),
+            ("probes_failed".to_string(), JsonValue::Number(health.probes_failed as f64)),
+            ("evicted".to_string(), JsonValue::Number(health.evicted as f64)),
+            ("churn".to_string(), churn),
+            ("transports".to_string(), JsonValue::Object(transports)),
+        ]))
+    }
 }
 
 /// Defines the network-specific settings
@@ -136,6 +227,10 @@ struct NetInfo {
     pub version: Version,
     /// Enable localnet hosts
     pub localnet: bool,
+    /// Base58-encoded secret used to sign our address replies
+    pub signing_key: Option<String>,
+    /// Base58-encoded public keys of other seeds we trust
+    pub seed_keys: Vec<String>,
 }
 
 /// Struct representing the daemon
@@ -149,69 +244,109 @@ struct Lilith {
 impl Lilith {
     /// Internal task to run a periodic purge of unreachable hosts
     /// for a specific P2P network.
-    async fn periodic_purge(name: String, p2p: P2pPtr, ex: Arc<Executor<'_>>) -> Result<()> {
+    async fn periodic_purge(
+        name: String,
+        p2p: P2pPtr,
+        health: Arc<Mutex<HostsHealth>>,
+        ex: Arc<Executor<'_>>,
+    ) -> Result<()> {
         info!(target: "lilith", "Starting periodic host purge task for \"{}\"", name);
         loop {
-            // We'll pick up to PROBE_HOSTS_N hosts every PURGE_PERIOD and try to
-            // connect to them. If we can't reach them, remove them from our set.
+            // Every PURGE_PERIOD we probe the hosts which weren't probed in the
+            // last PROBE_INTERVAL, starting from the ones probed longest ago.
+            // Reachable hosts are marked as seen, and hosts failing too many
+            // probes in a row are removed from our set.
             sleep(PURGE_PERIOD).await;
-            debug!(target: "lilith", "[{}] Picking random hosts from db", name);
-
-            let lottery_winners = p2p.clone().hosts().fetch_n_random(PROBE_HOSTS_N).await;
-            let win_str: Vec<&str> = lottery_winners.iter().map(|x| x.as_str()).collect();
-            debug!(target: "lilith", "[{}] Got: {:?}", name, win_str);
-
-            let mut tasks = vec![];
-
-            for host in &lottery_winners {
-                let p2p_ = p2p.clone();
-                let ex_ = ex.clone();
-                tasks.push(async move {
-                    let session_out = p2p_.session_outbound();
-                    let session_weak = Arc::downgrade(&session_out);
-
-                    let connector = Connector::new(p2p_.settings(), session_weak);
-                    debug!(target: "lilith", "Connecting to {}", host);
-                    match connector.connect(host).await {
-                        Ok((_url, channel)) => {
-                            debug!(target: "lilith", "Connected successfully!");
-                            let proto_ver = ProtocolVersion::new(
-                                channel.clone(),
-                                p2p_.settings().clone(),
-                                p2p_.hosts().clone(),
-                            )
-                            .await;
-
-                            let handshake_task = session_out.perform_handshake_protocols(
-                                proto_ver,
-                                channel.clone(),
-                                ex_.clone(),
-                            );
-
-                            channel.clone().start(ex_.clone());
-
-                            match handshake_task.await {
-                                Ok(()) => {
-                                    debug!(target: "lilith", "Handshake success! Stopping channel.");
-                                    channel.stop().await;
-                                }
-                                Err(e) => {
-                                    debug!(target: "lilith", "Handshake failure! {}", e);
-                                    p2p_.hosts().remove(host).await;
-                                }
-                            }
-                        }
 
-                        Err(e) => {
-                            debug!(target: "lilith", "Failed to connect to {}, removing from set ({})", host, e);
-                            // Remove from hosts set
-                            p2p_.hosts().remove(host).await;
-                        }
+            let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+            let mut due: Vec<(u64, Url)> = {
+                let health = health.lock().await;
+                p2p.hosts()
+                    .fetch_all()
+                    .await
+                    .into_iter()
+                    .map(|host| (*health.last_probe.get(&host).unwrap_or(&0), host))
+                    .filter(|(last_probe, _)| now.saturating_sub(*last_probe) >= PROBE_INTERVAL)
+                    .collect()
+            };
+            due.sort_by_key(|(last_probe, _)| *last_probe);
+            debug!(target: "lilith", "[{}] Probing {} hosts", name, due.len());
+
+            for batch in due.chunks(PROBE_HOSTS_N) {
+                let mut tasks = vec![];
+                for (_, host) in batch {
+                    tasks.push(Self::probe(p2p.clone(), host, ex.clone()));
+                }
+
+                let results = join_all(tasks).await;
+
+                let mut health = health.lock().await;
+                let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+                for ((_, host), reachable) in batch.iter().zip(results) {
+                    health.probes += 1;
+                    health.last_probe.insert(host.clone(), now);
+
+                    if reachable {
+                        health.failures.remove(host);
+                        p2p.hosts().mark_seen(host, now).await;
+                        continue
+                    }
+
+                    health.probes_failed += 1;
+                    let failures = health.failures.entry(host.clone()).or_insert(0);
+                    *failures += 1;
+                    if *failures >= PROBE_MAX_FAILURES {
+                        debug!(target: "lilith", "[{}] Evicting unreachable host {}", name, host);
+                        health.failures.remove(host);
+                        health.evicted += 1;
+                        p2p.hosts().remove(host).await;
                     }
-                });
+                }
             }
 
-            join_all(tasks).await;
+            let current: HashSet<Url> = p2p.hosts().fetch_all().await.into_iter().collect();
+            health.lock().await.update_churn(current);
+        }
+    }
+
+    /// Try to connect to a host and perform the handshake protocols with
+    /// it, which include the version check. Returns `true` on success.
+    async fn probe(p2p: P2pPtr, host: &Url, ex: Arc<Executor<'_>>) -> bool {
+        let session_out = p2p.session_outbound();
+        let session_weak = Arc::downgrade(&session_out);
+
+        let connector = Connector::new(p2p.settings(), session_weak);
+        debug!(target: "lilith", "Connecting to {}", host);
+        let channel = match connector.connect(host).await {
+            Ok((_url, channel)) => channel,
+            Err(e) => {
+                debug!(target: "lilith", "Failed to connect to {} ({})", host, e);
+                return false
+            }
+        };
+
+        debug!(target: "lilith", "Connected successfully!");
+        let proto_ver =
+            ProtocolVersion::new(channel.clone(), p2p.settings().clone(), p2p.hosts().clone())
+                .await;
+
+        let handshake_task =
+            session_out.perform_handshake_protocols(proto_ver, channel.clone(), ex.clone());
+
+        channel.clone().start(ex.clone());
+
+        let result = handshake_task.await;
+        channel.stop().await;
+
+        match result {
+            Ok(()) => {
+                debug!(target: "lilith", "Handshake success with {}", host);
+                true
+            }
+            Err(e) => {
+                debug!(target: "lilith", "Handshake failure with {}: {}", host, e);
+                false
+            }
         }
     }
 
@@ -230,6 +365,27 @@ impl Lilith {
 
         JsonResponse::new(json, id).into()
     }
+
+    // RPCAPI:
+    // Returns per-network statistics about the stored hosts: their amount,
+    // how many were recently verified reachable (`fresh`), how many failed
+    // their last probes, probe and eviction counters, the churn of the last
+    // purge round along with totals since startup, and the transport mix.
+    // --> {"jsonrpc": "2.0", "method": "stats", "params": [], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"networks": {"foo_network": network_stats}}, "id": 42}
+    async fn stats(&self, id: u16, _params: JsonValue) -> JsonResult {
+        let mut networks = HashMap::new();
+        for spawn in &self.networks {
+            networks.insert(spawn.name.clone(), spawn.stats().await);
+        }
+
+        let json = JsonValue::Object(HashMap::from([(
+            "networks".to_string(),
+            JsonValue::Object(networks),
+        )]));
+
+        JsonResponse::new(json, id).into()
+    }
 }
 
 #[async_trait]
@@ -238,6 +394,7 @@ impl RequestHandler for Lilith {
         match req.method.as_str() {
             "ping" => return self.pong(req.id, req.params).await,
             "spawns" => return self.spawns(req.id, req.params).await,
+            "stats" => return self.stats(req.id, req.params).await,
             _ => return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
     }
@@ -247,8 +404,10 @@ impl RequestHandler for Lilith {
     }
 }
 
-/// Attempt to read existing hosts tsv
-fn load_hosts(path: &Path, networks: &[&str]) -> HashMap<String, HashSet<Url>> {
+/// Attempt to read existing hosts tsv. Lines are in the form of
+/// `network\turl[\tlast_seen]`, where the optional last column holds
+/// the last time the host was verified reachable.
+fn load_hosts(path: &Path, networks: &[&str]) -> HashMap<String, HashMap<Url, Option<u64>>> {
     let mut saved_hosts = HashMap::new();
 
     let contents = load_file(path);
@@ -262,7 +421,7 @@ fn load_hosts(path: &Path, networks: &[&str]) -> HashMap<String, HashSet<Url>> {
         if networks.contains(&data[0]) {
             let mut hosts = match saved_hosts.get(data[0]) {
                 Some(hosts) => hosts.clone(),
-                None => HashSet::new(),
+                None => HashMap::new(),
             };
 
             let url = match Url::parse(data[1]) {
@@ -273,7 +432,9 @@ fn load_hosts(path: &Path, networks: &[&str]) -> HashMap<String, HashSet<Url>> {
                 }
             };
 
-            hosts.insert(url);
+            let last_seen = data.get(2).and_then(|ts| ts.parse::<u64>().ok());
+
+            hosts.insert(url, last_seen);
             saved_hosts.insert(data[0].to_string(), hosts);
         }
     }
@@ -285,8 +446,12 @@ async fn save_hosts(path: &Path, networks: &[Spawn]) {
     let mut tsv = String::new();
 
     for spawn in networks {
-        for host in spawn.p2p.hosts().fetch_all().await {
-            tsv.push_str(&format!("{}\t{}\n", spawn.name, host.as_str()));
+        let hosts = spawn.p2p.hosts();
+        for host in hosts.fetch_all().await {
+            match hosts.last_seen(&host).await {
+                Some(ts) => tsv.push_str(&format!("{}\t{}\t{}\n", spawn.name, host.as_str(), ts)),
+                None => tsv.push_str(&format!("{}\t{}\n", spawn.name, host.as_str())),
+            }
         }
     }
 
@@ -354,7 +519,16 @@ fn parse_configured_networks(data: &str) -> Result<HashMap<String, NetInfo>> {
                     semver::Version::parse(option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0"))?
                 };
 
-                let net_info = NetInfo { port, seeds, peers, version, localnet };
+                let signing_key =
+                    table.get("signing_key").and_then(|k| k.as_str()).map(String::from);
+
+                let mut seed_keys = vec![];
+                if let Some(k) = table.get("seed_keys").and_then(|k| k.as_array()) {
+                    seed_keys = k.iter().filter_map(|k| k.as_str()).map(String::from).collect();
+                }
+
+                let net_info =
+                    NetInfo { port, seeds, peers, version, localnet, signing_key, seed_keys };
                 ret.insert(name, net_info);
             }
         }
@@ -367,7 +541,7 @@ async fn spawn_net(
     name: String,
     info: &NetInfo,
     accept_addrs: &[Url],
-    saved_hosts: &HashSet<Url>,
+    saved_hosts: &HashMap<Url, Option<u64>>,
     ex: Arc<Executor<'static>>,
 ) -> Result<Spawn> {
     let mut listen_urls = vec![];
@@ -379,10 +553,30 @@ async fn spawn_net(
         listen_urls.push(url);
     }
 
+    // Keys used to sign our replies and to verify the other seeds
+    let seed_signing_key = match &info.signing_key {
+        Some(secret) => {
+            let keypair = net::settings::parse_seed_signing_key(secret)?;
+            info!(
+                target: "lilith", "Signing \"{}\" address replies with key {}",
+                name, bs58::encode(*keypair.pk).into_string(),
+            );
+            Some(keypair)
+        }
+        None => None,
+    };
+
+    let mut seed_keys = vec![];
+    for key in &info.seed_keys {
+        seed_keys.push(net::settings::parse_seed_key(key)?);
+    }
+
     // P2P network settings
     let settings = net::Settings {
         inbound_addrs: listen_urls.clone(),
         seeds: info.seeds.clone(),
+        seed_keys,
+        seed_signing_key,
         peers: info.peers.clone(),
         outbound_connections: 0,
         outbound_connect_timeout: 30,
@@ -403,15 +597,20 @@ async fn spawn_net(
     // Create P2P instance
     let p2p = P2p::new(settings, ex.clone()).await;
 
-    // Fill db with cached hosts
-    let hosts: Vec<Url> = saved_hosts.iter().cloned().collect();
+    // Fill db with cached hosts, along with their freshness
+    let hosts: Vec<Url> = saved_hosts.keys().cloned().collect();
     p2p.hosts().store(&hosts).await;
+    for (host, last_seen) in saved_hosts {
+        if let Some(ts) = last_seen {
+            p2p.hosts().mark_seen(host, *ts).await;
+        }
+    }
 
     let addrs_str: Vec<&str> = listen_urls.iter().map(|x| x.as_str()).collect();
     info!(target: "lilith", "Starting seed network node for \"{}\" on {:?}", name, addrs_str);
     p2p.clone().start().await?;
 
-    let spawn = Spawn { name, p2p };
+    let spawn = Spawn { name, p2p, health: Arc::new(Mutex::new(HostsHealth::default())) };
     Ok(spawn)
 }
 
@@ -442,7 +641,7 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
             name.to_string(),
             info,
             &args.accept_addrs,
-            saved_hosts.get(name).unwrap_or(&HashSet::new()),
+            saved_hosts.get(name).unwrap_or(&HashMap::new()),
             ex.clone(),
         )
         .await
@@ -462,7 +661,12 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
         let name = network.name.clone();
         let task = StoppableTask::new();
         task.clone().start(
-            Lilith::periodic_purge(name.clone(), network.p2p.clone(), ex.clone()),
+            Lilith::periodic_purge(
+                name.clone(),
+                network.p2p.clone(),
+                network.health.clone(),
+                ex.clone(),
+            ),
             |res| async move {
                 match res {
                     Ok(()) | Err(Error::DetachedTaskStopped) => { /* Do nothing */ }
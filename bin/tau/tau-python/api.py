@@ -54,6 +54,9 @@ async def get_archive_ref_ids(month_ts, server_name, port):
 async def fetch_task(refid, server_name, port):
     return await query("get_task_by_ref_id", [refid], server_name, int(port))
 
+async def query_tasks(task_filter, server_name, port):
+    return await query("query", [task_filter], server_name, int(port))
+
 async def change_task_status(refid, status, server_name, port):
     await query("set_state", [refid, status], server_name, int(port))
     return True
@@ -0,0 +1,430 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Conflict-free replication of task edits.
+//!
+//! Instead of whole [`TaskInfo`] snapshots, nodes broadcast field-level
+//! [`TaskOp`]s through the event graph. Every operation carries a Lamport
+//! clock which is greater than the clock of any operation on the same task
+//! its author had seen, so operations follow the causal order of the event
+//! graph. Concurrent operations are ordered by their clock and then by their
+//! unique id, which gives every node the same total order.
+//!
+//! Each node keeps the full [`OpLog`] of a task, and the task itself is
+//! obtained by replaying the log in that order: single-valued fields are
+//! last-writer-wins registers, tags, assignees and blockers are
+//! last-writer-wins element sets, and comments only grow. Since the
+//! result only depends on the set of known operations, all nodes converge
+//! to the same task regardless of the order operations were delivered in.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use darkfi_serial::{async_trait, deserialize, serialize, SerialDecodable, SerialEncodable};
+use log::debug;
+
+use darkfi::util::time::Timestamp;
+
+use crate::{
+    error::TaudResult,
+    recurrence::Recurrence,
+    task_info::{Comment, TaskEvent, TaskInfo},
+    util::gen_id,
+};
+
+/// Change of a task, replicated through the event graph
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable, PartialEq)]
+pub enum TaskOpKind {
+    /// Create the task with the given initial state
+    Create(TaskInfo),
+    SetTitle(String),
+    SetDesc(String),
+    SetDue(Option<Timestamp>),
+    SetRank(Option<f32>),
+    SetState(String),
+    SetProject(Vec<String>),
+    SetRecurrence(Option<Recurrence>),
+    AddTag(String),
+    RemoveTag(String),
+    AddAssign(String),
+    RemoveAssign(String),
+    AddBlocker(String),
+    RemoveBlocker(String),
+    AddComment(Comment),
+}
+
+impl TaskOpKind {
+    /// Action and content of the task event recorded for this change
+    fn event(&self) -> (&'static str, String) {
+        let opt = |v: Option<String>| v.unwrap_or_else(|| "None".to_string());
+
+        match self {
+            Self::Create(_) => ("add_task", String::new()),
+            Self::SetTitle(title) => ("title", title.clone()),
+            Self::SetDesc(desc) => ("desc", desc.clone()),
+            Self::SetDue(due) => ("due", opt(due.map(|d| d.0.to_string()))),
+            Self::SetRank(rank) => ("rank", opt(rank.map(|r| r.to_string()))),
+            Self::SetState(state) => ("state", state.clone()),
+            Self::SetProject(project) => ("project", project.join(", ")),
+            Self::SetRecurrence(rule) => ("recurrence", opt(rule.map(|r| r.to_string()))),
+            Self::AddTag(tag) => ("tags", format!("+{}", tag)),
+            Self::RemoveTag(tag) => ("tags", format!("-{}", tag)),
+            Self::AddAssign(assign) => ("assign", format!("@{}", assign)),
+            Self::RemoveAssign(assign) => ("assign", format!("-@{}", assign)),
+            Self::AddBlocker(ref_id) => ("blocked_by", format!("+{}", ref_id)),
+            Self::RemoveBlocker(ref_id) => ("blocked_by", format!("-{}", ref_id)),
+            Self::AddComment(comment) => ("comment", comment.content.clone()),
+        }
+    }
+}
+
+/// Single field-level change of a task
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable, PartialEq)]
+pub struct TaskOp {
+    /// Unique id of the operation
+    pub id: String,
+    /// Reference ID of the changed task
+    pub ref_id: String,
+    /// Workspace of the task, replaced by the receiving node with the
+    /// workspace whose key decrypted the operation
+    pub workspace: String,
+    /// Lamport clock of the operation
+    pub clock: u64,
+    pub author: String,
+    pub timestamp: Timestamp,
+    pub kind: TaskOpKind,
+}
+
+impl TaskOp {
+    pub fn new(ref_id: &str, workspace: &str, clock: u64, author: &str, kind: TaskOpKind) -> Self {
+        Self {
+            id: gen_id(30),
+            ref_id: ref_id.into(),
+            workspace: workspace.into(),
+            clock,
+            author: author.into(),
+            timestamp: Timestamp::current_time(),
+            kind,
+        }
+    }
+
+    /// Creation of a task known from a whole snapshot, as stored or
+    /// replicated before operations were introduced. Everything is derived
+    /// from the snapshot itself, so every node builds the same operation
+    /// for it. Snapshots come before any operation, and among themselves
+    /// they are ordered by their last change.
+    pub fn legacy(task: TaskInfo) -> Self {
+        // The workspace is local to each node
+        let mut snapshot = task.clone();
+        snapshot.workspace = String::new();
+        let hash = blake3::hash(&serialize(&snapshot));
+
+        let last_change = task
+            .events
+            .iter()
+            .map(|e| e.timestamp.0)
+            .chain(task.comments.iter().map(|c| c.timestamp.0))
+            .fold(task.created_at.0, u64::max);
+
+        Self {
+            id: format!("{:020}{}", last_change, hash.to_hex()),
+            ref_id: task.ref_id.clone(),
+            workspace: task.workspace.clone(),
+            clock: 0,
+            author: task.owner.clone(),
+            timestamp: task.created_at,
+            kind: TaskOpKind::Create(task),
+        }
+    }
+
+    /// The task event recorded when this operation is applied, if any
+    pub fn task_event(&self) -> Option<TaskEvent> {
+        let (action, content) = self.kind.event();
+        if content.is_empty() {
+            return None
+        }
+
+        Some(TaskEvent {
+            action: action.into(),
+            author: self.author.clone(),
+            content,
+            timestamp: self.timestamp,
+        })
+    }
+
+    /// Apply the operation on top of the given task state
+    fn apply(&self, task: &mut TaskInfo) {
+        let add = |list: &mut Vec<String>, v: &String| add_unique(list, v.clone());
+
+        match &self.kind {
+            TaskOpKind::Create(initial) => {
+                let events = std::mem::take(&mut task.events);
+                let comments = std::mem::take(&mut task.comments);
+                *task = initial.clone();
+                task.ref_id = self.ref_id.clone();
+                // Snapshots already carry the history known so far,
+                // which must not be recorded twice
+                for event in events {
+                    add_unique(&mut task.events, event);
+                }
+                for comment in comments {
+                    add_unique(&mut task.comments, comment);
+                }
+                return
+            }
+            TaskOpKind::SetTitle(title) => task.title = title.clone(),
+            TaskOpKind::SetDesc(desc) => task.desc = desc.clone(),
+            TaskOpKind::SetDue(due) => task.due = *due,
+            TaskOpKind::SetRank(rank) => task.rank = *rank,
+            TaskOpKind::SetState(state) => task.state = state.clone(),
+            TaskOpKind::SetProject(project) => task.project = project.clone(),
+            TaskOpKind::SetRecurrence(rule) => task.recurrence = *rule,
+            TaskOpKind::AddTag(tag) => add(&mut task.tags, tag),
+            TaskOpKind::RemoveTag(tag) => task.tags.retain(|t| t != tag),
+            TaskOpKind::AddAssign(assign) => add(&mut task.assign, assign),
+            TaskOpKind::RemoveAssign(assign) => task.assign.retain(|a| a != assign),
+            TaskOpKind::AddBlocker(ref_id) => add(&mut task.blocked_by, ref_id),
+            TaskOpKind::RemoveBlocker(ref_id) => task.blocked_by.retain(|id| id != ref_id),
+            TaskOpKind::AddComment(comment) => task.comments.push(comment.clone()),
+        }
+
+        if let Some(event) = self.task_event() {
+            task.events.push(event);
+        }
+    }
+}
+
+fn add_unique<T: PartialEq>(list: &mut Vec<T>, v: T) {
+    if !list.contains(&v) {
+        list.push(v)
+    }
+}
+
+/// All known operations on a single task, kept in replay order
+#[derive(Clone, Debug, Default, SerialEncodable, SerialDecodable)]
+pub struct OpLog {
+    ops: Vec<TaskOp>,
+}
+
+impl OpLog {
+    pub fn get_path(ref_id: &str, dataset_path: &Path) -> PathBuf {
+        dataset_path.join("ops").join(ref_id)
+    }
+
+    /// Load the operation log of a task. Tasks stored before operations
+    /// were introduced are seeded with their [`TaskOp::legacy`] creation,
+    /// so their existing state is preserved.
+    pub fn load(ref_id: &str, dataset_path: &Path) -> TaudResult<Self> {
+        debug!(target: "tau", "OpLog::load()");
+        match fs::read(Self::get_path(ref_id, dataset_path)) {
+            Ok(bytes) => Ok(deserialize(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let mut log = Self::default();
+                if let Ok(task) = TaskInfo::load(ref_id, dataset_path) {
+                    log.insert(TaskOp::legacy(task));
+                }
+                Ok(log)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, ref_id: &str, dataset_path: &Path) -> TaudResult<()> {
+        debug!(target: "tau", "OpLog::save()");
+        fs::write(Self::get_path(ref_id, dataset_path), serialize(self))?;
+        Ok(())
+    }
+
+    /// Insert an operation in replay order. Returns `false` if the
+    /// operation was already known.
+    pub fn insert(&mut self, op: TaskOp) -> bool {
+        let pos = self.ops.binary_search_by(|o| (o.clock, &o.id).cmp(&(op.clock, &op.id)));
+        match pos {
+            Ok(_) => false,
+            Err(pos) => {
+                self.ops.insert(pos, op);
+                true
+            }
+        }
+    }
+
+    /// Highest clock of any known operation
+    pub fn max_clock(&self) -> u64 {
+        self.ops.last().map(|op| op.clock).unwrap_or(0)
+    }
+
+    /// Replay the log into the current state of the task. Returns `None`
+    /// until the operation creating the task is known.
+    pub fn materialize(&self, workspace: &str) -> Option<TaskInfo> {
+        let create = self.ops.iter().find(|op| matches!(op.kind, TaskOpKind::Create(_)))?;
+        let TaskOpKind::Create(initial) = &create.kind else { unreachable!() };
+
+        let mut task = initial.clone();
+        task.events.clear();
+        task.comments.clear();
+        for op in self.ops.iter() {
+            op.apply(&mut task);
+        }
+
+        task.workspace = workspace.to_string();
+        Some(task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_info::LegacyTaskInfo;
+
+    #[test]
+    fn concurrent_edits_converge() {
+        let task =
+            TaskInfo::new("ws".into(), "Title", "Desc", "alice", None, None, Timestamp(0)).unwrap();
+        let ref_id = task.ref_id.clone();
+        let op = |clock, author: &str, kind| TaskOp::new(&ref_id, "ws", clock, author, kind);
+
+        let create = op(1, "alice", TaskOpKind::Create(task));
+        let tag = op(2, "alice", TaskOpKind::AddTag("bug".into()));
+        // Concurrent edits by alice and bob, who both saw the tag
+        let alice_title = op(3, "alice", TaskOpKind::SetTitle("Alice's title".into()));
+        let alice_comment = op(3, "alice", TaskOpKind::AddComment(Comment::new("hi", "alice")));
+        let bob_title = op(3, "bob", TaskOpKind::SetTitle("Bob's title".into()));
+        let bob_untag = op(3, "bob", TaskOpKind::RemoveTag("bug".into()));
+        let bob_state = op(3, "bob", TaskOpKind::SetState("start".into()));
+        // Causally after bob's edits
+        let alice_retitle = op(4, "alice", TaskOpKind::SetTitle("Final".into()));
+
+        let ops = vec![
+            create,
+            tag,
+            alice_title,
+            alice_comment,
+            bob_title,
+            bob_untag,
+            bob_state,
+            alice_retitle,
+        ];
+
+        let mut expected = None;
+        // Deliver the operations in many different orders
+        for shift in 0..ops.len() {
+            for reverse in [false, true] {
+                let mut order = ops.clone();
+                order.rotate_left(shift);
+                if reverse {
+                    order.reverse();
+                }
+
+                let mut log = OpLog::default();
+                for op in order {
+                    assert!(log.insert(op.clone()));
+                    assert!(!log.insert(op));
+                }
+
+                let task = log.materialize("ws").unwrap();
+                match &expected {
+                    None => expected = Some(task),
+                    Some(expected) => assert_eq!(&task, expected),
+                }
+            }
+        }
+
+        let task = expected.unwrap();
+        assert_eq!(task.title, "Final");
+        assert_eq!(task.state, "start");
+        assert!(task.tags.is_empty());
+        assert_eq!(task.comments.len(), 1);
+        assert_eq!(task.events.len(), 7);
+    }
+
+    #[test]
+    fn legacy_snapshots_are_deterministic() {
+        let mut task =
+            TaskInfo::new("ws".into(), "Title", "Desc", "alice", None, None, Timestamp(1)).unwrap();
+        task.events.push(TaskEvent {
+            action: "title".into(),
+            author: "alice".into(),
+            content: "Title".into(),
+            timestamp: Timestamp(5),
+        });
+
+        // Nodes storing the task in different workspaces build the same op
+        let mut elsewhere = task.clone();
+        elsewhere.workspace = "other".into();
+        let op = TaskOp::legacy(task.clone());
+        assert_eq!(op.id, TaskOp::legacy(elsewhere).id);
+        assert_eq!(op.clock, 0);
+        assert_eq!(op.timestamp, Timestamp(1));
+
+        // A later snapshot replaces the task without duplicating history
+        let mut later = task.clone();
+        later.title = "New title".into();
+        later.events.push(TaskEvent {
+            action: "title".into(),
+            author: "bob".into(),
+            content: "New title".into(),
+            timestamp: Timestamp(9),
+        });
+        let later_op = TaskOp::legacy(later.clone());
+        assert!(later_op.id > op.id);
+
+        let mut log = OpLog::default();
+        log.insert(later_op);
+        log.insert(op);
+        let materialized = log.materialize("ws").unwrap();
+        assert_eq!(materialized.title, "New title");
+        assert_eq!(materialized.events, later.events);
+    }
+
+    #[test]
+    fn snapshots_in_both_layouts_decode() {
+        let mut task =
+            TaskInfo::new("ws".into(), "Title", "Desc", "alice", None, None, Timestamp(1)).unwrap();
+        let legacy = LegacyTaskInfo {
+            ref_id: task.ref_id.clone(),
+            workspace: task.workspace.clone(),
+            title: task.title.clone(),
+            tags: task.tags.clone(),
+            desc: task.desc.clone(),
+            owner: task.owner.clone(),
+            assign: task.assign.clone(),
+            project: task.project.clone(),
+            due: task.due,
+            rank: task.rank,
+            created_at: task.created_at,
+            state: task.state.clone(),
+            events: task.events.clone(),
+            comments: task.comments.clone(),
+        };
+        assert_eq!(TaskInfo::decode_snapshot(&serialize(&legacy)).unwrap(), task);
+
+        task.blocked_by.push("other".into());
+        assert_eq!(TaskInfo::decode_snapshot(&serialize(&task)).unwrap(), task);
+    }
+
+    #[test]
+    fn missing_create() {
+        let mut log = OpLog::default();
+        log.insert(TaskOp::new("id", "ws", 2, "bob", TaskOpKind::SetTitle("Title".into())));
+        assert!(log.materialize("ws").is_none());
+    }
+}
@@ -42,17 +42,22 @@ use darkfi::{
 };
 
 use taud::{
+    crdt::{OpLog, TaskOp, TaskOpKind},
     error::{to_json_result, TaudError, TaudResult},
     month_tasks::MonthTasks,
+    query::{sort_by_rank, Query, QueryContext},
+    recurrence::Recurrence,
     task_info::{Comment, TaskInfo},
-    util::set_event,
+    util::{gen_id, set_event},
 };
 
 pub struct JsonRpcInterface {
     dataset_path: PathBuf,
-    notify_queue_sender: smol::channel::Sender<TaskInfo>,
+    notify_queue_sender: smol::channel::Sender<TaskOp>,
     nickname: String,
     workspace: Mutex<String>,
+    /// Lamport clock of the last operation created by this node
+    clock: Mutex<u64>,
     workspaces: Arc<HashMap<String, ChaChaBox>>,
     p2p: net::P2pPtr,
     dnet_sub: JsonSubscriber,
@@ -70,6 +75,7 @@ impl RequestHandler for JsonRpcInterface {
             "set_state" => self.set_state(req.params).await,
             "set_comment" => self.set_comment(req.params).await,
             "get_task_by_ref_id" => self.get_task_by_ref_id(req.params).await,
+            "query" => self.query(req.params).await,
             "switch_ws" => self.switch_ws(req.params).await,
             "get_ws" => self.get_ws(req.params).await,
             "export" => self.export_to(req.params).await,
@@ -102,7 +108,7 @@ impl HandlerP2p for JsonRpcInterface {
 impl JsonRpcInterface {
     pub fn new(
         dataset_path: PathBuf,
-        notify_queue_sender: smol::channel::Sender<TaskInfo>,
+        notify_queue_sender: smol::channel::Sender<TaskOp>,
         nickname: String,
         workspaces: Arc<HashMap<String, ChaChaBox>>,
         p2p: net::P2pPtr,
@@ -114,6 +120,7 @@ impl JsonRpcInterface {
             nickname,
             workspace,
             workspaces,
+            clock: Mutex::new(0),
             notify_queue_sender,
             p2p,
             rpc_connections: Mutex::new(HashSet::new()),
@@ -171,7 +178,9 @@ impl JsonRpcInterface {
     //          assign: [..],
     //          project: [..],
     //          "due": ..,
-    //          "rank": ..
+    //          "rank": ..,
+    //          "blocked_by": [..],
+    //          "recurrence": ".."
     //          }],
     //      "id": 1
     //      }
@@ -186,7 +195,10 @@ impl JsonRpcInterface {
 
         let params = params[0].get::<HashMap<String, JsonValue>>().unwrap();
 
-        if params.len() != 9 {
+        // "blocked_by" and "recurrence" are optional
+        let required =
+            params.keys().filter(|k| k.as_str() != "blocked_by" && k.as_str() != "recurrence");
+        if required.count() != 9 {
             return Err(TaudError::InvalidData("Invalid parameters".to_string()))
         }
 
@@ -244,13 +256,41 @@ impl JsonRpcInterface {
             projects
         };
 
+        let blocked_by = match params.get("blocked_by") {
+            None | Some(JsonValue::Null) => vec![],
+            Some(JsonValue::Array(ids)) => {
+                let mut blocked_by = vec![];
+
+                for val in ids.iter() {
+                    if let Some(id) = val.get::<String>() {
+                        blocked_by.push(id.clone());
+                    } else {
+                        return Err(TaudError::InvalidData(
+                            "Invalid parameter \"blocked_by\"".to_string(),
+                        ))
+                    }
+                }
+
+                blocked_by
+            }
+            _ => return Err(TaudError::InvalidData("Invalid parameter \"blocked_by\"".to_string())),
+        };
+
+        let recurrence = match params.get("recurrence") {
+            None | Some(JsonValue::Null) => None,
+            Some(JsonValue::String(rule)) => Some(rule.parse::<Recurrence>()?),
+            _ => return Err(TaudError::InvalidData("Invalid parameter \"recurrence\"".to_string())),
+        };
+
         let created_at = match params["created_at"] {
             JsonValue::Number(numba) => Some(numba as u64),
             _ => return Err(TaudError::InvalidData("Invalid parameter \"created_at\"".to_string())),
         };
 
+        let ws = self.workspace.lock().await.clone();
+
         let mut new_task: TaskInfo = TaskInfo::new(
-            self.workspace.lock().await.clone(),
+            ws.clone(),
             params["title"].get::<String>().unwrap(),
             params["desc"].get::<String>().unwrap(),
             &self.nickname,
@@ -261,8 +301,16 @@ impl JsonRpcInterface {
         new_task.set_project(&projects);
         new_task.set_assign(&assigns);
         new_task.set_tags(&tags);
+        new_task.set_recurrence(recurrence);
 
-        self.notify_queue_sender.send(new_task.clone()).await.map_err(Error::from)?;
+        if !blocked_by.is_empty() {
+            let blocked_by: Vec<String> = blocked_by.iter().map(|id| format!("+{}", id)).collect();
+            new_task.set_blocked_by(&blocked_by);
+            self.check_blockers(&new_task, &new_task.blocked_by, ws.clone())?;
+        }
+
+        let ref_id = new_task.get_ref_id();
+        self.send_ops(&ref_id, &ws, vec![TaskOpKind::Create(new_task)]).await?;
         Ok(JsonValue::Boolean(true))
     }
 
@@ -324,13 +372,14 @@ impl JsonRpcInterface {
 
         let ws = self.workspace.lock().await.clone();
 
-        let task = self.check_params_for_modify(
-            params[0].get::<String>().unwrap(),
+        let ref_id = params[0].get::<String>().unwrap();
+        let ops = self.check_params_for_modify(
+            ref_id,
             params[1].get::<HashMap<String, JsonValue>>().unwrap(),
-            ws,
+            ws.clone(),
         )?;
 
-        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+        self.send_ops(ref_id, &ws, ops).await?;
 
         Ok(JsonValue::Boolean(true))
     }
@@ -353,15 +402,31 @@ impl JsonRpcInterface {
         let state = params[1].get::<String>().unwrap();
         let ws = self.workspace.lock().await.clone();
 
-        let mut task: TaskInfo =
-            self.load_task_by_ref_id(params[0].get::<String>().unwrap(), ws)?;
+        let task: TaskInfo =
+            self.load_task_by_ref_id(params[0].get::<String>().unwrap(), ws.clone())?;
 
         if states.contains(&state.as_str()) {
-            task.set_state(state);
-            set_event(&mut task, "state", &self.nickname, state);
-        }
+            // Stopping a recurring task creates its next occurrence
+            if state == "stop" && task.get_state() != "stop" {
+                if let (Some(recurrence), Some(due)) = (task.recurrence, task.due) {
+                    let now = Timestamp::current_time();
+                    let mut next = task.clone();
+                    next.ref_id = gen_id(30);
+                    next.state = "open".into();
+                    next.created_at = now;
+                    next.due = Some(recurrence.next_due_after(due, now));
+                    next.blocked_by = vec![];
+                    next.events = vec![];
+                    next.comments = vec![];
+                    set_event(&mut next, "recurrence", &self.nickname, &task.ref_id);
+
+                    let next_ref_id = next.get_ref_id();
+                    self.send_ops(&next_ref_id, &ws, vec![TaskOpKind::Create(next)]).await?;
+                }
+            }
 
-        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+            self.send_ops(&task.ref_id, &ws, vec![TaskOpKind::SetState(state.clone())]).await?;
+        }
 
         Ok(JsonValue::Boolean(true))
     }
@@ -382,12 +447,10 @@ impl JsonRpcInterface {
         let comment_content = params[1].get::<String>().unwrap();
 
         let ws = self.workspace.lock().await.clone();
-        let mut task: TaskInfo = self.load_task_by_ref_id(ref_id, ws)?;
+        let task: TaskInfo = self.load_task_by_ref_id(ref_id, ws.clone())?;
 
-        task.set_comment(Comment::new(comment_content, &self.nickname));
-        set_event(&mut task, "comment", &self.nickname, comment_content);
-
-        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+        let comment = Comment::new(comment_content, &self.nickname);
+        self.send_ops(&task.ref_id, &ws, vec![TaskOpKind::AddComment(comment)]).await?;
 
         Ok(JsonValue::Boolean(true))
     }
@@ -411,6 +474,52 @@ impl JsonRpcInterface {
         Ok(task)
     }
 
+    // RPCAPI:
+    // Query active tasks with a filter expression, e.g.
+    // `project:darkfi and state:open and due<7d`. An empty filter matches
+    // every task. Returns the matching tasks sorted by descending rank,
+    // each one extended with the list of task ids it `blocks`.
+    // --> {"jsonrpc": "2.0", "method": "query", "params": ["project:darkfi and due<7d"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [task, ...], "id": 1}
+    async fn query(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::query() params {:?}", params);
+
+        if params.len() > 1 || (params.len() == 1 && !params[0].is_string()) {
+            return Err(TaudError::InvalidData("len of params should be 0 or 1".into()))
+        }
+
+        let query: Query = match params.first() {
+            Some(filter) => filter.get::<String>().unwrap().parse()?,
+            None => "".parse()?,
+        };
+
+        let ws = self.workspace.lock().await.clone();
+        let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, false)?;
+        let ctx = QueryContext::new(&tasks, Timestamp::current_time());
+
+        let mut matched: Vec<TaskInfo> =
+            tasks.iter().filter(|task| query.matches(task, &ctx)).cloned().collect();
+        sort_by_rank(&mut matched);
+
+        let mut result = vec![];
+        for task in matched {
+            let blocks: Vec<JsonValue> = tasks
+                .iter()
+                .filter(|t| t.blocked_by.contains(&task.ref_id))
+                .map(|t| JsonValue::String(t.get_ref_id()))
+                .collect();
+
+            let mut task: JsonValue = (&task).into();
+            if let JsonValue::Object(ref mut map) = task {
+                map.insert("blocks".to_string(), JsonValue::Array(blocks));
+            }
+            result.push(task);
+        }
+
+        Ok(JsonValue::Array(result))
+    }
+
     // RPCAPI:
     // Get all tasks.
     // --> {"jsonrpc": "2.0", "method": "fetch_deactive_tasks", "params": [task_id], "id": 1}
@@ -578,11 +687,28 @@ impl JsonRpcInterface {
                 continue
             }
 
-            self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+            let ref_id = task.get_ref_id();
+            self.send_ops(&ref_id, &ws, vec![TaskOpKind::Create(task)]).await?;
         }
         Ok(JsonValue::Boolean(true))
     }
 
+    /// Broadcast the given changes of a task. Every operation gets a clock
+    /// greater than any operation known for the task and any operation
+    /// previously created by this node, so they follow what we have seen.
+    async fn send_ops(&self, ref_id: &str, ws: &str, kinds: Vec<TaskOpKind>) -> TaudResult<()> {
+        let known = OpLog::load(ref_id, &self.dataset_path)?.max_clock();
+        let mut clock = self.clock.lock().await;
+
+        for kind in kinds {
+            *clock = (*clock).max(known) + 1;
+            let op = TaskOp::new(ref_id, ws, *clock, &self.nickname, kind);
+            self.notify_queue_sender.send(op).await.map_err(Error::from)?;
+        }
+
+        Ok(())
+    }
+
     fn load_task_by_ref_id(&self, task_ref_id: &str, ws: String) -> TaudResult<TaskInfo> {
         let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, false)?;
         let task = tasks.into_iter().find(|t| (t.get_ref_id()) == task_ref_id);
@@ -590,47 +716,78 @@ impl JsonRpcInterface {
         task.ok_or(TaudError::InvalidId)
     }
 
+    /// Make sure the blockers `added` to the given task exist, and that
+    /// they don't make its dependencies form a cycle. Existing blockers
+    /// were validated when they were added, and any new cycle has to go
+    /// through one of the added ones, so only those are checked.
+    fn check_blockers(&self, task: &TaskInfo, added: &[String], ws: String) -> TaudResult<()> {
+        let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, true)?;
+        let mut deps: HashMap<&str, &[String]> =
+            tasks.iter().map(|t| (t.ref_id.as_str(), t.blocked_by.as_slice())).collect();
+        deps.insert(&task.ref_id, &task.blocked_by);
+
+        for id in added.iter() {
+            if !deps.contains_key(id.as_str()) {
+                return Err(TaudError::InvalidData(format!("Unknown blocking task \"{}\"", id)))
+            }
+        }
+
+        // Walk the dependency graph looking for a path back to the task
+        let mut visited = HashSet::new();
+        let mut stack: Vec<&str> = added.iter().map(|id| id.as_str()).collect();
+        while let Some(id) = stack.pop() {
+            if id == task.ref_id {
+                return Err(TaudError::InvalidData("Task dependencies form a cycle".into()))
+            }
+
+            if !visited.insert(id) {
+                continue
+            }
+
+            if let Some(blockers) = deps.get(id) {
+                stack.extend(blockers.iter().map(|id| id.as_str()));
+            }
+        }
+
+        Ok(())
+    }
+
     fn check_params_for_modify(
         &self,
         task_ref_id: &str,
         fields: &HashMap<String, JsonValue>,
         ws: String,
-    ) -> TaudResult<TaskInfo> {
-        let mut task: TaskInfo = self.load_task_by_ref_id(task_ref_id, ws)?;
+    ) -> TaudResult<Vec<TaskOpKind>> {
+        let mut task: TaskInfo = self.load_task_by_ref_id(task_ref_id, ws.clone())?;
+        let mut ops = vec![];
 
         if fields.contains_key("title") {
             let title = fields["title"].get::<String>().unwrap();
             if !title.is_empty() {
-                task.set_title(title);
-                set_event(&mut task, "title", &self.nickname, title);
+                ops.push(TaskOpKind::SetTitle(title.clone()));
             }
         }
 
         if fields.contains_key("desc") {
             let desc = fields["desc"].get::<String>().unwrap();
             if !desc.is_empty() {
-                task.set_desc(desc);
-                set_event(&mut task, "desc", &self.nickname, desc);
+                ops.push(TaskOpKind::SetDesc(desc.clone()));
             }
         }
 
         if fields.contains_key("rank") {
             match fields["rank"] {
-                JsonValue::Null => set_event(&mut task, "rank", &self.nickname, "None"),
-                JsonValue::Number(rank) => {
-                    task.set_rank(Some(rank as f32));
-                    set_event(&mut task, "rank", &self.nickname, &rank.to_string())
-                }
+                JsonValue::Null => ops.push(TaskOpKind::SetRank(None)),
+                JsonValue::Number(rank) => ops.push(TaskOpKind::SetRank(Some(rank as f32))),
                 _ => unreachable!(),
             }
         }
 
         if fields.contains_key("due") {
             match &fields["due"] {
-                JsonValue::Null => set_event(&mut task, "due", &self.nickname, "None"),
+                JsonValue::Null => ops.push(TaskOpKind::SetDue(None)),
                 JsonValue::Number(ts_num) => {
-                    task.set_due(Some(Timestamp(*ts_num as u64)));
-                    set_event(&mut task, "due", &self.nickname, &ts_num.to_string())
+                    ops.push(TaskOpKind::SetDue(Some(Timestamp(*ts_num as u64))))
                 }
                 _ => unreachable!(),
             }
@@ -644,9 +801,12 @@ impl JsonRpcInterface {
                 .map(|x| x.get::<String>().unwrap().clone())
                 .collect();
 
-            if !assign.is_empty() {
-                task.set_assign(&assign);
-                set_event(&mut task, "assign", &self.nickname, &assign.join(", "));
+            for assign in assign.iter() {
+                if let Some(stripped) = assign.strip_prefix("-@") {
+                    ops.push(TaskOpKind::RemoveAssign(stripped.to_string()));
+                } else if let Some(stripped) = assign.strip_prefix('@') {
+                    ops.push(TaskOpKind::AddAssign(stripped.to_string()));
+                }
             }
         }
 
@@ -659,8 +819,7 @@ impl JsonRpcInterface {
                 .collect();
 
             if !project.is_empty() {
-                task.set_project(&project);
-                set_event(&mut task, "project", &self.nickname, &project.join(", "));
+                ops.push(TaskOpKind::SetProject(project));
             }
         }
 
@@ -672,12 +831,61 @@ impl JsonRpcInterface {
                 .map(|x| x.get::<String>().unwrap().clone())
                 .collect();
 
-            if !tags.is_empty() {
-                task.set_tags(&tags);
-                set_event(&mut task, "tags", &self.nickname, &tags.join(", "));
+            for tag in tags.iter() {
+                if let Some(stripped) = tag.strip_prefix('+') {
+                    ops.push(TaskOpKind::AddTag(stripped.to_string()));
+                } else if let Some(stripped) = tag.strip_prefix('-') {
+                    ops.push(TaskOpKind::RemoveTag(stripped.to_string()));
+                }
             }
         }
 
-        Ok(task)
+        if fields.contains_key("blocked_by") {
+            let Some(blocked_by) = fields["blocked_by"].get::<Vec<JsonValue>>() else {
+                return Err(TaudError::InvalidData("Invalid parameter \"blocked_by\"".into()))
+            };
+
+            let mut ids = vec![];
+            for val in blocked_by.iter() {
+                match val.get::<String>() {
+                    Some(id) if id.len() > 1 && (id.starts_with('+') || id.starts_with('-')) => {
+                        ids.push(id.clone())
+                    }
+                    _ => {
+                        return Err(TaudError::InvalidData(
+                            "Invalid parameter \"blocked_by\"".into(),
+                        ))
+                    }
+                }
+            }
+
+            if !ids.is_empty() {
+                let previous = task.blocked_by.clone();
+                task.set_blocked_by(&ids);
+
+                let added: Vec<String> =
+                    task.blocked_by.iter().filter(|id| !previous.contains(id)).cloned().collect();
+                if !added.is_empty() {
+                    self.check_blockers(&task, &added, ws)?;
+                }
+
+                for id in ids.iter() {
+                    match id.strip_prefix('+') {
+                        Some(stripped) => ops.push(TaskOpKind::AddBlocker(stripped.to_string())),
+                        None => ops.push(TaskOpKind::RemoveBlocker(id[1..].to_string())),
+                    }
+                }
+            }
+        }
+
+        if fields.contains_key("recurrence") {
+            match &fields["recurrence"] {
+                JsonValue::Null => ops.push(TaskOpKind::SetRecurrence(None)),
+                JsonValue::String(rule) => ops.push(TaskOpKind::SetRecurrence(Some(rule.parse()?))),
+                _ => return Err(TaudError::InvalidData("Invalid parameter \"recurrence\"".into())),
+            }
+        }
+
+        Ok(ops)
     }
 }
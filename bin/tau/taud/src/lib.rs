@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod crdt;
 pub mod error;
 pub mod month_tasks;
+pub mod query;
+pub mod recurrence;
 pub mod task_info;
 pub mod util;
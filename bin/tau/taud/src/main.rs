@@ -62,6 +62,7 @@ mod jsonrpc;
 mod settings;
 
 use taud::{
+    crdt::{OpLog, TaskOp, TaskOpKind},
     error::{TaudError, TaudResult},
     task_info::{TaskEvent, TaskInfo},
     util::pipe_write,
@@ -98,8 +99,29 @@ pub struct EncryptedTask {
     payload: String,
 }
 
+/// Version of the task payload carried by event graph events, appended
+/// after the [`EncryptedTask`]. Events without one were created before
+/// task operations were introduced, and carry a whole task snapshot.
+/// Older nodes decode events partially, so they ignore it.
+const TASK_PAYLOAD_VERSION: u8 = 1;
+
+/// Build the content of an event graph event carrying a task operation
+async fn encode_task_event(task: &EncryptedTask) -> Vec<u8> {
+    let mut content = serialize_async(task).await;
+    content.push(TASK_PAYLOAD_VERSION);
+    content
+}
+
+/// Read the encrypted task and its payload version from the content of
+/// an event graph event
+async fn decode_task_event(content: &[u8]) -> TaudResult<(EncryptedTask, u8)> {
+    let (task, len) = deserialize_async_partial(content).await?;
+    let version = content.get(len).copied().unwrap_or(0);
+    Ok((task, version))
+}
+
 fn encrypt_task(
-    task: &TaskInfo,
+    task: &TaskOp,
     chacha_box: &ChaChaBox,
     rng: &mut OsRng,
 ) -> TaudResult<EncryptedTask> {
@@ -118,7 +140,11 @@ fn encrypt_task(
     Ok(EncryptedTask { payload })
 }
 
-fn try_decrypt_task(encrypt_task: &EncryptedTask, chacha_box: &ChaChaBox) -> TaudResult<TaskInfo> {
+fn try_decrypt_task(
+    encrypt_task: &EncryptedTask,
+    version: u8,
+    chacha_box: &ChaChaBox,
+) -> TaudResult<TaskOp> {
     debug!("start decrypting task");
 
     let bytes = match bs58::decode(&encrypt_task.payload).into_vec() {
@@ -142,15 +168,18 @@ fn try_decrypt_task(encrypt_task: &EncryptedTask, chacha_box: &ChaChaBox) -> Tau
     // let nonce = encrypt_task.nonce.as_slice();
     let decrypted_task = chacha_box.decrypt(nonce, message)?;
 
-    let task = deserialize(&decrypted_task)?;
-
-    Ok(task)
+    match version {
+        // Snapshots replicated before task operations were introduced
+        0 => Ok(TaskOp::legacy(TaskInfo::decode_snapshot(&decrypted_task)?)),
+        TASK_PAYLOAD_VERSION => Ok(deserialize(&decrypted_task)?),
+        _ => Err(TaudError::DecryptionError(format!("Unknown task payload version {}", version))),
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn start_sync_loop(
     event_graph: EventGraphPtr,
-    broadcast_rcv: smol::channel::Receiver<TaskInfo>,
+    broadcast_rcv: smol::channel::Receiver<TaskOp>,
     workspaces: Arc<HashMap<String, ChaChaBox>>,
     datastore_path: std::path::PathBuf,
     piped: bool,
@@ -171,7 +200,7 @@ async fn start_sync_loop(
                     info!(target: "tau", "Send the task: ref: {}", tk.ref_id);
                     // Build a DAG event and return it.
                     let event = Event::new(
-                        serialize_async(&encrypted_task).await,
+                        encode_task_event(&encrypted_task).await,
                         event_graph.clone(),
                     )
                     .await;
@@ -206,14 +235,14 @@ async fn start_sync_loop(
                 }
 
                 // Try to deserialize the `Event`'s content into a `Privmsg`
-                let enc_task: EncryptedTask = match deserialize_async_partial(task_event.content()).await {
-                    Ok((v, _)) => v,
+                let (enc_task, version) = match decode_task_event(task_event.content()).await {
+                    Ok(v) => v,
                     Err(e) => {
                         error!("[TAUD] Failed deserializing incoming EncryptedTask event: {}", e);
                         continue
                     }
                 };
-                on_receive_task(&enc_task, &datastore_path, &workspaces, piped)
+                on_receive_task(&enc_task, version, &datastore_path, &workspaces, piped)
                     .await?;
             }
         }
@@ -222,52 +251,48 @@ async fn start_sync_loop(
 
 async fn on_receive_task(
     task: &EncryptedTask,
+    version: u8,
     datastore_path: &Path,
     workspaces: &HashMap<String, ChaChaBox>,
     piped: bool,
 ) -> TaudResult<()> {
     for (workspace, chacha_box) in workspaces.iter() {
-        let task = try_decrypt_task(task, chacha_box);
-        if let Err(e) = task {
+        let op = try_decrypt_task(task, version, chacha_box);
+        if let Err(e) = op {
             debug!("unable to decrypt the task: {}", e);
             continue
         }
 
-        let mut task = task.unwrap();
-        info!(target: "tau", "Save the task: ref: {}", task.ref_id);
-        task.workspace = workspace.clone();
+        let mut op = op.unwrap();
+        info!(target: "tau", "Apply the task operation: ref: {}", op.ref_id);
+        op.workspace = workspace.clone();
+
+        let mut op_log = OpLog::load(&op.ref_id, datastore_path)?;
+        if !op_log.insert(op.clone()) {
+            debug!("task operation {} is already known", op.id);
+            continue
+        }
+        op_log.save(&op.ref_id, datastore_path)?;
+
+        // The task can only be stored once its creation is known
+        let Some(task) = op_log.materialize(workspace) else { continue };
+
         if piped {
-            // if we can't load the task then it's a new task.
-            // otherwise it's a modification.
-            match TaskInfo::load(&task.ref_id, datastore_path) {
-                Ok(loaded_task) => {
-                    let loaded_events = loaded_task.events;
-                    let mut events = task.events.clone();
-                    events.retain(|ev| !loaded_events.contains(ev));
-
-                    let file = "/tmp/tau_pipe";
-                    let mut pipe_write = pipe_write(file)?;
-                    let mut task_clone = task.clone();
-                    task_clone.events = events;
-
-                    let json: JsonValue = (&task_clone).into();
-                    pipe_write.write_all(json.stringify().unwrap().as_bytes())?;
+            // Only the change made by this operation is written out
+            let events = match op.kind {
+                TaskOpKind::Create(_) => {
+                    vec![TaskEvent::new("add_task".to_string(), task.owner.clone(), "".to_string())]
                 }
-                Err(_) => {
-                    let file = "/tmp/tau_pipe";
-                    let mut pipe_write = pipe_write(file)?;
-                    let mut task_clone = task.clone();
-
-                    task_clone.events.push(TaskEvent::new(
-                        "add_task".to_string(),
-                        task_clone.owner.clone(),
-                        "".to_string(),
-                    ));
-
-                    let json: JsonValue = (&task_clone).into();
-                    pipe_write.write_all(json.stringify().unwrap().as_bytes())?;
-                }
-            }
+                _ => op.task_event().into_iter().collect(),
+            };
+
+            let file = "/tmp/tau_pipe";
+            let mut pipe_write = pipe_write(file)?;
+            let mut task_clone = task.clone();
+            task_clone.events = events;
+
+            let json: JsonValue = (&task_clone).into();
+            pipe_write.write_all(json.stringify().unwrap().as_bytes())?;
         }
         task.save(datastore_path)?;
     }
@@ -314,6 +339,7 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
     create_dir_all(datastore_path.clone())?;
     create_dir_all(datastore_path.join("month"))?;
     create_dir_all(datastore_path.join("task"))?;
+    create_dir_all(datastore_path.join("ops"))?;
 
     if settings.generate {
         println!("Generating a new workspace");
@@ -355,7 +381,7 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
 
     info!("Instantiating event DAG");
     let sled_db = sled::open(datastore)?;
-    let p2p = P2p::new(settings.net.into(), executor.clone()).await;
+    let p2p = P2p::new(settings.net.try_into()?, executor.clone()).await;
     let event_graph =
         EventGraph::new(p2p.clone(), sled_db.clone(), "taud_dag", 0, executor.clone()).await?;
 
@@ -369,7 +395,7 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
         })
         .await;
 
-    let (broadcast_snd, broadcast_rcv) = smol::channel::unbounded::<TaskInfo>();
+    let (broadcast_snd, broadcast_rcv) = smol::channel::unbounded::<TaskOp>();
 
     info!(target: "taud", "Starting P2P network");
     p2p.clone().start().await?;
@@ -423,10 +449,10 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
         let event = event_graph.dag_get(event_id).await.unwrap().unwrap();
 
         // Try to deserialize it. (Here we skip errors)
-        let Ok((enc_task, _)) = deserialize_async_partial(event.content()).await else { continue };
+        let Ok((enc_task, version)) = decode_task_event(event.content()).await else { continue };
 
         // Potentially decrypt the privmsg
-        on_receive_task(&enc_task, &datastore_path, &workspaces, false).await.unwrap();
+        on_receive_task(&enc_task, version, &datastore_path, &workspaces, false).await.unwrap();
 
         debug!("Marking event {} as seen", event_id);
         seen_events.insert(event_id.as_bytes(), &[]).unwrap();
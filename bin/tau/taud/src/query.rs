@@ -0,0 +1,637 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Filter expressions over tasks, evaluated by the `query` RPC method.
+//!
+//! A query is made of terms in the form `field<op>value`, which can be
+//! combined with `and`, `or`, `not` and parentheses. Terms separated by
+//! whitespace only are implicitly joined with `and`. An empty query
+//! matches every task.
+//!
+//! ```text
+//! project:darkfi and state:open and due<7d
+//! (assign:upgrayedd or assign:none) and not tag:wontfix
+//! title:"event graph" blocked:false rank>=2
+//! ```
+//!
+//! Supported operators are `:`, `=`, `!=`, `<`, `<=`, `>` and `>=`.
+//! `:` is a case-insensitive substring match for `title` and `desc`, and
+//! otherwise behaves like `=`. For list fields (`tag`, `project`, `assign`
+//! and `blocked_by`), `=` matches if any element is equal to the value,
+//! and `none` matches an empty list.
+//!
+//! `due` and `created` take a unix timestamp, a `YYYY-MM-DD` date or a
+//! duration relative to the current time such as `12h`, `7d`, `2w` or
+//! `-1d`. Comparing them with `:` or `=` matches the same UTC day.
+//! `due` and `rank` also accept `none`. `blocked` takes `true` or `false`
+//! and tells if the task is waiting on tasks which are not stopped yet.
+
+use std::{collections::HashSet, iter::Peekable, str::FromStr, vec::IntoIter};
+
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use darkfi::util::time::Timestamp;
+
+use crate::{
+    error::{TaudError, TaudResult},
+    task_info::TaskInfo,
+};
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Title,
+    Desc,
+    Tag,
+    Project,
+    Assign,
+    Owner,
+    State,
+    RefId,
+    Due,
+    Rank,
+    Created,
+    Blocked,
+    BlockedBy,
+}
+
+impl FromStr for Field {
+    type Err = TaudError;
+
+    fn from_str(s: &str) -> TaudResult<Self> {
+        let field = match s.to_lowercase().as_str() {
+            "title" => Self::Title,
+            "desc" => Self::Desc,
+            "tag" | "tags" => Self::Tag,
+            "project" => Self::Project,
+            "assign" => Self::Assign,
+            "owner" => Self::Owner,
+            "state" => Self::State,
+            "ref_id" | "id" => Self::RefId,
+            "due" => Self::Due,
+            "rank" => Self::Rank,
+            "created" | "created_at" => Self::Created,
+            "blocked" => Self::Blocked,
+            "blocked_by" => Self::BlockedBy,
+            _ => return Err(TaudError::InvalidData(format!("Unknown query field \"{}\"", s))),
+        };
+
+        Ok(field)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Match,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    const fn is_ordering(&self) -> bool {
+        matches!(self, Self::Lt | Self::Le | Self::Gt | Self::Ge)
+    }
+
+    fn compare<T: PartialOrd>(&self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Match | Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// Point in time given in a query term
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Time {
+    /// Seconds relative to the time the query is evaluated
+    Relative(i64),
+    /// Unix timestamp
+    Absolute(u64),
+}
+
+impl Time {
+    fn resolve(&self, now: Timestamp) -> u64 {
+        match self {
+            Self::Relative(secs) => (now.0 as i64).saturating_add(*secs).max(0) as u64,
+            Self::Absolute(ts) => *ts,
+        }
+    }
+}
+
+impl FromStr for Time {
+    type Err = TaudError;
+
+    fn from_str(s: &str) -> TaudResult<Self> {
+        if let Ok(ts) = s.parse::<u64>() {
+            return Ok(Self::Absolute(ts))
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            let date = date.and_hms_opt(0, 0, 0).unwrap();
+            return Ok(Self::Absolute(Utc.from_utc_datetime(&date).timestamp() as u64))
+        }
+
+        let err = || TaudError::InvalidData(format!("Invalid time \"{}\" in query", s));
+
+        let Some(unit) = s.chars().last() else { return Err(err()) };
+        let unit = match unit {
+            'h' => HOUR,
+            'd' => DAY,
+            'w' => 7 * DAY,
+            _ => return Err(err()),
+        };
+
+        let amount = s[..s.len() - 1].parse::<i64>().map_err(|_| err())?;
+        Ok(Self::Relative(amount.checked_mul(unit).ok_or_else(err)?))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Text(String),
+    Time(Option<Time>),
+    Number(Option<f32>),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Term {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+impl Term {
+    fn parse(word: &str) -> TaudResult<Self> {
+        let Some(pos) = word.find([':', '=', '!', '<', '>']) else {
+            return Err(TaudError::InvalidData(format!("Invalid query term \"{}\"", word)))
+        };
+
+        let field: Field = word[..pos].parse()?;
+        let rest = &word[pos..];
+
+        let (op, value) = [
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            (":", Op::Match),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ]
+        .into_iter()
+        .find_map(|(token, op)| rest.strip_prefix(token).map(|value| (op, value)))
+        .ok_or_else(|| TaudError::InvalidData(format!("Invalid query term \"{}\"", word)))?;
+
+        let invalid_op =
+            || TaudError::InvalidData(format!("Operator not supported for field in \"{}\"", word));
+
+        let is_none = value.eq_ignore_ascii_case("none");
+
+        let value = match field {
+            Field::Due | Field::Created => {
+                if is_none && field == Field::Due {
+                    if op.is_ordering() {
+                        return Err(invalid_op())
+                    }
+                    Value::Time(None)
+                } else {
+                    Value::Time(Some(value.parse()?))
+                }
+            }
+
+            Field::Rank => {
+                if is_none {
+                    if op.is_ordering() {
+                        return Err(invalid_op())
+                    }
+                    Value::Number(None)
+                } else {
+                    let rank = value.parse::<f32>().map_err(|_| {
+                        TaudError::InvalidData(format!("Invalid rank in \"{}\"", word))
+                    })?;
+                    Value::Number(Some(rank))
+                }
+            }
+
+            Field::Blocked => {
+                if op.is_ordering() {
+                    return Err(invalid_op())
+                }
+                match value.to_lowercase().as_str() {
+                    "true" | "yes" => Value::Bool(true),
+                    "false" | "no" => Value::Bool(false),
+                    _ => {
+                        return Err(TaudError::InvalidData(format!(
+                            "Invalid boolean in \"{}\"",
+                            word
+                        )))
+                    }
+                }
+            }
+
+            _ => {
+                if op.is_ordering() {
+                    return Err(invalid_op())
+                }
+                Value::Text(value.to_lowercase())
+            }
+        };
+
+        Ok(Self { field, op, value })
+    }
+
+    fn matches(&self, task: &TaskInfo, ctx: &QueryContext) -> bool {
+        match (&self.value, self.field) {
+            (Value::Text(value), Field::Title | Field::Desc) => {
+                let text = match self.field {
+                    Field::Title => task.title.to_lowercase(),
+                    _ => task.desc.to_lowercase(),
+                };
+                match self.op {
+                    Op::Match => text.contains(value.as_str()),
+                    _ => self.op.compare(text.as_str(), value.as_str()),
+                }
+            }
+
+            (Value::Text(value), Field::Owner | Field::State | Field::RefId) => {
+                let text = match self.field {
+                    Field::Owner => task.owner.to_lowercase(),
+                    Field::State => task.state.to_lowercase(),
+                    _ => task.ref_id.to_lowercase(),
+                };
+                self.op.compare(text.as_str(), value.as_str())
+            }
+
+            (
+                Value::Text(value),
+                Field::Tag | Field::Project | Field::Assign | Field::BlockedBy,
+            ) => {
+                let list = match self.field {
+                    Field::Tag => &task.tags,
+                    Field::Project => &task.project,
+                    Field::Assign => &task.assign,
+                    _ => &task.blocked_by,
+                };
+                let found = if value == "none" {
+                    list.is_empty()
+                } else {
+                    list.iter().any(|x| x.to_lowercase() == *value)
+                };
+                match self.op {
+                    Op::Ne => !found,
+                    _ => found,
+                }
+            }
+
+            (Value::Time(value), Field::Due | Field::Created) => {
+                let ts = match self.field {
+                    Field::Due => task.due,
+                    _ => Some(task.created_at),
+                };
+
+                let (Some(ts), Some(value)) = (ts, value) else {
+                    // At least one side is `none`
+                    let equal = ts.is_none() && value.is_none();
+                    return match self.op {
+                        Op::Ne => !equal,
+                        Op::Match | Op::Eq => equal,
+                        _ => false,
+                    }
+                };
+
+                let value = value.resolve(ctx.now);
+                match self.op {
+                    Op::Match | Op::Eq | Op::Ne => {
+                        let same_day = ts.0 / DAY as u64 == value / DAY as u64;
+                        same_day == (self.op != Op::Ne)
+                    }
+                    _ => self.op.compare(ts.0, value),
+                }
+            }
+
+            (Value::Number(value), Field::Rank) => match (task.rank, value) {
+                (Some(rank), Some(value)) => self.op.compare(rank, *value),
+                (rank, value) => {
+                    let equal = rank.is_none() && value.is_none();
+                    match self.op {
+                        Op::Ne => !equal,
+                        Op::Match | Op::Eq => equal,
+                        _ => false,
+                    }
+                }
+            },
+
+            (Value::Bool(value), Field::Blocked) => {
+                let blocked = ctx.is_blocked(task);
+                match self.op {
+                    Op::Ne => blocked != *value,
+                    _ => blocked == *value,
+                }
+            }
+
+            // Values are always built to match their field in `Term::parse()`
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    All,
+    Term(Term),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, task: &TaskInfo, ctx: &QueryContext) -> bool {
+        match self {
+            Self::All => true,
+            Self::Term(term) => term.matches(task, ctx),
+            Self::Not(expr) => !expr.matches(task, ctx),
+            Self::And(lhs, rhs) => lhs.matches(task, ctx) && rhs.matches(task, ctx),
+            Self::Or(lhs, rhs) => lhs.matches(task, ctx) || rhs.matches(task, ctx),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    /// A keyword or a term. Quoted words are never treated as keywords.
+    Word(String, bool),
+}
+
+fn tokenize(s: &str) -> TaudResult<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                let mut quoted = false;
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break
+                    }
+
+                    chars.next();
+                    if c != '"' {
+                        word.push(c);
+                        continue
+                    }
+
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => word.push(c),
+                            None => {
+                                return Err(TaudError::InvalidData(
+                                    "Unterminated quote in query".into(),
+                                ))
+                            }
+                        }
+                    }
+                }
+
+                tokens.push(Token::Word(word, quoted));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over query tokens
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn is_keyword(&mut self, keyword: &str) -> bool {
+        matches!(self.tokens.peek(), Some(Token::Word(w, false)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse_or(&mut self) -> TaudResult<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.is_keyword("or") {
+            self.tokens.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> TaudResult<Expr> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            if self.is_keyword("and") {
+                self.tokens.next();
+            } else if self.tokens.peek().is_none() ||
+                self.tokens.peek() == Some(&Token::RParen) ||
+                self.is_keyword("or")
+            {
+                break
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> TaudResult<Expr> {
+        if self.is_keyword("not") {
+            self.tokens.next();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)))
+        }
+
+        match self.tokens.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                if self.tokens.next() != Some(Token::RParen) {
+                    return Err(TaudError::InvalidData("Unbalanced parentheses in query".into()))
+                }
+                Ok(expr)
+            }
+            Some(Token::Word(word, _)) => Ok(Expr::Term(Term::parse(&word)?)),
+            Some(Token::RParen) => {
+                Err(TaudError::InvalidData("Unbalanced parentheses in query".into()))
+            }
+            None => Err(TaudError::InvalidData("Unexpected end of query".into())),
+        }
+    }
+}
+
+/// Parsed task filter expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    expr: Expr,
+}
+
+impl Query {
+    /// Check if the given task matches the query
+    pub fn matches(&self, task: &TaskInfo, ctx: &QueryContext) -> bool {
+        self.expr.matches(task, ctx)
+    }
+}
+
+impl FromStr for Query {
+    type Err = TaudError;
+
+    fn from_str(s: &str) -> TaudResult<Self> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Ok(Self { expr: Expr::All })
+        }
+
+        let mut parser = Parser { tokens: tokens.into_iter().peekable() };
+        let expr = parser.parse_or()?;
+
+        if parser.tokens.next().is_some() {
+            return Err(TaudError::InvalidData("Unbalanced parentheses in query".into()))
+        }
+
+        Ok(Self { expr })
+    }
+}
+
+/// State a query is evaluated against
+pub struct QueryContext {
+    /// Time used to resolve relative durations
+    now: Timestamp,
+    /// Reference IDs of tasks which are not stopped
+    unfinished: HashSet<String>,
+}
+
+impl QueryContext {
+    /// Create a new context over the given set of tasks
+    pub fn new(tasks: &[TaskInfo], now: Timestamp) -> Self {
+        let unfinished =
+            tasks.iter().filter(|t| t.state != "stop").map(|t| t.ref_id.clone()).collect();
+        Self { now, unfinished }
+    }
+
+    /// A task is blocked while any of the tasks it depends on is not stopped
+    pub fn is_blocked(&self, task: &TaskInfo) -> bool {
+        task.blocked_by.iter().any(|id| self.unfinished.contains(id))
+    }
+}
+
+/// Sort tasks by descending rank, placing tasks without rank last
+pub fn sort_by_rank(tasks: &mut [TaskInfo]) {
+    tasks.sort_by(|a, b| match (a.rank, b.rank) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(
+        title: &str,
+        project: &str,
+        state: &str,
+        due: Option<u64>,
+        rank: Option<f32>,
+    ) -> TaskInfo {
+        let mut task =
+            TaskInfo::new("ws".into(), title, "", "alice", None, rank, Timestamp(0)).unwrap();
+        task.project = vec![project.into()];
+        task.state = state.into();
+        task.due = due.map(Timestamp);
+        task
+    }
+
+    #[test]
+    fn parse_query() {
+        assert!("".parse::<Query>().is_ok());
+        assert!("project:darkfi and (state:open or state:start)".parse::<Query>().is_ok());
+        assert!("title:\"two words\" not tag:wontfix".parse::<Query>().is_ok());
+
+        assert!("foo:bar".parse::<Query>().is_err());
+        assert!("title<abc".parse::<Query>().is_err());
+        assert!("due<none".parse::<Query>().is_err());
+        assert!("(state:open".parse::<Query>().is_err());
+        assert!("state:open)".parse::<Query>().is_err());
+        assert!("state:open and".parse::<Query>().is_err());
+        assert!("title:\"open".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn evaluate_query() {
+        let now = Timestamp(100 * DAY as u64);
+        let mut tasks = vec![
+            task("Fix sync", "darkfi", "open", Some(now.0 + 2 * DAY as u64), Some(1.0)),
+            task("Write docs", "darkfi", "start", Some(now.0 + 30 * DAY as u64), Some(5.0)),
+            task("Buy milk", "home", "open", None, None),
+        ];
+        tasks[2].blocked_by = vec![tasks[0].ref_id.clone()];
+        let ctx = QueryContext::new(&tasks, now);
+
+        let select = |q: &str| -> Vec<String> {
+            let query: Query = q.parse().unwrap();
+            tasks.iter().filter(|t| query.matches(t, &ctx)).map(|t| t.title.clone()).collect()
+        };
+
+        assert_eq!(select("project:darkfi and state:open and due<7d"), vec!["Fix sync"]);
+        assert_eq!(select("project:darkfi state:start"), vec!["Write docs"]);
+        assert_eq!(select("due:none or rank>=5"), vec!["Write docs", "Buy milk"]);
+        assert_eq!(select("not project:DARKFI"), vec!["Buy milk"]);
+        assert_eq!(select("title:\"fix s\""), vec!["Fix sync"]);
+        assert_eq!(select("blocked:true"), vec!["Buy milk"]);
+        assert_eq!(select("blocked_by:none and (rank<2 or rank:none)"), vec!["Fix sync"]);
+        assert_eq!(select("").len(), 3);
+
+        // Stopping the blocker unblocks the task
+        tasks[0].state = "stop".into();
+        let ctx = QueryContext::new(&tasks, now);
+        let query: Query = "blocked:false".parse().unwrap();
+        assert!(query.matches(&tasks[2], &ctx));
+
+        sort_by_rank(&mut tasks);
+        let titles: Vec<_> = tasks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, vec!["Write docs", "Fix sync", "Buy milk"]);
+    }
+}
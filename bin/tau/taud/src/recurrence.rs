@@ -0,0 +1,186 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt, str::FromStr};
+
+use chrono::{Months, TimeZone, Utc};
+use darkfi_serial::{async_trait, SerialDecodable, SerialEncodable};
+
+use darkfi::util::time::Timestamp;
+
+use crate::error::{TaudError, TaudResult};
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, SerialEncodable, SerialDecodable, PartialEq, Eq)]
+pub enum RecurrenceUnit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// Recurrence rule for the due date of a task. When a recurring task is
+/// stopped, a new task is created with the due date moved forward by the
+/// given interval.
+///
+/// Rules are written as `daily`, `weekly`, `monthly`, `yearly`, or as
+/// an interval followed by a unit, e.g. `3d`, `2w`, `1m` or `1y`.
+#[derive(Clone, Copy, Debug, SerialEncodable, SerialDecodable, PartialEq, Eq)]
+pub struct Recurrence {
+    pub interval: u32,
+    pub unit: RecurrenceUnit,
+}
+
+impl Recurrence {
+    /// Compute the next due date following the given one.
+    /// Monthly and yearly recurrences keep the day of the month, clamped to
+    /// the last day of shorter months.
+    pub fn next_due(&self, due: Timestamp) -> Timestamp {
+        let interval = self.interval as u64;
+        match self.unit {
+            RecurrenceUnit::Day => Timestamp(due.0 + interval * DAY),
+            RecurrenceUnit::Week => Timestamp(due.0 + interval * 7 * DAY),
+            RecurrenceUnit::Month | RecurrenceUnit::Year => {
+                let months = match self.unit {
+                    RecurrenceUnit::Year => self.interval * 12,
+                    _ => self.interval,
+                };
+
+                let date = Utc.timestamp_opt(due.0 as i64, 0).unwrap();
+                let next = date.checked_add_months(Months::new(months)).unwrap_or(date);
+                Timestamp(next.timestamp() as u64)
+            }
+        }
+    }
+
+    /// Compute the first due date after `now`, starting from `due`.
+    /// This skips occurrences that were missed, e.g. when a daily task
+    /// gets stopped a week after its due date.
+    pub fn next_due_after(&self, due: Timestamp, now: Timestamp) -> Timestamp {
+        let mut next = self.next_due(due);
+        while next.0 <= now.0 {
+            let following = self.next_due(next);
+            // Guard against rules which can't make progress
+            if following.0 <= next.0 {
+                break
+            }
+            next = following;
+        }
+        next
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = match self.unit {
+            RecurrenceUnit::Day => "d",
+            RecurrenceUnit::Week => "w",
+            RecurrenceUnit::Month => "m",
+            RecurrenceUnit::Year => "y",
+        };
+        write!(f, "{}{}", self.interval, unit)
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = TaudError;
+
+    fn from_str(s: &str) -> TaudResult<Self> {
+        let s = s.trim().to_lowercase();
+        let (interval, unit) = match s.as_str() {
+            "daily" => (1, RecurrenceUnit::Day),
+            "weekly" => (1, RecurrenceUnit::Week),
+            "monthly" => (1, RecurrenceUnit::Month),
+            "yearly" => (1, RecurrenceUnit::Year),
+            _ => {
+                let Some(unit) = s.chars().last() else {
+                    return Err(TaudError::InvalidData("Empty recurrence rule".into()))
+                };
+
+                let unit = match unit {
+                    'd' => RecurrenceUnit::Day,
+                    'w' => RecurrenceUnit::Week,
+                    'm' => RecurrenceUnit::Month,
+                    'y' => RecurrenceUnit::Year,
+                    _ => {
+                        return Err(TaudError::InvalidData(format!(
+                            "Invalid recurrence unit in \"{}\"",
+                            s
+                        )))
+                    }
+                };
+
+                let interval = match s[..s.len() - 1].parse::<u32>() {
+                    Ok(v) if v > 0 => v,
+                    _ => {
+                        return Err(TaudError::InvalidData(format!(
+                            "Invalid recurrence interval in \"{}\"",
+                            s
+                        )))
+                    }
+                };
+
+                (interval, unit)
+            }
+        };
+
+        Ok(Self { interval, unit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    fn day_of_month(ts: Timestamp) -> u32 {
+        Utc.timestamp_opt(ts.0 as i64, 0).unwrap().day()
+    }
+
+    #[test]
+    fn parse_recurrence() {
+        let r: Recurrence = "weekly".parse().unwrap();
+        assert_eq!(r, Recurrence { interval: 1, unit: RecurrenceUnit::Week });
+
+        let r: Recurrence = "3d".parse().unwrap();
+        assert_eq!(r, Recurrence { interval: 3, unit: RecurrenceUnit::Day });
+        assert_eq!(r.to_string(), "3d");
+
+        assert!("0d".parse::<Recurrence>().is_err());
+        assert!("3x".parse::<Recurrence>().is_err());
+        assert!("".parse::<Recurrence>().is_err());
+    }
+
+    #[test]
+    fn next_due() {
+        // 2023-01-31 12:00:00 UTC
+        let due = Timestamp(1675166400);
+
+        let daily: Recurrence = "daily".parse().unwrap();
+        assert_eq!(daily.next_due(due), Timestamp(due.0 + DAY));
+
+        // Monthly recurrence is clamped to the end of February
+        let monthly: Recurrence = "monthly".parse().unwrap();
+        assert_eq!(day_of_month(monthly.next_due(due)), 28);
+
+        // Missed occurrences are skipped
+        let now = Timestamp(due.0 + 10 * DAY + 1);
+        assert_eq!(daily.next_due_after(due, now), Timestamp(due.0 + 11 * DAY));
+    }
+}
@@ -23,7 +23,7 @@ use std::{
     str::FromStr,
 };
 
-use darkfi_serial::{async_trait, SerialDecodable, SerialEncodable};
+use darkfi_serial::{async_trait, deserialize, SerialDecodable, SerialEncodable};
 use log::debug;
 use tinyjson::JsonValue;
 
@@ -38,6 +38,7 @@ use darkfi::{
 use crate::{
     error::{TaudError, TaudResult},
     month_tasks::MonthTasks,
+    recurrence::Recurrence,
     util::gen_id,
 };
 
@@ -142,9 +143,9 @@ impl From<&JsonValue> for TaskEvent {
 
 #[derive(Clone, Debug, SerialDecodable, SerialEncodable, PartialEq, Eq)]
 pub struct Comment {
-    content: String,
-    author: String,
-    timestamp: Timestamp,
+    pub content: String,
+    pub author: String,
+    pub timestamp: Timestamp,
 }
 
 impl std::fmt::Display for Comment {
@@ -200,6 +201,53 @@ pub struct TaskInfo {
     pub state: String,
     pub events: Vec<TaskEvent>,
     pub comments: Vec<Comment>,
+    /// Reference IDs of tasks which have to be stopped before this one
+    pub blocked_by: Vec<String>,
+    /// Rule used to create the next occurrence of the task when stopped
+    pub recurrence: Option<Recurrence>,
+}
+
+/// Encoding of [`TaskInfo`] used by nodes from before task dependencies
+/// and recurrence rules, which still replicate tasks in this layout.
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable, PartialEq)]
+pub struct LegacyTaskInfo {
+    pub ref_id: String,
+    pub workspace: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub desc: String,
+    pub owner: String,
+    pub assign: Vec<String>,
+    pub project: Vec<String>,
+    pub due: Option<Timestamp>,
+    pub rank: Option<f32>,
+    pub created_at: Timestamp,
+    pub state: String,
+    pub events: Vec<TaskEvent>,
+    pub comments: Vec<Comment>,
+}
+
+impl From<LegacyTaskInfo> for TaskInfo {
+    fn from(task: LegacyTaskInfo) -> Self {
+        Self {
+            ref_id: task.ref_id,
+            workspace: task.workspace,
+            title: task.title,
+            tags: task.tags,
+            desc: task.desc,
+            owner: task.owner,
+            assign: task.assign,
+            project: task.project,
+            due: task.due,
+            rank: task.rank,
+            created_at: task.created_at,
+            state: task.state,
+            events: task.events,
+            comments: task.comments,
+            blocked_by: vec![],
+            recurrence: None,
+        }
+    }
 }
 
 impl From<&TaskInfo> for JsonValue {
@@ -234,6 +282,15 @@ impl From<&TaskInfo> for JsonValue {
         let events: Vec<JsonValue> = task.events.iter().map(|x| x.clone().into()).collect();
         let comments: Vec<JsonValue> = task.comments.iter().map(|x| x.clone().into()).collect();
 
+        let blocked_by: Vec<JsonValue> =
+            task.blocked_by.iter().map(|x| JsonValue::String(x.clone())).collect();
+
+        let recurrence = if let Some(recurrence) = task.recurrence {
+            JsonValue::String(recurrence.to_string())
+        } else {
+            JsonValue::Null
+        };
+
         JsonValue::Object(HashMap::from([
             ("ref_id".to_string(), ref_id),
             ("workspace".to_string(), workspace),
@@ -249,6 +306,8 @@ impl From<&TaskInfo> for JsonValue {
             ("state".to_string(), state),
             ("events".to_string(), JsonValue::Array(events)),
             ("comments".to_string(), JsonValue::Array(comments)),
+            ("blocked_by".to_string(), JsonValue::Array(blocked_by)),
+            ("recurrence".to_string(), recurrence),
         ]))
     }
 }
@@ -286,6 +345,21 @@ impl From<JsonValue> for TaskInfo {
         let events: Vec<TaskEvent> = events.iter().map(|x| x.into()).collect();
         let comments: Vec<Comment> = comments.iter().map(|x| (*x).clone().into()).collect();
 
+        // Tasks saved by older versions don't have these fields
+        let map = value.get::<HashMap<String, JsonValue>>().unwrap();
+
+        let blocked_by = match map.get("blocked_by") {
+            Some(JsonValue::Array(ids)) => {
+                ids.iter().map(|x| x.get::<String>().unwrap().clone()).collect()
+            }
+            _ => vec![],
+        };
+
+        let recurrence = match map.get("recurrence") {
+            Some(JsonValue::String(rule)) => rule.parse().ok(),
+            _ => None,
+        };
+
         TaskInfo {
             ref_id: value["ref_id"].get::<String>().unwrap().clone(),
             workspace: value["workspace"].get::<String>().unwrap().clone(),
@@ -301,6 +375,8 @@ impl From<JsonValue> for TaskInfo {
             state: value["state"].get::<String>().unwrap().clone(),
             events,
             comments,
+            blocked_by,
+            recurrence,
         }
     }
 }
@@ -339,9 +415,21 @@ impl TaskInfo {
             state: "open".into(),
             comments: vec![],
             events: vec![],
+            blocked_by: vec![],
+            recurrence: None,
         })
     }
 
+    /// Decode a whole task snapshot replicated by another node, in either
+    /// the current or the [`LegacyTaskInfo`] layout. Each layout fails to
+    /// decode the other, as one lacks the trailing fields of the other.
+    pub fn decode_snapshot(bytes: &[u8]) -> TaudResult<Self> {
+        match deserialize::<Self>(bytes) {
+            Ok(task) => Ok(task),
+            Err(_) => Ok(deserialize::<LegacyTaskInfo>(bytes)?.into()),
+        }
+    }
+
     pub fn load(ref_id: &str, dataset_path: &Path) -> TaudResult<Self> {
         debug!(target: "tau", "TaskInfo::load()");
         let task = load_json_file(&Self::get_path(ref_id, dataset_path))?;
@@ -414,6 +502,24 @@ impl TaskInfo {
         }
     }
 
+    pub fn set_blocked_by(&mut self, ref_ids: &[String]) {
+        debug!(target: "tau", "TaskInfo::set_blocked_by()");
+        for ref_id in ref_ids.iter() {
+            let stripped = &ref_id[1..];
+            if ref_id.starts_with('+') && !self.blocked_by.contains(&stripped.to_string()) {
+                self.blocked_by.push(stripped.to_string());
+            }
+            if ref_id.starts_with('-') {
+                self.blocked_by.retain(|id| id != stripped);
+            }
+        }
+    }
+
+    pub fn set_recurrence(&mut self, r: Option<Recurrence>) {
+        debug!(target: "tau", "TaskInfo::set_recurrence()");
+        self.recurrence = r;
+    }
+
     pub fn set_assign(&mut self, assigns: &[String]) {
         debug!(target: "tau", "TaskInfo::set_assign()");
         // self.assign = assigns.to_owned();
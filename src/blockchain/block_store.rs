@@ -18,9 +18,10 @@
 
 use darkfi_sdk::{
     blockchain::Slot,
+    bridgetree::Hashable,
     crypto::{
         schnorr::{SchnorrSecret, Signature},
-        SecretKey,
+        MerkleNode, MerkleTree, SecretKey,
     },
     pasta::{group::ff::FromUniformBytes, pallas},
 };
@@ -129,11 +130,7 @@ impl BlockInfo {
 
     /// Append a transaction to the block. Also adds it to the Merkle tree.
     pub fn append_tx(&mut self, tx: Transaction) -> Result<()> {
-        let mut buf = [0u8; 64];
-        buf[..blake3::OUT_LEN].copy_from_slice(tx.hash()?.as_bytes());
-        let leaf = pallas::Base::from_uniform_bytes(&buf);
-
-        self.header.tree.append(leaf.into());
+        self.header.tree.append(tx_leaf(&tx)?);
         self.txs.push(tx);
 
         Ok(())
@@ -149,6 +146,36 @@ impl BlockInfo {
         Ok(())
     }
 
+    /// Verify the block header Merkle tree root corresponds to
+    /// the block transactions.
+    pub fn verify_txs_root(&self) -> Result<bool> {
+        let mut tree = MerkleTree::new(1);
+        for tx in &self.txs {
+            tree.append(tx_leaf(tx)?);
+        }
+
+        Ok(tree.root(0) == self.header.tree.root(0))
+    }
+
+    /// Generate the inclusion proof of provided transaction hash
+    /// against the block header Merkle tree root.
+    /// Returns `None` if the transaction is not part of the block.
+    pub fn tx_inclusion_proof(&self, tx_hash: &blake3::Hash) -> Result<Option<TxInclusionProof>> {
+        let mut tree = MerkleTree::new(1);
+        let mut position = None;
+        for tx in &self.txs {
+            tree.append(tx_leaf(tx)?);
+            if position.is_none() && &tx.hash()? == tx_hash {
+                position = tree.mark();
+            }
+        }
+
+        let Some(position) = position else { return Ok(None) };
+        let path = tree.witness(position, 0).unwrap();
+
+        Ok(Some(TxInclusionProof { height: self.header.height, position: position.into(), path }))
+    }
+
     /// Sign block header using provided secret key
     // TODO: sign more stuff?
     pub fn sign(&mut self, secret_key: &SecretKey) -> Result<()> {
@@ -158,6 +185,46 @@ impl BlockInfo {
     }
 }
 
+/// Compute the Merkle tree leaf of provided transaction
+fn tx_leaf(tx: &Transaction) -> Result<MerkleNode> {
+    let mut buf = [0u8; 64];
+    buf[..blake3::OUT_LEN].copy_from_slice(tx.hash()?.as_bytes());
+    Ok(pallas::Base::from_uniform_bytes(&buf).into())
+}
+
+/// Proof that a transaction is included in a block, verified
+/// against the Merkle tree root of the block header.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct TxInclusionProof {
+    /// Height of the block containing the transaction
+    pub height: u64,
+    /// Position of the transaction leaf in the Merkle tree
+    pub position: u64,
+    /// Merkle path from the transaction leaf to the root
+    pub path: Vec<MerkleNode>,
+}
+
+impl TxInclusionProof {
+    /// Verify provided transaction is included in the block of provided header
+    pub fn verify(&self, header: &Header, tx: &Transaction) -> Result<bool> {
+        if self.height != header.height {
+            return Ok(false)
+        }
+
+        let mut current = tx_leaf(tx)?;
+        for (level, sibling) in self.path.iter().enumerate() {
+            let level = level as u8;
+            current = if self.position & (1 << level) == 0 {
+                MerkleNode::combine(level.into(), &current, sibling)
+            } else {
+                MerkleNode::combine(level.into(), sibling, &current)
+            };
+        }
+
+        Ok(header.tree.root(0) == Some(current))
+    }
+}
+
 /// [`Block`] sled tree
 const SLED_BLOCK_TREE: &[u8] = b"_blocks";
 
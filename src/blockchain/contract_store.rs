@@ -16,7 +16,7 @@ r* This program is distributed in the hope that it will be useful,
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::io::Cursor;
+use std::{io::Cursor, ops::Bound};
 
 use darkfi_sdk::crypto::ContractId;
 use darkfi_serial::{deserialize, serialize};
@@ -28,40 +28,125 @@ use crate::{
     Error, Result,
 };
 
-use super::SledDbOverlayPtr;
+use super::{journal::StateDiff, SledDbOverlayPtr};
 
 const SLED_CONTRACTS_TREE: &[u8] = b"_contracts";
 const SLED_BINCODE_TREE: &[u8] = b"_wasm_bincode";
+const SLED_BINCODE_VERSIONS_TREE: &[u8] = b"_wasm_bincode_versions";
+const SLED_BINCODE_HEIGHTS_TREE: &[u8] = b"_wasm_bincode_heights";
 
 /// The hardcoded db name for the zkas circuits database tree
 pub const SMART_CONTRACT_ZKAS_DB_NAME: &str = "_zkas";
 
 /// The `WasmStore` is a `sled` tree that stores the wasm bincode for deployed
-/// contracts.
+/// contracts, along with all the versions of it that were ever deployed, so
+/// the bincode historical transactions were executed with is kept after an
+/// upgrade.
 #[derive(Clone)]
-pub struct WasmStore(sled::Tree);
+pub struct WasmStore {
+    /// Current bincode of every contract
+    bincode: sled::Tree,
+    /// Versioned bincode of every contract
+    versions: sled::Tree,
+    /// Block height each upgraded version took effect at
+    heights: sled::Tree,
+}
 
 impl WasmStore {
-    /// Opens or creates a `WasmStore`. This tree holds the wasm bincode.
+    /// Opens or creates a `WasmStore`. These trees hold the wasm bincode.
     /// The layout looks like this:
     /// ```plaintext
     ///  tree: "_wasm_bincode"
     ///   key: ContractId
     /// value: Vec<u8>
+    ///
+    ///  tree: "_wasm_bincode_versions"
+    ///   key: ContractId
+    /// value: u32 (latest version)
+    ///   key: (ContractId, u32)
+    /// value: Vec<u8>
+    ///
+    ///  tree: "_wasm_bincode_heights"
+    ///   key: (ContractId, u32)
+    /// value: u64
+    /// ```
     pub fn new(db: &sled::Db) -> Result<Self> {
-        let tree = db.open_tree(SLED_BINCODE_TREE)?;
-        Ok(Self(tree))
+        let bincode = db.open_tree(SLED_BINCODE_TREE)?;
+        let versions = db.open_tree(SLED_BINCODE_VERSIONS_TREE)?;
+        let heights = db.open_tree(SLED_BINCODE_HEIGHTS_TREE)?;
+        Ok(Self { bincode, versions, heights })
     }
 
     /// Fetches the bincode for a given ContractId
     /// Returns an error if the bincode is not found.
     pub fn get(&self, contract_id: ContractId) -> Result<Vec<u8>> {
-        if let Some(bincode) = self.0.get(serialize(&contract_id))? {
+        if let Some(bincode) = self.bincode.get(serialize(&contract_id))? {
             return Ok(bincode.to_vec())
         }
 
         Err(Error::WasmBincodeNotFound)
     }
+
+    /// Fetches the latest version number of a given ContractId, or `None`
+    /// if it was never deployed. Contracts deployed before versioning was
+    /// introduced are at version 0.
+    pub fn version(&self, contract_id: ContractId) -> Result<Option<u32>> {
+        let key = serialize(&contract_id);
+        if let Some(version) = self.versions.get(&key)? {
+            return Ok(Some(deserialize(&version)?))
+        }
+
+        Ok(self.bincode.contains_key(&key)?.then_some(0))
+    }
+
+    /// Fetches the version of a given ContractId that was in effect at given
+    /// block height, or `None` if it was never deployed. Upgrades take effect
+    /// from the block after the one they got deployed in.
+    pub fn version_at(&self, contract_id: ContractId, height: u64) -> Result<Option<u32>> {
+        let Some(latest) = self.version(contract_id)? else { return Ok(None) };
+
+        for version in (0..=latest).rev() {
+            // Versions without a recorded height were never an upgrade
+            let Some(activation) = self.heights.get(serialize(&(contract_id, version)))? else {
+                return Ok(Some(version))
+            };
+
+            if deserialize::<u64>(&activation)? <= height {
+                return Ok(Some(version))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetches the bincode of given version of a ContractId.
+    /// Returns an error if the bincode is not found.
+    pub fn get_version(&self, contract_id: ContractId, version: u32) -> Result<Vec<u8>> {
+        if let Some(bincode) = self.versions.get(serialize(&(contract_id, version)))? {
+            return Ok(bincode.to_vec())
+        }
+
+        // Contracts deployed before versioning only have their current bincode
+        if version == 0 && !self.versions.contains_key(serialize(&contract_id))? {
+            return self.get(contract_id)
+        }
+
+        Err(Error::WasmBincodeNotFound)
+    }
+
+    /// Retrieve the contracts whose bincode got replaced by the block
+    /// that the given [`StateDiff`] belongs to.
+    pub fn redeployed(diff: &StateDiff) -> Result<Vec<ContractId>> {
+        let mut ret = vec![];
+
+        for (tree, key, before) in &diff.before {
+            if tree == SLED_BINCODE_TREE && before.is_some() {
+                ret.push(deserialize(key)?);
+            }
+        }
+
+        Ok(ret)
+    }
 }
 
 /// Overlay structure over a [`WasmStore`] instance.
@@ -70,6 +155,8 @@ pub struct WasmStoreOverlay(SledDbOverlayPtr);
 impl WasmStoreOverlay {
     pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
         overlay.lock().unwrap().open_tree(SLED_BINCODE_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_BINCODE_VERSIONS_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_BINCODE_HEIGHTS_TREE)?;
         Ok(Self(overlay.clone()))
     }
 
@@ -85,19 +172,154 @@ impl WasmStoreOverlay {
         Err(Error::WasmBincodeNotFound)
     }
 
-    /// Inserts or replaces the bincode for a given ContractId
-    pub fn insert(&self, contract_id: ContractId, bincode: &[u8]) -> Result<()> {
-        if let Err(e) =
-            self.0.lock().unwrap().insert(SLED_BINCODE_TREE, &serialize(&contract_id), bincode)
+    /// Fetches the latest version number of a given ContractId, or `None`
+    /// if it was never deployed. Contracts deployed before versioning was
+    /// introduced are at version 0.
+    pub fn version(&self, contract_id: ContractId) -> Result<Option<u32>> {
+        let key = serialize(&contract_id);
+        let lock = self.0.lock().unwrap();
+        if let Some(version) = lock.get(SLED_BINCODE_VERSIONS_TREE, &key)? {
+            return Ok(Some(deserialize(&version)?))
+        }
+
+        Ok(lock.contains_key(SLED_BINCODE_TREE, &key)?.then_some(0))
+    }
+
+    /// Fetches the version of a given ContractId that was in effect at given
+    /// block height, or `None` if it was never deployed. Upgrades take effect
+    /// from the block after the one they got deployed in.
+    pub fn version_at(&self, contract_id: ContractId, height: u64) -> Result<Option<u32>> {
+        let Some(latest) = self.version(contract_id)? else { return Ok(None) };
+        let lock = self.0.lock().unwrap();
+
+        for version in (0..=latest).rev() {
+            // Versions without a recorded height were never an upgrade
+            let key = serialize(&(contract_id, version));
+            let Some(activation) = lock.get(SLED_BINCODE_HEIGHTS_TREE, &key)? else {
+                return Ok(Some(version))
+            };
+
+            if deserialize::<u64>(&activation)? <= height {
+                return Ok(Some(version))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetches the bincode of given version of a ContractId.
+    /// Returns an error if the bincode is not found.
+    pub fn get_version(&self, contract_id: ContractId, version: u32) -> Result<Vec<u8>> {
+        let lock = self.0.lock().unwrap();
+        if let Some(bincode) =
+            lock.get(SLED_BINCODE_VERSIONS_TREE, &serialize(&(contract_id, version)))?
         {
+            return Ok(bincode.to_vec())
+        }
+
+        // Contracts deployed before versioning only have their current bincode
+        let key = serialize(&contract_id);
+        if version == 0 && !lock.contains_key(SLED_BINCODE_VERSIONS_TREE, &key)? {
+            if let Some(bincode) = lock.get(SLED_BINCODE_TREE, &key)? {
+                return Ok(bincode.to_vec())
+            }
+        }
+
+        Err(Error::WasmBincodeNotFound)
+    }
+
+    /// Fetches the bincode of a given ContractId that was in effect at given
+    /// block height, as [`WasmStoreOverlay::version_at`] defines it.
+    /// Returns an error if the contract wasn't deployed.
+    pub fn get_at(&self, contract_id: ContractId, height: u64) -> Result<Vec<u8>> {
+        match self.version_at(contract_id, height)? {
+            Some(version) => self.get_version(contract_id, version),
+            None => Err(Error::WasmBincodeNotFound),
+        }
+    }
+
+    /// Inserts or replaces the bincode for a given ContractId, deployed in
+    /// the block with given height.
+    /// Replacing it with a different bincode creates a new version, taking
+    /// effect from the next block, and the previous ones are kept so they can
+    /// be fetched from the [`WasmStore`].
+    pub fn insert(&self, contract_id: ContractId, bincode: &[u8], height: u64) -> Result<()> {
+        let key = serialize(&contract_id);
+        let mut lock = self.0.lock().unwrap();
+
+        // Redeploying the same bincode doesn't create a new version
+        let current = lock.get(SLED_BINCODE_TREE, &key)?;
+        if current.as_deref() == Some(bincode) {
+            return Ok(())
+        }
+
+        let version = match lock.get(SLED_BINCODE_VERSIONS_TREE, &key)? {
+            Some(version) => deserialize::<u32>(&version)? + 1,
+            None => match current {
+                // Keep the bincode deployed before versioning was introduced
+                Some(current) => {
+                    lock.insert(
+                        SLED_BINCODE_VERSIONS_TREE,
+                        &serialize(&(contract_id, 0_u32)),
+                        &current,
+                    )?;
+                    1
+                }
+                None => 0,
+            },
+        };
+
+        if let Err(e) = lock.insert(SLED_BINCODE_TREE, &key, bincode) {
             error!(target: "blockchain::contractstoreoverlay", "Failed to insert bincode to WasmStore: {}", e);
             return Err(e.into())
         }
+        lock.insert(SLED_BINCODE_VERSIONS_TREE, &serialize(&(contract_id, version)), bincode)?;
+        lock.insert(SLED_BINCODE_VERSIONS_TREE, &key, &serialize(&version))?;
+
+        // Calls in the rest of the upgrade block keep using the previous
+        // version, so a block is verified against a single one of them.
+        if version > 0 {
+            lock.insert(
+                SLED_BINCODE_HEIGHTS_TREE,
+                &serialize(&(contract_id, version)),
+                &serialize(&(height + 1)),
+            )?;
+        }
 
         Ok(())
     }
 }
 
+/// Key under which a contract's zkas db keeps the circuit and `VerifyingKey`
+/// of `zkas_ns` that were replaced when upgrading the contract from given
+/// version. The version is big-endian so the keys of a namespace are ordered
+/// by version.
+pub fn zkas_version_key(zkas_ns: &str, version: u32) -> Vec<u8> {
+    let mut key = serialize(&zkas_ns);
+    key.extend_from_slice(&version.to_be_bytes());
+    key
+}
+
+/// Decode a `ZkBinary` and its respective `VerifyingKey` from the value of
+/// a contract's zkas db record.
+fn decode_zkas(zkas_bytes: &[u8]) -> (ZkBinary, VerifyingKey) {
+    // If anything in this function panics, that means corrupted data managed
+    // to get into this sled tree. This should not be possible.
+    let (zkbin, vkbin): (Vec<u8>, Vec<u8>) = deserialize(zkas_bytes).unwrap();
+
+    // The first vec is the compiled zkas binary
+    let zkbin = ZkBinary::decode(&zkbin).unwrap();
+
+    // Construct the circuit to be able to read the VerifyingKey
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin).unwrap(), &zkbin);
+
+    // The second one is the serialized VerifyingKey for it
+    let mut vk_buf = Cursor::new(vkbin);
+    let vk = VerifyingKey::read::<Cursor<Vec<u8>>, ZkCircuit>(&mut vk_buf, circuit).unwrap();
+
+    (zkbin, vk)
+}
+
 /// The `ContractStateStore` is a `sled` tree that stores pointers to contracts'
 /// databases. See the rustdoc for the impl functions for more info.
 #[derive(Clone)]
@@ -205,21 +427,37 @@ impl ContractStateStore {
             return Err(Error::ZkasBincodeNotFound)
         };
 
-        // If anything in this function panics, that means corrupted data managed
-        // to get into this sled tree. This should not be possible.
-        let (zkbin, vkbin): (Vec<u8>, Vec<u8>) = deserialize(&zkas_bytes).unwrap();
+        Ok(decode_zkas(&zkas_bytes))
+    }
 
-        // The first vec is the compiled zkas binary
-        let zkbin = ZkBinary::decode(&zkbin).unwrap();
+    /// Fetch the `ZkBinary` and its respective `VerifyingKey` that given version
+    /// of a contract used, from the contract's zkas sled tree.
+    pub fn get_zkas_version(
+        &self,
+        db: &sled::Db,
+        contract_id: &ContractId,
+        zkas_ns: &str,
+        version: u32,
+    ) -> Result<(ZkBinary, VerifyingKey)> {
+        debug!(target: "blockchain::contractstore", "Looking up \"{}:{}\" zkas circuit & vk of version {}", contract_id, zkas_ns, version);
 
-        // Construct the circuit to be able to read the VerifyingKey
-        let circuit = ZkCircuit::new(empty_witnesses(&zkbin).unwrap(), &zkbin);
+        let zkas_tree = self.lookup(db, contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?;
 
-        // The second one is the serialized VerifyingKey for it
-        let mut vk_buf = Cursor::new(vkbin);
-        let vk = VerifyingKey::read::<Cursor<Vec<u8>>, ZkCircuit>(&mut vk_buf, circuit).unwrap();
+        // The circuit was in use up to the first version it was replaced on.
+        // If it never was, the current one is still the same.
+        let prefix = serialize(&zkas_ns);
+        for record in zkas_tree.range(zkas_version_key(zkas_ns, version)..) {
+            let (key, zkas_bytes) = record?;
+            if !key.starts_with(&prefix) {
+                break
+            }
+
+            if key.len() == prefix.len() + 4 {
+                return Ok(decode_zkas(&zkas_bytes))
+            }
+        }
 
-        Ok((zkbin, vk))
+        self.get_zkas(db, contract_id, zkas_ns)
     }
 }
 
@@ -317,20 +555,164 @@ impl ContractStateStoreOverlay {
             return Err(Error::ZkasBincodeNotFound)
         };
 
-        // If anything in this function panics, that means corrupted data managed
-        // to get into this sled tree. This should not be possible.
-        let (zkbin, vkbin): (Vec<u8>, Vec<u8>) = deserialize(&zkas_bytes).unwrap();
+        Ok(decode_zkas(&zkas_bytes))
+    }
+
+    /// Fetch the `ZkBinary` and its respective `VerifyingKey` that given version
+    /// of a contract used, from the contract's zkas sled tree.
+    pub fn get_zkas_version(
+        &self,
+        contract_id: &ContractId,
+        zkas_ns: &str,
+        version: u32,
+    ) -> Result<(ZkBinary, VerifyingKey)> {
+        debug!(target: "blockchain::contractstoreoverlay", "Looking up \"{}:{}\" zkas circuit & vk of version {}", contract_id, zkas_ns, version);
+
+        let zkas_tree = self.lookup(contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?;
+
+        // The circuit was in use up to the first version it was replaced on.
+        // If it never was, the current one is still the same.
+        let prefix = serialize(&zkas_ns);
+        let bounds = (Bound::Included(zkas_version_key(zkas_ns, version)), Bound::Unbounded);
+        let archived = {
+            let lock = self.0.lock().unwrap();
+            let mut archived = None;
+            for record in lock.range(&zkas_tree, bounds)? {
+                let (key, zkas_bytes) = record?;
+                if !key.starts_with(&prefix) {
+                    break
+                }
+
+                if key.len() == prefix.len() + 4 {
+                    archived = Some(zkas_bytes);
+                    break
+                }
+            }
+            archived
+        };
+
+        match archived {
+            Some(zkas_bytes) => Ok(decode_zkas(&zkas_bytes)),
+            None => self.get_zkas(contract_id, zkas_ns),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockchain::{Blockchain, BlockchainOverlay},
+        zkas::{Analyzer, Compiler, Lexer, Parser},
+    };
+    use darkfi_sdk::pasta::pallas;
+
+    #[test]
+    fn upgrades_keep_previous_bincode() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&db)?;
+        let contract_id = ContractId::from(pallas::Base::from(42));
+
+        let overlay = BlockchainOverlay::new(&blockchain)?;
+        let overlay = overlay.lock().unwrap();
+        assert_eq!(overlay.wasm_bincode.version(contract_id)?, None);
+        assert_eq!(overlay.wasm_bincode.version_at(contract_id, 10)?, None);
+
+        overlay.wasm_bincode.insert(contract_id, b"v0", 5)?;
+        // Redeploying the same bincode doesn't create a new version
+        overlay.wasm_bincode.insert(contract_id, b"v0", 7)?;
+        overlay.wasm_bincode.insert(contract_id, b"v1", 10)?;
+        assert_eq!(overlay.wasm_bincode.version(contract_id)?, Some(1));
+        assert_eq!(overlay.wasm_bincode.get(contract_id)?, b"v1");
+        // The upgrade takes effect from the next block
+        assert_eq!(overlay.wasm_bincode.get_at(contract_id, 10)?, b"v0");
+        assert_eq!(overlay.wasm_bincode.get_at(contract_id, 11)?, b"v1");
+        overlay.overlay.lock().unwrap().apply()?;
+
+        let wasm_bincode = &blockchain.wasm_bincode;
+        assert_eq!(wasm_bincode.version(contract_id)?, Some(1));
+        assert_eq!(wasm_bincode.get(contract_id)?, b"v1");
+        assert_eq!(wasm_bincode.version_at(contract_id, 5)?, Some(0));
+        assert_eq!(wasm_bincode.version_at(contract_id, 10)?, Some(0));
+        assert_eq!(wasm_bincode.version_at(contract_id, 11)?, Some(1));
+        assert_eq!(wasm_bincode.get_version(contract_id, 0)?, b"v0");
+        assert_eq!(wasm_bincode.get_version(contract_id, 1)?, b"v1");
+        assert!(wasm_bincode.get_version(contract_id, 2).is_err());
 
-        // The first vec is the compiled zkas binary
-        let zkbin = ZkBinary::decode(&zkbin).unwrap();
+        Ok(())
+    }
+
+    /// Compile a circuit of given k, with namespace `Test`, into the value of
+    /// a zkas db record.
+    fn zkas_record(k: u32) -> Result<Vec<u8>> {
+        let source = format!(
+            r#"k = {};
+field = "pallas";
+constant "Test" {{}}
+witness "Test" {{ Base a, }}
+circuit "Test" {{ constrain_instance(a); }}
+"#,
+            k
+        );
+
+        let tokens = Lexer::new("test.zk", source.chars()).lex()?;
+        let (namespace, k, constants, witnesses, statements) =
+            Parser::new("test.zk", source.chars(), tokens).parse()?;
+        let mut analyzer =
+            Analyzer::new("test.zk", source.chars(), constants, witnesses, statements);
+        analyzer.analyze_types()?;
+        let zkas_bincode = Compiler::new(
+            "test.zk",
+            source.chars(),
+            namespace,
+            k,
+            analyzer.constants,
+            analyzer.witnesses,
+            analyzer.statements,
+            analyzer.literals,
+            false,
+        )
+        .compile()?;
+
+        let zkbin = ZkBinary::decode(&zkas_bincode)?;
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+        let mut vk_buf = vec![];
+        VerifyingKey::build(zkbin.k, &circuit).write(&mut vk_buf)?;
+
+        Ok(serialize(&(zkas_bincode, vk_buf)))
+    }
 
-        // Construct the circuit to be able to read the VerifyingKey
-        let circuit = ZkCircuit::new(empty_witnesses(&zkbin).unwrap(), &zkbin);
+    #[test]
+    fn upgrades_keep_previous_zkas() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&db)?;
+        let contract_id = ContractId::from(pallas::Base::from(42));
+
+        // Version 1 replaced the circuit version 0 used, as `zkas_db_set()` does
+        let overlay = BlockchainOverlay::new(&blockchain)?;
+        let overlay = overlay.lock().unwrap();
+        let zkas_tree = overlay.contracts.init(&contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?;
+        let mut lock = overlay.overlay.lock().unwrap();
+        lock.insert(&zkas_tree, &zkas_version_key("Test", 0), &zkas_record(11)?)?;
+        lock.insert(&zkas_tree, &serialize(&"Test"), &zkas_record(12)?)?;
+        drop(lock);
+
+        assert_eq!(overlay.contracts.get_zkas_version(&contract_id, "Test", 0)?.0.k, 11);
+        assert_eq!(overlay.contracts.get_zkas_version(&contract_id, "Test", 1)?.0.k, 12);
+        assert_eq!(overlay.contracts.get_zkas(&contract_id, "Test")?.0.k, 12);
+        overlay.overlay.lock().unwrap().apply()?;
+
+        let contracts = &blockchain.contracts;
+        assert_eq!(contracts.get_zkas_version(&db, &contract_id, "Test", 0)?.0.k, 11);
+        assert_eq!(contracts.get_zkas_version(&db, &contract_id, "Test", 1)?.0.k, 12);
+        assert!(contracts.get_zkas_version(&db, &contract_id, "Other", 0).is_err());
 
-        // The second one is the serialized VerifyingKey for it
-        let mut vk_buf = Cursor::new(vkbin);
-        let vk = VerifyingKey::read::<Cursor<Vec<u8>>, ZkCircuit>(&mut vk_buf, circuit).unwrap();
+        Ok(())
+    }
 
-        Ok((zkbin, vk))
+    #[test]
+    fn zkas_version_keys_are_ordered() {
+        assert!(zkas_version_key("Mint", 1) < zkas_version_key("Mint", 256));
+        assert!(zkas_version_key("Mint", 256).starts_with(&serialize(&"Mint")));
     }
 }
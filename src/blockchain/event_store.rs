@@ -0,0 +1,150 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::crypto::ContractId;
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
+
+use crate::Result;
+
+use super::SledDbOverlayPtr;
+
+const SLED_EVENTS_TREE: &[u8] = b"_contract_events";
+
+/// A structured event emitted by a contract while executing a transaction
+#[derive(Clone, Debug, Eq, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct ContractEvent {
+    /// Contract that emitted the event
+    pub contract_id: ContractId,
+    /// Event topic, used for filtering
+    pub topic: String,
+    /// Arbitrary event data
+    pub data: Vec<u8>,
+}
+
+/// The `EventStore` is a `sled` tree storing the events emitted by the
+/// contracts executed by each transaction, where the key is the transaction
+/// hash, and the value is the serialized vector of its [`ContractEvent`]s.
+/// Transactions that emitted no events have no record.
+#[derive(Clone)]
+pub struct EventStore(pub sled::Tree);
+
+impl EventStore {
+    /// Opens a new or existing `EventStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_EVENTS_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Fetch the events emitted by given transaction hashes, in the same
+    /// order as the input hashes. Transactions without events yield an
+    /// empty vector.
+    pub fn get(&self, tx_hashes: &[blake3::Hash]) -> Result<Vec<Vec<ContractEvent>>> {
+        let mut ret = Vec::with_capacity(tx_hashes.len());
+
+        for tx_hash in tx_hashes {
+            match self.0.get(tx_hash.as_bytes())? {
+                Some(found) => ret.push(deserialize(&found)?),
+                None => ret.push(vec![]),
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Retrieve records count
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Overlay structure over a [`EventStore`] instance.
+pub struct EventStoreOverlay(SledDbOverlayPtr);
+
+impl EventStoreOverlay {
+    pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
+        overlay.lock().unwrap().open_tree(SLED_EVENTS_TREE)?;
+        Ok(Self(overlay.clone()))
+    }
+
+    /// Insert the events emitted by given transaction hash into the overlay.
+    /// Nothing is written if there are no events.
+    pub fn insert(&self, tx_hash: &blake3::Hash, events: &[ContractEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(())
+        }
+
+        self.0.lock().unwrap().insert(SLED_EVENTS_TREE, tx_hash.as_bytes(), &serialize(&events))?;
+        Ok(())
+    }
+
+    /// Fetch the events emitted by given transaction hash from the overlay.
+    pub fn get(&self, tx_hash: &blake3::Hash) -> Result<Vec<ContractEvent>> {
+        match self.0.lock().unwrap().get(SLED_EVENTS_TREE, tx_hash.as_bytes())? {
+            Some(found) => Ok(deserialize(&found)?),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{Blockchain, BlockchainOverlay};
+    use darkfi_sdk::pasta::pallas;
+
+    #[test]
+    fn store_transaction_events() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&db)?;
+
+        let contract_id = ContractId::from(pallas::Base::from(42));
+        let events = vec![
+            ContractEvent { contract_id, topic: "mint".to_string(), data: vec![1, 2, 3] },
+            ContractEvent { contract_id, topic: "transfer".to_string(), data: vec![] },
+        ];
+        let tx_hash = blake3::hash(b"tx");
+        let quiet_tx_hash = blake3::hash(b"quiet");
+        let reverted_tx_hash = blake3::hash(b"reverted");
+
+        let overlay = BlockchainOverlay::new(&blockchain)?;
+        let overlay = overlay.lock().unwrap();
+        overlay.events.insert(&tx_hash, &events)?;
+        overlay.events.insert(&quiet_tx_hash, &[])?;
+        assert_eq!(overlay.events.get(&tx_hash)?, events);
+
+        // Events of a reverted transaction are discarded along with its state
+        overlay.checkpoint();
+        overlay.events.insert(&reverted_tx_hash, &events)?;
+        overlay.revert_to_checkpoint()?;
+        assert!(overlay.events.get(&reverted_tx_hash)?.is_empty());
+
+        overlay.overlay.lock().unwrap().apply()?;
+
+        // Transactions without events have no record
+        assert_eq!(blockchain.events.len(), 1);
+        assert_eq!(blockchain.events.get(&[quiet_tx_hash, tx_hash])?, vec![vec![], events.clone()]);
+
+        Ok(())
+    }
+}
@@ -28,9 +28,9 @@ use darkfi_serial::{deserialize, serialize, Encodable, SerialDecodable, SerialEn
 
 use crate::{util::time::Timestamp, Error, Result};
 
-use super::{parse_record, SledDbOverlayPtr};
+use super::{monero::MoneroPowData, SledDbOverlayPtr};
 
-/// This struct represents a tuple of the form (version, previous, epoch, height, timestamp, nonce, merkle_root).
+/// This struct represents a tuple of the form (version, previous, epoch, height, timestamp, nonce, merkle_root, pow_data).
 #[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
 pub struct Header {
     /// Block version
@@ -49,9 +49,45 @@ pub struct Header {
     pub nonce: pallas::Base,
     /// Merkle tree of the transactions contained in this block
     pub tree: MerkleTree,
+    /// Monero merge mining proof, if the block was merge mined.
+    /// It is not part of the header hash, since it commits to it,
+    /// so [`Header::verify_pow_data`] must pass before the hash is
+    /// trusted to identify the header.
+    pub pow_data: Option<MoneroPowData>,
+}
+
+/// Header layout used before merge mining support, without `pow_data`.
+/// Headers stored by older nodes are decoded through it.
+#[derive(SerialDecodable)]
+struct LegacyHeader {
+    version: u8,
+    previous: blake3::Hash,
+    epoch: u64,
+    height: u64,
+    timestamp: Timestamp,
+    nonce: pallas::Base,
+    tree: MerkleTree,
+}
+
+impl From<LegacyHeader> for Header {
+    fn from(header: LegacyHeader) -> Self {
+        Self {
+            version: header.version,
+            previous: header.previous,
+            epoch: header.epoch,
+            height: header.height,
+            timestamp: header.timestamp,
+            nonce: header.nonce,
+            tree: header.tree,
+            pow_data: None,
+        }
+    }
 }
 
 impl Header {
+    /// Byte offset of the nonce in the header hashing blob
+    pub const NONCE_OFFSET: usize = 57;
+
     pub fn new(
         previous: blake3::Hash,
         epoch: u64,
@@ -61,22 +97,55 @@ impl Header {
     ) -> Self {
         let version = block_version(height);
         let tree = MerkleTree::new(1);
-        Self { version, previous, epoch, height, timestamp, nonce, tree }
+        Self { version, previous, epoch, height, timestamp, nonce, tree, pow_data: None }
     }
 
     /// Compute the header's hash
     pub fn hash(&self) -> Result<blake3::Hash> {
-        let mut hasher = blake3::Hasher::new();
+        Ok(blake3::hash(&self.hashing_blob()?))
+    }
+
+    /// Serialize the header fields that get hashed, in the same order.
+    /// External miners fill in the nonce at [`Header::NONCE_OFFSET`]
+    /// and hash the blob themselves.
+    pub fn hashing_blob(&self) -> Result<Vec<u8>> {
+        let mut blob = vec![];
+
+        self.version.encode(&mut blob)?;
+        self.previous.encode(&mut blob)?;
+        self.epoch.encode(&mut blob)?;
+        self.height.encode(&mut blob)?;
+        self.timestamp.encode(&mut blob)?;
+        self.nonce.encode(&mut blob)?;
+        self.tree.root(0).unwrap().encode(&mut blob)?;
+
+        Ok(blob)
+    }
+
+    /// Verify that the merge mining data, if any, commits to this header,
+    /// returning the Monero block hashing blob the proof of work must be
+    /// checked against.
+    pub fn verify_pow_data(&self) -> Result<Option<Vec<u8>>> {
+        let Some(pow_data) = &self.pow_data else { return Ok(None) };
+
+        if !pow_data.commits_to(&self.hash()?) {
+            return Err(Error::PoWInvalidMergeMiningData)
+        }
+
+        match pow_data.hashing_blob() {
+            Some(blob) => Ok(Some(blob)),
+            None => Err(Error::PoWInvalidMergeMiningData),
+        }
+    }
 
-        self.version.encode(&mut hasher)?;
-        self.previous.encode(&mut hasher)?;
-        self.epoch.encode(&mut hasher)?;
-        self.height.encode(&mut hasher)?;
-        self.timestamp.encode(&mut hasher)?;
-        self.nonce.encode(&mut hasher)?;
-        self.tree.root(0).unwrap().encode(&mut hasher)?;
+    /// Decode a header stored in the database, accepting the layout
+    /// written before merge mining support.
+    pub fn decode_stored(bytes: &[u8]) -> Result<Self> {
+        if let Ok(header) = deserialize::<Header>(bytes) {
+            return Ok(header)
+        }
 
-        Ok(hasher.finalize())
+        Ok(deserialize::<LegacyHeader>(bytes)?.into())
     }
 }
 
@@ -149,7 +218,7 @@ impl HeaderStore {
 
         for hash in headerhashes {
             if let Some(found) = self.0.get(hash.as_bytes())? {
-                let header = deserialize(&found)?;
+                let header = Header::decode_stored(&found)?;
                 ret.push(Some(header));
             } else {
                 if strict {
@@ -169,8 +238,10 @@ impl HeaderStore {
     pub fn get_all(&self) -> Result<Vec<(blake3::Hash, Header)>> {
         let mut headers = vec![];
 
-        for header in self.0.iter() {
-            headers.push(parse_record(header.unwrap())?);
+        for record in self.0.iter() {
+            let (key, value) = record.unwrap();
+            let hash = deserialize(&key)?;
+            headers.push((hash, Header::decode_stored(&value)?));
         }
 
         Ok(headers)
@@ -214,7 +285,7 @@ impl HeaderStoreOverlay {
 
         for hash in headerhashes {
             if let Some(found) = lock.get(SLED_HEADER_TREE, hash.as_bytes())? {
-                let header = deserialize(&found)?;
+                let header = Header::decode_stored(&found)?;
                 ret.push(Some(header));
             } else {
                 if strict {
@@ -228,3 +299,48 @@ impl HeaderStoreOverlay {
         Ok(ret)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_hashing_blob() -> Result<()> {
+        let mut header = Header::default();
+        header.nonce = pallas::Base::from(1337);
+
+        let blob = header.hashing_blob()?;
+        let nonce = serialize(&header.nonce);
+        assert_eq!(&blob[Header::NONCE_OFFSET..Header::NONCE_OFFSET + nonce.len()], &nonce[..]);
+        assert_eq!(blake3::hash(&blob), header.hash()?);
+
+        // Merge mining data is not hashed
+        header.pow_data = Some(MoneroPowData {
+            header: vec![1],
+            seed_hash: [2; 32],
+            coinbase: vec![3],
+            tx_hashes: vec![],
+        });
+        assert_eq!(header.hashing_blob()?, blob);
+
+        // But it must commit to the header
+        assert!(header.verify_pow_data().is_err());
+        header.pow_data = None;
+        assert_eq!(header.verify_pow_data()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_header_decoding() -> Result<()> {
+        let header = Header::default();
+        let bytes = serialize(&header);
+        assert_eq!(Header::decode_stored(&bytes)?, header);
+
+        // Headers stored before merge mining support lack the `pow_data` tag
+        assert!(deserialize::<Header>(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(Header::decode_stored(&bytes[..bytes.len() - 1])?, header);
+
+        Ok(())
+    }
+}
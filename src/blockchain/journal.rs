@@ -0,0 +1,623 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    ops::Bound,
+};
+
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
+
+use crate::{Error, Result};
+
+use super::parse_u64_key_record;
+
+const SLED_STATE_JOURNAL_TREE: &[u8] = b"_state_journal";
+const SLED_PENDING_DROPS_TREE: &[u8] = b"_state_journal_pending_drops";
+
+/// State changes performed by a single block, recorded so they can be undone.
+#[derive(Clone, Debug, Default, SerialEncodable, SerialDecodable)]
+pub struct StateDiff {
+    /// Trees created while applying the block
+    pub new_trees: Vec<Vec<u8>>,
+    /// Values of all keys the block wrote, before it was applied, in the
+    /// form of a tuple (`tree`, `key`, `value`). The value is `None` if
+    /// the key did not exist.
+    pub before: Vec<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+/// The `StateJournalStore` is a `sled` tree storing the [`StateDiff`] of
+/// every block applied through a [`JournaledOverlay`], where the key is
+/// the block height, and the value is the serialized diff.
+#[derive(Clone)]
+pub struct StateJournalStore(pub sled::Tree);
+
+impl StateJournalStore {
+    /// Opens a new or existing `StateJournalStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_STATE_JOURNAL_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Insert a slice of [`StateDiff`] of the blocks with given heights
+    /// into the store.
+    pub fn insert(&self, heights: &[u64], diffs: &[StateDiff]) -> Result<()> {
+        let batch = self.insert_batch(heights, diffs)?;
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Generate the sled batch corresponding to an insert, so caller
+    /// can handle the write operation.
+    pub fn insert_batch(&self, heights: &[u64], diffs: &[StateDiff]) -> Result<sled::Batch> {
+        if heights.len() != diffs.len() {
+            return Err(Error::InvalidInputLengths)
+        }
+
+        let mut batch = sled::Batch::default();
+
+        for (i, height) in heights.iter().enumerate() {
+            batch.insert(&height.to_be_bytes(), serialize(&diffs[i]));
+        }
+
+        Ok(batch)
+    }
+
+    /// Fetch the state diffs of given block heights from the store.
+    /// The resulting vector contains `Option`, which is `Some` if the diff
+    /// was found in the store, and otherwise it is `None`, if it has not.
+    /// The second parameter is a boolean which tells the function to fail
+    /// in case at least one diff was not found.
+    pub fn get(&self, heights: &[u64], strict: bool) -> Result<Vec<Option<StateDiff>>> {
+        let mut ret = Vec::with_capacity(heights.len());
+
+        for height in heights {
+            if let Some(found) = self.0.get(height.to_be_bytes())? {
+                let diff = deserialize(&found)?;
+                ret.push(Some(diff));
+            } else {
+                if strict {
+                    return Err(Error::StateJournalNotFound(*height))
+                }
+                ret.push(None);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Fetch all state diffs of blocks after given height, in the form
+    /// of a tuple (`height`, `diff`), in ascending height order.
+    pub fn get_after(&self, height: u64) -> Result<Vec<(u64, StateDiff)>> {
+        let mut ret = vec![];
+
+        let Some(start) = height.checked_add(1) else { return Ok(ret) };
+        for record in self.0.range(start.to_be_bytes()..) {
+            ret.push(parse_u64_key_record(record?)?);
+        }
+
+        Ok(ret)
+    }
+
+    /// Generate the sled batch corresponding to a remove, so caller
+    /// can handle the write operation.
+    pub fn remove_batch(&self, heights: &[u64]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for height in heights {
+            batch.remove(&height.to_be_bytes());
+        }
+
+        batch
+    }
+
+    /// Remove the state diffs of all blocks up to and including given
+    /// height, so they can no longer be rolled back.
+    pub fn prune(&self, height: u64) -> Result<()> {
+        let mut batch = sled::Batch::default();
+
+        for record in self.0.range(..=height.to_be_bytes()) {
+            let (key, _) = record?;
+            batch.remove(key);
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Retrieve records count
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The `PendingDropsStore` is a `sled` tree holding the names of trees
+/// created by rolled back blocks, which still have to be dropped. They are
+/// recorded atomically with the rollback writes, so trees left behind by a
+/// crash get dropped the next time the blockchain is opened.
+#[derive(Clone)]
+pub struct PendingDropsStore(pub sled::Tree);
+
+impl PendingDropsStore {
+    /// Opens a new or existing `PendingDropsStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_PENDING_DROPS_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Generate the sled batch corresponding to an insert of given tree
+    /// names, so caller can handle the write operation.
+    pub fn insert_batch(&self, trees: &[Vec<u8>]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for tree in trees {
+            batch.insert(tree.as_slice(), &[]);
+        }
+
+        batch
+    }
+
+    /// Drop all pending trees from given database, removing each of them
+    /// from the store once dropped.
+    pub fn drop_pending(&self, db: &sled::Db) -> Result<()> {
+        for record in self.0.iter() {
+            let (tree, _) = record?;
+            db.drop_tree(&tree)?;
+            self.0.remove(tree)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// State diffs of a sequence of blocks merged together, holding what is
+/// needed to restore the state before the first of them.
+#[derive(Debug, Default)]
+pub struct JournalRestore {
+    /// Value each written key had before the blocks, grouped by tree.
+    /// The value is `None` if the key did not exist.
+    pub records: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+    /// Trees created by the blocks
+    pub new_trees: Vec<Vec<u8>>,
+    /// Heights of the merged blocks
+    pub heights: Vec<u64>,
+}
+
+impl JournalRestore {
+    /// Merge the given state diffs, in the form of a tuple (`height`, `diff`),
+    /// in ascending height order.
+    pub fn new(journal: &[(u64, StateDiff)]) -> Self {
+        let mut restore = Self::default();
+
+        // Going from the newest block to the oldest, so each key ends up
+        // with the value it had before the first block.
+        for (height, diff) in journal.iter().rev() {
+            for (tree, key, value) in &diff.before {
+                restore.records.entry(tree.clone()).or_default().insert(key.clone(), value.clone());
+            }
+            restore.new_trees.extend(diff.new_trees.iter().cloned());
+            restore.heights.push(*height);
+        }
+
+        restore
+    }
+}
+
+/// Overlay structure over a [`sled_overlay::SledDbOverlay`] instance,
+/// journaling the state changes of each block written through it.
+///
+/// Once [`JournaledOverlay::begin_block`] has been called, the value each
+/// written key had before the block, along with any tree the block
+/// created, is recorded in the block's [`StateDiff`]. Writes performed
+/// before any block began are not journaled. On apply, the diffs are
+/// stored in the [`StateJournalStore`] before the overlay changes are
+/// written, so every applied block can later be rolled back.
+#[derive(Clone)]
+pub struct JournaledOverlay {
+    /// Main pointer to the sled db connection
+    db: sled::Db,
+    /// Underlying overlay
+    overlay: sled_overlay::SledDbOverlay,
+    /// Height of the block currently being written, if any
+    height: Option<u64>,
+    /// State diffs of the blocks written in the overlay, keyed by height
+    journal: BTreeMap<u64, StateDiff>,
+    /// (`tree`, `key`) pairs already recorded for the current block
+    touched: HashSet<(Vec<u8>, Vec<u8>)>,
+    /// (`tree`, `key`) pairs created in the overlay, if tracked
+    created: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+    /// Keys written in the overlay that were not applied yet, per tree
+    written: BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>,
+}
+
+impl JournaledOverlay {
+    pub fn new(db: &sled::Db) -> Self {
+        Self {
+            db: db.clone(),
+            overlay: sled_overlay::SledDbOverlay::new(db),
+            height: None,
+            journal: BTreeMap::new(),
+            touched: HashSet::new(),
+            created: None,
+            written: BTreeMap::new(),
+        }
+    }
+
+    /// Attribute all following writes to the block with the given height.
+    /// If the block was already written in this overlay, its previously
+    /// recorded state diff is extended.
+    pub fn begin_block(&mut self, height: u64) {
+        self.height = Some(height);
+        let diff = self.journal.entry(height).or_default();
+        self.touched =
+            diff.before.iter().map(|(tree, key, _)| (tree.clone(), key.clone())).collect();
+    }
+
+    /// Start tracking the keys created by all following writes, i.e. keys
+    /// that didn't exist before being inserted, such as revealed nullifiers.
+    /// Tracking is not affected by checkpoint reverts.
+    pub fn track_created_keys(&mut self) {
+        self.created = Some(vec![]);
+    }
+
+    /// Retrieve the tracked created keys, in the form of a tuple (`tree`, `key`).
+    pub fn created_keys(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.created.clone().unwrap_or_default()
+    }
+
+    /// Record the value of a key before its first write by the current block.
+    fn record(&mut self, tree_key: &[u8], key: &[u8]) -> Result<()> {
+        let Some(height) = self.height else { return Ok(()) };
+
+        if !self.touched.insert((tree_key.to_vec(), key.to_vec())) {
+            return Ok(())
+        }
+
+        let value = self.overlay.get(tree_key, key)?.map(|v| v.to_vec());
+        self.journal.get_mut(&height).unwrap().before.push((
+            tree_key.to_vec(),
+            key.to_vec(),
+            value,
+        ));
+
+        Ok(())
+    }
+
+    /// Open a new or existing tree in the overlay.
+    pub fn open_tree(&mut self, tree_key: &[u8]) -> Result<()> {
+        if let Some(height) = self.height {
+            let exists = self.db.tree_names().iter().any(|name| name.as_ref() == tree_key) ||
+                self.journal.values().any(|diff| diff.new_trees.iter().any(|t| t == tree_key));
+
+            if !exists {
+                self.journal.get_mut(&height).unwrap().new_trees.push(tree_key.to_vec());
+            }
+        }
+
+        self.overlay.open_tree(tree_key)?;
+        Ok(())
+    }
+
+    /// Drop all trees created in the overlay that were not applied yet.
+    pub fn purge_new_trees(&mut self) -> Result<()> {
+        self.overlay.purge_new_trees()?;
+        Ok(())
+    }
+
+    /// Check if given tree contains the given key.
+    pub fn contains_key(&self, tree_key: &[u8], key: &[u8]) -> Result<bool> {
+        Ok(self.overlay.contains_key(tree_key, key)?)
+    }
+
+    /// Retrieve the value of a key in given tree.
+    pub fn get(&self, tree_key: &[u8], key: &[u8]) -> Result<Option<sled::IVec>> {
+        Ok(self.overlay.get(tree_key, key)?)
+    }
+
+    /// Insert a key to a new value in given tree, returning the last value if it was set.
+    pub fn insert(
+        &mut self,
+        tree_key: &[u8],
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<sled::IVec>> {
+        self.record(tree_key, key)?;
+        self.written.entry(tree_key.to_vec()).or_default().insert(key.to_vec());
+        let previous = self.overlay.insert(tree_key, key, value)?;
+
+        if let (None, Some(created)) = (&previous, &mut self.created) {
+            created.push((tree_key.to_vec(), key.to_vec()));
+        }
+
+        Ok(previous)
+    }
+
+    /// Delete a value from given tree, returning the previous value if it existed.
+    pub fn remove(&mut self, tree_key: &[u8], key: &[u8]) -> Result<Option<sled::IVec>> {
+        self.record(tree_key, key)?;
+        self.written.entry(tree_key.to_vec()).or_default().insert(key.to_vec());
+
+        if let Some(created) = &mut self.created {
+            created.retain(|(t, k)| t != tree_key || k != key);
+        }
+
+        Ok(self.overlay.remove(tree_key, key)?)
+    }
+
+    /// Retrieve the last record of given tree, based on the `Ord` implementation
+    /// for `Vec<u8>`.
+    pub fn last(&self, tree_key: &[u8]) -> Result<Option<(sled::IVec, sled::IVec)>> {
+        Ok(self.overlay.last(tree_key)?)
+    }
+
+    /// Iterate over the records of given tree with keys inside the given
+    /// bounds, in ascending key order, as seen through the overlay. Records
+    /// written but not applied yet are included, and removed ones skipped.
+    /// Records are read lazily, so the iteration can be stopped early.
+    pub fn range<'a>(
+        &'a self,
+        tree_key: &[u8],
+        bounds: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    ) -> Result<impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a> {
+        let tree_key = tree_key.to_vec();
+
+        // Inverted bounds contain no keys, so they are replaced by an empty
+        // range, since inverted ranges can't be created.
+        let inverted = match (&bounds.0, &bounds.1) {
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            _ => false,
+        };
+        let bounds = match bounds {
+            (Bound::Included(start) | Bound::Excluded(start), _) if inverted => {
+                (Bound::Included(start.clone()), Bound::Excluded(start))
+            }
+            _ => bounds,
+        };
+
+        // Keys already applied to the database. The tree is not opened if
+        // it doesn't exist, so reading it doesn't create it.
+        let applied = match self.db.tree_names().iter().any(|name| name.as_ref() == &tree_key[..]) {
+            true => Some(self.db.open_tree(&tree_key)?.range(bounds.clone())),
+            false => None,
+        };
+        let mut applied =
+            applied.into_iter().flatten().map(|r| r.map(|(k, _)| k.to_vec())).peekable();
+
+        // Keys written in the overlay, which might not exist in the database
+        let mut written = self
+            .written
+            .get(&tree_key)
+            .into_iter()
+            .flat_map(move |keys| keys.range(bounds.clone()))
+            .peekable();
+
+        // Merge both sorted key sequences and retrieve the current value of
+        // each key from the overlay, skipping the removed ones.
+        Ok(std::iter::from_fn(move || loop {
+            let order = match (applied.peek(), written.peek()) {
+                (None, None) => return None,
+                (Some(Err(_)), _) | (Some(Ok(_)), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(Ok(a)), Some(w)) => a.cmp(*w),
+            };
+
+            let key = match order {
+                Ordering::Less | Ordering::Equal => {
+                    if order == Ordering::Equal {
+                        written.next();
+                    }
+                    match applied.next().unwrap() {
+                        Ok(key) => key,
+                        Err(e) => return Some(Err(Error::from(e))),
+                    }
+                }
+                Ordering::Greater => written.next().unwrap().clone(),
+            };
+
+            match self.overlay.get(&tree_key, &key) {
+                Ok(Some(value)) => return Some(Ok((key, value.to_vec()))),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(Error::from(e))),
+            }
+        }))
+    }
+
+    /// Check if given tree contains any records.
+    pub fn is_empty(&self, tree_key: &[u8]) -> Result<bool> {
+        Ok(self.overlay.is_empty(tree_key)?)
+    }
+
+    /// Store the state diffs of all written blocks and then atomically
+    /// write the overlay changes to the sled database. A crash in between
+    /// leaves journal entries whose before values still match the database,
+    /// so rolling them back is harmless.
+    pub fn apply(&mut self) -> Result<()> {
+        if !self.journal.is_empty() {
+            let heights: Vec<u64> = self.journal.keys().copied().collect();
+            let diffs: Vec<StateDiff> = self.journal.values().cloned().collect();
+            StateJournalStore::new(&self.db)?.insert(&heights, &diffs)?;
+        }
+
+        self.overlay.apply()?;
+
+        self.height = None;
+        self.journal.clear();
+        self.touched.clear();
+        self.created = None;
+        self.written.clear();
+
+        Ok(())
+    }
+
+    /// Checkpoint the overlay so we can revert to it, if needed.
+    pub fn checkpoint(&mut self) {
+        self.overlay.checkpoint();
+    }
+
+    /// Revert to current overlay checkpoint.
+    pub fn revert_to_checkpoint(&mut self) -> Result<()> {
+        self.overlay.revert_to_checkpoint()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{Blockchain, BlockchainOverlay};
+
+    const TEST_TREE: &[u8] = b"_test_state";
+
+    #[test]
+    fn rollback_journaled_blocks() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&db)?;
+
+        // Each block bumps a counter and adds a new record to a tree
+        // created by the first block.
+        for height in 0..4u64 {
+            let overlay = BlockchainOverlay::new(&blockchain)?;
+            let overlay = overlay.lock().unwrap();
+            overlay.overlay.lock().unwrap().begin_block(height);
+            overlay.order.insert(&[height], &[blake3::hash(&height.to_be_bytes())])?;
+
+            let mut lock = overlay.overlay.lock().unwrap();
+            lock.open_tree(TEST_TREE)?;
+            lock.insert(TEST_TREE, b"counter", &height.to_be_bytes())?;
+            lock.insert(TEST_TREE, &height.to_be_bytes(), b"coin")?;
+            lock.apply()?;
+        }
+        assert_eq!(blockchain.journal.len(), 4);
+
+        // Rolling back to the last block is a no-op
+        blockchain.rollback_to(3)?;
+        assert_eq!(blockchain.journal.len(), 4);
+
+        blockchain.rollback_to(1)?;
+        assert_eq!(blockchain.last()?.0, 1);
+        assert_eq!(blockchain.journal.len(), 2);
+
+        let tree = db.open_tree(TEST_TREE)?;
+        assert_eq!(tree.get(b"counter")?.unwrap().as_ref(), 1u64.to_be_bytes());
+        assert!(tree.get(1u64.to_be_bytes())?.is_some());
+        assert!(tree.get(2u64.to_be_bytes())?.is_none());
+        assert!(tree.get(3u64.to_be_bytes())?.is_none());
+
+        // Trees created by rolled back blocks get dropped
+        let block_tree = |height: u64| format!("_test_block_{}", height).into_bytes();
+        for height in 2..4u64 {
+            let overlay = BlockchainOverlay::new(&blockchain)?;
+            let overlay = overlay.lock().unwrap();
+            overlay.overlay.lock().unwrap().begin_block(height);
+            overlay.order.insert(&[height], &[blake3::hash(&height.to_be_bytes())])?;
+
+            let mut lock = overlay.overlay.lock().unwrap();
+            lock.open_tree(&block_tree(height))?;
+            lock.insert(&block_tree(height), b"key", b"value")?;
+            lock.apply()?;
+        }
+        blockchain.rollback_to(2)?;
+        assert!(db.tree_names().iter().any(|name| name.as_ref() == block_tree(2)));
+        assert!(!db.tree_names().iter().any(|name| name.as_ref() == block_tree(3)));
+        assert!(blockchain.pending_drops.is_empty());
+
+        // A drop interrupted by a crash is completed on the next open
+        let orphan = db.open_tree(b"_test_orphan")?;
+        orphan.insert(b"key", b"value")?;
+        blockchain.pending_drops.0.insert(b"_test_orphan", &[])?;
+        let blockchain = Blockchain::new(&db)?;
+        assert!(!db.tree_names().iter().any(|name| name.as_ref() == b"_test_orphan"));
+        assert!(blockchain.pending_drops.is_empty());
+
+        // The tree created by the genesis block is kept
+        blockchain.rollback_to(0)?;
+        assert_eq!(blockchain.len(), 1);
+        assert!(db.tree_names().iter().any(|name| name.as_ref() == TEST_TREE));
+        assert_eq!(tree.get(b"counter")?.unwrap().as_ref(), 0u64.to_be_bytes());
+
+        // Blocks without a journal entry can't be rolled back
+        blockchain.order.insert(&[1], &[blake3::hash(b"unjournaled")])?;
+        assert!(blockchain.rollback_to(0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn range_over_unapplied_writes() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let mut overlay = JournaledOverlay::new(&db);
+        overlay.open_tree(TEST_TREE)?;
+        for key in [1u8, 3, 5, 7] {
+            overlay.insert(TEST_TREE, &[key], b"applied")?;
+        }
+        overlay.apply()?;
+
+        // Mix applied records with written, updated and removed ones
+        overlay.insert(TEST_TREE, &[2], b"written")?;
+        overlay.insert(TEST_TREE, &[5], b"updated")?;
+        overlay.remove(TEST_TREE, &[3])?;
+        overlay.insert(TEST_TREE, &[8], b"written")?;
+
+        let records = |overlay: &JournaledOverlay, bounds| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            overlay.range(TEST_TREE, bounds)?.collect()
+        };
+
+        let all = records(&overlay, (Bound::Unbounded, Bound::Unbounded))?;
+        assert_eq!(
+            all,
+            vec![
+                (vec![1], b"applied".to_vec()),
+                (vec![2], b"written".to_vec()),
+                (vec![5], b"updated".to_vec()),
+                (vec![7], b"applied".to_vec()),
+                (vec![8], b"written".to_vec()),
+            ]
+        );
+
+        let keys = |records: Vec<(Vec<u8>, Vec<u8>)>| -> Vec<Vec<u8>> {
+            records.into_iter().map(|(k, _)| k).collect()
+        };
+        let some = records(&overlay, (Bound::Included(vec![2]), Bound::Excluded(vec![7])))?;
+        assert_eq!(keys(some), vec![vec![2], vec![5]]);
+
+        // Inverted bounds contain nothing
+        assert!(records(&overlay, (Bound::Included(vec![7]), Bound::Excluded(vec![2])))?.is_empty());
+        assert!(records(&overlay, (Bound::Excluded(vec![5]), Bound::Excluded(vec![5])))?.is_empty());
+
+        // Reverted writes are not visible
+        overlay.checkpoint();
+        overlay.insert(TEST_TREE, &[4], b"reverted")?;
+        overlay.revert_to_checkpoint()?;
+        assert_eq!(records(&overlay, (Bound::Unbounded, Bound::Unbounded))?, all);
+
+        Ok(())
+    }
+}
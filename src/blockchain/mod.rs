@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::{Arc, Mutex};
+use std::{
+    io::{Read, Seek, Write},
+    sync::{Arc, Mutex},
+};
 
 use log::debug;
 use sled::Transactional;
@@ -26,11 +29,14 @@ use darkfi_serial::{deserialize, serialize, Decodable};
 
 use crate::{tx::Transaction, Error, Result};
 
+/// Maximum number of records written per batch when importing a snapshot
+const SNAPSHOT_IMPORT_BATCH: usize = 10000;
+
 /// Block related definitions and storage implementations
 pub mod block_store;
 pub use block_store::{
     Block, BlockDifficultyStore, BlockDifficultyStoreOverlay, BlockInfo, BlockOrderStore,
-    BlockOrderStoreOverlay, BlockStore, BlockStoreOverlay,
+    BlockOrderStoreOverlay, BlockStore, BlockStoreOverlay, TxInclusionProof,
 };
 
 /// Header definition and storage implementation
@@ -43,7 +49,10 @@ pub use slot_store::{BlocksSlotsStore, BlocksSlotsStoreOverlay, SlotStore, SlotS
 
 /// Transactions related storage implementations
 pub mod tx_store;
-pub use tx_store::{PendingTxOrderStore, PendingTxStore, TxStore, TxStoreOverlay};
+pub use tx_store::{
+    PendingTxOrderStore, PendingTxStore, TxLocationStore, TxLocationStoreOverlay, TxStore,
+    TxStoreOverlay,
+};
 
 /// Contracts and Wasm storage implementations
 pub mod contract_store;
@@ -51,6 +60,24 @@ pub use contract_store::{
     ContractStateStore, ContractStateStoreOverlay, WasmStore, WasmStoreOverlay,
 };
 
+/// Contract events storage implementation
+pub mod event_store;
+pub use event_store::{ContractEvent, EventStore, EventStoreOverlay};
+
+/// Per-block state diff journal, used for rollbacks
+pub mod journal;
+pub use journal::{
+    JournalRestore, JournaledOverlay, PendingDropsStore, StateDiff, StateJournalStore,
+};
+
+/// Blockchain and contracts state snapshots
+pub mod snapshot;
+pub use snapshot::{SnapshotEntry, SnapshotReader, SnapshotWriter};
+
+/// Monero merge mining proofs
+pub mod monero;
+pub use monero::MoneroPowData;
+
 /// Structure holding all sled trees that define the concept of Blockchain.
 #[derive(Clone)]
 pub struct Blockchain {
@@ -70,6 +97,8 @@ pub struct Blockchain {
     pub difficulties: BlockDifficultyStore,
     /// Transactions sled tree
     pub transactions: TxStore,
+    /// Transactions locations sled tree
+    pub tx_locations: TxLocationStore,
     /// Pending transactions sled tree
     pub pending_txs: PendingTxStore,
     /// Pending transactions order sled tree
@@ -78,6 +107,12 @@ pub struct Blockchain {
     pub contracts: ContractStateStore,
     /// Wasm bincodes
     pub wasm_bincode: WasmStore,
+    /// Contract events sled tree
+    pub events: EventStore,
+    /// Blocks state diffs journal
+    pub journal: StateJournalStore,
+    /// Trees of rolled back blocks pending to be dropped
+    pub pending_drops: PendingDropsStore,
 }
 
 impl Blockchain {
@@ -90,10 +125,17 @@ impl Blockchain {
         let blocks_slots = BlocksSlotsStore::new(db)?;
         let difficulties = BlockDifficultyStore::new(db)?;
         let transactions = TxStore::new(db)?;
+        let tx_locations = TxLocationStore::new(db)?;
         let pending_txs = PendingTxStore::new(db)?;
         let pending_txs_order = PendingTxOrderStore::new(db)?;
         let contracts = ContractStateStore::new(db)?;
         let wasm_bincode = WasmStore::new(db)?;
+        let events = EventStore::new(db)?;
+        let journal = StateJournalStore::new(db)?;
+        let pending_drops = PendingDropsStore::new(db)?;
+
+        // Finish dropping the trees of an interrupted rollback
+        pending_drops.drop_pending(db)?;
 
         Ok(Self {
             sled_db: db.clone(),
@@ -104,10 +146,14 @@ impl Blockchain {
             blocks_slots,
             difficulties,
             transactions,
+            tx_locations,
             pending_txs,
             pending_txs_order,
             contracts,
             wasm_bincode,
+            events,
+            journal,
+            pending_drops,
         })
     }
 
@@ -121,10 +167,15 @@ impl Blockchain {
         let mut batches = vec![];
 
         // Store transactions
-        let (txs_batch, _) = self.transactions.insert_batch(&block.txs)?;
+        let (txs_batch, txs_hashes) = self.transactions.insert_batch(&block.txs)?;
         trees.push(self.transactions.0.clone());
         batches.push(txs_batch);
 
+        // Store transactions locations
+        let tx_locations_batch = self.tx_locations.insert_batch(&txs_hashes, block.header.height);
+        trees.push(self.tx_locations.0.clone());
+        batches.push(tx_locations_batch);
+
         // Store header
         let (headers_batch, _) = self.headers.insert_batch(&[block.header.clone()])?;
         trees.push(self.headers.0.clone());
@@ -227,6 +278,34 @@ impl Blockchain {
         Ok(ret)
     }
 
+    /// Retrieve the location of a transaction, in the form of a tuple
+    /// (`block height`, `tx index`). Returns `None` if the transaction
+    /// is not contained in any stored block.
+    pub fn get_tx_location(&self, tx_hash: &blake3::Hash) -> Result<Option<(u64, u64)>> {
+        Ok(self.tx_locations.get(&[*tx_hash], false)?[0])
+    }
+
+    /// Retrieve [`BlockInfo`]s in the given inclusive height range.
+    /// Heights without a stored block are skipped.
+    pub fn get_blocks_in_range(&self, start: u64, end: u64) -> Result<Vec<BlockInfo>> {
+        debug!(target: "blockchain", "get_blocks_in_range(): {} -> {}", start, end);
+        let heights: Vec<u64> = (start..=end).collect();
+        self.get_blocks_by_slot(&heights)
+    }
+
+    /// Retrieve the [`Header`]s of the blocks in the given inclusive height range.
+    /// Heights without a stored block are skipped.
+    pub fn get_headers_in_range(&self, start: u64, end: u64) -> Result<Vec<Header>> {
+        debug!(target: "blockchain", "get_headers_in_range(): {} -> {}", start, end);
+        let heights: Vec<u64> = (start..=end).collect();
+        let hashes: Vec<blake3::Hash> =
+            self.order.get(&heights, false)?.into_iter().flatten().collect();
+
+        // Block hashes are their header hashes
+        let headers = self.headers.get(&hashes, true)?;
+        Ok(headers.into_iter().map(|x| x.unwrap()).collect())
+    }
+
     /// Retrieve [`BlockInfo`]s by given slots. Does not fail if any of them are not found.
     pub fn get_blocks_by_slot(&self, slots: &[u64]) -> Result<Vec<BlockInfo>> {
         debug!(target: "blockchain", "get_blocks_by_slot(): {:?}", slots);
@@ -366,6 +445,212 @@ impl Blockchain {
         Ok(())
     }
 
+    /// Roll back the blockchain to the given block height, undoing all block,
+    /// transaction, slot and contract state writes of the blocks after it,
+    /// using their journaled [`StateDiff`]s. All writes are reverted atomically,
+    /// along with recording the trees created by the removed blocks in the
+    /// [`PendingDropsStore`], which are dropped afterwards.
+    /// Fails if any of the blocks to remove has no journal entry, which is
+    /// the case for blocks that were not added through a [`BlockchainOverlay`].
+    pub fn rollback_to(&self, height: u64) -> Result<()> {
+        debug!(target: "blockchain", "rollback_to(): {}", height);
+        let (last, _) = self.last()?;
+        if height >= last {
+            return Ok(())
+        }
+
+        let restore = self.journal_restore(height)?;
+
+        let mut trees = vec![];
+        let mut batches = vec![];
+        for (tree, records) in restore.records {
+            let mut batch = sled::Batch::default();
+            for (key, value) in records {
+                match value {
+                    Some(value) => batch.insert(key, value),
+                    None => batch.remove(key),
+                }
+            }
+            trees.push(self.sled_db.open_tree(tree)?);
+            batches.push(batch);
+        }
+
+        trees.push(self.journal.0.clone());
+        batches.push(self.journal.remove_batch(&restore.heights));
+
+        trees.push(self.pending_drops.0.clone());
+        batches.push(self.pending_drops.insert_batch(&restore.new_trees));
+
+        // Perform an atomic transaction over the trees and apply the batches.
+        self.atomic_write(&trees, &batches)?;
+
+        // Created trees are already emptied, so they can now be dropped
+        self.pending_drops.drop_pending(&self.sled_db)?;
+
+        Ok(())
+    }
+
+    /// Merge the journaled [`StateDiff`]s of all blocks after given height.
+    /// Fails if any of those blocks has no journal entry.
+    fn journal_restore(&self, height: u64) -> Result<JournalRestore> {
+        // Every block after given height must have been journaled
+        let journal = self.journal.get_after(height)?;
+        for record in self.order.0.range((height + 1).to_be_bytes()..) {
+            let (block_height, _) = parse_u64_key_record::<blake3::Hash>(record?)?;
+            if journal.binary_search_by_key(&block_height, |(h, _)| *h).is_err() {
+                return Err(Error::StateJournalNotFound(block_height))
+            }
+        }
+
+        Ok(JournalRestore::new(&journal))
+    }
+
+    /// Export a snapshot of the finalized blockchain and contracts state at
+    /// given height into given writer, returning the snapshot hash. The
+    /// state of the blocks after it is reverted in the snapshot using their
+    /// journaled [`StateDiff`]s, leaving the database untouched, so heights
+    /// whose journal was already pruned can't be exported. Pending
+    /// transactions and the journal are not included. Trees are streamed
+    /// one record at a time, so only the journal is kept in memory.
+    pub fn export_snapshot<W: Write>(&self, height: u64, writer: W) -> Result<blake3::Hash> {
+        debug!(target: "blockchain", "export_snapshot(): {}", height);
+        let (last, _) = self.last()?;
+        if height > last {
+            return Err(Error::SnapshotHeightInvalid(height))
+        }
+
+        let mut restore = match self.journal_restore(height) {
+            Err(Error::StateJournalNotFound(pruned)) => {
+                return Err(Error::SnapshotJournalPruned(height, pruned))
+            }
+            restore => restore?,
+        };
+        let excluded = [
+            self.sled_db.name(),
+            self.pending_txs.0.name(),
+            self.pending_txs_order.0.name(),
+            self.journal.0.name(),
+            self.pending_drops.0.name(),
+        ];
+
+        // Trees created after given height didn't exist at it
+        let mut names: Vec<sled::IVec> = self
+            .sled_db
+            .tree_names()
+            .into_iter()
+            .filter(|name| {
+                !excluded.contains(name) && !restore.new_trees.iter().any(|t| t[..] == name[..])
+            })
+            .collect();
+        names.sort();
+
+        let mut snapshot = SnapshotWriter::new(writer, height)?;
+        for name in names {
+            snapshot.write_entry(&SnapshotEntry::Tree(name.to_vec()))?;
+
+            // Merge the tree records with the values the blocks after given
+            // height overwrote, both in key order.
+            let mut restored =
+                restore.records.remove(&name[..]).unwrap_or_default().into_iter().peekable();
+            for record in self.sled_db.open_tree(&name)?.iter() {
+                let (key, value) = record?;
+
+                // Keys removed after given height
+                while let Some((key, value)) = restored.next_if(|(k, _)| k[..] < key[..]) {
+                    if let Some(value) = value {
+                        snapshot.write_entry(&SnapshotEntry::Record(key, value))?;
+                    }
+                }
+
+                match restored.next_if(|(k, _)| k[..] == key[..]) {
+                    Some((key, Some(value))) => {
+                        snapshot.write_entry(&SnapshotEntry::Record(key, value))?
+                    }
+                    // Key created after given height
+                    Some((_, None)) => {}
+                    None => snapshot
+                        .write_entry(&SnapshotEntry::Record(key.to_vec(), value.to_vec()))?,
+                }
+            }
+
+            for (key, value) in restored {
+                if let Some(value) = value {
+                    snapshot.write_entry(&SnapshotEntry::Record(key, value))?;
+                }
+            }
+        }
+
+        snapshot.finish()
+    }
+
+    /// Import a snapshot from given reader into an empty database, after
+    /// verifying it matches the given trusted checkpoint, in the form of a
+    /// tuple (`height`, `snapshot hash`), returning the snapshot height.
+    /// The snapshot is read twice: once to verify its hash, and once to
+    /// write its records in bounded batches. The block order is written
+    /// last, so an interrupted import leaves the database without blocks
+    /// and can be retried.
+    pub fn import_snapshot<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        checkpoint: (u64, blake3::Hash),
+    ) -> Result<u64> {
+        if !self.order.is_empty() {
+            return Err(Error::SnapshotDatabaseNotEmpty)
+        }
+
+        let snapshot = SnapshotReader::new(&mut reader)?;
+        let height = snapshot.height();
+        debug!(target: "blockchain", "import_snapshot(): {}", height);
+        if (height, snapshot.finish()?) != checkpoint {
+            return Err(Error::SnapshotCheckpointMismatch)
+        }
+
+        reader.rewind()?;
+        let mut snapshot = SnapshotReader::new(&mut reader)?;
+        let mut tree: Option<sled::Tree> = None;
+        let mut batch = sled::Batch::default();
+        let mut batch_len = 0;
+        let mut order_batch = sled::Batch::default();
+        while let Some(entry) = snapshot.next_entry()? {
+            match entry {
+                SnapshotEntry::Tree(name) => {
+                    if let Some(tree) = tree.take() {
+                        tree.apply_batch(std::mem::take(&mut batch))?;
+                        batch_len = 0;
+                    }
+                    if name[..] != self.order.0.name()[..] {
+                        tree = Some(self.sled_db.open_tree(name)?);
+                    }
+                }
+                SnapshotEntry::Record(key, value) => {
+                    // Block order records are held back until the end
+                    let Some(tree) = &tree else {
+                        order_batch.insert(key, value);
+                        continue
+                    };
+
+                    batch.insert(key, value);
+                    batch_len += 1;
+                    if batch_len == SNAPSHOT_IMPORT_BATCH {
+                        tree.apply_batch(std::mem::take(&mut batch))?;
+                        batch_len = 0;
+                    }
+                }
+                SnapshotEntry::End => unreachable!(),
+            }
+        }
+        if let Some(tree) = tree {
+            tree.apply_batch(batch)?;
+        }
+
+        // Make sure all state is on disk before the blocks become visible
+        self.sled_db.flush()?;
+        self.order.0.apply_batch(order_batch)?;
+
+        Ok(height)
+    }
+
     /// Auxiliary function to write to multiple trees completely atomic.
     fn atomic_write(&self, trees: &[sled::Tree], batches: &[sled::Batch]) -> Result<()> {
         if trees.len() != batches.len() {
@@ -395,14 +680,14 @@ impl Blockchain {
 }
 
 /// Atomic pointer to sled db overlay.
-pub type SledDbOverlayPtr = Arc<Mutex<sled_overlay::SledDbOverlay>>;
+pub type SledDbOverlayPtr = Arc<Mutex<JournaledOverlay>>;
 
 /// Atomic pointer to blockchain overlay.
 pub type BlockchainOverlayPtr = Arc<Mutex<BlockchainOverlay>>;
 
 /// Overlay structure over a [`Blockchain`] instance.
 pub struct BlockchainOverlay {
-    /// Main [`sled_overlay::SledDbOverlay`] to the sled db connection
+    /// Main [`JournaledOverlay`] to the sled db connection
     pub overlay: SledDbOverlayPtr,
     /// Headers overlay
     pub headers: HeaderStoreOverlay,
@@ -418,16 +703,20 @@ pub struct BlockchainOverlay {
     pub difficulties: BlockDifficultyStoreOverlay,
     /// Transactions overlay
     pub transactions: TxStoreOverlay,
+    /// Transactions locations overlay
+    pub tx_locations: TxLocationStoreOverlay,
     /// Contract states overlay
     pub contracts: ContractStateStoreOverlay,
     /// Wasm bincodes overlay
     pub wasm_bincode: WasmStoreOverlay,
+    /// Contract events overlay
+    pub events: EventStoreOverlay,
 }
 
 impl BlockchainOverlay {
     /// Instantiate a new `BlockchainOverlay` over the given [`Blockchain`] instance.
     pub fn new(blockchain: &Blockchain) -> Result<BlockchainOverlayPtr> {
-        let overlay = Arc::new(Mutex::new(sled_overlay::SledDbOverlay::new(&blockchain.sled_db)));
+        let overlay = Arc::new(Mutex::new(JournaledOverlay::new(&blockchain.sled_db)));
         let headers = HeaderStoreOverlay::new(&overlay)?;
         let blocks = BlockStoreOverlay::new(&overlay)?;
         let order = BlockOrderStoreOverlay::new(&overlay)?;
@@ -435,8 +724,10 @@ impl BlockchainOverlay {
         let blocks_slots = BlocksSlotsStoreOverlay::new(&overlay)?;
         let difficulties = BlockDifficultyStoreOverlay::new(&overlay)?;
         let transactions = TxStoreOverlay::new(&overlay)?;
+        let tx_locations = TxLocationStoreOverlay::new(&overlay)?;
         let contracts = ContractStateStoreOverlay::new(&overlay)?;
         let wasm_bincode = WasmStoreOverlay::new(&overlay)?;
+        let events = EventStoreOverlay::new(&overlay)?;
 
         Ok(Arc::new(Mutex::new(Self {
             overlay,
@@ -447,8 +738,10 @@ impl BlockchainOverlay {
             blocks_slots,
             difficulties,
             transactions,
+            tx_locations,
             contracts,
             wasm_bincode,
+            events,
         })))
     }
 
@@ -477,7 +770,10 @@ impl BlockchainOverlay {
     /// the writes atomically.
     pub fn add_block(&self, block: &BlockInfo) -> Result<blake3::Hash> {
         // Store transactions
-        self.transactions.insert(&block.txs)?;
+        let txs_hashes = self.transactions.insert(&block.txs)?;
+
+        // Store transactions locations
+        self.tx_locations.insert(&txs_hashes, block.header.height)?;
 
         // Store header
         self.headers.insert(&[block.header.clone()])?;
@@ -578,7 +874,7 @@ impl BlockchainOverlay {
         Ok(())
     }
 
-    /// Auxiliary function to create a full clone using SledDbOverlay::clone,
+    /// Auxiliary function to create a full clone using JournaledOverlay::clone,
     /// generating new pointers for the underlying overlays.
     pub fn full_clone(&self) -> Result<BlockchainOverlayPtr> {
         let overlay = Arc::new(Mutex::new(self.overlay.lock().unwrap().clone()));
@@ -589,8 +885,10 @@ impl BlockchainOverlay {
         let blocks_slots = BlocksSlotsStoreOverlay::new(&overlay)?;
         let difficulties = BlockDifficultyStoreOverlay::new(&overlay)?;
         let transactions = TxStoreOverlay::new(&overlay)?;
+        let tx_locations = TxLocationStoreOverlay::new(&overlay)?;
         let contracts = ContractStateStoreOverlay::new(&overlay)?;
         let wasm_bincode = WasmStoreOverlay::new(&overlay)?;
+        let events = EventStoreOverlay::new(&overlay)?;
 
         Ok(Arc::new(Mutex::new(Self {
             overlay,
@@ -601,8 +899,10 @@ impl BlockchainOverlay {
             blocks_slots,
             difficulties,
             transactions,
+            tx_locations,
             contracts,
             wasm_bincode,
+            events,
         })))
     }
 }
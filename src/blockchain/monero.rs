@@ -0,0 +1,505 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+use std::ops::Range;
+
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Monero `tx_extra` merge mining tag
+pub const MERGE_MINING_TAG: u8 = 0x03;
+
+/// Size of a serialized merge mining tag, committing to a single chain
+pub const MERGE_MINING_TAG_SIZE: usize = 35;
+
+/// Maximum size of a serialized Monero block header
+const MAX_HEADER_SIZE: usize = 128;
+
+/// Maximum size of a serialized Monero coinbase transaction
+const MAX_COINBASE_SIZE: usize = 65536;
+
+/// Maximum number of Monero block transactions
+const MAX_TX_HASHES: usize = 65536;
+
+/// Monero `tx_extra` padding tag, which spans the rest of the field
+const PADDING_TAG: u8 = 0x00;
+
+/// Monero `tx_extra` transaction public key tag
+const PUBKEY_TAG: u8 = 0x01;
+
+/// Monero `tx_extra` nonce tag
+const NONCE_TAG: u8 = 0x02;
+
+/// Monero `tx_extra` additional public keys tag
+const ADDITIONAL_PUBKEYS_TAG: u8 = 0x04;
+
+/// Monero `tx_extra` MinerGate tag, used by some pools
+const MINERGATE_TAG: u8 = 0xde;
+
+/// Monero merge mining proof of a [`Header`](super::Header). The Monero
+/// coinbase transaction commits to the header hash with a merge mining tag,
+/// and the Monero block hashing blob built from this data is what got
+/// hashed with RandomX to satisfy the header's mine target.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct MoneroPowData {
+    /// Serialized Monero block header
+    pub header: Vec<u8>,
+    /// RandomX key the Monero block was hashed with. This is provided by the
+    /// miner and is not verified to be the key of the current Monero seed
+    /// epoch, since that would require access to the Monero chain. This does
+    /// not weaken the proof of work: the hashing blob commits to the DarkFi
+    /// header, so work can't be precomputed or reused under any key, and
+    /// switching keys requires a new RandomX cache and dataset, which costs
+    /// far more than trying another nonce.
+    pub seed_hash: [u8; 32],
+    /// Serialized Monero coinbase transaction
+    pub coinbase: Vec<u8>,
+    /// Hashes of the rest of the Monero block transactions
+    pub tx_hashes: Vec<[u8; 32]>,
+}
+
+impl MoneroPowData {
+    /// Parse a serialized Monero block, hashed with given RandomX key.
+    /// Returns `None` if the blob is malformed.
+    pub fn from_block_blob(blob: &[u8], seed_hash: [u8; 32]) -> Option<Self> {
+        let mut reader = BlobReader { blob, pos: 0 };
+
+        // Header: major and minor version, timestamp, previous id and nonce
+        reader.varint()?;
+        reader.varint()?;
+        reader.varint()?;
+        reader.take(32 + 4)?;
+        let header = blob[..reader.pos].to_vec();
+
+        // Coinbase transaction
+        let coinbase_start = reader.pos;
+        reader.coinbase()?;
+        let coinbase = blob[coinbase_start..reader.pos].to_vec();
+
+        // Rest of the transactions hashes
+        let mut tx_hashes = vec![];
+        for _ in 0..reader.varint()? {
+            tx_hashes.push(reader.take(32)?.try_into().unwrap());
+        }
+
+        if reader.pos != blob.len() {
+            return None
+        }
+
+        Some(Self { header, seed_hash, coinbase, tx_hashes })
+    }
+
+    /// Check if the coinbase transaction commits to given header hash.
+    /// The coinbase `tx_extra` must be well formed and contain exactly one
+    /// merge mining tag, which must be the one committing to the hash.
+    pub fn commits_to(&self, hash: &blake3::Hash) -> bool {
+        let mut reader = BlobReader { blob: &self.coinbase, pos: 0 };
+        let Some(extra) = reader.coinbase() else { return false };
+        if reader.pos != self.coinbase.len() {
+            return false
+        }
+
+        let Some(fields) = extra_fields(&self.coinbase[extra]) else { return false };
+        let mut tags = fields.iter().filter(|(tag, _)| *tag == MERGE_MINING_TAG);
+        match (tags.next(), tags.next()) {
+            (Some((_, field)), None) => *field == &merge_mining_tag(hash)[2..],
+            _ => false,
+        }
+    }
+
+    /// Compute the coinbase transaction hash. Only version 2 coinbase
+    /// transactions, without RingCT data, are supported.
+    pub fn coinbase_hash(&self) -> Option<[u8; 32]> {
+        let (rct_type, prefix) = self.coinbase.split_last()?;
+        if self.coinbase[0] != 2 || *rct_type != 0 {
+            return None
+        }
+
+        // Prefix hash, RingCT base hash and (null) prunable hash
+        let mut hashes = keccak(prefix).to_vec();
+        hashes.extend_from_slice(&keccak(&[*rct_type]));
+        hashes.extend_from_slice(&[0; 32]);
+
+        Some(keccak(&hashes))
+    }
+
+    /// Build the Monero block hashing blob, which is the RandomX input.
+    /// Returns `None` if the data is malformed or exceeds size limits.
+    pub fn hashing_blob(&self) -> Option<Vec<u8>> {
+        if self.header.len() > MAX_HEADER_SIZE ||
+            self.coinbase.len() > MAX_COINBASE_SIZE ||
+            self.tx_hashes.len() > MAX_TX_HASHES
+        {
+            return None
+        }
+
+        let mut leaves = vec![self.coinbase_hash()?];
+        leaves.extend_from_slice(&self.tx_hashes);
+
+        let mut blob = self.header.clone();
+        blob.extend_from_slice(&tree_hash(&leaves));
+        write_varint(&mut blob, leaves.len() as u64);
+
+        Some(blob)
+    }
+}
+
+/// Serialize a merge mining tag committing to given header hash,
+/// with a merkle tree depth of zero since we are the only chain.
+pub fn merge_mining_tag(hash: &blake3::Hash) -> Vec<u8> {
+    let mut tag = vec![MERGE_MINING_TAG, 33, 0];
+    tag.extend_from_slice(hash.as_bytes());
+    tag
+}
+
+/// Insert a merge mining tag committing to given header hash as the first
+/// field of the coinbase transaction extra of a serialized Monero block.
+/// Returns the new blob along with the number of bytes the extra grew by,
+/// or `None` if the blob is malformed or already carries a merge mining tag.
+pub fn insert_merge_mining_tag(blob: &[u8], hash: &blake3::Hash) -> Option<(Vec<u8>, usize)> {
+    let mut reader = BlobReader { blob, pos: 0 };
+    reader.varint()?;
+    reader.varint()?;
+    reader.varint()?;
+    reader.take(32 + 4)?;
+    let extra = reader.coinbase()?;
+
+    let fields = extra_fields(&blob[extra.clone()])?;
+    if fields.iter().any(|(tag, _)| *tag == MERGE_MINING_TAG) {
+        return None
+    }
+
+    // The extra length must have been canonically encoded for us to replace it
+    let mut old_len = vec![];
+    write_varint(&mut old_len, extra.len() as u64);
+    let len_start = extra.start.checked_sub(old_len.len())?;
+    if blob[len_start..extra.start] != old_len[..] {
+        return None
+    }
+
+    let tag = merge_mining_tag(hash);
+    let mut new_blob = blob[..len_start].to_vec();
+    write_varint(&mut new_blob, (extra.len() + tag.len()) as u64);
+    new_blob.extend_from_slice(&tag);
+    new_blob.extend_from_slice(&blob[extra.start..]);
+
+    let growth = new_blob.len() - blob.len();
+    Some((new_blob, growth))
+}
+
+/// Split a Monero `tx_extra` into its fields, following `parse_tx_extra`
+/// of Monero's `cryptonote_basic/cryptonote_format_utils.cpp`. Returns
+/// the tag and data of each field, or `None` if the extra is malformed
+/// or contains unknown tags.
+fn extra_fields(extra: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let mut reader = BlobReader { blob: extra, pos: 0 };
+    let mut fields = vec![];
+
+    while reader.pos < extra.len() {
+        let tag = reader.take(1)?[0];
+        let data = match tag {
+            // Padding must be all zeroes up to the end of the extra
+            PADDING_TAG => {
+                let padding = reader.take(extra.len() - reader.pos)?;
+                if padding.iter().any(|byte| *byte != 0) {
+                    return None
+                }
+                padding
+            }
+            PUBKEY_TAG => reader.take(32)?,
+            NONCE_TAG => {
+                let len = reader.take(1)?[0] as usize;
+                reader.take(len)?
+            }
+            MERGE_MINING_TAG | MINERGATE_TAG => {
+                let len = reader.varint()?;
+                reader.take(usize::try_from(len).ok()?)?
+            }
+            ADDITIONAL_PUBKEYS_TAG => {
+                let count = reader.varint()?;
+                reader.take(usize::try_from(count).ok()?.checked_mul(32)?)?
+            }
+            _ => return None,
+        };
+
+        fields.push((tag, data));
+    }
+
+    Some(fields)
+}
+
+/// Monero's `cn_fast_hash`
+fn keccak(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut output = [0; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Hash two tree nodes together
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut data = [0; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    keccak(&data)
+}
+
+/// Monero's transactions merkle root, following `tree_hash` of
+/// its `crypto/tree-hash.c`.
+pub fn tree_hash(hashes: &[[u8; 32]]) -> [u8; 32] {
+    match hashes.len() {
+        0 => [0; 32],
+        1 => hashes[0],
+        2 => hash_pair(&hashes[0], &hashes[1]),
+        count => {
+            // Largest power of two below count
+            let mut cnt = count.next_power_of_two() >> 1;
+            if cnt == count {
+                cnt >>= 1;
+            }
+
+            let mut ints = hashes[..2 * cnt - count].to_vec();
+            for pair in hashes[2 * cnt - count..].chunks(2) {
+                ints.push(hash_pair(&pair[0], &pair[1]));
+            }
+
+            while cnt > 2 {
+                cnt >>= 1;
+                ints = ints.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            }
+
+            hash_pair(&ints[0], &ints[1])
+        }
+    }
+}
+
+/// Write a Monero varint
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Auxiliary struct to read Monero serialized data
+struct BlobReader<'a> {
+    blob: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BlobReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.blob.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    /// Read a coinbase transaction, returning the position of its `tx_extra`
+    fn coinbase(&mut self) -> Option<Range<usize>> {
+        let version = self.varint()?;
+        self.varint()?;
+        for _ in 0..self.varint()? {
+            // Only generation inputs are allowed
+            if self.take(1)?[0] != 0xff {
+                return None
+            }
+            self.varint()?;
+        }
+        for _ in 0..self.varint()? {
+            self.varint()?;
+            match self.take(1)?[0] {
+                // txout_to_key
+                0x02 => self.take(32)?,
+                // txout_to_tagged_key
+                0x03 => self.take(33)?,
+                _ => return None,
+            };
+        }
+        let extra_len = self.varint()?;
+        let extra_start = self.pos;
+        self.take(usize::try_from(extra_len).ok()?)?;
+        let extra = extra_start..self.pos;
+        if version >= 2 {
+            self.take(1)?;
+        }
+
+        Some(extra)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a Monero block blob with a version 2 coinbase, carrying given extra
+    fn block_blob(extra: &[u8], tx_hashes: &[[u8; 32]]) -> Vec<u8> {
+        // Header
+        let mut blob = vec![16, 16];
+        write_varint(&mut blob, 1700000000);
+        blob.extend_from_slice(&[1; 32]);
+        blob.extend_from_slice(&[0; 4]);
+
+        // Coinbase with a generation input and a tagged key output
+        blob.extend_from_slice(&[2, 60, 1, 0xff]);
+        write_varint(&mut blob, 3000000);
+        blob.push(1);
+        write_varint(&mut blob, 600000000000);
+        blob.push(0x03);
+        blob.extend_from_slice(&[2; 33]);
+        write_varint(&mut blob, extra.len() as u64);
+        blob.extend_from_slice(extra);
+        blob.push(0);
+
+        write_varint(&mut blob, tx_hashes.len() as u64);
+        for hash in tx_hashes {
+            blob.extend_from_slice(hash);
+        }
+
+        blob
+    }
+
+    #[test]
+    fn monero_pow_data() {
+        let commitment = blake3::hash(b"darkfi");
+        let mut extra = vec![0x01];
+        extra.extend_from_slice(&[3; 32]);
+        extra.extend_from_slice(&merge_mining_tag(&commitment));
+        let tx_hashes = [[4; 32], [5; 32]];
+        let blob = block_blob(&extra, &tx_hashes);
+
+        let pow_data = MoneroPowData::from_block_blob(&blob, [6; 32]).unwrap();
+        assert_eq!(pow_data.header.len(), 2 + 5 + 36);
+        assert_eq!(pow_data.tx_hashes, tx_hashes);
+        assert!(pow_data.commits_to(&commitment));
+        assert!(!pow_data.commits_to(&blake3::hash(b"monero")));
+
+        // The tag must be an actual extra field, not just appear in it
+        let mut nonce = vec![0x02, MERGE_MINING_TAG_SIZE as u8];
+        nonce.extend_from_slice(&merge_mining_tag(&commitment));
+        let pow_data = MoneroPowData::from_block_blob(&block_blob(&nonce, &[]), [6; 32]).unwrap();
+        assert!(!pow_data.commits_to(&commitment));
+
+        // A second merge mining tag is rejected
+        let mut twice = extra.clone();
+        twice.extend_from_slice(&merge_mining_tag(&blake3::hash(b"monero")));
+        let pow_data = MoneroPowData::from_block_blob(&block_blob(&twice, &[]), [6; 32]).unwrap();
+        assert!(!pow_data.commits_to(&commitment));
+
+        // Unknown tags and non-zero padding make the extra malformed
+        for trailer in [vec![0x05, 0], vec![0x00, 0, 1]] {
+            let malformed = [extra.clone(), trailer].concat();
+            let pow_data =
+                MoneroPowData::from_block_blob(&block_blob(&malformed, &[]), [6; 32]).unwrap();
+            assert!(!pow_data.commits_to(&commitment));
+        }
+
+        // Trailing zero padding is allowed
+        let padded = [extra.clone(), vec![0; 8]].concat();
+        let pow_data = MoneroPowData::from_block_blob(&block_blob(&padded, &[]), [6; 32]).unwrap();
+        assert!(pow_data.commits_to(&commitment));
+
+        let pow_data = MoneroPowData::from_block_blob(&blob, [6; 32]).unwrap();
+
+        // Hashing blob is the header, the transactions root and their count
+        let leaves = [pow_data.coinbase_hash().unwrap(), tx_hashes[0], tx_hashes[1]];
+        let hashing_blob = pow_data.hashing_blob().unwrap();
+        assert_eq!(&hashing_blob[..43], &pow_data.header[..]);
+        assert_eq!(&hashing_blob[43..75], &tree_hash(&leaves));
+        assert_eq!(hashing_blob[75..], [3]);
+
+        // Malformed blobs are rejected
+        assert!(MoneroPowData::from_block_blob(&blob[..blob.len() - 1], [6; 32]).is_none());
+        let mut trailing = blob.clone();
+        trailing.push(0);
+        assert!(MoneroPowData::from_block_blob(&trailing, [6; 32]).is_none());
+    }
+
+    #[test]
+    fn monero_insert_merge_mining_tag() {
+        let commitment = blake3::hash(b"darkfi");
+
+        // Extra with a public key and trailing padding, as the tag goes first
+        let mut extra = vec![0x01];
+        extra.extend_from_slice(&[3; 32]);
+        extra.extend_from_slice(&[0; 8]);
+        let blob = block_blob(&extra, &[[4; 32]]);
+
+        let (tagged, growth) = insert_merge_mining_tag(&blob, &commitment).unwrap();
+        assert_eq!(growth, MERGE_MINING_TAG_SIZE);
+        assert_eq!(tagged.len(), blob.len() + growth);
+        let pow_data = MoneroPowData::from_block_blob(&tagged, [6; 32]).unwrap();
+        assert!(pow_data.commits_to(&commitment));
+        assert_eq!(pow_data.tx_hashes, [[4; 32]]);
+
+        // Everything after the extra keeps its position relative to the end
+        assert_eq!(&tagged[tagged.len() - 34..], &blob[blob.len() - 34..]);
+
+        // A second tag is refused
+        assert!(insert_merge_mining_tag(&tagged, &blake3::hash(b"monero")).is_none());
+
+        // Growing the extra past 127 bytes widens its length varint
+        let blob = block_blob(&[0; 100], &[]);
+        let (tagged, growth) = insert_merge_mining_tag(&blob, &commitment).unwrap();
+        assert_eq!(growth, MERGE_MINING_TAG_SIZE + 1);
+        let pow_data = MoneroPowData::from_block_blob(&tagged, [6; 32]).unwrap();
+        assert!(pow_data.commits_to(&commitment));
+
+        // Malformed blobs are rejected
+        assert!(insert_merge_mining_tag(&blob[..50], &commitment).is_none());
+    }
+
+    #[test]
+    fn monero_tree_hash() {
+        let leaves: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+        assert_eq!(tree_hash(&leaves[..1]), leaves[0]);
+        assert_eq!(tree_hash(&leaves[..2]), hash_pair(&leaves[0], &leaves[1]));
+
+        // Three leaves: first one is carried over, last two get paired
+        let expected = hash_pair(&leaves[0], &hash_pair(&leaves[1], &leaves[2]));
+        assert_eq!(tree_hash(&leaves[..3]), expected);
+
+        // Four leaves form a full tree
+        let expected =
+            hash_pair(&hash_pair(&leaves[0], &leaves[1]), &hash_pair(&leaves[2], &leaves[3]));
+        assert_eq!(tree_hash(&leaves[..4]), expected);
+
+        // Five leaves: first three are carried over, last two get paired
+        let expected = hash_pair(
+            &hash_pair(&leaves[0], &leaves[1]),
+            &hash_pair(&leaves[2], &hash_pair(&leaves[3], &leaves[4])),
+        );
+        assert_eq!(tree_hash(&leaves), expected);
+    }
+}
@@ -0,0 +1,242 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+
+use darkfi_serial::{serialize, Decodable, Encodable, SerialDecodable, SerialEncodable};
+
+use crate::Result;
+
+/// A single entry of a snapshot stream.
+///
+/// A snapshot of the finalized blockchain and contracts state at a given
+/// block height contains the blocks, headers, difficulties, contract state
+/// trees and bincodes, so a node can be bootstrapped without replaying
+/// every block. It is streamed as the block height, followed by the trees
+/// in name order, each one as a [`SnapshotEntry::Tree`] followed by its
+/// records in key order, and terminated by [`SnapshotEntry::End`], so it
+/// never has to be kept in memory as a whole. Created by
+/// [`super::Blockchain::export_snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub enum SnapshotEntry {
+    /// Start of the records of the tree with given name
+    Tree(Vec<u8>),
+    /// A record of the current tree, in the form of (`key`, `value`)
+    Record(Vec<u8>, Vec<u8>),
+    /// End of the snapshot
+    End,
+}
+
+/// Writer of a snapshot stream, hashing everything written so the
+/// snapshot can be verified against a trusted checkpoint.
+pub struct SnapshotWriter<W: Write> {
+    writer: W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    /// Start the snapshot stream of the state at given height.
+    pub fn new(writer: W, height: u64) -> Result<Self> {
+        let mut snapshot = Self { writer, hasher: blake3::Hasher::new() };
+        snapshot.write(&height)?;
+        Ok(snapshot)
+    }
+
+    /// Write the next entry of the stream.
+    pub fn write_entry(&mut self, entry: &SnapshotEntry) -> Result<()> {
+        self.write(entry)
+    }
+
+    fn write<T: Encodable>(&mut self, item: &T) -> Result<()> {
+        let bytes = serialize(item);
+        self.hasher.update(&bytes);
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Terminate the stream, returning the snapshot hash.
+    pub fn finish(mut self) -> Result<blake3::Hash> {
+        self.write(&SnapshotEntry::End)?;
+        self.writer.flush()?;
+        Ok(self.hasher.finalize())
+    }
+}
+
+/// Auxiliary reader hashing everything read through it.
+struct HashReader<R: Read> {
+    reader: R,
+    hasher: blake3::Hasher,
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Reader of a snapshot stream, hashing everything read so the
+/// snapshot can be verified against a trusted checkpoint.
+pub struct SnapshotReader<R: Read> {
+    reader: HashReader<R>,
+    height: u64,
+    done: bool,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    /// Start reading a snapshot stream.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = HashReader { reader, hasher: blake3::Hasher::new() };
+        let height = u64::decode(&mut reader)?;
+        Ok(Self { reader, height, done: false })
+    }
+
+    /// Height of the last block included in the snapshot
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Read the next entry of the stream, returning `None` once its
+    /// end has been reached.
+    pub fn next_entry(&mut self) -> Result<Option<SnapshotEntry>> {
+        if self.done {
+            return Ok(None)
+        }
+
+        match SnapshotEntry::decode(&mut self.reader)? {
+            SnapshotEntry::End => {
+                self.done = true;
+                Ok(None)
+            }
+            entry => Ok(Some(entry)),
+        }
+    }
+
+    /// Read the rest of the stream, returning the snapshot hash.
+    pub fn finish(mut self) -> Result<blake3::Hash> {
+        while self.next_entry()?.is_some() {}
+        Ok(self.reader.hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        blockchain::{Blockchain, BlockchainOverlay},
+        Error,
+    };
+
+    const TEST_TREE: &[u8] = b"_test_state";
+    const TEST_NEW_TREE: &[u8] = b"_test_new_state";
+
+    fn export(blockchain: &Blockchain, height: u64) -> Result<(Vec<u8>, blake3::Hash)> {
+        let mut bytes = vec![];
+        let hash = blockchain.export_snapshot(height, &mut bytes)?;
+        Ok((bytes, hash))
+    }
+
+    #[test]
+    fn export_import_snapshot() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&db)?;
+
+        // Each block bumps a counter and removes the key written by the
+        // previous one, while the third one also creates a new tree.
+        for height in 0..4u64 {
+            let overlay = BlockchainOverlay::new(&blockchain)?;
+            let overlay = overlay.lock().unwrap();
+            overlay.overlay.lock().unwrap().begin_block(height);
+            overlay.order.insert(&[height], &[blake3::hash(&height.to_be_bytes())])?;
+
+            let mut lock = overlay.overlay.lock().unwrap();
+            lock.open_tree(TEST_TREE)?;
+            lock.insert(TEST_TREE, b"counter", &height.to_be_bytes())?;
+            lock.insert(TEST_TREE, &height.to_be_bytes(), b"latest")?;
+            if let Some(previous) = height.checked_sub(1) {
+                lock.remove(TEST_TREE, &previous.to_be_bytes())?;
+            }
+            if height == 2 {
+                lock.open_tree(TEST_NEW_TREE)?;
+                lock.insert(TEST_NEW_TREE, b"key", b"value")?;
+            }
+            lock.apply()?;
+        }
+
+        // Pending transactions are not part of the snapshot
+        blockchain.add_pending_txs(&[Default::default()])?;
+
+        assert!(export(&blockchain, 4).is_err());
+        let (tip, tip_hash) = export(&blockchain, 3)?;
+        let (snapshot, hash) = export(&blockchain, 1)?;
+        assert_ne!(hash, tip_hash);
+        assert_eq!(SnapshotReader::new(Cursor::new(&snapshot))?.finish()?, hash);
+
+        let trees = |bytes: &[u8]| -> Result<Vec<Vec<u8>>> {
+            let mut reader = SnapshotReader::new(Cursor::new(bytes))?;
+            let mut trees = vec![];
+            while let Some(entry) = reader.next_entry()? {
+                if let SnapshotEntry::Tree(name) = entry {
+                    trees.push(name);
+                }
+            }
+            Ok(trees)
+        };
+        assert!(trees(&tip)?.iter().any(|name| name == TEST_NEW_TREE));
+        assert!(!trees(&snapshot)?.iter().any(|name| name == TEST_NEW_TREE));
+
+        // Exporting doesn't modify the database
+        assert_eq!(export(&blockchain, 3)?.0, tip);
+
+        // Import must match the trusted checkpoint
+        let new_db = sled::Config::new().temporary(true).open()?;
+        let imported = Blockchain::new(&new_db)?;
+        assert!(imported.import_snapshot(Cursor::new(&snapshot), (1, tip_hash)).is_err());
+        assert!(imported.import_snapshot(Cursor::new(&snapshot), (3, hash)).is_err());
+        assert!(imported.order.is_empty());
+        assert_eq!(imported.import_snapshot(Cursor::new(&snapshot), (1, hash))?, 1);
+
+        assert_eq!(imported.last()?.0, 1);
+        assert!(imported.get_pending_txs()?.is_empty());
+        let tree = new_db.open_tree(TEST_TREE)?;
+        assert_eq!(tree.get(b"counter")?.unwrap().as_ref(), 1u64.to_be_bytes());
+        assert!(tree.get(0u64.to_be_bytes())?.is_none());
+        assert!(tree.get(1u64.to_be_bytes())?.is_some());
+        assert_eq!(export(&imported, 1)?.0, snapshot);
+
+        // Snapshots can't be imported over an existing chain
+        assert!(imported.import_snapshot(Cursor::new(&snapshot), (1, hash)).is_err());
+
+        // Exported state matches the one after a rollback
+        blockchain.rollback_to(1)?;
+        assert_eq!(export(&blockchain, 1)?, (snapshot, hash));
+
+        // Snapshots at heights whose journal was pruned can't be exported
+        blockchain.journal.prune(1)?;
+        assert!(matches!(export(&blockchain, 0), Err(Error::SnapshotJournalPruned(0, 1))));
+        assert_eq!(export(&blockchain, 1)?.1, hash);
+
+        Ok(())
+    }
+}
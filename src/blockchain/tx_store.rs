@@ -25,6 +25,7 @@ use crate::{tx::Transaction, Error, Result};
 use super::{parse_record, parse_u64_key_record, SledDbOverlayPtr};
 
 const SLED_TX_TREE: &[u8] = b"_transactions";
+const SLED_TX_LOCATION_TREE: &[u8] = b"_transaction_location";
 const SLED_PENDING_TX_TREE: &[u8] = b"_pending_transactions";
 const SLED_PENDING_TX_ORDER_TREE: &[u8] = b"_pending_transactions_order";
 
@@ -186,6 +187,123 @@ impl TxStoreOverlay {
     }
 }
 
+/// The `TxLocationStore` is a `sled` tree storing the location of all
+/// the blockchain's transactions, where the key is the transaction hash,
+/// and the value is the serialized tuple (`block height`, `tx index`) of
+/// the block containing it and its position in the block's transactions.
+#[derive(Clone)]
+pub struct TxLocationStore(pub sled::Tree);
+
+impl TxLocationStore {
+    /// Opens a new or existing `TxLocationStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_TX_LOCATION_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Insert a slice of [`blake3::Hash`] of the transactions contained
+    /// in the block with the given height, in the block's order.
+    pub fn insert(&self, tx_hashes: &[blake3::Hash], block_height: u64) -> Result<()> {
+        let batch = self.insert_batch(tx_hashes, block_height);
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Generate the sled batch corresponding to an insert, so caller
+    /// can handle the write operation.
+    pub fn insert_batch(&self, tx_hashes: &[blake3::Hash], block_height: u64) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for (index, tx_hash) in tx_hashes.iter().enumerate() {
+            batch.insert(tx_hash.as_bytes(), serialize(&(block_height, index as u64)));
+        }
+
+        batch
+    }
+
+    /// Fetch the locations of given tx hashes from the store.
+    /// The resulting vector contains `Option`, which is `Some` if the tx
+    /// location was found in the store, and otherwise it is `None`, if it
+    /// has not. The second parameter is a boolean which tells the function
+    /// to fail in case at least one location was not found.
+    pub fn get(&self, tx_hashes: &[blake3::Hash], strict: bool) -> Result<Vec<Option<(u64, u64)>>> {
+        let mut ret = Vec::with_capacity(tx_hashes.len());
+
+        for tx_hash in tx_hashes {
+            if let Some(found) = self.0.get(tx_hash.as_bytes())? {
+                let location = deserialize(&found)?;
+                ret.push(Some(location));
+            } else {
+                if strict {
+                    let s = tx_hash.to_hex().as_str().to_string();
+                    return Err(Error::TransactionNotFound(s))
+                }
+                ret.push(None);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Retrieve records count
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Overlay structure over a [`TxLocationStore`] instance.
+pub struct TxLocationStoreOverlay(SledDbOverlayPtr);
+
+impl TxLocationStoreOverlay {
+    pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
+        overlay.lock().unwrap().open_tree(SLED_TX_LOCATION_TREE)?;
+        Ok(Self(overlay.clone()))
+    }
+
+    /// Insert a slice of [`blake3::Hash`] of the transactions contained
+    /// in the block with the given height into the overlay, in the
+    /// block's order.
+    pub fn insert(&self, tx_hashes: &[blake3::Hash], block_height: u64) -> Result<()> {
+        let mut lock = self.0.lock().unwrap();
+
+        for (index, tx_hash) in tx_hashes.iter().enumerate() {
+            let location = serialize(&(block_height, index as u64));
+            lock.insert(SLED_TX_LOCATION_TREE, tx_hash.as_bytes(), &location)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the locations of given tx hashes from the overlay.
+    /// The resulting vector contains `Option`, which is `Some` if the tx
+    /// location was found in the overlay, and otherwise it is `None`, if it
+    /// has not. The second parameter is a boolean which tells the function
+    /// to fail in case at least one location was not found.
+    pub fn get(&self, tx_hashes: &[blake3::Hash], strict: bool) -> Result<Vec<Option<(u64, u64)>>> {
+        let mut ret = Vec::with_capacity(tx_hashes.len());
+        let lock = self.0.lock().unwrap();
+
+        for tx_hash in tx_hashes {
+            if let Some(found) = lock.get(SLED_TX_LOCATION_TREE, tx_hash.as_bytes())? {
+                let location = deserialize(&found)?;
+                ret.push(Some(location));
+            } else {
+                if strict {
+                    let s = tx_hash.to_hex().as_str().to_string();
+                    return Err(Error::TransactionNotFound(s))
+                }
+                ret.push(None);
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
 /// The `PendingTxStore` is a `sled` tree storing all the node pending
 /// transactions where the key is the transaction hash, and the value is
 /// the serialized transaction.
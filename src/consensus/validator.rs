@@ -1061,7 +1061,7 @@ impl ValidatorState {
             // After getting the metadata, we run the "exec" function with the same
             // runtime and the same payload.
             info!(target: "consensus::validator", "Executing \"exec\" call");
-            let state_update = runtime.exec(&payload)?;
+            let (state_update, _) = runtime.exec(&payload)?;
 
             info!(target: "consensus::validator", "Successfully executed \"exec\" call");
             updates.push(state_update);
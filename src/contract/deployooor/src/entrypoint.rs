@@ -27,9 +27,9 @@ use darkfi_sdk::{
 use darkfi_serial::{deserialize, serialize};
 
 use crate::{
-    model::{DeployUpdateV1, LockUpdateV1},
-    DeployFunction, DEPLOY_CONTRACT_DB_VERSION, DEPLOY_CONTRACT_INFO_TREE,
-    DEPLOY_CONTRACT_LOCK_TREE,
+    model::{DeployUpdateV1, LockUpdateV1, SetAuthorityUpdateV1, UpgradeUpdateV1},
+    DeployFunction, DEPLOY_CONTRACT_AUTHORITY_TREE, DEPLOY_CONTRACT_DB_VERSION,
+    DEPLOY_CONTRACT_INFO_TREE, DEPLOY_CONTRACT_LOCK_TREE,
 };
 
 /// `Deployooor::Deploy` functions
@@ -40,6 +40,19 @@ use deploy_v1::{deploy_get_metadata_v1, deploy_process_instruction_v1, deploy_pr
 mod lock_v1;
 use lock_v1::{lock_get_metadata_v1, lock_process_instruction_v1, lock_process_update_v1};
 
+/// `Deployooor::Upgrade` functions
+mod upgrade_v1;
+use upgrade_v1::{
+    upgrade_get_metadata_v1, upgrade_process_instruction_v1, upgrade_process_update_v1,
+};
+
+/// `Deployooor::SetAuthority` functions
+mod set_authority_v1;
+use set_authority_v1::{
+    set_authority_get_metadata_v1, set_authority_process_instruction_v1,
+    set_authority_process_update_v1,
+};
+
 darkfi_sdk::define_contract!(
     init: init_contract,
     exec: process_instruction,
@@ -70,6 +83,12 @@ fn init_contract(cid: ContractId, _ix: &[u8]) -> ContractResult {
         db_init(cid, DEPLOY_CONTRACT_LOCK_TREE)?;
     }
 
+    // Set up a database to hold the upgrade authorities that were set
+    // k=ContractId, v=UpgradeAuthority
+    if db_lookup(cid, DEPLOY_CONTRACT_AUTHORITY_TREE).is_err() {
+        db_init(cid, DEPLOY_CONTRACT_AUTHORITY_TREE)?;
+    }
+
     // Update db version
     db_set(
         info_db,
@@ -100,6 +119,16 @@ fn get_metadata(cid: ContractId, ix: &[u8]) -> ContractResult {
             let metadata = lock_get_metadata_v1(cid, call_idx, calls)?;
             Ok(set_return_data(&metadata)?)
         }
+
+        DeployFunction::UpgradeV1 => {
+            let metadata = upgrade_get_metadata_v1(cid, call_idx, calls)?;
+            Ok(set_return_data(&metadata)?)
+        }
+
+        DeployFunction::SetAuthorityV1 => {
+            let metadata = set_authority_get_metadata_v1(cid, call_idx, calls)?;
+            Ok(set_return_data(&metadata)?)
+        }
     }
 }
 
@@ -122,6 +151,16 @@ fn process_instruction(cid: ContractId, ix: &[u8]) -> ContractResult {
             let update_data = lock_process_instruction_v1(cid, call_idx, calls)?;
             Ok(set_return_data(&update_data)?)
         }
+
+        DeployFunction::UpgradeV1 => {
+            let update_data = upgrade_process_instruction_v1(cid, call_idx, calls)?;
+            Ok(set_return_data(&update_data)?)
+        }
+
+        DeployFunction::SetAuthorityV1 => {
+            let update_data = set_authority_process_instruction_v1(cid, call_idx, calls)?;
+            Ok(set_return_data(&update_data)?)
+        }
     }
 }
 
@@ -140,5 +179,15 @@ fn process_update(cid: ContractId, update_data: &[u8]) -> ContractResult {
             let update: LockUpdateV1 = deserialize(&update_data[1..])?;
             Ok(lock_process_update_v1(cid, update)?)
         }
+
+        DeployFunction::UpgradeV1 => {
+            let update: UpgradeUpdateV1 = deserialize(&update_data[1..])?;
+            Ok(upgrade_process_update_v1(cid, update)?)
+        }
+
+        DeployFunction::SetAuthorityV1 => {
+            let update: SetAuthorityUpdateV1 = deserialize(&update_data[1..])?;
+            Ok(set_authority_process_update_v1(cid, update)?)
+        }
     }
 }
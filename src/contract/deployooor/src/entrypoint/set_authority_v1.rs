@@ -0,0 +1,88 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::{
+    crypto::ContractId,
+    db::{db_lookup, db_set},
+    error::{ContractError, ContractResult},
+    msg,
+    pasta::pallas,
+    ContractCall,
+};
+use darkfi_serial::{deserialize, serialize, Encodable, WriteExt};
+
+use super::upgrade_v1::{authority_signature_pubkeys, check_upgrade_authority};
+use crate::{
+    model::{SetAuthorityParamsV1, SetAuthorityUpdateV1},
+    DeployFunction, DEPLOY_CONTRACT_AUTHORITY_TREE,
+};
+
+/// `get_metadata` function for `Deploy::SetAuthorityV1`
+pub(crate) fn set_authority_get_metadata_v1(
+    _cid: ContractId,
+    call_idx: u32,
+    calls: Vec<ContractCall>,
+) -> Result<Vec<u8>, ContractError> {
+    let self_ = &calls[call_idx as usize];
+    let params: SetAuthorityParamsV1 = deserialize(&self_.data[1..])?;
+
+    // Public inputs for the ZK proofs we have to verify
+    let zk_public_inputs: Vec<(String, Vec<pallas::Base>)> = vec![];
+    // Public keys for the transaction signatures we have to verify
+    let signature_pubkeys = authority_signature_pubkeys(params.public_key)?;
+
+    // Serialize everything gathered and return it
+    let mut metadata = vec![];
+    zk_public_inputs.encode(&mut metadata)?;
+    signature_pubkeys.encode(&mut metadata)?;
+
+    Ok(metadata)
+}
+
+/// `process_instruction` function for `Deploy::SetAuthorityV1`
+pub(crate) fn set_authority_process_instruction_v1(
+    cid: ContractId,
+    call_idx: u32,
+    calls: Vec<ContractCall>,
+) -> Result<Vec<u8>, ContractError> {
+    let self_ = &calls[call_idx as usize];
+    let params: SetAuthorityParamsV1 = deserialize(&self_.data[1..])?;
+
+    // Only the current upgrade authority can hand it over
+    check_upgrade_authority(cid, &params.contract_id, &params.public_key)?;
+
+    let update =
+        SetAuthorityUpdateV1 { contract_id: params.contract_id, authority: params.authority };
+    let mut update_data = vec![];
+    update_data.write_u8(DeployFunction::SetAuthorityV1 as u8)?;
+    update.encode(&mut update_data)?;
+
+    Ok(update_data)
+}
+
+/// `process_update` function for `Deploy::SetAuthorityV1`
+pub(crate) fn set_authority_process_update_v1(
+    cid: ContractId,
+    update: SetAuthorityUpdateV1,
+) -> ContractResult {
+    msg!("[SetAuthorityV1] Setting ContractID upgrade authority");
+    let authority_db = db_lookup(cid, DEPLOY_CONTRACT_AUTHORITY_TREE)?;
+    db_set(authority_db, &serialize(&update.contract_id), &serialize(&update.authority))?;
+
+    Ok(())
+}
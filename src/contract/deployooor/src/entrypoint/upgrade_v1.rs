@@ -0,0 +1,145 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::{
+    call::{get_caller, upgrade_contract},
+    crypto::{ContractId, PublicKey},
+    db::{db_get, db_lookup},
+    error::{ContractError, ContractResult},
+    msg,
+    pasta::pallas,
+    ContractCall,
+};
+use darkfi_serial::{deserialize, serialize, Encodable, WriteExt};
+
+use crate::{
+    error::DeployError,
+    model::{UpgradeAuthority, UpgradeParamsV1, UpgradeUpdateV1},
+    DeployFunction, DEPLOY_CONTRACT_AUTHORITY_TREE, DEPLOY_CONTRACT_LOCK_TREE,
+};
+
+/// `get_metadata` function for `Deploy::UpgradeV1`
+pub(crate) fn upgrade_get_metadata_v1(
+    _cid: ContractId,
+    call_idx: u32,
+    calls: Vec<ContractCall>,
+) -> Result<Vec<u8>, ContractError> {
+    let self_ = &calls[call_idx as usize];
+    let params: UpgradeParamsV1 = deserialize(&self_.data[1..])?;
+
+    // Public inputs for the ZK proofs we have to verify
+    let zk_public_inputs: Vec<(String, Vec<pallas::Base>)> = vec![];
+    // Public keys for the transaction signatures we have to verify
+    let signature_pubkeys = authority_signature_pubkeys(params.public_key)?;
+
+    // Serialize everything gathered and return it
+    let mut metadata = vec![];
+    zk_public_inputs.encode(&mut metadata)?;
+    signature_pubkeys.encode(&mut metadata)?;
+
+    Ok(metadata)
+}
+
+/// Public keys that must sign the transaction for the upgrade authority check.
+/// Calls invoked by a governance contract are authorized by the caller itself,
+/// and nested calls can't request signatures, so none are required for them.
+pub(crate) fn authority_signature_pubkeys(
+    public_key: PublicKey,
+) -> Result<Vec<PublicKey>, ContractError> {
+    match get_caller()? {
+        Some(_) => Ok(vec![]),
+        None => Ok(vec![public_key]),
+    }
+}
+
+/// Check that the given contract exists, isn't locked, and that the call is
+/// made by its upgrade authority: either the given public key, which signed
+/// the transaction, or the governance contract invoking this one. Calls
+/// invoked by any other contract are rejected, since their signatures are
+/// not verified.
+pub(crate) fn check_upgrade_authority(
+    cid: ContractId,
+    contract_id: &ContractId,
+    public_key: &PublicKey,
+) -> ContractResult {
+    let lock_db = db_lookup(cid, DEPLOY_CONTRACT_LOCK_TREE)?;
+    let Some(v) = db_get(lock_db, &serialize(contract_id))? else {
+        msg!("Error: Contract ID doesn't exist.");
+        return Err(DeployError::ContractNonExistent.into())
+    };
+
+    let locked: bool = deserialize(&v)?;
+    if locked {
+        msg!("Error: Contract is locked. Cannot upgrade.");
+        return Err(DeployError::ContractLocked.into())
+    }
+
+    let caller = get_caller()?;
+    let authority_db = db_lookup(cid, DEPLOY_CONTRACT_AUTHORITY_TREE)?;
+    let authorized = match db_get(authority_db, &serialize(contract_id))? {
+        Some(v) => match deserialize::<UpgradeAuthority>(&v)? {
+            UpgradeAuthority::PublicKey(authority) => caller.is_none() && &authority == public_key,
+            UpgradeAuthority::Contract(authority) => caller == Some(authority),
+        },
+        // The deployer stays the authority until it's set
+        None => caller.is_none() && &ContractId::derive_public(*public_key) == contract_id,
+    };
+
+    if !authorized {
+        msg!("Error: Caller is not the upgrade authority.");
+        return Err(DeployError::UnauthorizedUpgrade.into())
+    }
+
+    Ok(())
+}
+
+/// `process_instruction` function for `Deploy::UpgradeV1`
+pub(crate) fn upgrade_process_instruction_v1(
+    cid: ContractId,
+    call_idx: u32,
+    calls: Vec<ContractCall>,
+) -> Result<Vec<u8>, ContractError> {
+    let self_ = &calls[call_idx as usize];
+    let params: UpgradeParamsV1 = deserialize(&self_.data[1..])?;
+
+    check_upgrade_authority(cid, &params.contract_id, &params.public_key)?;
+
+    let update = UpgradeUpdateV1 {
+        contract_id: params.contract_id,
+        wasm_bincode: params.wasm_bincode,
+        migrate_payload: params.migrate_payload,
+    };
+    let mut update_data = vec![];
+    update_data.write_u8(DeployFunction::UpgradeV1 as u8)?;
+    update.encode(&mut update_data)?;
+
+    Ok(update_data)
+}
+
+/// `process_update` function for `Deploy::UpgradeV1`
+pub(crate) fn upgrade_process_update_v1(
+    _cid: ContractId,
+    update: UpgradeUpdateV1,
+) -> ContractResult {
+    // The runtime keeps the previous bincode as an older version, deploys
+    // the new one and runs its migration.
+    msg!("[UpgradeV1] Upgrading ContractID to new version");
+    upgrade_contract(update.contract_id, &update.wasm_bincode, &update.migrate_payload)?;
+
+    Ok(())
+}
@@ -25,6 +25,9 @@ pub enum DeployError {
 
     #[error("Contract does not exist.")]
     ContractNonExistent,
+
+    #[error("Caller is not the contract upgrade authority.")]
+    UnauthorizedUpgrade,
 }
 
 impl From<DeployError> for ContractError {
@@ -32,6 +35,7 @@ impl From<DeployError> for ContractError {
         match e {
             DeployError::ContractLocked => Self::Custom(1),
             DeployError::ContractNonExistent => Self::Custom(2),
+            DeployError::UnauthorizedUpgrade => Self::Custom(3),
         }
     }
 }
@@ -25,6 +25,8 @@ use darkfi_sdk::error::ContractError;
 pub enum DeployFunction {
     DeployV1 = 0x00,
     LockV1 = 0x01,
+    UpgradeV1 = 0x02,
+    SetAuthorityV1 = 0x03,
 }
 
 impl TryFrom<u8> for DeployFunction {
@@ -34,6 +36,8 @@ impl TryFrom<u8> for DeployFunction {
         match b {
             0x00 => Ok(Self::DeployV1),
             0x01 => Ok(Self::LockV1),
+            0x02 => Ok(Self::UpgradeV1),
+            0x03 => Ok(Self::SetAuthorityV1),
             _ => Err(ContractError::InvalidFunction),
         }
     }
@@ -52,6 +56,7 @@ pub mod error;
 // These are the different sled trees that will be created
 pub const DEPLOY_CONTRACT_INFO_TREE: &str = "info";
 pub const DEPLOY_CONTRACT_LOCK_TREE: &str = "lock";
+pub const DEPLOY_CONTRACT_AUTHORITY_TREE: &str = "authority";
 
 // These are keys inside the info tree
 pub const DEPLOY_CONTRACT_DB_VERSION: &str = "db_version";
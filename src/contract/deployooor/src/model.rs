@@ -51,3 +51,58 @@ pub struct LockUpdateV1 {
     /// The `ContractId` to lock
     pub contract_id: ContractId,
 }
+
+/// Authority allowed to upgrade a deployed contract. Until it is set with
+/// `Deploy::SetAuthority`, it's the public key the `ContractId` was derived from.
+#[derive(Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub enum UpgradeAuthority {
+    /// Holder of the secret key, signing the upgrade transactions
+    PublicKey(PublicKey),
+    /// Governance contract, like the DAO, invoking the upgrade from its own
+    /// state update through a cross-contract call
+    Contract(ContractId),
+}
+
+/// Parameters for `Deploy::Upgrade`
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct UpgradeParamsV1 {
+    /// The `ContractId` to upgrade
+    pub contract_id: ContractId,
+    /// Webassembly bincode of the new smart contract version
+    pub wasm_bincode: Vec<u8>,
+    /// Payload for the `__migrate` function of the new version, if it has one
+    pub migrate_payload: Vec<u8>,
+    /// Public key used to sign the transaction, when the upgrade authority is one
+    pub public_key: PublicKey,
+}
+
+/// State update for `Deploy::Upgrade`
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct UpgradeUpdateV1 {
+    /// The `ContractId` to upgrade
+    pub contract_id: ContractId,
+    /// Webassembly bincode of the new smart contract version
+    pub wasm_bincode: Vec<u8>,
+    /// Payload for the `__migrate` function of the new version
+    pub migrate_payload: Vec<u8>,
+}
+
+/// Parameters for `Deploy::SetAuthority`
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct SetAuthorityParamsV1 {
+    /// The `ContractId` to set the upgrade authority of
+    pub contract_id: ContractId,
+    /// The new upgrade authority
+    pub authority: UpgradeAuthority,
+    /// Public key used to sign the transaction, when the upgrade authority is one
+    pub public_key: PublicKey,
+}
+
+/// State update for `Deploy::SetAuthority`
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct SetAuthorityUpdateV1 {
+    /// The `ContractId` to set the upgrade authority of
+    pub contract_id: ContractId,
+    /// The new upgrade authority
+    pub authority: UpgradeAuthority,
+}
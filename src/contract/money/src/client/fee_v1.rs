@@ -0,0 +1,194 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{
+    zk::{Proof, ProvingKey},
+    zkas::ZkBinary,
+    ClientFailed, Result,
+};
+use darkfi_sdk::{
+    crypto::{
+        note::AeadEncryptedNote, pasta_prelude::*, MerkleTree, Nullifier, PublicKey, SecretKey,
+        DARK_TOKEN_ID,
+    },
+    pasta::pallas,
+};
+use log::{debug, error, info};
+use rand::rngs::OsRng;
+
+use crate::{
+    client::{
+        transfer_v1::{
+            create_transfer_burn_proof, create_transfer_mint_proof, TransactionBuilderInputInfo,
+            TransactionBuilderOutputInfo,
+        },
+        MoneyNote, OwnCoin,
+    },
+    model::{Input, MoneyFeeParamsV1, Output},
+};
+
+/// Output metadata claimed from building a `Money::Fee` call
+pub struct FeeCallDebris {
+    /// The parameters for `Money::Fee` respective to this call
+    pub params: MoneyFeeParamsV1,
+    /// The ZK proofs created in this builder
+    pub proofs: Vec<Proof>,
+    /// The ephemeral secret key created for signing
+    pub signature_secret: SecretKey,
+    /// The coin that has been spent in this builder
+    pub spent_coin: OwnCoin,
+    /// The change coin that has been minted in this builder
+    pub change_coin: OwnCoin,
+}
+
+/// Struct holding necessary information to build a `Money::FeeV1` contract call.
+pub struct FeeCallBuilder {
+    /// `OwnCoin` we're given to pay the fee with
+    pub coin: OwnCoin,
+    /// Fee value to pay
+    pub fee_value: u64,
+    /// Merkle tree of coins used to create inclusion proofs
+    pub tree: MerkleTree,
+    /// `Mint_V1` zkas circuit ZkBinary
+    pub mint_zkbin: ZkBinary,
+    /// Proving key for the `Mint_V1` zk circuit
+    pub mint_pk: ProvingKey,
+    /// `Burn_V1` zkas circuit ZkBinary
+    pub burn_zkbin: ZkBinary,
+    /// Proving key for the `Burn_V1` zk circuit
+    pub burn_pk: ProvingKey,
+}
+
+impl FeeCallBuilder {
+    pub fn build(&self) -> Result<FeeCallDebris> {
+        debug!("Building Money::FeeV1 contract call");
+        if self.coin.note.token_id != *DARK_TOKEN_ID {
+            error!("Fees can only be paid with the native token");
+            return Err(ClientFailed::InvalidTokenId(self.coin.note.token_id.to_string()).into())
+        }
+
+        if self.coin.note.value < self.fee_value {
+            error!("Not enough value to pay the fee");
+            return Err(ClientFailed::NotEnoughValue(self.coin.note.value).into())
+        }
+
+        // The change goes back to the coin owner
+        let change_public = PublicKey::from_secret(self.coin.secret);
+        let change_value = self.coin.note.value - self.fee_value;
+
+        // Create new random blinds and an ephemeral signature key. The fee
+        // value blind is the difference of the input and output value blinds,
+        // so the value commitments balance out with the clear fee value.
+        let input_value_blind = pallas::Scalar::random(&mut OsRng);
+        let output_value_blind = pallas::Scalar::random(&mut OsRng);
+        let fee_value_blind = input_value_blind - output_value_blind;
+        let token_blind = pallas::Base::random(&mut OsRng);
+        let user_data_blind = pallas::Base::random(&mut OsRng);
+        let signature_secret = SecretKey::random(&mut OsRng);
+
+        info!("Creating fee burn proof for input");
+        let input = TransactionBuilderInputInfo {
+            leaf_position: self.coin.leaf_position,
+            merkle_path: self.tree.witness(self.coin.leaf_position, 0).unwrap(),
+            secret: self.coin.secret,
+            note: self.coin.note.clone(),
+        };
+        let (burn_proof, burn_revealed) = create_transfer_burn_proof(
+            &self.burn_zkbin,
+            &self.burn_pk,
+            &input,
+            input_value_blind,
+            token_blind,
+            user_data_blind,
+            signature_secret,
+        )?;
+
+        info!("Creating fee mint proof for change output");
+        let output = TransactionBuilderOutputInfo {
+            value: change_value,
+            token_id: *DARK_TOKEN_ID,
+            public_key: change_public,
+        };
+        let serial = pallas::Base::random(&mut OsRng);
+        let spend_hook = pallas::Base::ZERO;
+        let user_data = pallas::Base::ZERO;
+        let (mint_proof, mint_revealed) = create_transfer_mint_proof(
+            &self.mint_zkbin,
+            &self.mint_pk,
+            &output,
+            output_value_blind,
+            token_blind,
+            serial,
+            spend_hook,
+            user_data,
+        )?;
+
+        // Encrypted note
+        let note = MoneyNote {
+            serial,
+            value: change_value,
+            token_id: *DARK_TOKEN_ID,
+            spend_hook,
+            user_data,
+            value_blind: output_value_blind,
+            token_blind,
+            memo: vec![],
+        };
+        let encrypted_note = AeadEncryptedNote::encrypt(&note, &change_public, &mut OsRng)?;
+
+        let change_coin = OwnCoin {
+            coin: mint_revealed.coin,
+            note,
+            secret: SecretKey::from(pallas::Base::ZERO),
+            nullifier: Nullifier::from(pallas::Base::ZERO),
+            leaf_position: 0.into(),
+        };
+
+        let params = MoneyFeeParamsV1 {
+            fee_value: self.fee_value,
+            fee_value_blind,
+            token_blind,
+            input: Input {
+                value_commit: burn_revealed.value_commit,
+                token_commit: burn_revealed.token_commit,
+                nullifier: burn_revealed.nullifier,
+                merkle_root: burn_revealed.merkle_root,
+                spend_hook: burn_revealed.spend_hook,
+                user_data_enc: burn_revealed.user_data_enc,
+                signature_public: burn_revealed.signature_public,
+            },
+            output: Output {
+                value_commit: mint_revealed.value_commit,
+                token_commit: mint_revealed.token_commit,
+                coin: mint_revealed.coin,
+                note: encrypted_note,
+            },
+        };
+
+        // Now we should have all the params, zk proofs, and signature secret.
+        // We return it all and let the caller deal with it.
+        let debris = FeeCallDebris {
+            params,
+            proofs: vec![burn_proof, mint_proof],
+            signature_secret,
+            spent_coin: self.coin.clone(),
+            change_coin,
+        };
+        Ok(debris)
+    }
+}
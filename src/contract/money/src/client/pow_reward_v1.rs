@@ -83,6 +83,8 @@ pub struct PoWRewardCallBuilder {
     pub spend_hook: pallas::Base,
     /// User data for the output
     pub user_data: pallas::Base,
+    /// Total fees paid by the rewarded block transactions
+    pub fees: u64,
     /// `Mint_V1` zkas circuit ZkBinary
     pub mint_zkbin: ZkBinary,
     /// Proving key for the `Mint_V1` zk circuit
@@ -163,6 +165,7 @@ impl PoWRewardCallBuilder {
             fork_hash: self.fork_hash,
             fork_previous_hash: self.fork_previous_hash,
             vrf_proof,
+            fees: self.fees,
         };
         let debris = PoWRewardCallDebris { params, proofs: vec![proof] };
         Ok(debris)
@@ -171,7 +174,7 @@ impl PoWRewardCallBuilder {
     pub fn build(&self) -> Result<PoWRewardCallDebris> {
         let reward = expected_reward(self.block_height);
         assert!(reward != 0);
-        self._build(reward)
+        self._build(reward + self.fees)
     }
 
     /// This function should only be used for testing, as PoW reward values are predefined
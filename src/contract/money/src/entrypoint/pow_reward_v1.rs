@@ -133,8 +133,13 @@ pub(crate) fn money_pow_reward_process_instruction_v1(
         return Err(MoneyError::TransferClearInputNonNativeToken.into())
     }
 
-    // Verify reward value matches the expected one for this slot(block height)
-    let expected_reward = expected_reward(verifying_slot);
+    // Verify reward value matches the expected one for this slot(block height),
+    // plus the block transactions fees. The validator verifies the fees match
+    // the ones paid in the block.
+    let Some(expected_reward) = expected_reward(verifying_slot).checked_add(params.fees) else {
+        msg!("[PoWRewardV1] Error: Block fees({}) overflow the reward value", params.fees);
+        return Err(MoneyError::PoWRewardFeesOverflow.into())
+    };
     if params.input.value != expected_reward {
         msg!(
             "[PoWRewardV1] Error: Reward value({}) is not the block height({}) expected one: {}",
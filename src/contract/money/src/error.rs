@@ -129,6 +129,12 @@ pub enum MoneyError {
 
     #[error("Eta VRF proof couldn't be verified")]
     PoWRewardErroneousVrfProof,
+
+    #[error("Input used non-native token")]
+    FeeInputNonNativeToken,
+
+    #[error("Fee value overflows the block reward")]
+    PoWRewardFeesOverflow,
 }
 
 impl From<MoneyError> for ContractError {
@@ -170,6 +176,8 @@ impl From<MoneyError> for ContractError {
             MoneyError::PoWRewardMissingSlot => Self::Custom(34),
             MoneyError::PoWRewardExtendsUnknownFork => Self::Custom(35),
             MoneyError::PoWRewardErroneousVrfProof => Self::Custom(36),
+            MoneyError::FeeInputNonNativeToken => Self::Custom(37),
+            MoneyError::PoWRewardFeesOverflow => Self::Custom(38),
         }
     }
 }
@@ -17,14 +17,15 @@
  */
 
 //! Smart contract implementing money transfers, atomic swaps, token
-//! minting and freezing, and staking/unstaking of consensus tokens.
+//! minting and freezing, staking/unstaking of consensus tokens, and
+//! transaction fee payments.
 
 use darkfi_sdk::error::ContractError;
 
 /// Functions available in the contract
 #[repr(u8)]
 pub enum MoneyFunction {
-    //Fee = 0x00,
+    FeeV1 = 0x00,
     GenesisMintV1 = 0x01,
     TransferV1 = 0x02,
     OtcSwapV1 = 0x03,
@@ -40,7 +41,7 @@ impl TryFrom<u8> for MoneyFunction {
 
     fn try_from(b: u8) -> core::result::Result<Self, Self::Error> {
         match b {
-            //0x00 => Ok(Self::Fee),
+            0x00 => Ok(Self::FeeV1),
             0x01 => Ok(Self::GenesisMintV1),
             0x02 => Ok(Self::TransferV1),
             0x03 => Ok(Self::OtcSwapV1),
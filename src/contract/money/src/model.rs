@@ -219,6 +219,10 @@ pub struct MoneyPoWRewardParamsV1 {
     pub fork_previous_hash: blake3::Hash,
     /// VRF proof for block rank calculation
     pub vrf_proof: VrfProof,
+    /// Total fees paid by the block transactions, rewarded on top of
+    /// the block reward. Kept last so the validator can read it without
+    /// decoding the whole call.
+    pub fees: u64,
 }
 
 /// State update for `Money::PoWReward`
@@ -228,6 +232,31 @@ pub struct MoneyPoWRewardUpdateV1 {
     pub coin: Coin,
 }
 
+/// Parameters for `Money::Fee`
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct MoneyFeeParamsV1 {
+    /// Fee value paid, in the clear. Kept first so the validator can
+    /// read it without decoding the whole call.
+    pub fee_value: u64,
+    /// Blinding factor for the fee value commitment
+    pub fee_value_blind: pallas::Scalar,
+    /// Blinding factor for the native token ID commitment
+    pub token_blind: pallas::Base,
+    /// Anonymous input paying the fee
+    pub input: Input,
+    /// Anonymous change output
+    pub output: Output,
+}
+
+/// State update for `Money::Fee`
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct MoneyFeeUpdateV1 {
+    /// Revealed nullifier
+    pub nullifier: Nullifier,
+    /// The newly minted change coin
+    pub coin: Coin,
+}
+
 /// Parameters for `Money::Stake`
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
 // ANCHOR: MoneyStakeParams
@@ -0,0 +1,138 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Test for fee transaction verification correctness.
+//!
+//! We first mint Alice some native tokens on genesis slot, and then enable
+//! fee verification so her transactions must pay for their size, proofs and
+//! gas usage.
+//!
+//! With this test, we want to confirm fee calls are verified correctly and
+//! transactions missing or underpaying their fee are rejected.
+
+use darkfi::Result;
+use darkfi_contract_test_harness::{init_logger, Holder, TestHarness, TxAction};
+use darkfi_sdk::crypto::DARK_TOKEN_ID;
+use log::info;
+
+#[test]
+fn fee() -> Result<()> {
+    smol::block_on(async {
+        init_logger();
+
+        // Holders this test will use
+        const HOLDERS: [Holder; 2] = [Holder::Alice, Holder::Bob];
+
+        // Some numbers we want to assert
+        const ALICE_INITIAL: u64 = 1_000_000_000;
+        const FEE_VALUE: u64 = 10_000_000;
+
+        // Slot to verify against
+        let current_slot = 0;
+
+        // Initialize harness
+        let mut th = TestHarness::new(&["money".to_string()]).await?;
+
+        info!(target: "money", "[Alice] ========================");
+        info!(target: "money", "[Alice] Building genesis mint tx");
+        info!(target: "money", "[Alice] ========================");
+        let (genesis_mint_tx, genesis_mint_params) =
+            th.genesis_mint(&Holder::Alice, ALICE_INITIAL)?;
+
+        for holder in &HOLDERS {
+            info!(target: "money", "[{holder:?}] ================================");
+            info!(target: "money", "[{holder:?}] Executing Alice genesis mint tx");
+            info!(target: "money", "[{holder:?}] ================================");
+            th.execute_genesis_mint_tx(
+                holder,
+                &genesis_mint_tx,
+                &genesis_mint_params,
+                current_slot,
+            )
+            .await?;
+        }
+
+        th.assert_trees(&HOLDERS);
+
+        // Alice gathers her new owncoin
+        let alice_oc = th.gather_owncoin(&Holder::Alice, &genesis_mint_params.output, None)?;
+
+        // From now on, transactions must pay their fees
+        for holder in &HOLDERS {
+            th.holders.get(holder).unwrap().validator.write().await.verify_fees = true;
+        }
+
+        info!(target: "money", "[Malicious] ================================");
+        info!(target: "money", "[Malicious] Checking transfer tx without fee");
+        info!(target: "money", "[Malicious] ================================");
+        let (transfer_tx, _, _) = th.transfer(
+            ALICE_INITIAL / 2,
+            &Holder::Alice,
+            &Holder::Bob,
+            &[alice_oc.clone()],
+            *DARK_TOKEN_ID,
+        )?;
+        th.execute_erroneous_txs(
+            TxAction::MoneyTransfer,
+            &Holder::Alice,
+            &[transfer_tx],
+            current_slot,
+            1,
+        )
+        .await?;
+
+        info!(target: "money", "[Malicious] ===========================");
+        info!(target: "money", "[Malicious] Checking underpaying fee tx");
+        info!(target: "money", "[Malicious] ===========================");
+        let (fee_tx, _, _) = th.fee(&Holder::Alice, &alice_oc, 1)?;
+        th.execute_erroneous_txs(TxAction::MoneyFee, &Holder::Alice, &[fee_tx], current_slot, 1)
+            .await?;
+
+        info!(target: "money", "[Alice] ===============");
+        info!(target: "money", "[Alice] Building fee tx");
+        info!(target: "money", "[Alice] ===============");
+        let (fee_tx, fee_params, spent_coin) = th.fee(&Holder::Alice, &alice_oc, FEE_VALUE)?;
+        assert!(spent_coin == alice_oc);
+        assert!(fee_params.fee_value == FEE_VALUE);
+
+        for holder in &HOLDERS {
+            info!(target: "money", "[{holder:?}] ======================");
+            info!(target: "money", "[{holder:?}] Executing Alice fee tx");
+            info!(target: "money", "[{holder:?}] ======================");
+            th.execute_fee_tx(holder, &fee_tx, &fee_params, current_slot).await?;
+        }
+
+        th.assert_trees(&HOLDERS);
+
+        // Alice gathers her change, the fee value is gone
+        let alice_oc = th.gather_owncoin(&Holder::Alice, &fee_params.output, None)?;
+        assert!(alice_oc.note.value == ALICE_INITIAL - FEE_VALUE);
+
+        info!(target: "money", "[Malicious] ===============================");
+        info!(target: "money", "[Malicious] Checking duplicate fee tx spend");
+        info!(target: "money", "[Malicious] ===============================");
+        th.execute_erroneous_txs(TxAction::MoneyFee, &Holder::Alice, &[fee_tx], current_slot, 1)
+            .await?;
+
+        // Statistics
+        th.statistics();
+
+        // Thanks for reading
+        Ok(())
+    })
+}
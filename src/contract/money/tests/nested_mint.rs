@@ -0,0 +1,97 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Test for contracts minting tokens through nested Money calls.
+//!
+//! Alice builds a valid token mint transaction, and a malicious contract
+//! embeds its call, invoking Money with it while being deployed.
+//!
+//! With this test, we want to confirm nested calls can't invoke Money with
+//! it, since the transaction doesn't verify the ZK proofs and signatures
+//! requested by their metadata.
+
+use darkfi::{
+    blockchain::BlockchainOverlay,
+    runtime::{
+        vm_runtime::{ContractSection, Runtime},
+        wat,
+    },
+    util::time::{TimeKeeper, Timestamp},
+    Error, Result,
+};
+use darkfi_contract_test_harness::{init_logger, Holder, TestHarness};
+use darkfi_sdk::{
+    crypto::{ContractId, MONEY_CONTRACT_ID},
+    error::ContractError,
+    pasta::pallas,
+};
+use darkfi_serial::{Encodable, WriteExt};
+use log::info;
+
+#[test]
+fn nested_mint() -> Result<()> {
+    smol::block_on(async {
+        init_logger();
+
+        // Slot to verify against
+        let current_slot = 0;
+
+        // Initialize harness
+        let mut th = TestHarness::new(&["money".to_string()]).await?;
+
+        info!("[Alice] Building token mint tx");
+        let (token_mint_tx, token_mint_params) =
+            th.token_mint(1000, &Holder::Alice, &Holder::Alice, None, None)?;
+
+        // Money call payload, as the transaction would pass it
+        let mut payload = vec![];
+        payload.write_u32(0)?;
+        token_mint_tx.calls.encode(&mut payload)?;
+
+        info!("[Malicious] Deploying contract minting through Money");
+        {
+            let validator = th.holders.get(&Holder::Alice).unwrap().validator.read().await;
+            let overlay = BlockchainOverlay::new(&validator.blockchain)?;
+            let minter_id = ContractId::from(pallas::Base::from(1337));
+            let wasm = wat::invoker(ContractSection::Deploy, &MONEY_CONTRACT_ID, &payload, true);
+            overlay.lock().unwrap().wasm_bincode.insert(
+                minter_id,
+                wasm.as_bytes(),
+                current_slot,
+            )?;
+
+            let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, current_slot);
+            let mut runtime =
+                Runtime::new(wasm.as_bytes(), overlay.clone(), minter_id, time_keeper)?;
+            let Err(Error::ContractError(ContractError::CallerAccessDenied)) = runtime.deploy(&[])
+            else {
+                panic!("Nested Money mint wasn't denied")
+            };
+
+            overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
+        }
+
+        // The transaction itself is valid
+        info!("[Alice] Executing token mint tx");
+        th.execute_token_mint_tx(&Holder::Alice, &token_mint_tx, &token_mint_params, current_slot)
+            .await?;
+
+        // Thanks for reading
+        Ok(())
+    })
+}
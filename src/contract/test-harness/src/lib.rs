@@ -63,6 +63,7 @@ mod dao_mint;
 mod dao_propose;
 mod dao_vote;
 mod money_airdrop;
+mod money_fee;
 mod money_genesis_mint;
 mod money_otc_swap;
 mod money_pow_reward;
@@ -110,6 +111,7 @@ pub enum TxAction {
     MoneyTransfer,
     MoneyOtcSwap,
     MoneyPoWReward,
+    MoneyFee,
     ConsensusGenesisStake,
     ConsensusStake,
     ConsensusProposal,
@@ -155,7 +157,7 @@ impl Wallet {
         // NOTE: we are not using consensus constants here so we
         // don't get circular dependencies.
         let time_keeper = TimeKeeper::new(genesis_block.header.timestamp, 10, 90, 0);
-        let config = ValidatorConfig::new(
+        let mut config = ValidatorConfig::new(
             time_keeper,
             3,
             1,
@@ -165,6 +167,9 @@ impl Wallet {
             faucet_pubkeys.to_vec(),
             false,
         );
+        // Harness transactions don't pay fees, tests can enable this
+        // on the holder validators when they need it.
+        config.verify_fees = false;
         let validator = Validator::new(&sled_db, config).await?;
 
         // Create necessary Merkle trees for tracking
@@ -261,6 +266,7 @@ impl TestHarness {
         tx_action_benchmarks.insert(TxAction::MoneyOtcSwap, TxActionBenchmarks::default());
         tx_action_benchmarks.insert(TxAction::MoneyTransfer, TxActionBenchmarks::default());
         tx_action_benchmarks.insert(TxAction::MoneyPoWReward, TxActionBenchmarks::default());
+        tx_action_benchmarks.insert(TxAction::MoneyFee, TxActionBenchmarks::default());
         tx_action_benchmarks.insert(TxAction::ConsensusGenesisStake, TxActionBenchmarks::default());
         tx_action_benchmarks.insert(TxAction::ConsensusStake, TxActionBenchmarks::default());
         tx_action_benchmarks.insert(TxAction::ConsensusProposal, TxActionBenchmarks::default());
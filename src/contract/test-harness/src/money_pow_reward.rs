@@ -75,6 +75,7 @@ impl TestHarness {
             fork_previous_hash: fork_hash,
             spend_hook,
             user_data,
+            fees: 0,
             mint_zkbin: mint_zkbin.clone(),
             mint_pk: mint_pk.clone(),
         };
@@ -143,6 +143,12 @@ pub enum Error {
     #[error("Failed to reach any seeds")]
     SeedFailed,
 
+    #[error("Seed response is not signed by a pinned key")]
+    SeedResponseUnverified,
+
+    #[error("Invalid seed key: {0}")]
+    InvalidSeedKey(String),
+
     #[error("Network service stopped")]
     NetworkServiceStopped,
 
@@ -351,6 +357,9 @@ pub enum Error {
     #[error("Provided output hash is greater than current target")]
     PoWInvalidOutHash,
 
+    #[error("Provided merge mining data doesn't commit to the block header")]
+    PoWInvalidMergeMiningData,
+
     // ===============
     // Database errors
     // ===============
@@ -372,6 +381,12 @@ pub enum Error {
     #[error("Transaction already seen")]
     TransactionAlreadySeen,
 
+    #[error("Transaction conflicts with pending transaction {0}")]
+    MempoolConflict(String),
+
+    #[error("Mempool is full")]
+    MempoolFull,
+
     #[error("Input vectors have different length")]
     InvalidInputLengths,
 
@@ -396,6 +411,21 @@ pub enum Error {
     #[error("Block difficulty for height number {0} not found in database")]
     BlockDifficultyNotFound(u64),
 
+    #[error("State journal entry for block height {0} not found in database")]
+    StateJournalNotFound(u64),
+
+    #[error("Snapshot height {0} is after the last block")]
+    SnapshotHeightInvalid(u64),
+
+    #[error("Can't export snapshot at height {0}, state journal of block {1} has been pruned")]
+    SnapshotJournalPruned(u64, u64),
+
+    #[error("Snapshot does not match the trusted checkpoint")]
+    SnapshotCheckpointMismatch,
+
+    #[error("Snapshots can only be imported into an empty database")]
+    SnapshotDatabaseNotEmpty,
+
     #[error("Block {0} contains 0 transactions")]
     BlockContainsNoTransactions(String),
 
@@ -566,6 +596,12 @@ pub enum Error {
     #[error("Geode chunk route not found")]
     GeodeChunkRouteNotFound,
 
+    #[error("Geode chunk encryption failed")]
+    GeodeChunkEncryptionFailed,
+
+    #[error("Geode chunk decryption failed")]
+    GeodeChunkDecryptionFailed,
+
     // ==================
     // Event Graph errors
     // ==================
@@ -612,6 +648,9 @@ pub enum TxVerifyFailed {
     #[error("Missing Money::Fee call in transaction")]
     MissingFee,
 
+    #[error("Insufficient transaction fee: paid {0}, required {1}")]
+    InsufficientFee(u64, u64),
+
     #[error("Invalid ZK proof in transaction")]
     InvalidZkProof,
 
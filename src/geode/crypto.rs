@@ -0,0 +1,205 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Optional per-file encryption of Geode chunks.
+//!
+//! An encrypted chunk is stored as `nonce || ciphertext || tag`, where the
+//! ciphertext is ChaCha20-Poly1305 over the plaintext chunk. The nonce is
+//! derived deterministically from the file key and the plaintext, so that
+//! inserting the same content with the same key always yields the same
+//! chunks (and therefore deduplicates), while never reusing a nonce for
+//! different plaintexts under one key.
+//!
+//! Since the stored chunk may not exceed [`MAX_CHUNK_SIZE`], the plaintext
+//! is split into [`ENCRYPTED_CHUNK_PLAINTEXT_SIZE`] pieces instead.
+//!
+//! Nodes hosting encrypted chunks only ever see ciphertext. The file hash
+//! and chunk hashes are computed over the encrypted data, so they can be
+//! verified and served without knowledge of the key.
+
+use crypto_api_chachapoly::ChachaPolyIetf;
+use rand::{rngs::OsRng, RngCore};
+use smol::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, SeekFrom};
+
+use super::MAX_CHUNK_SIZE;
+use crate::{Error, Result};
+
+/// Size of the nonce prepended to every encrypted chunk
+pub const NONCE_SIZE: usize = 12;
+/// Size of the Poly1305 authentication tag
+pub const AEAD_TAG_SIZE: usize = 16;
+/// Maximum plaintext size of a single encrypted chunk
+pub const ENCRYPTED_CHUNK_PLAINTEXT_SIZE: usize = MAX_CHUNK_SIZE - NONCE_SIZE - AEAD_TAG_SIZE;
+
+/// BLAKE3 context used to derive convergent keys from file contents
+const CONVERGENT_KEY_CONTEXT: &str = "darkfi geode 2023-11 convergent file key";
+
+/// Symmetric key used to encrypt and decrypt the chunks of a single file.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct FileKey([u8; 32]);
+
+impl FileKey {
+    /// Generate a new random key. Files encrypted with random keys do not
+    /// deduplicate against other insertions of the same content.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Derive a convergent key from the given plaintext. Anyone holding the
+    /// same content derives the same key, which allows deduplication of
+    /// encrypted files at the cost of revealing whether a node hosts a
+    /// known plaintext.
+    pub fn convergent(plaintext: &[u8]) -> Self {
+        Self(blake3::derive_key(CONVERGENT_KEY_CONTEXT, plaintext))
+    }
+
+    /// Derive a convergent key by reading the entire given stream, and then
+    /// rewind the stream to its start so it can be passed on to
+    /// [`Geode::insert_encrypted`](super::Geode::insert_encrypted).
+    pub async fn convergent_from_stream(
+        stream: &mut (impl AsyncRead + AsyncSeek + Unpin),
+    ) -> Result<Self> {
+        let mut hasher = blake3::Hasher::new_derive_key(CONVERGENT_KEY_CONTEXT);
+        let mut buf = vec![0u8; MAX_CHUNK_SIZE];
+
+        loop {
+            let bytes_read = stream.read(&mut buf).await?;
+            if bytes_read == 0 {
+                break
+            }
+            hasher.update(&buf[..bytes_read]);
+        }
+
+        stream.seek(SeekFrom::Start(0)).await?;
+        Ok(Self(*hasher.finalize().as_bytes()))
+    }
+
+    /// Instantiate a key from raw bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Return the raw bytes of the key
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Encrypt a single plaintext chunk. The plaintext must not be larger
+    /// than [`ENCRYPTED_CHUNK_PLAINTEXT_SIZE`].
+    pub fn encrypt_chunk(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        assert!(plaintext.len() <= ENCRYPTED_CHUNK_PLAINTEXT_SIZE);
+
+        let nonce_hash = blake3::keyed_hash(&self.0, plaintext);
+        let nonce = &nonce_hash.as_bytes()[..NONCE_SIZE];
+
+        let mut chunk = vec![0u8; NONCE_SIZE + plaintext.len() + AEAD_TAG_SIZE];
+        chunk[..NONCE_SIZE].copy_from_slice(nonce);
+
+        if ChachaPolyIetf::aead_cipher()
+            .seal_to(&mut chunk[NONCE_SIZE..], plaintext, &[], &self.0, nonce)
+            .is_err()
+        {
+            return Err(Error::GeodeChunkEncryptionFailed)
+        }
+
+        Ok(chunk)
+    }
+
+    /// Decrypt and authenticate a single stored chunk
+    pub fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>> {
+        if chunk.len() < NONCE_SIZE + AEAD_TAG_SIZE {
+            return Err(Error::GeodeChunkDecryptionFailed)
+        }
+
+        let (nonce, ciphertext) = chunk.split_at(NONCE_SIZE);
+        let mut plaintext = vec![0u8; ciphertext.len()];
+
+        let Ok(len) =
+            ChachaPolyIetf::aead_cipher().open_to(&mut plaintext, ciphertext, &[], &self.0, nonce)
+        else {
+            return Err(Error::GeodeChunkDecryptionFailed)
+        };
+
+        plaintext.truncate(len);
+        Ok(plaintext)
+    }
+}
+
+impl std::fmt::Debug for FileKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Do not leak key material into logs
+        write!(f, "FileKey(..)")
+    }
+}
+
+impl std::str::FromStr for FileKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = match bs58::decode(s).into_vec() {
+            Ok(v) => v,
+            Err(e) => return Err(Error::Custom(format!("Invalid geode file key: {}", e))),
+        };
+
+        match bytes.try_into() {
+            Ok(v) => Ok(Self(v)),
+            Err(_) => Err(Error::Custom("Invalid geode file key length".to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for FileKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", bs58::encode(self.0).into_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_encryption_roundtrip() {
+        let key = FileKey::random();
+        let plaintext = vec![42u8; ENCRYPTED_CHUNK_PLAINTEXT_SIZE];
+
+        let chunk = key.encrypt_chunk(&plaintext).unwrap();
+        assert_eq!(chunk.len(), MAX_CHUNK_SIZE);
+        assert_eq!(key.decrypt_chunk(&chunk).unwrap(), plaintext);
+
+        // Deterministic for the same key and content
+        assert_eq!(key.encrypt_chunk(&plaintext).unwrap(), chunk);
+
+        // Wrong key fails authentication
+        assert!(FileKey::random().decrypt_chunk(&chunk).is_err());
+
+        // Tampering fails authentication
+        let mut tampered = chunk.clone();
+        tampered[NONCE_SIZE] ^= 1;
+        assert!(key.decrypt_chunk(&tampered).is_err());
+    }
+
+    #[test]
+    fn file_key_encoding() {
+        let key = FileKey::convergent(b"hello geode");
+        let decoded: FileKey = key.to_string().parse().unwrap();
+        assert_eq!(key, decoded);
+    }
+}
@@ -0,0 +1,363 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-memory bookkeeping of what Geode stores on disk.
+//!
+//! The index is rebuilt from the filesystem every time Geode is
+//! instantiated, so it can never drift from the actual state of
+//! the `files` and `chunks` directories across restarts.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::SystemTime,
+};
+
+/// Position of a file in the LRU order. The access time is paired with
+/// a monotonic counter so files accessed at the same time stay distinct.
+type AccessKey = (SystemTime, u64);
+
+/// Metadata tracked for a single stored file
+struct FileEntry {
+    /// Chunks of the file, in order
+    chunks: Vec<blake3::Hash>,
+    /// Last time the file was inserted or accessed
+    access: AccessKey,
+}
+
+/// Object chosen for eviction when the storage quota is exceeded
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Eviction {
+    /// A chunk not referenced by any stored file
+    Chunk(blake3::Hash),
+    /// The least recently used file that is not pinned
+    File(blake3::Hash),
+}
+
+/// Index of stored files and chunks, holding chunk reference counts,
+/// pinned files, access times and storage usage.
+///
+/// Eviction candidates are kept in dedicated sets which are updated
+/// on every change, so picking the next object to evict never has to
+/// scan the whole index.
+#[derive(Default)]
+pub(super) struct StorageIndex {
+    /// Stored file metadata
+    files: HashMap<blake3::Hash, FileEntry>,
+    /// Amount of references to a chunk from stored files
+    chunk_refs: HashMap<blake3::Hash, usize>,
+    /// Sizes of chunks available on disk
+    chunk_sizes: HashMap<blake3::Hash, u64>,
+    /// Files pinned by the user, which are never evicted
+    pinned: HashSet<blake3::Hash>,
+    /// Chunks written by insertions in progress, which have not yet
+    /// been referenced by file metadata
+    reserved: HashMap<blake3::Hash, usize>,
+    /// Files currently being downloaded, which are never evicted
+    downloading: HashSet<blake3::Hash>,
+    /// Files which may be evicted, ordered by last access
+    lru: BTreeMap<AccessKey, blake3::Hash>,
+    /// Chunks on disk which are neither referenced nor reserved
+    unreferenced: HashSet<blake3::Hash>,
+    /// Counter used to order files accessed at the same time
+    ticks: u64,
+    /// Total size of the chunks available on disk
+    used: u64,
+}
+
+impl StorageIndex {
+    /// Total size of the chunks available on disk
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Check if the given file is known
+    pub fn contains_file(&self, file_hash: &blake3::Hash) -> bool {
+        self.files.contains_key(file_hash)
+    }
+
+    /// Check if the given file is pinned
+    pub fn is_pinned(&self, file_hash: &blake3::Hash) -> bool {
+        self.pinned.contains(file_hash)
+    }
+
+    /// Amount of stored files referencing the given chunk
+    pub fn chunk_refs(&self, chunk_hash: &blake3::Hash) -> usize {
+        *self.chunk_refs.get(chunk_hash).unwrap_or(&0)
+    }
+
+    /// Add a file to the index, taking a reference to each of its chunks.
+    /// If the file is already known, its previous references are replaced.
+    pub fn add_file(
+        &mut self,
+        file_hash: blake3::Hash,
+        chunks: Vec<blake3::Hash>,
+        last_access: SystemTime,
+    ) {
+        self.release_file(&file_hash);
+
+        for chunk in &chunks {
+            *self.chunk_refs.entry(*chunk).or_insert(0) += 1;
+            self.unreferenced.remove(chunk);
+        }
+
+        let access = self.access_key(last_access);
+        self.files.insert(file_hash, FileEntry { chunks, access });
+        self.update_file(&file_hash);
+    }
+
+    /// Remove a file from the index. Returns the chunks that are no
+    /// longer referenced by any other stored file and are available
+    /// on disk, so they can be safely deleted.
+    pub fn remove_file(&mut self, file_hash: &blake3::Hash) -> Vec<blake3::Hash> {
+        self.release_file(file_hash)
+            .into_iter()
+            .filter(|c| self.chunk_sizes.contains_key(c))
+            .collect()
+    }
+
+    /// Drop the chunk references held by the given file. Returns the
+    /// chunks whose reference count dropped to zero.
+    fn release_file(&mut self, file_hash: &blake3::Hash) -> Vec<blake3::Hash> {
+        let Some(entry) = self.files.remove(file_hash) else { return vec![] };
+        self.lru.remove(&entry.access);
+
+        let mut orphans = vec![];
+        for chunk in entry.chunks {
+            let Some(refs) = self.chunk_refs.get_mut(&chunk) else { continue };
+            *refs -= 1;
+            if *refs == 0 {
+                self.chunk_refs.remove(&chunk);
+                self.update_chunk(&chunk);
+                orphans.push(chunk);
+            }
+        }
+
+        orphans
+    }
+
+    /// Record a chunk as available on disk
+    pub fn add_chunk(&mut self, chunk_hash: blake3::Hash, size: u64) {
+        if let Some(old_size) = self.chunk_sizes.insert(chunk_hash, size) {
+            self.used -= old_size;
+        }
+        self.used += size;
+        self.update_chunk(&chunk_hash);
+    }
+
+    /// Record a chunk as removed from disk
+    pub fn remove_chunk(&mut self, chunk_hash: &blake3::Hash) {
+        if let Some(size) = self.chunk_sizes.remove(chunk_hash) {
+            self.used -= size;
+        }
+        self.unreferenced.remove(chunk_hash);
+    }
+
+    /// Protect a chunk from eviction until the file using it is inserted
+    pub fn reserve_chunk(&mut self, chunk_hash: blake3::Hash) {
+        *self.reserved.entry(chunk_hash).or_insert(0) += 1;
+        self.unreferenced.remove(&chunk_hash);
+    }
+
+    /// Drop reservations previously taken with `reserve_chunk()`
+    pub fn release_chunks(&mut self, chunks: &[blake3::Hash]) {
+        for chunk in chunks {
+            let Some(count) = self.reserved.get_mut(chunk) else { continue };
+            *count -= 1;
+            if *count == 0 {
+                self.reserved.remove(chunk);
+                self.update_chunk(chunk);
+            }
+        }
+    }
+
+    /// Mark a file as accessed at the given time
+    pub fn touch(&mut self, file_hash: &blake3::Hash, time: SystemTime) {
+        let access = self.access_key(time);
+        let Some(entry) = self.files.get_mut(file_hash) else { return };
+        let previous = std::mem::replace(&mut entry.access, access);
+        self.lru.remove(&previous);
+        self.update_file(file_hash);
+    }
+
+    /// Pin a file so it is never evicted
+    pub fn pin(&mut self, file_hash: blake3::Hash) {
+        self.pinned.insert(file_hash);
+        self.update_file(&file_hash);
+    }
+
+    /// Unpin a file, making it subject to eviction
+    pub fn unpin(&mut self, file_hash: &blake3::Hash) {
+        self.pinned.remove(file_hash);
+        self.update_file(file_hash);
+    }
+
+    /// Protect a file from eviction while it is being downloaded. The file
+    /// does not have to be known yet, so this can be called before its
+    /// metadata is fetched.
+    pub fn begin_download(&mut self, file_hash: blake3::Hash) {
+        self.downloading.insert(file_hash);
+        self.update_file(&file_hash);
+    }
+
+    /// Drop the protection taken with `begin_download()`
+    pub fn end_download(&mut self, file_hash: &blake3::Hash) {
+        self.downloading.remove(file_hash);
+        self.update_file(file_hash);
+    }
+
+    /// Create the LRU position for an access at the given time
+    fn access_key(&mut self, time: SystemTime) -> AccessKey {
+        self.ticks += 1;
+        (time, self.ticks)
+    }
+
+    /// Add or remove a file from the eviction order, depending on
+    /// whether it is pinned or being downloaded.
+    fn update_file(&mut self, file_hash: &blake3::Hash) {
+        let Some(entry) = self.files.get(file_hash) else { return };
+
+        if self.pinned.contains(file_hash) || self.downloading.contains(file_hash) {
+            self.lru.remove(&entry.access);
+        } else {
+            self.lru.insert(entry.access, *file_hash);
+        }
+    }
+
+    /// Add or remove a chunk from the unreferenced set, depending on
+    /// whether it is on disk, referenced or reserved.
+    fn update_chunk(&mut self, chunk_hash: &blake3::Hash) {
+        if self.chunk_sizes.contains_key(chunk_hash) &&
+            !self.chunk_refs.contains_key(chunk_hash) &&
+            !self.reserved.contains_key(chunk_hash)
+        {
+            self.unreferenced.insert(*chunk_hash);
+        } else {
+            self.unreferenced.remove(chunk_hash);
+        }
+    }
+
+    /// Pick the next object to evict in order to free up space.
+    /// Unreferenced chunks are chosen first, and then the least
+    /// recently used file which is not pinned. The file or chunk
+    /// given in `keep` is never chosen, which is used to protect
+    /// an object that is currently being inserted.
+    pub fn eviction_candidate(&self, keep: Option<&blake3::Hash>) -> Option<Eviction> {
+        if let Some(chunk) = self.unreferenced.iter().find(|c| Some(*c) != keep) {
+            return Some(Eviction::Chunk(*chunk))
+        }
+
+        self.lru.values().find(|hash| Some(*hash) != keep).map(|hash| Eviction::File(*hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn refcounting_and_eviction() {
+        let chunk = |i: u8| blake3::hash(&[i]);
+        let file = |i: u8| blake3::hash(&[0xff, i]);
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+
+        let mut index = StorageIndex::default();
+        for i in 0..3 {
+            index.add_chunk(chunk(i), 10);
+        }
+        assert_eq!(index.used(), 30);
+
+        // Two files sharing chunk 1
+        index.add_file(file(0), vec![chunk(0), chunk(1)], t0);
+        index.add_file(file(1), vec![chunk(1), chunk(2)], t1);
+        assert_eq!(index.chunk_refs(&chunk(1)), 2);
+
+        // The least recently used file is evicted first
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(0))));
+
+        // Pinned files are never evicted
+        index.pin(file(0));
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(1))));
+        assert_eq!(index.eviction_candidate(Some(&file(1))), None);
+
+        // Removing a file only releases chunks no other file references
+        assert_eq!(index.remove_file(&file(0)), vec![chunk(0)]);
+        assert!(!index.contains_file(&file(0)));
+        assert_eq!(index.chunk_refs(&chunk(1)), 1);
+
+        // Unreferenced chunks are evicted before any file, unless reserved
+        index.reserve_chunk(chunk(0));
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(1))));
+        index.release_chunks(&[chunk(0)]);
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::Chunk(chunk(0))));
+        // A chunk being inserted is protected like a file
+        assert_eq!(index.eviction_candidate(Some(&chunk(0))), Some(Eviction::File(file(1))));
+        index.remove_chunk(&chunk(0));
+        assert_eq!(index.used(), 20);
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(1))));
+
+        let released: HashSet<_> = index.remove_file(&file(1)).into_iter().collect();
+        assert_eq!(released, HashSet::from([chunk(1), chunk(2)]));
+    }
+
+    #[test]
+    fn lru_order_and_downloads() {
+        let chunk = |i: u8| blake3::hash(&[i]);
+        let file = |i: u8| blake3::hash(&[0xff, i]);
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+        let t2 = t0 + Duration::from_secs(2);
+
+        let mut index = StorageIndex::default();
+        index.add_file(file(0), vec![chunk(0)], t0);
+        index.add_file(file(1), vec![chunk(1)], t0);
+        index.add_file(file(2), vec![chunk(2)], t1);
+
+        // Files accessed at the same time are evicted in insertion order
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(0))));
+
+        // Accessing a file moves it to the back of the queue
+        index.touch(&file(0), t2);
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(1))));
+
+        // Files being downloaded are protected, even before their metadata is known
+        index.begin_download(file(1));
+        index.begin_download(file(3));
+        index.add_file(file(3), vec![chunk(3)], t0);
+        index.add_chunk(chunk(3), 10);
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(2))));
+
+        // Unpinning a file being downloaded keeps it protected
+        index.pin(file(2));
+        index.unpin(&file(1));
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(0))));
+        index.pin(file(0));
+        assert_eq!(index.eviction_candidate(None), None);
+
+        // Once the download is done, the file is subject to eviction again
+        index.end_download(&file(3));
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::File(file(3))));
+
+        // Chunks of a removed file become eviction candidates
+        assert_eq!(index.remove_file(&file(3)), vec![chunk(3)]);
+        assert_eq!(index.eviction_candidate(None), Some(Eviction::Chunk(chunk(3))));
+        index.remove_chunk(&chunk(3));
+        assert_eq!(index.eviction_candidate(None), None);
+    }
+}
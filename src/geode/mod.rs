@@ -19,11 +19,11 @@
 //! Chunk-based file storage implementation.
 //! This is a building block for a DHT or something similar.
 //!
-//! The API supports file insertion and retrieval. There is intentionally no
-//! `remove` support. File removal should be handled externally, and then it
-//! is only required to run `garbage_collect()` to clean things up.
+//! The API supports file insertion, retrieval and removal. Corrupted files
+//! and chunks are cleaned up by running `garbage_collect()`.
 //!
-//! The filesystem hierarchy stores two directories: `files` and `chunks`.
+//! The filesystem hierarchy stores three directories: `files`, `chunks`
+//! and `pins`.
 //! `chunks` store [`MAX_CHUNK_SIZE`] files, where the filename is a BLAKE3
 //! hash of the chunk's contents.
 //! `files` store metadata about a full file, which can be retrieved by
@@ -53,10 +53,33 @@
 //!
 //! It is important to note that multiple files can use the same chunks.
 //! This is some kind of naive deduplication, so we actually don't consider
-//! chunks to be specific to a single file and therefore when we do garbage
-//! collection, we keep chunks and files independent of each other.
-
-use std::{collections::HashSet, path::PathBuf};
+//! chunks to be specific to a single file. Instead, Geode keeps a reference
+//! count for every chunk, and [`Geode::remove`] only deletes the chunks that
+//! are not used by any other stored file.
+//!
+//! Files can be pinned with [`Geode::pin`], which creates an empty file named
+//! by the file hash under `/pins`. When a storage quota is configured using
+//! [`Geode::set_quota`], Geode evicts unreferenced chunks and then the least
+//! recently used unpinned files until the chunks on disk fit into the quota.
+//! Pinned files (usually the ones the user explicitly added) are never evicted,
+//! while unpinned files are considered a cache of content fetched for others.
+//! Files marked with [`Geode::begin_download`] are protected as well until
+//! [`Geode::end_download`] is called, so a download can't evict its own chunks.
+//!
+//! Stored files can be read back as a stream using [`Geode::open`], which
+//! returns a [`GeodeReader`] implementing `AsyncRead` and `AsyncSeek`, or
+//! reassembled onto the filesystem using [`Geode::export`].
+//!
+//! Files can optionally be encrypted with a per-file [`FileKey`] when
+//! inserted using [`Geode::insert_encrypted`]. In that case the chunks and
+//! the file hash refer to the encrypted data, so nodes are able to host and
+//! serve content they cannot read. See the [`crypto`] module for details.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use futures::AsyncRead;
 use log::{debug, info, warn};
@@ -64,11 +87,25 @@ use smol::{
     fs,
     fs::{File, OpenOptions},
     io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, Cursor, SeekFrom},
+    lock::Mutex,
     stream::StreamExt,
 };
 
 use crate::{Error, Result};
 
+/// Optional per-file chunk encryption
+pub mod crypto;
+pub use crypto::FileKey;
+use crypto::ENCRYPTED_CHUNK_PLAINTEXT_SIZE;
+
+/// Streaming reader over stored files
+mod reader;
+pub use reader::GeodeReader;
+
+/// Reference counting and eviction bookkeeping
+mod index;
+use index::{Eviction, StorageIndex};
+
 /// Defined maximum size of a stored chunk (256 KiB)
 pub const MAX_CHUNK_SIZE: usize = 262_144;
 
@@ -76,6 +113,8 @@ pub const MAX_CHUNK_SIZE: usize = 262_144;
 const FILES_PATH: &str = "files";
 /// Path prefix where file chunks are stored
 const CHUNKS_PATH: &str = "chunks";
+/// Path prefix where file pins are stored
+const PINS_PATH: &str = "pins";
 
 /// `ChunkedFile` is a representation of a file we're trying to
 /// retrieve from `Geode`. The tuple contains `blake3::Hash` of
@@ -107,6 +146,12 @@ pub struct Geode {
     files_path: PathBuf,
     /// Path to the filesystem directory where file chunks are stored
     chunks_path: PathBuf,
+    /// Path to the filesystem directory where file pins are stored
+    pins_path: PathBuf,
+    /// Maximum amount of bytes chunks are allowed to take up on disk
+    quota: Option<u64>,
+    /// Reference counts, pins and access times of stored objects
+    index: Mutex<StorageIndex>,
 }
 
 impl Geode {
@@ -116,14 +161,82 @@ impl Geode {
     pub async fn new(base_path: &PathBuf) -> Result<Self> {
         let mut files_path: PathBuf = base_path.into();
         let mut chunks_path: PathBuf = base_path.into();
+        let mut pins_path: PathBuf = base_path.into();
         files_path.push(FILES_PATH);
         chunks_path.push(CHUNKS_PATH);
+        pins_path.push(PINS_PATH);
 
         // Create necessary directory structure if needed
         fs::create_dir_all(&files_path).await?;
         fs::create_dir_all(&chunks_path).await?;
+        fs::create_dir_all(&pins_path).await?;
+
+        let geode = Self {
+            files_path,
+            chunks_path,
+            pins_path,
+            quota: None,
+            index: Mutex::new(StorageIndex::default()),
+        };
+        geode.load_index().await?;
 
-        Ok(Self { files_path, chunks_path })
+        Ok(geode)
+    }
+
+    /// Set the maximum amount of bytes stored chunks are allowed to take up
+    /// on disk. Passing `None` disables the quota. The quota is enforced on
+    /// every insertion, or explicitly by calling [`Geode::evict`].
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.quota = quota;
+    }
+
+    /// Rebuild the in-memory index by scanning the filesystem hierarchy.
+    /// Entries which are not named by a BLAKE3 hash are ignored, and
+    /// corrupted metadata is left for `garbage_collect()` to handle.
+    async fn load_index(&self) -> Result<()> {
+        debug!(target: "geode::load_index()", "Building storage index");
+        let mut index = self.index.lock().await;
+
+        for (chunk_hash, chunk_path) in Self::read_hash_dir(&self.chunks_path).await? {
+            let size = fs::metadata(&chunk_path).await?.len();
+            index.add_chunk(chunk_hash, size);
+        }
+
+        for (file_hash, file_path) in Self::read_hash_dir(&self.files_path).await? {
+            let Ok(chunks) = Self::read_metadata(&file_path).await else { continue };
+            // The last modification time of the metadata is used as the
+            // last access time, since it gets updated on every access.
+            let last_access = fs::metadata(&file_path).await?.modified()?;
+            index.add_file(file_hash, chunks, last_access);
+        }
+
+        for (file_hash, _) in Self::read_hash_dir(&self.pins_path).await? {
+            index.pin(file_hash);
+        }
+
+        Ok(())
+    }
+
+    /// Read a directory and return the plain files in it whose names
+    /// are BLAKE3 hashes, along with their paths.
+    async fn read_hash_dir(path: &PathBuf) -> Result<Vec<(blake3::Hash, PathBuf)>> {
+        let mut ret = vec![];
+
+        let mut entries = fs::read_dir(path).await?;
+        while let Some(entry) = entries.next().await {
+            let Ok(entry) = entry else { continue };
+            let path = entry.path();
+
+            if !path.is_file() {
+                continue
+            }
+
+            let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+            let Ok(hash) = blake3::Hash::from_hex(file_name) else { continue };
+            ret.push((hash, path));
+        }
+
+        Ok(ret)
     }
 
     /// Attempt to read chunk hashes from a given file path and return
@@ -212,6 +325,11 @@ impl Geode {
             }
         }
 
+        let mut index = self.index.lock().await;
+        for chunk_hash in &deleted_chunks {
+            index.remove_chunk(chunk_hash);
+        }
+
         // Perform health check over file metadata. For now we just ensure they
         // have the correct format.
         let mut file_paths = fs::read_dir(&self.files_path).await?;
@@ -245,6 +363,9 @@ impl Geode {
                     );
                 }
 
+                // Chunks used by the corrupted file are kept, since they can
+                // still be referenced by metadata that gets fetched again.
+                index.remove_file(&file_hash);
                 deleted_files.insert(file_hash);
                 continue
             }
@@ -265,7 +386,7 @@ impl Geode {
         info!(target: "geode::insert()", "[Geode] Inserting file...");
         let mut file_hasher = blake3::Hasher::new();
         let mut chunk_hashes = vec![];
-        let mut buf = [0u8; MAX_CHUNK_SIZE];
+        let mut buf = vec![0u8; MAX_CHUNK_SIZE];
 
         while let Ok(bytes_read) = stream.read(&mut buf).await {
             if bytes_read == 0 {
@@ -273,59 +394,139 @@ impl Geode {
             }
 
             let chunk_slice = &buf[..bytes_read];
-            let chunk_hash = blake3::hash(chunk_slice);
             file_hasher.update(chunk_slice);
-            chunk_hashes.push(chunk_hash);
-
-            // Write the chunk to a file, if necessary. We first perform
-            // a consistency check and if things are fine, we don't have
-            // to perform a write, which is usually more expensive than
-            // reading from disk.
-            let mut chunk_path = self.chunks_path.clone();
-            chunk_path.push(chunk_hash.to_hex().as_str());
-            let mut chunk_fd =
-                OpenOptions::new().read(true).write(true).create(true).open(&chunk_path).await?;
-
-            let mut fs_buf = [0u8; MAX_CHUNK_SIZE];
-            let fs_bytes_read = chunk_fd.read(&mut fs_buf).await?;
-            let fs_chunk_slice = &fs_buf[..fs_bytes_read];
-            let fs_chunk_hash = blake3::hash(fs_chunk_slice);
-
-            if fs_chunk_hash != chunk_hash {
-                debug!(
-                    target: "geode::insert()",
-                    "Existing chunk inconsistent or unavailable. Writing chunk to {:?}",
-                    chunk_path,
-                );
-                // Here the chunk is broken, so we'll truncate and write the new one.
-                chunk_fd.set_len(0).await?;
-                chunk_fd.seek(SeekFrom::Start(0)).await?;
-                chunk_fd.write_all(chunk_slice).await?;
-            } else {
-                debug!(
-                    target: "geode::insert()",
-                    "Existing chunk consistent. Skipping write to {:?}",
-                    chunk_path,
-                );
+            match self.write_chunk(chunk_slice).await {
+                Ok(chunk_hash) => chunk_hashes.push(chunk_hash),
+                Err(e) => {
+                    self.index.lock().await.release_chunks(&chunk_hashes);
+                    return Err(e)
+                }
             }
-
-            buf = [0u8; MAX_CHUNK_SIZE];
         }
 
         // This hash is the file's chunks hashed in order.
         let file_hash = file_hasher.finalize();
-        let mut file_path = self.files_path.clone();
-        file_path.push(file_hash.to_hex().as_str());
+        let ret = self.insert_file(&file_hash, &chunk_hashes).await;
+        self.index.lock().await.release_chunks(&chunk_hashes);
+        ret?;
 
-        // We always overwrite the metadata.
-        let mut file_fd = File::create(&file_path).await?;
-        for ch in &chunk_hashes {
-            file_fd.write(format!("{}\n", ch.to_hex().as_str()).as_bytes()).await?;
+        Ok((file_hash, chunk_hashes))
+    }
+
+    /// Insert a file into Geode, encrypting every chunk with the given [`FileKey`].
+    /// The key can either be random, or convergent (derived from the plaintext).
+    /// The returned file hash and chunk hashes refer to the encrypted chunks, so
+    /// the file can be distributed and served by nodes that don't know the key.
+    /// Returns a tuple of `(blake3::Hash, Vec<blake3::Hash>)` which represents the
+    /// file name, and the file's chunks, respectively.
+    pub async fn insert_encrypted(
+        &self,
+        mut stream: impl AsyncRead + Unpin,
+        key: &FileKey,
+    ) -> Result<(blake3::Hash, Vec<blake3::Hash>)> {
+        info!(target: "geode::insert_encrypted()", "[Geode] Inserting encrypted file...");
+        let mut file_hasher = blake3::Hasher::new();
+        let mut chunk_hashes = vec![];
+        let mut buf = vec![0u8; ENCRYPTED_CHUNK_PLAINTEXT_SIZE];
+
+        loop {
+            // Fill up the buffer as much as possible, so all chunks except
+            // the last one have the same plaintext size. This is required
+            // for seeking over encrypted files.
+            let mut filled = 0;
+            while filled < buf.len() {
+                let bytes_read = match stream.read(&mut buf[filled..]).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        self.index.lock().await.release_chunks(&chunk_hashes);
+                        return Err(e.into())
+                    }
+                };
+                if bytes_read == 0 {
+                    break
+                }
+                filled += bytes_read;
+            }
+
+            if filled == 0 {
+                break
+            }
+
+            let chunk = match key.encrypt_chunk(&buf[..filled]) {
+                Ok(v) => v,
+                Err(e) => {
+                    self.index.lock().await.release_chunks(&chunk_hashes);
+                    return Err(e)
+                }
+            };
+
+            file_hasher.update(&chunk);
+            match self.write_chunk(&chunk).await {
+                Ok(chunk_hash) => chunk_hashes.push(chunk_hash),
+                Err(e) => {
+                    self.index.lock().await.release_chunks(&chunk_hashes);
+                    return Err(e)
+                }
+            }
+
+            if filled < buf.len() {
+                break
+            }
         }
 
+        let file_hash = file_hasher.finalize();
+        let ret = self.insert_file(&file_hash, &chunk_hashes).await;
+        self.index.lock().await.release_chunks(&chunk_hashes);
+        ret?;
+
         Ok((file_hash, chunk_hashes))
     }
 
+    /// Write a single chunk to the filesystem, if necessary. We first perform
+    /// a consistency check and if things are fine, we don't have to perform a
+    /// write, which is usually more expensive than reading from disk.
+    /// The chunk is reserved in the index, so it can't be evicted before the
+    /// caller inserts the file metadata referencing it and releases it.
+    /// Returns the chunk hash.
+    async fn write_chunk(&self, chunk_slice: &[u8]) -> Result<blake3::Hash> {
+        let chunk_hash = blake3::hash(chunk_slice);
+        let mut index = self.index.lock().await;
+
+        let mut chunk_path = self.chunks_path.clone();
+        chunk_path.push(chunk_hash.to_hex().as_str());
+        let mut chunk_fd =
+            OpenOptions::new().read(true).write(true).create(true).open(&chunk_path).await?;
+
+        let mut fs_buf = vec![0u8; MAX_CHUNK_SIZE];
+        let fs_bytes_read = chunk_fd.read(&mut fs_buf).await?;
+        let fs_chunk_slice = &fs_buf[..fs_bytes_read];
+        let fs_chunk_hash = blake3::hash(fs_chunk_slice);
+
+        if fs_chunk_hash != chunk_hash {
+            debug!(
+                target: "geode::write_chunk()",
+                "Existing chunk inconsistent or unavailable. Writing chunk to {:?}",
+                chunk_path,
+            );
+            // Here the chunk is broken, so we'll truncate and write the new one.
+            chunk_fd.set_len(0).await?;
+            chunk_fd.seek(SeekFrom::Start(0)).await?;
+            chunk_fd.write_all(chunk_slice).await?;
+            chunk_fd.flush().await?;
+        } else {
+            debug!(
+                target: "geode::write_chunk()",
+                "Existing chunk consistent. Skipping write to {:?}",
+                chunk_path,
+            );
+        }
+
+        index.add_chunk(chunk_hash, chunk_slice.len() as u64);
+        index.reserve_chunk(chunk_hash);
+
+        Ok(chunk_hash)
+    }
+
     /// Create and insert file metadata into Geode given a list of hashes.
     /// Always overwrites any existing file.
     pub async fn insert_file(
@@ -340,9 +541,16 @@ impl Geode {
         let mut file_fd = File::create(&file_path).await?;
 
         for ch in chunk_hashes {
-            file_fd.write(format!("{}\n", ch.to_hex().as_str()).as_bytes()).await?;
+            file_fd.write_all(format!("{}\n", ch.to_hex().as_str()).as_bytes()).await?;
         }
 
+        // smol buffers writes internally, so make sure everything hits the disk
+        // before anyone tries to read the metadata back.
+        file_fd.flush().await?;
+
+        self.index.lock().await.add_file(*file_hash, chunk_hashes.to_vec(), SystemTime::now());
+        self.enforce_quota(Some(file_hash)).await?;
+
         Ok(())
     }
 
@@ -360,8 +568,16 @@ impl Geode {
 
         let mut chunk_path = self.chunks_path.clone();
         chunk_path.push(chunk_hash.to_hex().as_str());
-        let mut chunk_fd = File::create(&chunk_path).await?;
-        chunk_fd.write_all(chunk_slice).await?;
+
+        {
+            let mut index = self.index.lock().await;
+            let mut chunk_fd = File::create(&chunk_path).await?;
+            chunk_fd.write_all(chunk_slice).await?;
+            chunk_fd.flush().await?;
+            index.add_chunk(chunk_hash, chunk_slice.len() as u64);
+        }
+
+        self.enforce_quota(Some(&chunk_hash)).await?;
 
         Ok(chunk_hash)
     }
@@ -386,10 +602,12 @@ impl Geode {
             },
         };
 
+        self.touch(file_hash, &file_path).await;
+
         let mut chunked_file = ChunkedFile::new(&chunk_hashes);
 
         // Iterate over chunks and find which chunks we have available locally.
-        let mut buf = [0u8; MAX_CHUNK_SIZE];
+        let mut buf = vec![0u8; MAX_CHUNK_SIZE];
         for (chunk_hash, chunk_path) in chunked_file.0.iter_mut() {
             let mut c_path = self.chunks_path.clone();
             c_path.push(chunk_hash.to_hex().as_str());
@@ -406,12 +624,10 @@ impl Geode {
             let hashed_chunk = blake3::hash(chunk_slice);
             if &hashed_chunk != chunk_hash {
                 // The chunk is corrupted/inconsistent. Garbage collection should run.
-                buf = [0u8; MAX_CHUNK_SIZE];
                 continue
             }
 
             *chunk_path = Some(c_path);
-            buf = [0u8; MAX_CHUNK_SIZE];
         }
 
         Ok(chunked_file)
@@ -442,4 +658,329 @@ impl Geode {
 
         Ok(chunk_path)
     }
+
+    /// Remove a file from Geode. The file's metadata and pin are deleted, along
+    /// with the chunks that are not referenced by any other stored file.
+    /// Returns the set of deleted chunks.
+    pub async fn remove(&self, file_hash: &blake3::Hash) -> Result<HashSet<blake3::Hash>> {
+        info!(target: "geode::remove()", "[Geode] Removing file {}", file_hash);
+        let mut index = self.index.lock().await;
+        self.remove_locked(&mut index, file_hash).await
+    }
+
+    /// Remove a file while holding the index lock. See [`Geode::remove`].
+    async fn remove_locked(
+        &self,
+        index: &mut StorageIndex,
+        file_hash: &blake3::Hash,
+    ) -> Result<HashSet<blake3::Hash>> {
+        if !index.contains_file(file_hash) {
+            return Err(Error::GeodeFileNotFound)
+        }
+
+        let mut file_path = self.files_path.clone();
+        file_path.push(file_hash.to_hex().as_str());
+        Self::remove_if_exists(&file_path).await?;
+
+        let mut pin_path = self.pins_path.clone();
+        pin_path.push(file_hash.to_hex().as_str());
+        Self::remove_if_exists(&pin_path).await?;
+        index.unpin(file_hash);
+
+        let mut deleted_chunks = HashSet::new();
+        for chunk_hash in index.remove_file(file_hash) {
+            self.remove_chunk_locked(index, &chunk_hash).await?;
+            deleted_chunks.insert(chunk_hash);
+        }
+
+        Ok(deleted_chunks)
+    }
+
+    /// Delete a chunk from the filesystem while holding the index lock.
+    async fn remove_chunk_locked(
+        &self,
+        index: &mut StorageIndex,
+        chunk_hash: &blake3::Hash,
+    ) -> Result<()> {
+        debug!(target: "geode::remove_chunk()", "Removing chunk {}", chunk_hash);
+        let mut chunk_path = self.chunks_path.clone();
+        chunk_path.push(chunk_hash.to_hex().as_str());
+        Self::remove_if_exists(&chunk_path).await?;
+        index.remove_chunk(chunk_hash);
+        Ok(())
+    }
+
+    /// Remove a filesystem entry, ignoring the case where it does not exist.
+    async fn remove_if_exists(path: &PathBuf) -> Result<()> {
+        match fs::remove_file(path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Pin a stored file, protecting it from eviction. Pins are persisted on the
+    /// filesystem. Usually files explicitly added by the user should be pinned.
+    pub async fn pin(&self, file_hash: &blake3::Hash) -> Result<()> {
+        info!(target: "geode::pin()", "[Geode] Pinning file {}", file_hash);
+        let mut index = self.index.lock().await;
+        if !index.contains_file(file_hash) {
+            return Err(Error::GeodeFileNotFound)
+        }
+
+        let mut pin_path = self.pins_path.clone();
+        pin_path.push(file_hash.to_hex().as_str());
+        File::create(&pin_path).await?;
+        index.pin(*file_hash);
+
+        Ok(())
+    }
+
+    /// Unpin a stored file, making it subject to eviction when the storage
+    /// quota is exceeded.
+    pub async fn unpin(&self, file_hash: &blake3::Hash) -> Result<()> {
+        info!(target: "geode::unpin()", "[Geode] Unpinning file {}", file_hash);
+        let mut index = self.index.lock().await;
+
+        let mut pin_path = self.pins_path.clone();
+        pin_path.push(file_hash.to_hex().as_str());
+        Self::remove_if_exists(&pin_path).await?;
+        index.unpin(file_hash);
+
+        Ok(())
+    }
+
+    /// Check whether a stored file is pinned.
+    pub async fn is_pinned(&self, file_hash: &blake3::Hash) -> bool {
+        self.index.lock().await.is_pinned(file_hash)
+    }
+
+    /// Protect a file and its chunks from eviction while it is being fetched.
+    /// This can be called before the file metadata is inserted. Every call
+    /// should be followed by [`Geode::end_download`] once the fetch is over.
+    pub async fn begin_download(&self, file_hash: &blake3::Hash) {
+        debug!(target: "geode::begin_download()", "Downloading file {}", file_hash);
+        self.index.lock().await.begin_download(*file_hash);
+    }
+
+    /// Drop the eviction protection taken with [`Geode::begin_download`].
+    /// The quota is enforced again on the next insertion or [`Geode::evict`].
+    pub async fn end_download(&self, file_hash: &blake3::Hash) {
+        debug!(target: "geode::end_download()", "Finished downloading file {}", file_hash);
+        self.index.lock().await.end_download(file_hash);
+    }
+
+    /// Return the amount of bytes stored chunks take up on disk.
+    pub async fn usage(&self) -> u64 {
+        self.index.lock().await.used()
+    }
+
+    /// Evict unreferenced chunks and least recently used unpinned files until
+    /// the stored chunks fit into the configured quota. Does nothing if no quota
+    /// is set. Returns sets representing evicted files and evicted chunks.
+    pub async fn evict(&self) -> Result<(HashSet<blake3::Hash>, HashSet<blake3::Hash>)> {
+        self.enforce_quota(None).await
+    }
+
+    /// Enforce the storage quota, never evicting the file or chunk given in `keep`.
+    async fn enforce_quota(
+        &self,
+        keep: Option<&blake3::Hash>,
+    ) -> Result<(HashSet<blake3::Hash>, HashSet<blake3::Hash>)> {
+        let mut evicted_files = HashSet::new();
+        let mut evicted_chunks = HashSet::new();
+
+        let Some(quota) = self.quota else { return Ok((evicted_files, evicted_chunks)) };
+
+        let mut index = self.index.lock().await;
+        while index.used() > quota {
+            match index.eviction_candidate(keep) {
+                Some(Eviction::Chunk(chunk_hash)) => {
+                    self.remove_chunk_locked(&mut index, &chunk_hash).await?;
+                    evicted_chunks.insert(chunk_hash);
+                }
+
+                Some(Eviction::File(file_hash)) => {
+                    debug!(target: "geode::evict()", "Evicting file {}", file_hash);
+                    evicted_chunks.extend(self.remove_locked(&mut index, &file_hash).await?);
+                    evicted_files.insert(file_hash);
+                }
+
+                None => {
+                    warn!(
+                        target: "geode::evict()",
+                        "[Geode] Storage quota exceeded, but nothing is left to evict",
+                    );
+                    break
+                }
+            }
+        }
+
+        if !evicted_files.is_empty() || !evicted_chunks.is_empty() {
+            info!(
+                target: "geode::evict()",
+                "[Geode] Evicted {} files and {} chunks",
+                evicted_files.len(), evicted_chunks.len(),
+            );
+        }
+
+        Ok((evicted_files, evicted_chunks))
+    }
+
+    /// Mark a file as accessed, both in the index and on the filesystem by
+    /// updating the modification time of its metadata, which is used to
+    /// restore access times on restart.
+    async fn touch(&self, file_hash: &blake3::Hash, file_path: &Path) {
+        let now = SystemTime::now();
+        self.index.lock().await.touch(file_hash, now);
+
+        let path = file_path.to_path_buf();
+        let res = smol::unblock(move || {
+            std::fs::File::options().write(true).open(path)?.set_modified(now)
+        })
+        .await;
+
+        if let Err(e) = res {
+            warn!(target: "geode::touch()", "[Geode] Failed updating access time: {}", e);
+        }
+    }
+
+    /// Open a stored file for reading. Returns a [`GeodeReader`] which implements
+    /// `AsyncRead` and `AsyncSeek` over the reassembled file. If the file was
+    /// inserted encrypted, the according [`FileKey`] must be passed, and chunks
+    /// will be decrypted on the fly. Returns [`Error::GeodeChunkNotFound`] if
+    /// any of the file's chunks are not available locally.
+    pub async fn open(
+        &self,
+        file_hash: &blake3::Hash,
+        key: Option<FileKey>,
+    ) -> Result<GeodeReader> {
+        info!(target: "geode::open()", "[Geode] Opening file {}", file_hash);
+        let chunked_file = self.get(file_hash).await?;
+
+        let mut chunks = Vec::with_capacity(chunked_file.0.len());
+        for (chunk_hash, chunk_path) in chunked_file.0 {
+            let Some(chunk_path) = chunk_path else { return Err(Error::GeodeChunkNotFound) };
+            chunks.push((chunk_hash, chunk_path));
+        }
+
+        GeodeReader::new(chunks, key).await
+    }
+
+    /// Reassemble a stored file and write it to the given filesystem path.
+    /// Optionally, a [`FileKey`] can be passed to decrypt an encrypted file.
+    /// The file is first written into a temporary path next to the destination
+    /// and then atomically moved into place. Returns the amount of bytes written.
+    pub async fn export(
+        &self,
+        file_hash: &blake3::Hash,
+        key: Option<FileKey>,
+        path: &Path,
+    ) -> Result<u64> {
+        info!(target: "geode::export()", "[Geode] Exporting file {} to {:?}", file_hash, path);
+        let reader = self.open(file_hash, key).await?;
+
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".part");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut fd = File::create(&tmp_path).await?;
+        let written = match smol::io::copy(reader, &mut fd).await {
+            Ok(v) => v,
+            Err(e) => {
+                drop(fd);
+                if let Err(e) = fs::remove_file(&tmp_path).await {
+                    warn!(
+                        target: "geode::export()",
+                        "[Geode] Failed to remove partial export {:?}: {}", tmp_path, e,
+                    );
+                }
+                return Err(e.into())
+            }
+        };
+
+        fd.sync_all().await?;
+        drop(fd);
+        fs::rename(&tmp_path, path).await?;
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geode_open_and_seek() {
+        smol::block_on(async {
+            let mut base_path = std::env::temp_dir();
+            base_path.push(format!("geode_test_{}", std::process::id()));
+            let geode = Geode::new(&base_path).await.unwrap();
+
+            let data: Vec<u8> = (0..MAX_CHUNK_SIZE * 2 + 1337).map(|i| (i % 251) as u8).collect();
+
+            for key in [None, Some(FileKey::random()), Some(FileKey::convergent(&data))] {
+                let (file_hash, _) = match key {
+                    Some(key) => geode.insert_encrypted(Cursor::new(&data), &key).await.unwrap(),
+                    None => geode.insert(Cursor::new(&data)).await.unwrap(),
+                };
+
+                let mut reader = geode.open(&file_hash, key).await.unwrap();
+                assert_eq!(reader.len(), data.len() as u64);
+
+                let mut read_back = vec![];
+                reader.read_to_end(&mut read_back).await.unwrap();
+                assert_eq!(read_back, data);
+
+                // Seek across a chunk boundary
+                let offset = MAX_CHUNK_SIZE as u64 - 10;
+                reader.seek(SeekFrom::Start(offset)).await.unwrap();
+                let mut buf = [0u8; 100];
+                reader.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf[..], &data[offset as usize..offset as usize + 100]);
+            }
+
+            fs::remove_dir_all(&base_path).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn geode_remove_and_quota() {
+        smol::block_on(async {
+            let mut base_path = std::env::temp_dir();
+            base_path.push(format!("geode_quota_test_{}", std::process::id()));
+            let mut geode = Geode::new(&base_path).await.unwrap();
+
+            // Two files sharing their first chunk
+            let shared = vec![1u8; MAX_CHUNK_SIZE];
+            let data_a = [shared.clone(), vec![2u8; 100]].concat();
+            let data_b = [shared.clone(), vec![3u8; 100]].concat();
+
+            let (file_a, chunks_a) = geode.insert(Cursor::new(&data_a)).await.unwrap();
+            let (file_b, _) = geode.insert(Cursor::new(&data_b)).await.unwrap();
+            assert_eq!(geode.usage().await, MAX_CHUNK_SIZE as u64 + 200);
+
+            // Removing A must keep the shared chunk used by B
+            let deleted = geode.remove(&file_a).await.unwrap();
+            assert_eq!(deleted, HashSet::from([chunks_a[1]]));
+            assert!(geode.get(&file_b).await.unwrap().is_complete());
+
+            // With a quota, unpinned files get evicted, pinned ones are kept
+            let (file_a, _) = geode.insert(Cursor::new(&data_a)).await.unwrap();
+            geode.pin(&file_a).await.unwrap();
+            geode.set_quota(Some(MAX_CHUNK_SIZE as u64 + 100));
+            let (evicted_files, _) = geode.evict().await.unwrap();
+            assert_eq!(evicted_files, HashSet::from([file_b]));
+            assert!(geode.get(&file_a).await.unwrap().is_complete());
+            assert!(geode.get(&file_b).await.is_err());
+
+            // Pins and refcounts survive a restart
+            let geode = Geode::new(&base_path).await.unwrap();
+            assert!(geode.is_pinned(&file_a).await);
+            assert_eq!(geode.usage().await, MAX_CHUNK_SIZE as u64 + 100);
+
+            fs::remove_dir_all(&base_path).await.unwrap();
+        });
+    }
 }
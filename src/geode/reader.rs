@@ -0,0 +1,214 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Streaming read access over a file stored in Geode.
+
+use std::{
+    future::Future,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{AsyncRead, AsyncSeek};
+use smol::{fs, io::SeekFrom};
+
+use super::crypto::{FileKey, AEAD_TAG_SIZE, NONCE_SIZE};
+use crate::{Error, Result};
+
+/// Stored size overhead of an encrypted chunk
+const ENCRYPTION_OVERHEAD: u64 = (NONCE_SIZE + AEAD_TAG_SIZE) as u64;
+
+/// Future resolving into the plaintext of a single chunk
+type ChunkFuture = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send>>;
+
+/// Location of a single chunk inside the reassembled file
+struct ChunkEntry {
+    /// BLAKE3 hash of the stored chunk
+    hash: blake3::Hash,
+    /// Path to the chunk on the filesystem
+    path: PathBuf,
+    /// Offset of the chunk's first plaintext byte inside the file
+    offset: u64,
+}
+
+/// `GeodeReader` implements [`AsyncRead`] and [`AsyncSeek`] over a file
+/// stored in Geode, transparently concatenating (and if needed,
+/// decrypting) its chunks. Only a single chunk is held in memory at
+/// any given time. Chunks are verified against their hash when loaded.
+pub struct GeodeReader {
+    /// Chunks of the file, in order
+    chunks: Vec<ChunkEntry>,
+    /// Optional key used to decrypt chunks
+    key: Option<FileKey>,
+    /// Total plaintext length of the file
+    len: u64,
+    /// Current read position
+    pos: u64,
+    /// Currently loaded chunk index and its plaintext
+    current: Option<(usize, Vec<u8>)>,
+    /// Chunk that is currently being loaded from disk
+    pending: Option<(usize, ChunkFuture)>,
+}
+
+impl GeodeReader {
+    /// Create a new reader over the given chunk hashes and their paths.
+    /// The stored size of every chunk is read from the filesystem in order
+    /// to compute plaintext offsets, which enables seeking.
+    pub(super) async fn new(
+        chunks: Vec<(blake3::Hash, PathBuf)>,
+        key: Option<FileKey>,
+    ) -> Result<Self> {
+        let mut entries = Vec::with_capacity(chunks.len());
+        let mut offset = 0;
+
+        for (hash, path) in chunks {
+            let stored_len = fs::metadata(&path).await?.len();
+            let len = match key {
+                Some(_) => {
+                    if stored_len < ENCRYPTION_OVERHEAD {
+                        return Err(Error::GeodeChunkDecryptionFailed)
+                    }
+                    stored_len - ENCRYPTION_OVERHEAD
+                }
+                None => stored_len,
+            };
+
+            entries.push(ChunkEntry { hash, path, offset });
+            offset += len;
+        }
+
+        Ok(Self { chunks: entries, key, len: offset, pos: 0, current: None, pending: None })
+    }
+
+    /// Total plaintext length of the file
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the file is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Find the index of the chunk containing the given offset
+    fn chunk_index(&self, pos: u64) -> usize {
+        match self.chunks.binary_search_by(|c| c.offset.cmp(&pos)) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        }
+    }
+
+    /// Read a chunk from disk, verify its hash, and decrypt it if needed
+    fn load_chunk(&self, index: usize) -> ChunkFuture {
+        let path = self.chunks[index].path.clone();
+        let hash = self.chunks[index].hash;
+        let key = self.key;
+
+        Box::pin(async move {
+            let data = fs::read(&path).await?;
+            if blake3::hash(&data) != hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "chunk is corrupted, geode needs garbage collection",
+                ))
+            }
+
+            match key {
+                Some(key) => key
+                    .decrypt_chunk(&data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+                None => Ok(data),
+            }
+        })
+    }
+}
+
+impl AsyncRead for GeodeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Poll::Ready(Ok(0))
+        }
+
+        let index = self.chunk_index(self.pos);
+
+        // Make sure the chunk containing the current position is loaded
+        if !matches!(self.current, Some((i, _)) if i == index) {
+            if !matches!(self.pending, Some((i, _)) if i == index) {
+                let fut = self.load_chunk(index);
+                self.pending = Some((index, fut));
+            }
+
+            let (_, fut) = self.pending.as_mut().unwrap();
+            let data = match fut.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(v)) => v,
+                Poll::Ready(Err(e)) => {
+                    self.pending = None;
+                    return Poll::Ready(Err(e))
+                }
+            };
+
+            self.pending = None;
+            self.current = Some((index, data));
+        }
+
+        let start = (self.pos - self.chunks[index].offset) as usize;
+        let (_, data) = self.current.as_ref().unwrap();
+        let Some(available) = data.get(start..) else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk is shorter than expected",
+            )))
+        };
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+
+        self.pos += n as u64;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncSeek for GeodeReader {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => self.len.checked_add_signed(p),
+            SeekFrom::Current(p) => self.pos.checked_add_signed(p),
+        };
+
+        let Some(new_pos) = new_pos else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )))
+        };
+
+        self.pos = new_pos;
+        Poll::Ready(Ok(new_pos))
+    }
+}
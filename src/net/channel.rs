@@ -119,6 +119,8 @@ impl Channel {
         subsystem.add_dispatch::<message::PongMessage>().await;
         subsystem.add_dispatch::<message::GetAddrsMessage>().await;
         subsystem.add_dispatch::<message::AddrsMessage>().await;
+        subsystem.add_dispatch::<message::GetSignedAddrsMessage>().await;
+        subsystem.add_dispatch::<message::SignedAddrsMessage>().await;
     }
 
     /// Starts the channel. Runs a receive loop to start receiving messages
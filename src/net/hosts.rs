@@ -19,6 +19,7 @@
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
+    time::UNIX_EPOCH,
 };
 
 use log::debug;
@@ -50,6 +51,15 @@ pub struct Hosts {
     /// Peers we reject from connecting
     rejected: RwLock<HashSet<String>>,
 
+    /// Last time stored addresses were verified to be reachable, e.g. by
+    /// a successful handshake. Addresses which were never verified are
+    /// not included.
+    last_seen: RwLock<HashMap<Url, u64>>,
+
+    /// Addresses received from seeds during seeding, along with the seeds
+    /// which returned them. They are only stored once all seeds replied.
+    seeded: RwLock<HashMap<Url, HashSet<Url>>>,
+
     /// Subscriber listening for store updates
     store_subscriber: SubscriberPtr<usize>,
 
@@ -64,6 +74,8 @@ impl Hosts {
             addrs: RwLock::new(HashSet::new()),
             quarantine: RwLock::new(HashMap::new()),
             rejected: RwLock::new(HashSet::new()),
+            last_seen: RwLock::new(HashMap::new()),
+            seeded: RwLock::new(HashMap::new()),
             store_subscriber: Subscriber::new(),
             settings,
         })
@@ -94,6 +106,44 @@ impl Hosts {
         debug!(target: "net::hosts::store()", "hosts::store() [END]");
     }
 
+    /// Record addresses returned by the given seed, to be cross-checked
+    /// with the replies of other seeds in [`Hosts::commit_seed_addrs()`].
+    pub async fn stage_seed_addrs(&self, seed: &Url, addrs: &[Url]) {
+        let mut seeded = self.seeded.write().await;
+        for addr in addrs {
+            seeded.entry(addr.clone()).or_default().insert(seed.clone());
+        }
+    }
+
+    /// Store the addresses staged by seeds, preferring ones corroborated
+    /// by more than one seed. Addresses returned by a single seed are only
+    /// used to fill up to `min_addrs`, unless no more than one seed replied.
+    /// Returns the number of addresses passed on to [`Hosts::store()`].
+    pub async fn commit_seed_addrs(&self, min_addrs: usize) -> usize {
+        let seeded = std::mem::take(&mut *self.seeded.write().await);
+        let seeds: HashSet<&Url> = seeded.values().flatten().collect();
+
+        let mut addrs: Vec<(&Url, usize)> =
+            seeded.iter().map(|(addr, seeds)| (addr, seeds.len())).collect();
+        addrs.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let mut selected = vec![];
+        for (addr, corroborations) in addrs {
+            if corroborations < 2 && seeds.len() > 1 && selected.len() >= min_addrs {
+                break
+            }
+            selected.push(addr.clone());
+        }
+
+        debug!(
+            target: "net::hosts::commit_seed_addrs()",
+            "Selected {} of {} addrs returned by {} seeds", selected.len(), seeded.len(), seeds.len(),
+        );
+
+        self.store(&selected).await;
+        selected.len()
+    }
+
     pub async fn subscribe_store(&self) -> Result<Subscription<usize>> {
         let sub = self.store_subscriber.clone().subscribe().await;
         Ok(sub)
@@ -192,6 +242,7 @@ impl Hosts {
         debug!(target: "net::hosts::remove()", "Removing peer {}", url);
         self.addrs.write().await.remove(url);
         self.quarantine.write().await.remove(url);
+        self.last_seen.write().await.remove(url);
     }
 
     /// Quarantine a peer. If they've been quarantined for 50 times, forget them.
@@ -199,6 +250,7 @@ impl Hosts {
         debug!(target: "net::hosts::remove()", "Quarantining peer {}", url);
         // Remove from main hosts set
         self.addrs.write().await.remove(url);
+        self.last_seen.write().await.remove(url);
 
         let mut q = self.quarantine.write().await;
         if let Some(retries) = q.get_mut(url) {
@@ -249,6 +301,23 @@ impl Hosts {
         }
     }
 
+    /// Record that a stored address was verified to be reachable at the
+    /// given UNIX timestamp. Unknown addresses are ignored.
+    pub async fn mark_seen(&self, url: &Url, timestamp: u64) {
+        if !self.addrs.read().await.contains(url) {
+            return
+        }
+
+        let mut last_seen = self.last_seen.write().await;
+        let entry = last_seen.entry(url.clone()).or_insert(timestamp);
+        *entry = timestamp.max(*entry);
+    }
+
+    /// Get the last time a stored address was verified to be reachable
+    pub async fn last_seen(&self, url: &Url) -> Option<u64> {
+        self.last_seen.read().await.get(url).copied()
+    }
+
     /// Check if the host list is empty.
     pub async fn is_empty(&self) -> bool {
         self.addrs.read().await.is_empty()
@@ -273,6 +342,32 @@ impl Hosts {
         urls
     }
 
+    /// Get up to n random hosts from the hosts set, preferring hosts which
+    /// were verified reachable within the configured freshness window.
+    /// If there are not enough of them, the rest is filled with random hosts.
+    pub async fn fetch_n_fresh(&self, n: u32) -> Vec<Url> {
+        let n = n as usize;
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let addrs = self.addrs.read().await;
+        let last_seen = self.last_seen.read().await;
+
+        let (fresh, stale): (Vec<&Url>, Vec<&Url>) =
+            addrs.iter().partition(|addr| match last_seen.get(*addr) {
+                Some(ts) => now.saturating_sub(*ts) <= self.settings.hosts_freshness,
+                None => false,
+            });
+
+        let mut urls: Vec<Url> =
+            fresh.into_iter().choose_multiple(&mut OsRng, n).into_iter().cloned().collect();
+
+        if urls.len() < n {
+            let rest = stale.into_iter().choose_multiple(&mut OsRng, n - urls.len());
+            urls.extend(rest.into_iter().cloned());
+        }
+
+        urls
+    }
+
     /// Get all peers that match the given transport schemes from the hosts set.
     /// TODO: add a limit: usize argument
     pub async fn fetch_with_schemes(&self, schemes: &[String]) -> Vec<Url> {
@@ -301,6 +396,38 @@ impl Hosts {
 mod tests {
     use super::{super::settings::Settings, *};
 
+    #[test]
+    fn test_commit_seed_addrs() {
+        smol::block_on(async {
+            let settings = Settings { localnet: true, ..Default::default() };
+            let hosts = Hosts::new(Arc::new(settings));
+
+            let url = |s: &str| Url::parse(s).unwrap();
+            let (seed_a, seed_b) = (url("tcp://seed-a.dark.fi:1"), url("tcp://seed-b.dark.fi:1"));
+            let shared = url("tcp://shared.dark.fi:80");
+            let only_a = url("tcp://only-a.dark.fi:80");
+            let only_b = url("tcp://only-b.dark.fi:80");
+
+            hosts.stage_seed_addrs(&seed_a, &[shared.clone(), only_a.clone()]).await;
+            hosts.stage_seed_addrs(&seed_b, &[shared.clone(), only_b.clone()]).await;
+            assert_eq!(hosts.commit_seed_addrs(0).await, 1);
+            assert!(hosts.contains(&shared).await);
+            assert!(!hosts.contains(&only_a).await);
+            assert!(!hosts.contains(&only_b).await);
+
+            // Uncorroborated addresses are used to reach the minimum
+            hosts.stage_seed_addrs(&seed_a, &[shared.clone(), only_a.clone()]).await;
+            hosts.stage_seed_addrs(&seed_b, &[shared.clone(), only_b.clone()]).await;
+            assert_eq!(hosts.commit_seed_addrs(2).await, 2);
+            assert!(hosts.contains(&only_a).await || hosts.contains(&only_b).await);
+
+            // With a single seed there is nothing to cross-check against
+            hosts.stage_seed_addrs(&seed_a, &[only_a.clone(), only_b.clone()]).await;
+            assert_eq!(hosts.commit_seed_addrs(0).await, 2);
+            assert!(hosts.contains(&only_b).await);
+        });
+    }
+
     #[test]
     fn test_store_localnet() {
         smol::block_on(async {
@@ -382,4 +509,43 @@ mod tests {
             assert!(!hosts.contains(&remote_hosts[2]).await);
         });
     }
+
+    #[test]
+    fn test_fetch_fresh() {
+        smol::block_on(async {
+            let settings = Settings { localnet: true, hosts_freshness: 60, ..Default::default() };
+            let hosts = Hosts::new(Arc::new(settings));
+            let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+
+            let stored: Vec<Url> = (0..6)
+                .map(|i| Url::parse(&format!("tcp://127.0.0.1:{}", 1000 + i)).unwrap())
+                .collect();
+            hosts.store(&stored).await;
+
+            // Unknown hosts can't be marked as seen
+            let unknown = Url::parse("tcp://127.0.0.1:999").unwrap();
+            hosts.mark_seen(&unknown, now).await;
+            assert_eq!(hosts.last_seen(&unknown).await, None);
+
+            // Two fresh hosts, and one seen too long ago
+            hosts.mark_seen(&stored[0], now).await;
+            hosts.mark_seen(&stored[1], now - 10).await;
+            hosts.mark_seen(&stored[2], now - 120).await;
+            assert_eq!(hosts.last_seen(&stored[1]).await, Some(now - 10));
+
+            let fetched = hosts.fetch_n_fresh(2).await;
+            assert_eq!(fetched.len(), 2);
+            assert!(fetched.contains(&stored[0]) && fetched.contains(&stored[1]));
+
+            // Missing hosts are filled up with the stale ones
+            let fetched = hosts.fetch_n_fresh(4).await;
+            assert_eq!(fetched.len(), 4);
+            assert!(fetched.contains(&stored[0]) && fetched.contains(&stored[1]));
+            assert_eq!(hosts.fetch_n_fresh(10).await.len(), 6);
+
+            // Removed hosts are forgotten
+            hosts.remove(&stored[0]).await;
+            assert_eq!(hosts.last_seen(&stored[0]).await, None);
+        });
+    }
 }
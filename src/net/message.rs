@@ -16,8 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::time::UNIX_EPOCH;
+
 use darkfi_serial::{
-    async_trait, AsyncDecodable, AsyncEncodable, Decodable, Encodable, SerialDecodable,
+    async_trait, serialize, AsyncDecodable, AsyncEncodable, Decodable, Encodable, SerialDecodable,
     SerialEncodable, VarInt,
 };
 use log::trace;
@@ -72,6 +74,53 @@ pub struct AddrsMessage {
 }
 impl_p2p_message!(AddrsMessage, "addr");
 
+/// Requests addresses signed by a seed node. Only sent by nodes pinning
+/// seed keys, so peers unaware of signed replies never receive them.
+#[derive(Debug, Copy, Clone, SerialEncodable, SerialDecodable)]
+pub struct GetSignedAddrsMessage {
+    /// Maximum number of addresses to receive
+    pub max: u32,
+}
+impl_p2p_message!(GetSignedAddrsMessage, "getsignedaddr");
+
+/// Address information signed by a seed node, so it can be verified
+/// against pinned seed keys. Seeds configured with a signing key send
+/// it in response to `GetSignedAddrsMessage`.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct SignedAddrsMessage {
+    pub addrs: Vec<Url>,
+    /// UNIX timestamp the addresses were signed at
+    pub timestamp: u64,
+    /// Public key of the signing seed
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+impl_p2p_message!(SignedAddrsMessage, "signedaddr");
+
+impl SignedAddrsMessage {
+    /// Sign the given addresses with the seed's key pair
+    pub fn new(addrs: Vec<Url>, keypair: &ed25519_compact::KeyPair) -> Self {
+        let timestamp = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let mut msg = Self { addrs, timestamp, public_key: *keypair.pk, signature: [0; 64] };
+        msg.signature = *keypair.sk.sign(msg.signed_data(), None);
+        msg
+    }
+
+    /// Check that the signature was made by the embedded public key
+    pub fn verify(&self) -> bool {
+        let public_key = ed25519_compact::PublicKey::new(self.public_key);
+        let signature = ed25519_compact::Signature::new(self.signature);
+        public_key.verify(self.signed_data(), &signature).is_ok()
+    }
+
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = b"darkfi:seed_addrs".to_vec();
+        data.extend(serialize(&self.addrs));
+        data.extend(self.timestamp.to_le_bytes());
+        data
+    }
+}
+
 /// Requests version information of outbound connection.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
 pub struct VersionMessage {
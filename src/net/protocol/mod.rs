@@ -62,8 +62,9 @@ pub use protocol_address::ProtocolAddress;
 ///
 /// To start the seed protocol, we create a subscription to the address
 /// message, and send our address to the seed server. Then we send a
-/// get-address message and receive an address message. We add these addresses
-/// to our internal store.
+/// get-address message and receive an address message, which must be signed
+/// by a pinned seed key if any are configured. The addresses are staged in
+/// our internal store until the seed session cross-checks them.
 pub mod protocol_seed;
 pub use protocol_seed::ProtocolSeed;
 
@@ -26,7 +26,7 @@ use super::{
     super::{
         channel::ChannelPtr,
         hosts::HostsPtr,
-        message::{AddrsMessage, GetAddrsMessage},
+        message::{AddrsMessage, GetAddrsMessage, GetSignedAddrsMessage, SignedAddrsMessage},
         message_subscriber::MessageSubscription,
         p2p::P2pPtr,
         session::SESSION_OUTBOUND,
@@ -42,6 +42,7 @@ pub struct ProtocolAddress {
     channel: ChannelPtr,
     addrs_sub: MessageSubscription<AddrsMessage>,
     get_addrs_sub: MessageSubscription<GetAddrsMessage>,
+    get_signed_addrs_sub: MessageSubscription<GetSignedAddrsMessage>,
     hosts: HostsPtr,
     settings: SettingsPtr,
     jobsman: ProtocolJobsManagerPtr,
@@ -65,10 +66,17 @@ impl ProtocolAddress {
         let get_addrs_sub =
             channel.subscribe_msg::<GetAddrsMessage>().await.expect("Missing getaddrs dispatcher!");
 
+        // Creates a subscription to get-signed-address message
+        let get_signed_addrs_sub = channel
+            .subscribe_msg::<GetSignedAddrsMessage>()
+            .await
+            .expect("Missing getsignedaddrs dispatcher!");
+
         Arc::new(Self {
             channel: channel.clone(),
             addrs_sub,
             get_addrs_sub,
+            get_signed_addrs_sub,
             hosts,
             jobsman: ProtocolJobsManager::new(PROTO_NAME, channel),
             settings,
@@ -113,7 +121,7 @@ impl ProtocolAddress {
                 "Received GetAddrs({}) message from {}", get_addrs_msg.max, self.channel.address(),
             );
 
-            let addrs = self.hosts.fetch_n_random(get_addrs_msg.max).await;
+            let addrs = self.hosts.fetch_n_fresh(get_addrs_msg.max).await;
             debug!(
                 target: "net::protocol_address::handle_receive_get_addrs()",
                 "Sending {} addresses to {}", addrs.len(), self.channel.address(),
@@ -124,6 +132,37 @@ impl ProtocolAddress {
         }
     }
 
+    /// Handles receiving the get-signed-address message, sent by nodes pinning
+    /// seed keys. Replies with a signed address message if we have a signing
+    /// key, otherwise the request is ignored and the requester times out.
+    async fn handle_receive_get_signed_addrs(self: Arc<Self>) -> Result<()> {
+        debug!(
+            target: "net::protocol_address::handle_receive_get_signed_addrs()",
+            "[START] address={}", self.channel.address(),
+        );
+
+        loop {
+            let get_addrs_msg = self.get_signed_addrs_sub.receive().await?;
+
+            let Some(keypair) = &self.settings.seed_signing_key else {
+                debug!(
+                    target: "net::protocol_address::handle_receive_get_signed_addrs()",
+                    "No signing key configured, ignoring request from {}", self.channel.address(),
+                );
+                continue
+            };
+
+            let addrs = self.hosts.fetch_n_fresh(get_addrs_msg.max).await;
+            debug!(
+                target: "net::protocol_address::handle_receive_get_signed_addrs()",
+                "Sending {} signed addresses to {}", addrs.len(), self.channel.address(),
+            );
+
+            let signed_msg = SignedAddrsMessage::new(addrs, keypair);
+            self.channel.send(&signed_msg).await?;
+        }
+    }
+
     /// Periodically send our external addresses through the channel.
     async fn send_my_addrs(self: Arc<Self>) -> Result<()> {
         debug!(
@@ -157,7 +196,8 @@ impl ProtocolBase for ProtocolAddress {
         }
 
         self.jobsman.clone().spawn(self.clone().handle_receive_addrs(), ex.clone()).await;
-        self.jobsman.spawn(self.clone().handle_receive_get_addrs(), ex).await;
+        self.jobsman.clone().spawn(self.clone().handle_receive_get_addrs(), ex.clone()).await;
+        self.jobsman.spawn(self.clone().handle_receive_get_signed_addrs(), ex).await;
 
         // Send get_address message.
         let get_addrs = GetAddrsMessage { max: self.settings.outbound_connections as u32 };
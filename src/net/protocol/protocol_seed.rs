@@ -16,24 +16,28 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
 
 use async_trait::async_trait;
-use log::debug;
+use log::{debug, warn};
 use smol::Executor;
+use url::Url;
 
 use super::{
     super::{
         channel::ChannelPtr,
         hosts::HostsPtr,
-        message::{AddrsMessage, GetAddrsMessage},
+        message::{AddrsMessage, GetAddrsMessage, GetSignedAddrsMessage, SignedAddrsMessage},
         message_subscriber::MessageSubscription,
         p2p::P2pPtr,
         settings::SettingsPtr,
     },
     protocol_base::{ProtocolBase, ProtocolBasePtr},
 };
-use crate::Result;
+use crate::{system::timeout::timeout, Error, Result};
 
 /// Implements the seed protocol
 pub struct ProtocolSeed {
@@ -41,10 +45,14 @@ pub struct ProtocolSeed {
     hosts: HostsPtr,
     settings: SettingsPtr,
     addr_sub: MessageSubscription<AddrsMessage>,
+    signed_addr_sub: MessageSubscription<SignedAddrsMessage>,
 }
 
 const PROTO_NAME: &str = "ProtocolSeed";
 
+/// Maximum age in seconds of a signed seed reply we accept
+const SIGNED_ADDRS_MAX_AGE: u64 = 600;
+
 impl ProtocolSeed {
     /// Create a new seed protocol.
     pub async fn init(channel: ChannelPtr, p2p: P2pPtr) -> ProtocolBasePtr {
@@ -55,7 +63,57 @@ impl ProtocolSeed {
         let addr_sub =
             channel.subscribe_msg::<AddrsMessage>().await.expect("Missing addr dispatcher!");
 
-        Arc::new(Self { channel, hosts, settings, addr_sub })
+        // Create a subscription to signed address message
+        let signed_addr_sub = channel
+            .subscribe_msg::<SignedAddrsMessage>()
+            .await
+            .expect("Missing signedaddr dispatcher!");
+
+        Arc::new(Self { channel, hosts, settings, addr_sub, signed_addr_sub })
+    }
+
+    /// Request and receive the seed's addresses. If seed keys are pinned in
+    /// settings, only a reply signed by one of them within
+    /// [`SIGNED_ADDRS_MAX_AGE`] is accepted, and the seed is rejected if it
+    /// does not reply within the channel handshake timeout.
+    async fn receive_addrs(&self) -> Result<Vec<Url>> {
+        let max = self.settings.outbound_connections as u32;
+
+        if self.settings.seed_keys.is_empty() {
+            self.channel.send(&GetAddrsMessage { max }).await?;
+            let addrs_msg = self.addr_sub.receive().await?;
+            return Ok(addrs_msg.addrs.clone())
+        }
+
+        self.channel.send(&GetSignedAddrsMessage { max }).await?;
+        let reply_timeout = Duration::from_secs(self.settings.channel_handshake_timeout);
+        let Ok(msg) = timeout(reply_timeout, self.signed_addr_sub.receive()).await else {
+            warn!(
+                target: "net::protocol_seed::receive_addrs()",
+                "Seed {} did not send a signed reply in time", self.channel.address(),
+            );
+            return Err(Error::SeedResponseUnverified)
+        };
+        let msg = msg?;
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+
+        if !self.settings.seed_keys.iter().any(|pk| **pk == msg.public_key) {
+            warn!(
+                target: "net::protocol_seed::receive_addrs()",
+                "Seed {} signed its reply with an unknown key", self.channel.address(),
+            );
+            return Err(Error::SeedResponseUnverified)
+        }
+
+        if now.abs_diff(msg.timestamp) > SIGNED_ADDRS_MAX_AGE || !msg.verify() {
+            warn!(
+                target: "net::protocol_seed::receive_addrs()",
+                "Seed {} sent an invalid or stale signed reply", self.channel.address(),
+            );
+            return Err(Error::SeedResponseUnverified)
+        }
+
+        Ok(msg.addrs.clone())
     }
 
     /// Sends own external addresses over a channel. Imports own external addresses
@@ -92,17 +150,14 @@ impl ProtocolBase for ProtocolSeed {
         // Send own address to the seed server
         self.send_self_address().await?;
 
-        // Send get address message
-        let get_addr = GetAddrsMessage { max: self.settings.outbound_connections as u32 };
-        self.channel.send(&get_addr).await?;
-
-        // Receive addresses
-        let addrs_msg = self.addr_sub.receive().await?;
+        // Request and receive addresses. They are only stored once the seed session
+        // has cross-checked them against the replies of the other seeds.
+        let addrs = self.receive_addrs().await?;
         debug!(
             target: "net::protocol_seed::start()",
-            "Received {} addrs from {}", addrs_msg.addrs.len(), self.channel.address(),
+            "Received {} addrs from {}", addrs.len(), self.channel.address(),
         );
-        self.hosts.store(&addrs_msg.addrs).await;
+        self.hosts.stage_seed_addrs(self.channel.address(), &addrs).await;
 
         debug!(target: "net::protocol_seed::start()", "END => address={}", self.channel.address());
         Ok(())
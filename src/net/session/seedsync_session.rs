@@ -36,6 +36,12 @@
 //! task that runs the version exchange with the `perform_handshake_protocols()`
 //! function. This runs the version exchange protocol, stores the channel in the
 //! p2p list of channels, and subscribes to a stop signal.
+//!
+//! Seeds are queried concurrently and the addresses they return are staged
+//! in [`Hosts`](super::super::hosts::Hosts). Once all seeds replied, addresses
+//! returned by more than one seed are preferred, so a single compromised
+//! seed cannot easily eclipse us. If seed keys are pinned in the settings,
+//! only replies signed by one of them are taken into account.
 
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
@@ -110,6 +116,13 @@ impl SeedSyncSession {
         // Poll concurrently
         join_all(tasks).await;
 
+        // Cross-check the replies and store the preferred addresses
+        let stored = self.p2p().hosts().commit_seed_addrs(settings.outbound_connections).await;
+        info!(
+            target: "net::session::seedsync_session",
+            "[P2P] Stored {} addresses received from seeds", stored,
+        );
+
         if failed.load(Ordering::SeqCst) == settings.seeds.len() {
             return Err(Error::SeedFailed)
         }
@@ -21,6 +21,8 @@ use std::sync::Arc;
 use structopt::StructOpt;
 use url::Url;
 
+use crate::{Error, Result};
+
 /// Atomic pointer to network settings
 pub type SettingsPtr = Arc<Settings>;
 
@@ -40,6 +42,12 @@ pub struct Settings {
     /// Seed nodes to connect to for peer discovery and/or adversising our
     /// own external addresses
     pub seeds: Vec<Url>,
+    /// Pinned public keys of seed nodes. When not empty, addresses are
+    /// only accepted from seeds replying with a valid signature by one
+    /// of these keys.
+    pub seed_keys: Vec<ed25519_compact::PublicKey>,
+    /// Key used to sign our address replies when acting as a seed node
+    pub seed_signing_key: Option<ed25519_compact::KeyPair>,
     /// Application version, used for convenient protocol matching
     pub app_version: semver::Version,
     /// Whitelisted network transports for outbound connections
@@ -64,6 +72,9 @@ pub struct Settings {
     pub localnet: bool,
     /// Delete a peer from hosts if they've been quarantined N times
     pub hosts_quarantine_limit: usize,
+    /// Hosts verified reachable within this many seconds are preferred
+    /// when replying to address requests
+    pub hosts_freshness: u64,
     /// Cooling off time for peer discovery when unsuccessful
     pub outbound_peer_discovery_cooloff_time: u64,
     /// Time between peer discovery attempts
@@ -81,6 +92,8 @@ impl Default for Settings {
             external_addrs: vec![],
             peers: vec![],
             seeds: vec![],
+            seed_keys: vec![],
+            seed_signing_key: None,
             app_version,
             allowed_transports: vec![],
             transport_mixing: true,
@@ -92,6 +105,7 @@ impl Default for Settings {
             channel_heartbeat_interval: 30,
             localnet: false,
             hosts_quarantine_limit: 50,
+            hosts_freshness: 3600,
             outbound_peer_discovery_cooloff_time: 30,
             outbound_peer_discovery_attempt_time: 5,
         }
@@ -136,6 +150,12 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub seeds: Vec<Url>,
 
+    /// Base58-encoded public keys of trusted seed nodes. When set,
+    /// only signed seed replies by one of these keys are accepted.
+    #[serde(default)]
+    #[structopt(long = "seed-key")]
+    pub seed_keys: Vec<String>,
+
     /// Manual connections retry limit
     #[structopt(skip)]
     pub manual_attempt_limit: Option<usize>,
@@ -175,6 +195,10 @@ pub struct SettingsOpt {
     #[structopt(skip)]
     pub hosts_quarantine_limit: Option<usize>,
 
+    /// Prefer hosts verified reachable within this many seconds
+    #[structopt(skip)]
+    pub hosts_freshness: Option<u64>,
+
     /// Cooling off time for peer discovery when unsuccessful
     #[structopt(skip)]
     pub outbound_peer_discovery_cooloff_time: Option<u64>,
@@ -184,17 +208,28 @@ pub struct SettingsOpt {
     pub outbound_peer_discovery_attempt_time: Option<u64>,
 }
 
-impl From<SettingsOpt> for Settings {
-    fn from(opt: SettingsOpt) -> Self {
+impl TryFrom<SettingsOpt> for Settings {
+    type Error = Error;
+
+    fn try_from(opt: SettingsOpt) -> Result<Self> {
         let version = option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0");
         let app_version = semver::Version::parse(version).unwrap();
 
-        Self {
+        Ok(Self {
             node_id: opt.node_id,
             inbound_addrs: opt.inbound,
             external_addrs: opt.external_addrs,
             peers: opt.peers,
             seeds: opt.seeds,
+            seed_keys: opt
+                .seed_keys
+                .iter()
+                .map(|key| {
+                    parse_seed_key(key)
+                        .map_err(|_| Error::ParseFailed("Invalid seed key in network settings"))
+                })
+                .collect::<Result<_>>()?,
+            seed_signing_key: None,
             app_version,
             allowed_transports: opt.allowed_transports,
             transport_mixing: opt.transport_mixing.unwrap_or(false),
@@ -206,12 +241,28 @@ impl From<SettingsOpt> for Settings {
             channel_heartbeat_interval: opt.channel_heartbeat_interval.unwrap_or(30),
             localnet: opt.localnet,
             hosts_quarantine_limit: opt.hosts_quarantine_limit.unwrap_or(15),
+            hosts_freshness: opt.hosts_freshness.unwrap_or(3600),
             outbound_peer_discovery_cooloff_time: opt
                 .outbound_peer_discovery_cooloff_time
                 .unwrap_or(30),
             outbound_peer_discovery_attempt_time: opt
                 .outbound_peer_discovery_attempt_time
                 .unwrap_or(5),
-        }
+        })
     }
 }
+
+/// Parse a base58-encoded seed node public key
+pub fn parse_seed_key(key: &str) -> Result<ed25519_compact::PublicKey> {
+    let err = || Error::InvalidSeedKey(key.to_string());
+    let bytes = bs58::decode(key).into_vec().map_err(|_| err())?;
+    ed25519_compact::PublicKey::from_slice(&bytes).map_err(|_| err())
+}
+
+/// Parse a base58-encoded 32 byte secret into a seed node signing key pair
+pub fn parse_seed_signing_key(secret: &str) -> Result<ed25519_compact::KeyPair> {
+    let err = || Error::InvalidSeedKey("malformed signing secret".to_string());
+    let bytes = bs58::decode(secret).into_vec().map_err(|_| err())?;
+    let seed = ed25519_compact::Seed::from_slice(&bytes).map_err(|_| err())?;
+    Ok(ed25519_compact::KeyPair::from_seed(seed))
+}
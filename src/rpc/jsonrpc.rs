@@ -17,7 +17,7 @@
  */
 
 //! JSON-RPC 2.0 object definitions
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use rand::{rngs::OsRng, Rng};
 use tinyjson::JsonValue;
@@ -483,19 +483,41 @@ impl TryFrom<&JsonValue> for JsonError {
     }
 }
 
+/// Filter over JSON-RPC notification parameters, returning `true`
+/// for the notifications that should be pushed
+pub type JsonNotificationFilter = Arc<dyn Fn(&JsonValue) -> bool + Send + Sync>;
+
 /// A JSON-RPC subscriber for notifications
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct JsonSubscriber {
     /// Notification method
     pub method: &'static str,
     /// Notification subscriber
     pub sub: SubscriberPtr<JsonNotification>,
+    /// Optional filter of the notifications pushed to the subscription
+    pub filter: Option<JsonNotificationFilter>,
+}
+
+impl std::fmt::Debug for JsonSubscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonSubscriber")
+            .field("method", &self.method)
+            .field("sub", &self.sub)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
 }
 
 impl JsonSubscriber {
     pub fn new(method: &'static str) -> Self {
         let sub = Subscriber::new();
-        Self { method, sub }
+        Self { method, sub, filter: None }
+    }
+
+    /// Create a copy of the subscriber, whose subscriptions will
+    /// only receive the notifications accepted by given filter
+    pub fn with_filter(&self, filter: JsonNotificationFilter) -> Self {
+        Self { method: self.method, sub: self.sub.clone(), filter: Some(filter) }
     }
 
     /// Send a notification to the subscriber with the given JSON object
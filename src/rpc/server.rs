@@ -39,6 +39,13 @@ use crate::{
 pub trait RequestHandler: Sync + Send {
     async fn handle_request(&self, req: JsonRequest) -> JsonResult;
 
+    /// Handle a request along with the address of the client that sent it.
+    /// Handlers that care about who is calling them (e.g. for rate limiting)
+    /// can override this, otherwise it calls [`RequestHandler::handle_request()`].
+    async fn handle_request_from(&self, req: JsonRequest, _peer: &Url) -> JsonResult {
+        self.handle_request(req).await
+    }
+
     async fn pong(&self, id: u16, _params: JsonValue) -> JsonResult {
         JsonResponse::new(JsonValue::String("pong".to_string()), id).into()
     }
@@ -98,7 +105,7 @@ pub async fn accept(
 
         debug!(target: "rpc::server", "{} --> {}", addr, val.stringify()?);
 
-        let rep = rh.handle_request(req).await;
+        let rep = rh.handle_request_from(req, &addr).await;
 
         match rep {
             JsonResult::Subscriber(subscriber) => {
@@ -108,6 +115,13 @@ pub async fn accept(
                     // Listen for notifications
                     let notification = subscription.receive().await;
 
+                    // Skip notifications the subscriber filters out
+                    if let Some(filter) = &subscriber.filter {
+                        if !filter(&notification.params) {
+                            continue
+                        }
+                    }
+
                     // Push notification
                     debug!(target: "rpc::server", "{} <-- {}", addr, notification.stringify()?);
                     let notification = JsonResult::Notification(notification);
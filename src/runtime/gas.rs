@@ -0,0 +1,300 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use wasmer::{wasmparser::Operator, AsStoreMut, Global, Instance, Value};
+
+use crate::Result;
+
+/// Gas limit for a contract
+pub const GAS_LIMIT: u64 = 400_000_000;
+
+/// Version of the gas schedule. Operator costs get compiled into the
+/// contract modules, so this must be bumped whenever they change, in
+/// order to not reuse modules compiled with a previous schedule.
+pub const GAS_SCHEDULE_VERSION: u32 = 1;
+
+/// Cost of simple operators, like constants, locals access, comparisons,
+/// additions, and bitwise operations
+pub const GAS_OP_BASE: u64 = 1;
+/// Cost of control flow operators, like branches and returns
+pub const GAS_OP_CONTROL: u64 = 2;
+/// Cost of multiplications
+pub const GAS_OP_MUL: u64 = 3;
+/// Cost of memory loads and stores, and globals access
+pub const GAS_OP_MEMORY: u64 = 3;
+/// Cost of floating point operators
+pub const GAS_OP_FLOAT: u64 = 4;
+/// Cost of divisions and remainders
+pub const GAS_OP_DIV: u64 = 8;
+/// Cost of direct function calls
+pub const GAS_OP_CALL: u64 = 10;
+/// Cost of indirect function calls, performing a table lookup
+pub const GAS_OP_CALL_INDIRECT: u64 = 20;
+/// Cost of bulk memory operators
+pub const GAS_OP_MEMORY_BULK: u64 = 100;
+/// Cost of growing the memory
+pub const GAS_OP_MEMORY_GROW: u64 = 10_000;
+
+/// Base cost of calling any host function
+pub const GAS_HOST_CALL: u64 = 100;
+/// Cost per byte copied from or into the VM memory
+pub const GAS_PER_BYTE_COPIED: u64 = 1;
+/// Base cost of a database read
+pub const GAS_DB_READ: u64 = 1_000;
+/// Cost per byte read from a database
+pub const GAS_PER_BYTE_READ: u64 = 2;
+/// Base cost of a database write or removal
+pub const GAS_DB_WRITE: u64 = 5_000;
+/// Cost per byte written to a database
+pub const GAS_PER_BYTE_WRITTEN: u64 = 20;
+/// Cost of a Poseidon permutation, absorbing up to two field elements
+pub const GAS_POSEIDON_PERMUTATION: u64 = 10_000;
+/// Cost of a Pallas point addition
+pub const GAS_EC_ADD: u64 = 1_000;
+/// Cost of a Pallas point scalar multiplication
+pub const GAS_EC_MUL: u64 = 60_000;
+/// Cost of a Schnorr signature verification, excluding the message hashing
+pub const GAS_SCHNORR_VERIFY: u64 = 2 * GAS_EC_MUL;
+
+/// Return the cost of given wasm operator, charged by the metering middleware
+/// each time the operator gets executed.
+/// <https://docs.rs/wasmparser/latest/wasmparser/enum.Operator.html>
+pub fn operator_cost(operator: &Operator) -> u64 {
+    match operator {
+        Operator::Call { .. } => GAS_OP_CALL,
+
+        Operator::CallIndirect { .. } => GAS_OP_CALL_INDIRECT,
+
+        Operator::MemoryGrow { .. } => GAS_OP_MEMORY_GROW,
+
+        Operator::MemoryCopy { .. } |
+        Operator::MemoryFill { .. } |
+        Operator::MemoryInit { .. } |
+        Operator::DataDrop { .. } => GAS_OP_MEMORY_BULK,
+
+        Operator::I32DivS |
+        Operator::I32DivU |
+        Operator::I32RemS |
+        Operator::I32RemU |
+        Operator::I64DivS |
+        Operator::I64DivU |
+        Operator::I64RemS |
+        Operator::I64RemU => GAS_OP_DIV,
+
+        Operator::I32Mul | Operator::I64Mul => GAS_OP_MUL,
+
+        Operator::I32Load { .. } |
+        Operator::I64Load { .. } |
+        Operator::F32Load { .. } |
+        Operator::F64Load { .. } |
+        Operator::I32Load8S { .. } |
+        Operator::I32Load8U { .. } |
+        Operator::I32Load16S { .. } |
+        Operator::I32Load16U { .. } |
+        Operator::I64Load8S { .. } |
+        Operator::I64Load8U { .. } |
+        Operator::I64Load16S { .. } |
+        Operator::I64Load16U { .. } |
+        Operator::I64Load32S { .. } |
+        Operator::I64Load32U { .. } |
+        Operator::I32Store { .. } |
+        Operator::I64Store { .. } |
+        Operator::F32Store { .. } |
+        Operator::F64Store { .. } |
+        Operator::I32Store8 { .. } |
+        Operator::I32Store16 { .. } |
+        Operator::I64Store8 { .. } |
+        Operator::I64Store16 { .. } |
+        Operator::I64Store32 { .. } |
+        Operator::GlobalGet { .. } |
+        Operator::GlobalSet { .. } |
+        Operator::MemorySize { .. } => GAS_OP_MEMORY,
+
+        Operator::Br { .. } |
+        Operator::BrIf { .. } |
+        Operator::BrTable { .. } |
+        Operator::If { .. } |
+        Operator::Else |
+        Operator::Return |
+        Operator::Select |
+        Operator::TypedSelect { .. } => GAS_OP_CONTROL,
+
+        Operator::F32Add |
+        Operator::F32Sub |
+        Operator::F32Mul |
+        Operator::F32Div |
+        Operator::F32Sqrt |
+        Operator::F64Add |
+        Operator::F64Sub |
+        Operator::F64Mul |
+        Operator::F64Div |
+        Operator::F64Sqrt => GAS_OP_FLOAT,
+
+        _ => GAS_OP_BASE,
+    }
+}
+
+/// Gas consumption report of a contract runtime
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct GasReport {
+    /// Gas consumed by all the sections executed so far
+    pub used: u64,
+    /// Gas remaining until the limit is reached
+    pub remaining: u64,
+}
+
+/// Handles to the metering globals of an instance, used by host
+/// functions to charge their gas, which the metering middleware
+/// knows nothing about.
+#[derive(Clone)]
+pub struct GasMeter {
+    remaining: Global,
+    exhausted: Global,
+}
+
+impl GasMeter {
+    /// Grab the metering globals of given instance
+    pub fn new(instance: &Instance) -> Result<Self> {
+        let remaining = instance.exports.get_global("wasmer_metering_remaining_points")?.clone();
+        let exhausted = instance.exports.get_global("wasmer_metering_points_exhausted")?.clone();
+        Ok(Self { remaining, exhausted })
+    }
+
+    /// Retrieve the remaining gas
+    pub fn remaining(&self, store: &mut impl AsStoreMut) -> u64 {
+        if matches!(self.exhausted.get(store), Value::I32(v) if v != 0) {
+            return 0
+        }
+
+        match self.remaining.get(store) {
+            Value::I64(v) => v as u64,
+            _ => 0,
+        }
+    }
+
+    /// Charge given amount of gas. If there isn't enough gas left, the meter
+    /// gets exhausted and `false` is returned. Host functions should then
+    /// abort, and the contract execution will trap at the next metering
+    /// point, same as when the wasm operators exhaust it.
+    pub fn charge(&self, store: &mut impl AsStoreMut, gas: u64) -> bool {
+        let remaining = self.remaining(store);
+
+        if gas > remaining {
+            // These can't fail, as we write values of the globals types
+            let _ = self.remaining.set(store, Value::I64(0));
+            let _ = self.exhausted.set(store, Value::I32(1));
+            return false
+        }
+
+        let _ = self.remaining.set(store, Value::I64((remaining - gas) as i64));
+        true
+    }
+}
+
+/// Cost of a host function call copying `copied` bytes between the
+/// host and the VM memory.
+pub fn host_call_cost(copied: usize) -> u64 {
+    GAS_HOST_CALL.saturating_add((copied as u64).saturating_mul(GAS_PER_BYTE_COPIED))
+}
+
+/// Cost of a database read, returning `read` bytes.
+pub fn db_read_cost(read: usize) -> u64 {
+    GAS_DB_READ.saturating_add((read as u64).saturating_mul(GAS_PER_BYTE_READ))
+}
+
+/// Cost of a database write, storing `written` bytes.
+pub fn db_write_cost(written: usize) -> u64 {
+    GAS_DB_WRITE.saturating_add((written as u64).saturating_mul(GAS_PER_BYTE_WRITTEN))
+}
+
+/// Cost of a Poseidon hash of `messages` field elements.
+pub fn poseidon_cost(messages: usize) -> u64 {
+    let permutations = messages.div_ceil(2).max(1) as u64;
+    permutations.saturating_mul(GAS_POSEIDON_PERMUTATION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer::{imports, CompilerConfig, Function, FunctionEnv, FunctionEnvMut, Module, Store};
+    use wasmer_compiler_singlepass::Singlepass;
+    use wasmer_middlewares::{
+        metering::{get_remaining_points, MeteringPoints},
+        Metering,
+    };
+
+    #[test]
+    fn operator_costs() {
+        assert_eq!(operator_cost(&Operator::I32Add), GAS_OP_BASE);
+        assert_eq!(operator_cost(&Operator::I64Mul), GAS_OP_MUL);
+        assert_eq!(operator_cost(&Operator::I64DivU), GAS_OP_DIV);
+        assert_eq!(operator_cost(&Operator::Call { function_index: 0 }), GAS_OP_CALL);
+        assert_eq!(
+            operator_cost(&Operator::MemoryGrow { mem: 0, mem_byte: 0 }),
+            GAS_OP_MEMORY_GROW
+        );
+        assert_eq!(host_call_cost(10), GAS_HOST_CALL + 10 * GAS_PER_BYTE_COPIED);
+        assert_eq!(db_write_cost(usize::MAX), u64::MAX);
+        assert_eq!(poseidon_cost(1), GAS_POSEIDON_PERMUTATION);
+        assert_eq!(poseidon_cost(2), GAS_POSEIDON_PERMUTATION);
+        assert_eq!(poseidon_cost(5), 3 * GAS_POSEIDON_PERMUTATION);
+    }
+
+    #[test]
+    fn host_charges() -> Result<()> {
+        const WASM: &[u8] = br#"
+            (module
+                (import "env" "charge" (func $charge (param i64) (result i32)))
+                (func (export "f") (param i64) (result i32)
+                    local.get 0
+                    call $charge))
+        "#;
+        const LIMIT: u64 = 1_000;
+
+        let mut compiler_config = Singlepass::new();
+        compiler_config.push_middleware(std::sync::Arc::new(Metering::new(LIMIT, operator_cost)));
+        let mut store = Store::new(compiler_config);
+        let module = Module::new(&store, WASM)?;
+
+        let env = FunctionEnv::new(&mut store, None::<GasMeter>);
+        let charge = |mut ctx: FunctionEnvMut<Option<GasMeter>>, gas: i64| -> i32 {
+            let meter = ctx.data().clone().unwrap();
+            meter.charge(&mut ctx, gas as u64) as i32
+        };
+        let imports = imports! {
+            "env" => { "charge" => Function::new_typed_with_env(&mut store, &env, charge) }
+        };
+        let instance = wasmer::Instance::new(&mut store, &module, &imports)?;
+        *env.as_mut(&mut store) = Some(GasMeter::new(&instance)?);
+        let f = instance.exports.get_typed_function::<i64, i32>(&store, "f")?;
+
+        // Host charges get deducted along with the operators costs
+        assert_eq!(f.call(&mut store, 500)?, 1);
+        let MeteringPoints::Remaining(remaining) = get_remaining_points(&mut store, &instance)
+        else {
+            panic!("Gas exhausted")
+        };
+        assert!(remaining < LIMIT - 500);
+
+        // Exceeding the remaining gas exhausts the meter and traps
+        assert!(f.call(&mut store, remaining as i64).is_err());
+        assert_eq!(get_remaining_points(&mut store, &instance), MeteringPoints::Exhausted);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Cursor;
+
+use darkfi_sdk::{
+    crypto::{contract_id::DEPLOYOOOR_CONTRACT_ID, ContractId, PublicKey},
+    entrypoint,
+    error::{
+        CALLER_ACCESS_DENIED, CALL_DEPTH_EXCEEDED, CONTRACT_CALL_FAILED, CONTRACT_UPGRADE_FAILED,
+    },
+    pasta::pallas,
+};
+use darkfi_serial::{serialize, Decodable};
+use log::{debug, error};
+use wasmer::{FunctionEnvMut, WasmPtr};
+
+use crate::{
+    runtime::{
+        gas::{host_call_cost, GAS_HOST_CALL},
+        vm_runtime::{ContractSection, Env, Runtime},
+    },
+    Result,
+};
+
+/// Maximum depth of nested contract calls
+pub(crate) const MAX_CALL_DEPTH: u32 = 8;
+
+/// Everyone can call this. Invokes the `exec` entrypoint of another contract
+/// in a separate runtime, sharing the same blockchain overlay, and returns an
+/// object index holding the data it returned. If requested, its `apply`
+/// entrypoint is then invoked with that data, which only `deploy()`,
+/// `update()` and `migrate()` can do. The gas consumed by the invoked
+/// contract is charged to the caller.
+///
+/// The transaction only verifies the ZK proofs and signatures requested by
+/// the `metadata` of its own calls, and nested calls can't carry any. So the
+/// invoked contract's `metadata` is run with the payload first, and the call
+/// is denied if it requests any, as the `exec` result would be unverified.
+/// Nested calls are therefore limited to payloads authorized by the state
+/// and the caller's identity alone, e.g. queries, or updates the invoked
+/// contract gates on `get_caller()`.
+pub(crate) fn invoke_contract(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+
+    if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+        return CONTRACT_CALL_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
+
+    let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
+        error!(target: "runtime::call::invoke_contract()", "Failed to make slice from ptr");
+        return CONTRACT_CALL_FAILED
+    };
+
+    let mut buf = vec![0_u8; len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(target: "runtime::call::invoke_contract()", "Failed to read from memory slice: {}", e);
+        return CONTRACT_CALL_FAILED
+    };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    let contract_id: ContractId = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Failed to decode ContractId: {}", e);
+            return CONTRACT_CALL_FAILED
+        }
+    };
+
+    let data: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Failed to decode call data: {}", e);
+            return CONTRACT_CALL_FAILED
+        }
+    };
+
+    let apply: bool = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Failed to decode apply flag: {}", e);
+            return CONTRACT_CALL_FAILED
+        }
+    };
+
+    if apply &&
+        env.contract_section != ContractSection::Deploy &&
+        env.contract_section != ContractSection::Update &&
+        env.contract_section != ContractSection::Migrate
+    {
+        error!(target: "runtime::call::invoke_contract()", "State-changing call in unauthorized section");
+        return CALLER_ACCESS_DENIED
+    }
+
+    if env.call_depth >= MAX_CALL_DEPTH {
+        error!(target: "runtime::call::invoke_contract()", "Call depth limit exceeded");
+        return CALL_DEPTH_EXCEEDED
+    }
+
+    // The callee runs the version in effect at the verifying slot, like the
+    // contracts called by the transaction do.
+    let wasm = match env
+        .blockchain
+        .lock()
+        .unwrap()
+        .wasm_bincode
+        .get_at(contract_id, env.time_keeper.verifying_slot)
+    {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Failed to find contract {}: {}", contract_id, e);
+            return CONTRACT_CALL_FAILED
+        }
+    };
+
+    debug!(target: "runtime::call::invoke_contract()", "Invoking contract {} from {}", contract_id, env.contract_id);
+    let mut runtime = match Runtime::new(
+        &wasm,
+        env.blockchain.clone(),
+        contract_id,
+        env.time_keeper.clone(),
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Failed to instantiate contract {}: {}", contract_id, e);
+            return CONTRACT_CALL_FAILED
+        }
+    };
+
+    // The invoked contract can't use more gas than the caller has left
+    let gas_remaining = env.gas_meter.as_ref().unwrap().remaining(&mut store);
+    runtime.set_caller(env.contract_id, env.call_depth + 1, gas_remaining);
+
+    let denied = match requires_verification(&mut runtime, &data) {
+        Ok(false) => None,
+        Ok(true) => {
+            error!(target: "runtime::call::invoke_contract()", "Contract {} requires proofs or signatures, so it can't be invoked", contract_id);
+            Some(CALLER_ACCESS_DENIED)
+        }
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Contract {} metadata failed: {}", contract_id, e);
+            Some(CONTRACT_CALL_FAILED)
+        }
+    };
+
+    if let Some(err) = denied {
+        let _ = env.charge_gas(&mut store, runtime.gas_used());
+        return err
+    }
+
+    let result = match runtime.exec(&data) {
+        Ok((ret, _)) if apply => match runtime.apply(&ret) {
+            Ok(()) => Ok(ret),
+            Err(e) => {
+                // The state might have been partially changed, so the caller must fail
+                env.call_failed.set(true);
+                Err(e)
+            }
+        },
+        Ok((ret, _)) => Ok(ret),
+        Err(e) => Err(e),
+    };
+
+    if !env.charge_gas(&mut store, runtime.gas_used()) {
+        error!(target: "runtime::call::invoke_contract()", "Gas exhausted by contract {}", contract_id);
+        return CONTRACT_CALL_FAILED
+    }
+
+    let ret = match result {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::invoke_contract()", "Contract {} call failed: {}", contract_id, e);
+            return CONTRACT_CALL_FAILED
+        }
+    };
+
+    // Events of the invoked contract are emitted along with the caller's
+    env.events.borrow_mut().extend(runtime.take_events());
+
+    // Copy Vec<u8> to the VM
+    let mut objects = env.objects.borrow_mut();
+    objects.push(ret);
+    (objects.len() - 1) as i64
+}
+
+/// Run the `metadata` entrypoint of an invoked contract with given payload,
+/// returning `true` if it requests any ZK proof or signature verification.
+fn requires_verification(runtime: &mut Runtime, payload: &[u8]) -> Result<bool> {
+    let metadata = runtime.metadata(payload)?;
+    let mut decoder = Cursor::new(&metadata);
+
+    // The tuple is (zkas_ns, public_inputs)
+    let zkp_pub: Vec<(String, Vec<pallas::Base>)> = Decodable::decode(&mut decoder)?;
+    let sig_pub: Vec<PublicKey> = Decodable::decode(&mut decoder)?;
+
+    Ok(!zkp_pub.is_empty() || !sig_pub.is_empty())
+}
+
+/// Everyone can call this. Returns an object index holding the serialized
+/// `Option<ContractId>` of the contract that invoked the current one.
+pub(crate) fn get_caller(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    if !env.charge_gas(&mut store, GAS_HOST_CALL) {
+        return CONTRACT_CALL_FAILED
+    }
+
+    // Copy Vec<u8> to the VM
+    let mut objects = env.objects.borrow_mut();
+    objects.push(serialize(&env.caller));
+    (objects.len() - 1) as i64
+}
+
+/// Only the deployooor contract's `update()` can call this. Deploys new wasm
+/// bincode for a contract in a separate runtime, sharing the same blockchain
+/// overlay, running its `deploy` entrypoint and then, if exported, its
+/// `migrate` entrypoint with given payload. The previous bincode is kept as
+/// an older version of the contract. The gas consumed by the upgraded
+/// contract is charged to the caller.
+pub(crate) fn upgrade_contract(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+
+    if env.contract_section != ContractSection::Update || env.contract_id != *DEPLOYOOOR_CONTRACT_ID
+    {
+        error!(target: "runtime::call::upgrade_contract()", "upgrade_contract called in unauthorized section or contract");
+        return CALLER_ACCESS_DENIED
+    }
+
+    if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+        return CONTRACT_UPGRADE_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
+
+    let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
+        error!(target: "runtime::call::upgrade_contract()", "Failed to make slice from ptr");
+        return CONTRACT_UPGRADE_FAILED
+    };
+
+    let mut buf = vec![0_u8; len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(target: "runtime::call::upgrade_contract()", "Failed to read from memory slice: {}", e);
+        return CONTRACT_UPGRADE_FAILED
+    };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    let contract_id: ContractId = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::upgrade_contract()", "Failed to decode ContractId: {}", e);
+            return CONTRACT_UPGRADE_FAILED
+        }
+    };
+
+    let wasm_bincode: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::upgrade_contract()", "Failed to decode wasm bincode: {}", e);
+            return CONTRACT_UPGRADE_FAILED
+        }
+    };
+
+    let migrate_payload: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::upgrade_contract()", "Failed to decode migrate payload: {}", e);
+            return CONTRACT_UPGRADE_FAILED
+        }
+    };
+
+    if env.call_depth >= MAX_CALL_DEPTH {
+        error!(target: "runtime::call::upgrade_contract()", "Call depth limit exceeded");
+        return CALL_DEPTH_EXCEEDED
+    }
+
+    debug!(target: "runtime::call::upgrade_contract()", "Upgrading contract {}", contract_id);
+    let mut runtime = match Runtime::new(
+        &wasm_bincode,
+        env.blockchain.clone(),
+        contract_id,
+        env.time_keeper.clone(),
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::upgrade_contract()", "Failed to instantiate contract {}: {}", contract_id, e);
+            return CONTRACT_UPGRADE_FAILED
+        }
+    };
+
+    if let Err(e) = runtime.sanity_check() {
+        error!(target: "runtime::call::upgrade_contract()", "Invalid bincode for contract {}: {}", contract_id, e);
+        return CONTRACT_UPGRADE_FAILED
+    }
+
+    // The upgraded contract can't use more gas than the caller has left
+    let gas_remaining = env.gas_meter.as_ref().unwrap().remaining(&mut store);
+    runtime.set_caller(env.contract_id, env.call_depth + 1, gas_remaining);
+
+    // The state might have been partially changed, so the caller must fail
+    // along with any of these.
+    let result = runtime.deploy(&[]).and_then(|()| runtime.migrate(&migrate_payload));
+    if let Err(e) = result {
+        error!(target: "runtime::call::upgrade_contract()", "Contract {} upgrade failed: {}", contract_id, e);
+        env.call_failed.set(true);
+        return CONTRACT_UPGRADE_FAILED
+    }
+
+    if !env.charge_gas(&mut store, runtime.gas_used()) {
+        error!(target: "runtime::call::upgrade_contract()", "Gas exhausted by contract {}", contract_id);
+        return CONTRACT_UPGRADE_FAILED
+    }
+
+    // Events of the upgraded contract are emitted along with the caller's
+    env.events.borrow_mut().extend(runtime.take_events());
+
+    entrypoint::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi_sdk::{error::ContractError, pasta::pallas};
+    use darkfi_serial::deserialize;
+
+    use crate::{
+        blockchain::{Blockchain, BlockchainOverlay, BlockchainOverlayPtr},
+        runtime::wat,
+        util::time::{TimeKeeper, Timestamp},
+        Error, Result,
+    };
+
+    /// Contract returning the serialized ID of the contract that invoked it
+    fn callee() -> String {
+        let imports = [
+            r#""get_caller_" (func $get_caller (result i64))"#,
+            r#""get_object_size_" (func $size (param i32) (result i64))"#,
+            r#""get_object_bytes_" (func $bytes (param i32 i32) (result i64))"#,
+        ];
+        let body = r#"
+            (local $obj i32)
+            (local.set $obj (i32.wrap_i64 (call $get_caller)))
+            (drop (call $bytes (i32.const 2048) (local.get $obj)))
+            (drop (call $ret (i32.const 2048) (i32.wrap_i64 (call $size (local.get $obj)))))
+            (i64.const 0)
+        "#;
+        wat::contract(ContractSection::Exec, &imports, &[], body)
+    }
+
+    /// Contract whose metadata requests a ZK proof verification
+    fn guarded() -> String {
+        let zkp_pub = vec![("x".to_string(), Vec::<pallas::Base>::new())];
+        let mut metadata = serialize(&zkp_pub);
+        metadata.extend_from_slice(&serialize(&Vec::<PublicKey>::new()));
+        let body = format!(
+            "(drop (call $ret (i32.const {}) (i32.const {}))) (i64.const 0)",
+            wat::DATA_OFFSET,
+            metadata.len()
+        );
+        wat::contract(ContractSection::Metadata, &[], &metadata, &body)
+    }
+
+    /// Contract invoking given contract with an empty payload, and returning
+    /// the data it returned
+    fn caller(callee: &ContractId) -> String {
+        wat::invoker(ContractSection::Exec, callee, &[], false)
+    }
+
+    fn runtime(overlay: &BlockchainOverlayPtr, contract_id: ContractId) -> Result<Runtime> {
+        let wasm = overlay.lock().unwrap().wasm_bincode.get(contract_id)?;
+        let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, 0);
+        Runtime::new(&wasm, overlay.clone(), contract_id, time_keeper)
+    }
+
+    #[test]
+    fn cross_contract_calls() -> Result<()> {
+        let sled_db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&sled_db)?;
+        let overlay = BlockchainOverlay::new(&blockchain)?;
+
+        let callee_id = ContractId::from(pallas::Base::from(42));
+        let caller_id = ContractId::from(pallas::Base::from(69));
+        let recursive_id = ContractId::from(pallas::Base::from(420));
+        let guarded_id = ContractId::from(pallas::Base::from(1337));
+        let guarded_caller_id = ContractId::from(pallas::Base::from(7331));
+        {
+            let lock = overlay.lock().unwrap();
+            lock.wasm_bincode.insert(callee_id, callee().as_bytes(), 0)?;
+            lock.wasm_bincode.insert(caller_id, caller(&callee_id).as_bytes(), 0)?;
+            lock.wasm_bincode.insert(recursive_id, caller(&recursive_id).as_bytes(), 0)?;
+            lock.wasm_bincode.insert(guarded_id, guarded().as_bytes(), 0)?;
+            lock.wasm_bincode.insert(guarded_caller_id, caller(&guarded_id).as_bytes(), 0)?;
+        }
+
+        // Invoked directly, the callee has no caller
+        let (ret, _) = runtime(&overlay, callee_id)?.exec(&[])?;
+        assert_eq!(deserialize::<Option<ContractId>>(&ret)?, None);
+
+        // Invoked by another contract, which gets charged its gas
+        let (_, callee_gas) = runtime(&overlay, callee_id)?.exec(&[])?;
+        let (ret, caller_gas) = runtime(&overlay, caller_id)?.exec(&[])?;
+        assert_eq!(deserialize::<Option<ContractId>>(&ret)?, Some(caller_id));
+        assert!(caller_gas.used > callee_gas.used);
+
+        // Endless recursion stops at the call depth limit
+        let Err(Error::ContractError(ContractError::ContractCallFailed)) =
+            runtime(&overlay, recursive_id)?.exec(&[])
+        else {
+            panic!("Recursive call didn't fail")
+        };
+
+        // Nested calls can't carry proofs, so payloads requesting any are denied
+        let Err(Error::ContractError(ContractError::CallerAccessDenied)) =
+            runtime(&overlay, guarded_caller_id)?.exec(&[])
+        else {
+            panic!("Call requesting a proof wasn't denied")
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::{
+    crypto::{
+        schnorr::{SchnorrPublic, Signature},
+        smt::{calculate_root_from_path, Poseidon},
+        util::{poseidon_hash_slice, MAX_POSEIDON_MESSAGES},
+        PublicKey,
+    },
+    error::HOST_CRYPTO_FAILED,
+    pasta::pallas,
+};
+use darkfi_serial::{deserialize, serialize};
+use log::error;
+use wasmer::{FunctionEnvMut, WasmPtr};
+
+use crate::runtime::{
+    gas::{
+        host_call_cost, poseidon_cost, GAS_EC_ADD, GAS_EC_MUL, GAS_POSEIDON_PERMUTATION,
+        GAS_SCHNORR_VERIFY,
+    },
+    vm_runtime::Env,
+};
+
+/// Maximum height of the sparse Merkle tree paths `smt_verify` accepts
+pub(crate) const MAX_SMT_PATH_LEN: usize = 256;
+
+/// Read the arguments of a host function call from VM memory.
+fn read_args(ctx: &mut FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> Option<Vec<u8>> {
+    let (env, mut store) = ctx.data_and_store_mut();
+    if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+        return None
+    }
+
+    let memory_view = env.memory_view(&store);
+
+    let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
+        error!(target: "runtime::crypto", "Failed to make slice from ptr");
+        return None
+    };
+
+    let mut buf = vec![0_u8; len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(target: "runtime::crypto", "Failed to read from memory slice: {}", e);
+        return None
+    };
+
+    Some(buf)
+}
+
+/// Charge the gas of a cryptographic operation, before performing it.
+fn charge(ctx: &mut FunctionEnvMut<Env>, gas: u64) -> bool {
+    let (env, mut store) = ctx.data_and_store_mut();
+    env.charge_gas(&mut store, gas)
+}
+
+/// Push the result of a cryptographic operation to the object store,
+/// returning its index.
+fn push_object(ctx: &FunctionEnvMut<Env>, result: Vec<u8>) -> i64 {
+    // Copy Vec<u8> to the VM
+    let mut objects = ctx.data().objects.borrow_mut();
+    objects.push(result);
+    (objects.len() - 1) as i64
+}
+
+/// Everyone can call this. Returns an object index holding the Poseidon
+/// hash of the given field elements.
+pub(crate) fn poseidon_hash(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let Some(buf) = read_args(&mut ctx, ptr, len) else { return HOST_CRYPTO_FAILED };
+
+    let messages: Vec<pallas::Base> = match deserialize(&buf) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::crypto::poseidon_hash()", "Failed to decode messages: {}", e);
+            return HOST_CRYPTO_FAILED
+        }
+    };
+
+    if messages.is_empty() || messages.len() > MAX_POSEIDON_MESSAGES {
+        error!(target: "runtime::crypto::poseidon_hash()", "Invalid number of messages: {}", messages.len());
+        return HOST_CRYPTO_FAILED
+    }
+
+    if !charge(&mut ctx, poseidon_cost(messages.len())) {
+        return HOST_CRYPTO_FAILED
+    }
+
+    let hash = poseidon_hash_slice(&messages).unwrap();
+    push_object(&ctx, serialize(&hash))
+}
+
+/// Everyone can call this. Returns an object index holding the sum of the
+/// two given Pallas points.
+pub(crate) fn ec_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let Some(buf) = read_args(&mut ctx, ptr, len) else { return HOST_CRYPTO_FAILED };
+
+    let (a, b): (pallas::Point, pallas::Point) = match deserialize(&buf) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::crypto::ec_add()", "Failed to decode points: {}", e);
+            return HOST_CRYPTO_FAILED
+        }
+    };
+
+    if !charge(&mut ctx, GAS_EC_ADD) {
+        return HOST_CRYPTO_FAILED
+    }
+
+    push_object(&ctx, serialize(&(a + b)))
+}
+
+/// Everyone can call this. Returns an object index holding the given Pallas
+/// point multiplied by the given scalar.
+pub(crate) fn ec_mul(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let Some(buf) = read_args(&mut ctx, ptr, len) else { return HOST_CRYPTO_FAILED };
+
+    let (point, scalar): (pallas::Point, pallas::Scalar) = match deserialize(&buf) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::crypto::ec_mul()", "Failed to decode point and scalar: {}", e);
+            return HOST_CRYPTO_FAILED
+        }
+    };
+
+    if !charge(&mut ctx, GAS_EC_MUL) {
+        return HOST_CRYPTO_FAILED
+    }
+
+    push_object(&ctx, serialize(&(point * scalar)))
+}
+
+/// Everyone can call this. Verifies a Schnorr signature over a message with
+/// a public key, returning 1 if it's valid, and 0 otherwise.
+pub(crate) fn schnorr_verify(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let Some(buf) = read_args(&mut ctx, ptr, len) else { return HOST_CRYPTO_FAILED };
+
+    let (public_key, message, signature): (PublicKey, Vec<u8>, Signature) = match deserialize(&buf)
+    {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::crypto::schnorr_verify()", "Failed to decode arguments: {}", e);
+            return HOST_CRYPTO_FAILED
+        }
+    };
+
+    // The message gets hashed along with the signature commit
+    if !charge(&mut ctx, GAS_SCHNORR_VERIFY.saturating_add(host_call_cost(message.len()))) {
+        return HOST_CRYPTO_FAILED
+    }
+
+    i64::from(public_key.verify(&message, &signature))
+}
+
+/// Everyone can call this. Verifies a leaf belongs to a Poseidon sparse Merkle
+/// tree with the given root, using its path of sibling pairs from the leaf
+/// level up to the root, returning 1 if it does, and 0 otherwise.
+pub(crate) fn smt_verify(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let Some(buf) = read_args(&mut ctx, ptr, len) else { return HOST_CRYPTO_FAILED };
+
+    let (root, leaf, path): (pallas::Base, pallas::Base, Vec<(pallas::Base, pallas::Base)>) =
+        match deserialize(&buf) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "runtime::crypto::smt_verify()", "Failed to decode arguments: {}", e);
+                return HOST_CRYPTO_FAILED
+            }
+        };
+
+    if path.is_empty() || path.len() > MAX_SMT_PATH_LEN {
+        error!(target: "runtime::crypto::smt_verify()", "Invalid path length: {}", path.len());
+        return HOST_CRYPTO_FAILED
+    }
+
+    // Every level of the path is hashed
+    let gas = (path.len() as u64).saturating_mul(GAS_POSEIDON_PERMUTATION);
+    if !charge(&mut ctx, gas) {
+        return HOST_CRYPTO_FAILED
+    }
+
+    let hasher = Poseidon::<pallas::Base, 2>::new();
+    match calculate_root_from_path(&path, &leaf, &hasher) {
+        Ok(calculated) => i64::from(calculated == root),
+        // Invalid leaves or paths just don't belong to the tree
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use darkfi_sdk::crypto::{
+        pasta_prelude::*, poseidon_hash, schnorr::SchnorrSecret, smt::FieldHasher, ContractId,
+        SecretKey,
+    };
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        blockchain::{Blockchain, BlockchainOverlay},
+        runtime::{
+            gas::GAS_LIMIT,
+            vm_runtime::{ContractSection, Runtime},
+            wat,
+        },
+        util::time::{TimeKeeper, Timestamp},
+        Result,
+    };
+
+    /// Memory offset the test contract stores the host function return code at
+    const CODE_OFFSET: u64 = 512;
+
+    /// Outcome of a host function call made by a test contract
+    struct Call {
+        /// Whether the contract execution succeeded
+        success: bool,
+        /// Return code of the host function
+        code: i64,
+        /// Object the return code points to, if any
+        object: Option<Vec<u8>>,
+    }
+
+    /// Run a test contract passing `args` to given crypto host function, with
+    /// given gas limit. The arguments are encoded as the sdk wrappers do.
+    fn call(func: &str, args: &[u8], gas_limit: u64) -> Result<Call> {
+        let import = format!(r#""{}_" (func $f (param i32 i32) (result i64))"#, func);
+        let body = format!(
+            "(i64.store (i32.const {}) (call $f (i32.const {}) (i32.const {}))) (i64.const 0)",
+            CODE_OFFSET,
+            wat::DATA_OFFSET,
+            args.len()
+        );
+        let wasm = wat::contract(ContractSection::Exec, &[import.as_str()], args, &body);
+
+        let sled_db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&sled_db)?;
+        let overlay = BlockchainOverlay::new(&blockchain)?;
+        let contract_id = ContractId::from(pallas::Base::from(42));
+        let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, 0);
+        let mut runtime = Runtime::new(wasm.as_bytes(), overlay, contract_id, time_keeper)?;
+        runtime.set_caller(contract_id, 0, gas_limit);
+
+        // The code is stored before running out of gas traps the contract
+        let success = runtime.exec(&[]).is_ok();
+
+        let env = runtime.ctx.as_ref(&runtime.store);
+        let mut code = [0; 8];
+        env.memory_view(&runtime.store).read(CODE_OFFSET, &mut code)?;
+        let code = i64::from_le_bytes(code);
+        let object = usize::try_from(code).ok().and_then(|i| env.objects.borrow().get(i).cloned());
+
+        Ok(Call { success, code, object })
+    }
+
+    #[test]
+    fn crypto_host_functions() -> Result<()> {
+        // Poseidon hash of 1 up to MAX_POSEIDON_MESSAGES messages
+        let messages = vec![pallas::Base::from(1), pallas::Base::from(2), pallas::Base::from(3)];
+        let ret = call("poseidon_hash", &serialize(&messages), GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!(
+            ret.object,
+            Some(serialize(&poseidon_hash([messages[0], messages[1], messages[2]])))
+        );
+
+        for len in [0, MAX_POSEIDON_MESSAGES + 1] {
+            let messages = vec![pallas::Base::from(1); len];
+            let ret = call("poseidon_hash", &serialize(&messages), GAS_LIMIT)?;
+            assert_eq!(ret.code, HOST_CRYPTO_FAILED);
+        }
+
+        // Point addition and scalar multiplication
+        let a = pallas::Point::generator() * pallas::Scalar::random(&mut OsRng);
+        let b = pallas::Point::generator() * pallas::Scalar::random(&mut OsRng);
+        let scalar = pallas::Scalar::random(&mut OsRng);
+
+        let ret = call("ec_add", &serialize(&(a, b)), GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!(ret.object, Some(serialize(&(a + b))));
+
+        let ret = call("ec_mul", &serialize(&(a, scalar)), GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!(ret.object, Some(serialize(&(a * scalar))));
+
+        // Schnorr signatures, where invalid ones are no errors
+        let secret = SecretKey::random(&mut OsRng);
+        let public_key = PublicKey::from_secret(secret);
+        let message = b"darkfi".to_vec();
+        let signature = secret.sign(&mut OsRng, &message);
+
+        let ret = call("schnorr_verify", &serialize(&(public_key, message, signature)), GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!((ret.code, ret.object), (1, None));
+
+        let args = serialize(&(public_key, b"monero".to_vec(), signature));
+        let ret = call("schnorr_verify", &args, GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!(ret.code, 0);
+
+        // Sparse Merkle tree membership, with a two levels path
+        let hasher = Poseidon::<pallas::Base, 2>::new();
+        let leaf = pallas::Base::from(69);
+        let siblings = [pallas::Base::from(420), pallas::Base::from(1337)];
+        let node = hasher.hash([leaf, siblings[0]])?;
+        let root = hasher.hash([siblings[1], node])?;
+        let path = vec![(leaf, siblings[0]), (siblings[1], node)];
+        assert_eq!(calculate_root_from_path(&path, &leaf, &hasher)?, root);
+
+        let ret = call("smt_verify", &serialize(&(root, leaf, path.clone())), GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!(ret.code, 1);
+
+        let args = serialize(&(root + pallas::Base::ONE, leaf, path));
+        let ret = call("smt_verify", &args, GAS_LIMIT)?;
+        assert!(ret.success);
+        assert_eq!(ret.code, 0);
+
+        let path = vec![(leaf, siblings[0]); MAX_SMT_PATH_LEN + 1];
+        let ret = call("smt_verify", &serialize(&(root, leaf, path)), GAS_LIMIT)?;
+        assert_eq!(ret.code, HOST_CRYPTO_FAILED);
+
+        // Running out of gas fails the host function, then traps the contract
+        let ret = call("ec_mul", &serialize(&(a, scalar)), GAS_EC_MUL / 2)?;
+        assert!(!ret.success);
+        assert_eq!(ret.code, HOST_CRYPTO_FAILED);
+
+        Ok(())
+    }
+}
@@ -16,13 +16,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::io::Cursor;
+use std::{io::Cursor, ops::Bound};
 
 use darkfi_sdk::{
     crypto::ContractId,
     db::{
         CALLER_ACCESS_DENIED, DB_CONTAINS_KEY_FAILED, DB_DEL_FAILED, DB_GET_FAILED, DB_INIT_FAILED,
-        DB_LOOKUP_FAILED, DB_SET_FAILED, DB_SUCCESS,
+        DB_ITER_FAILED, DB_LOOKUP_FAILED, DB_SET_FAILED, DB_SUCCESS,
     },
 };
 use darkfi_serial::{deserialize, serialize, Decodable};
@@ -30,8 +30,11 @@ use log::{debug, error, info};
 use wasmer::{FunctionEnvMut, WasmPtr};
 
 use crate::{
-    blockchain::contract_store::SMART_CONTRACT_ZKAS_DB_NAME,
-    runtime::vm_runtime::{ContractSection, Env},
+    blockchain::contract_store::{zkas_version_key, SMART_CONTRACT_ZKAS_DB_NAME},
+    runtime::{
+        gas::{db_read_cost, db_write_cost, host_call_cost, GAS_PER_BYTE_READ},
+        vm_runtime::{ContractSection, Env},
+    },
     zk::{empty_witnesses, VerifyingKey, ZkCircuit},
     zkas::ZkBinary,
 };
@@ -48,17 +51,24 @@ impl DbHandle {
     }
 }
 
-/// Only deploy() can call this. Creates a new database instance for this contract.
-pub(crate) fn db_init(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+/// Only deploy() and migrate() can call this. Creates a new database instance for
+/// this contract.
+pub(crate) fn db_init(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     // Exit as soon as possible
-    if env.contract_section != ContractSection::Deploy {
+    if env.contract_section != ContractSection::Deploy &&
+        env.contract_section != ContractSection::Migrate
+    {
         error!(target: "runtime::db::db_init()", "db_init called in unauthorized section");
         return CALLER_ACCESS_DENIED
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(&mut store, host_call_cost(len as usize).saturating_add(db_write_cost(0))) {
+        return DB_INIT_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
     let contracts = &env.blockchain.lock().unwrap().contracts;
     let contract_id = &env.contract_id;
 
@@ -124,14 +134,15 @@ pub(crate) fn db_init(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i
 }
 
 /// Everyone can call this. Lookups up a database handle from its name.
-pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+pub(crate) fn db_lookup(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     match env.contract_section {
         ContractSection::Deploy |
         ContractSection::Exec |
         ContractSection::Update |
-        ContractSection::Metadata => {
+        ContractSection::Metadata |
+        ContractSection::Migrate => {
             // pass
         }
 
@@ -141,7 +152,11 @@ pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) ->
         }
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(&mut store, host_call_cost(len as usize).saturating_add(db_read_cost(0))) {
+        return DB_LOOKUP_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
     let contracts = &env.blockchain.lock().unwrap().contracts;
 
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
@@ -198,17 +213,25 @@ pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) ->
 }
 
 /// Set a value within the transaction.
-pub(crate) fn db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+pub(crate) fn db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     if env.contract_section != ContractSection::Deploy &&
-        env.contract_section != ContractSection::Update
+        env.contract_section != ContractSection::Update &&
+        env.contract_section != ContractSection::Migrate
     {
         error!(target: "runtime::db::db_set()", "db_set called in unauthorized section");
         return CALLER_ACCESS_DENIED
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(
+        &mut store,
+        host_call_cost(len as usize).saturating_add(db_write_cost(len as usize)),
+    ) {
+        return DB_SET_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
 
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
         error!(target: "runtime::db::db_set()", "Failed to make slice from ptr");
@@ -289,17 +312,25 @@ pub(crate) fn db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i3
 }
 
 /// Remove a key from the database.
-pub(crate) fn db_del(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+pub(crate) fn db_del(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     if env.contract_section != ContractSection::Deploy &&
-        env.contract_section != ContractSection::Update
+        env.contract_section != ContractSection::Update &&
+        env.contract_section != ContractSection::Migrate
     {
         error!(target: "runtime::db::db_del()", "db_del called in unauthorized section");
         return CALLER_ACCESS_DENIED
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(
+        &mut store,
+        host_call_cost(len as usize).saturating_add(db_write_cost(len as usize)),
+    ) {
+        return DB_DEL_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
 
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
         error!(target: "runtime::db::db_del()", "Failed to make slice from ptr");
@@ -364,18 +395,23 @@ pub(crate) fn db_del(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i3
 }
 
 /// Will read a key from the key-value store.
-pub(crate) fn db_get(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
-    let env = ctx.data();
+pub(crate) fn db_get(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     if env.contract_section != ContractSection::Deploy &&
         env.contract_section != ContractSection::Exec &&
-        env.contract_section != ContractSection::Metadata
+        env.contract_section != ContractSection::Metadata &&
+        env.contract_section != ContractSection::Migrate
     {
         error!(target: "runtime::db::db_get()", "db_get called in unauthorized section");
         return CALLER_ACCESS_DENIED.into()
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(&mut store, host_call_cost(len as usize).saturating_add(db_read_cost(0))) {
+        return DB_GET_FAILED.into()
+    }
+
+    let memory_view = env.memory_view(&store);
 
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
         error!(target: "runtime::db::db_get()", "Failed to make slice from ptr");
@@ -439,6 +475,10 @@ pub(crate) fn db_get(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i6
         return -127
     };
 
+    if !env.charge_gas(&mut store, (return_data.len() as u64).saturating_mul(GAS_PER_BYTE_READ)) {
+        return DB_GET_FAILED.into()
+    }
+
     // Copy Vec<u8> to the VM
     let mut objects = env.objects.borrow_mut();
     objects.push(return_data.to_vec());
@@ -446,19 +486,24 @@ pub(crate) fn db_get(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i6
 }
 
 /// Everyone can call this. Will check if a given db contains given key.
-pub(crate) fn db_contains_key(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+pub(crate) fn db_contains_key(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     if env.contract_section != ContractSection::Deploy &&
         env.contract_section != ContractSection::Exec &&
         env.contract_section != ContractSection::Update &&
-        env.contract_section != ContractSection::Metadata
+        env.contract_section != ContractSection::Metadata &&
+        env.contract_section != ContractSection::Migrate
     {
         error!(target: "runtime::db::db_contains_key()", "db_contains_key called in unauthorized section");
         return CALLER_ACCESS_DENIED
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(&mut store, host_call_cost(len as usize).saturating_add(db_read_cost(0))) {
+        return DB_CONTAINS_KEY_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
 
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
         error!(target: "runtime::db::db_contains_key()", "Failed to make slice from ptr");
@@ -518,17 +563,184 @@ pub(crate) fn db_contains_key(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u
     }
 }
 
+/// Everyone can call this. Will read the records of a database in ascending
+/// key order, up to a given limit.
+pub(crate) fn db_iter(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    db_scan(ctx, ptr, len, "runtime::db::db_iter()", |_| Ok((Bound::Unbounded, Bound::Unbounded)))
+}
+
+/// Everyone can call this. Will read the records of a database with keys
+/// from a given start key, inclusive, to a given end key, exclusive, in
+/// ascending key order, up to a given limit.
+pub(crate) fn db_range(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    db_scan(ctx, ptr, len, "runtime::db::db_range()", |buf_reader| {
+        let start: Vec<u8> = Decodable::decode(&mut *buf_reader)?;
+        let end: Vec<u8> = Decodable::decode(&mut *buf_reader)?;
+        Ok((Bound::Included(start), Bound::Excluded(end)))
+    })
+}
+
+/// Everyone can call this. Will read the records of a database with keys
+/// starting with a given prefix, in ascending key order, up to a given limit.
+pub(crate) fn db_prefix(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    db_scan(ctx, ptr, len, "runtime::db::db_prefix()", |buf_reader| {
+        let prefix: Vec<u8> = Decodable::decode(buf_reader)?;
+        Ok(prefix_bounds(prefix))
+    })
+}
+
+/// Key bounds containing all the keys starting with given prefix.
+fn prefix_bounds(prefix: Vec<u8>) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    // The first key after the prefixed ones is the prefix incremented,
+    // ignoring its trailing 0xff bytes, which can't be incremented.
+    let mut end = prefix.clone();
+    while let Some(byte) = end.pop() {
+        if byte < u8::MAX {
+            end.push(byte + 1);
+            return (Bound::Included(prefix), Bound::Excluded(end))
+        }
+    }
+
+    (Bound::Included(prefix), Bound::Unbounded)
+}
+
+/// Reads a DbHandle, the key bounds to scan using given decoder, and the
+/// maximum number of records to return, and returns an object index holding
+/// the serialized `Vec<(Vec<u8>, Vec<u8>)>` of the records found through the
+/// overlay, so writes that are not applied yet are visible. Gas is charged
+/// for every record read.
+fn db_scan(
+    mut ctx: FunctionEnvMut<Env>,
+    ptr: WasmPtr<u8>,
+    len: u32,
+    target: &str,
+    decode_bounds: impl FnOnce(
+        &mut Cursor<Vec<u8>>,
+    ) -> std::io::Result<(Bound<Vec<u8>>, Bound<Vec<u8>>)>,
+) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+
+    match env.contract_section {
+        ContractSection::Deploy |
+        ContractSection::Exec |
+        ContractSection::Update |
+        ContractSection::Metadata |
+        ContractSection::Migrate => {
+            // pass
+        }
+
+        _ => {
+            error!(target: target, "Database scan called in unauthorized section");
+            return CALLER_ACCESS_DENIED.into()
+        }
+    }
+
+    if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+        return DB_ITER_FAILED.into()
+    }
+
+    let memory_view = env.memory_view(&store);
+
+    let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
+        error!(target: target, "Failed to make slice from ptr");
+        return DB_ITER_FAILED.into()
+    };
+
+    let mut buf = vec![0_u8; len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(target: target, "Failed to read from memory slice: {}", e);
+        return DB_ITER_FAILED.into()
+    };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    // FIXME: There's a type DbHandle=u32, but this should maybe be renamed
+    let db_handle: u32 = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: target, "Failed to decode DbHandle: {}", e);
+            return DB_ITER_FAILED.into()
+        }
+    };
+    let db_handle = db_handle as usize;
+
+    let bounds = match decode_bounds(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: target, "Failed to decode key bounds: {}", e);
+            return DB_ITER_FAILED.into()
+        }
+    };
+
+    let limit: u32 = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: target, "Failed to decode limit: {}", e);
+            return DB_ITER_FAILED.into()
+        }
+    };
+
+    let db_handles = env.db_handles.borrow();
+
+    if db_handles.len() <= db_handle {
+        error!(target: target, "Requested DbHandle that is out of bounds");
+        return DB_ITER_FAILED.into()
+    }
+
+    let handle_idx = db_handle;
+    let db_handle = &db_handles[handle_idx];
+
+    let blockchain = env.blockchain.lock().unwrap();
+    let overlay = blockchain.overlay.lock().unwrap();
+    let records = match overlay.range(&db_handle.tree, bounds) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: target, "Internal error iterating tree: {}", e);
+            return DB_ITER_FAILED.into()
+        }
+    };
+
+    let mut ret = vec![];
+    for record in records.take(limit as usize) {
+        let (key, value) = match record {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: target, "Internal error iterating tree: {}", e);
+                return DB_ITER_FAILED.into()
+            }
+        };
+
+        if !env.charge_gas(&mut store, db_read_cost(key.len() + value.len())) {
+            return DB_ITER_FAILED.into()
+        }
+
+        ret.push((key, value));
+    }
+
+    // Copy Vec<u8> to the VM
+    let mut objects = env.objects.borrow_mut();
+    objects.push(serialize(&ret));
+    (objects.len() - 1) as i64
+}
+
 /// Only `deploy()` can call this. Given a zkas circuit, create a VerifyingKey and insert
 /// them both into the db.
-pub(crate) fn zkas_db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+pub(crate) fn zkas_db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     if env.contract_section != ContractSection::Deploy {
         error!(target: "runtime::db::zkas_db_set()", "zkas_db_set called in unauthorized section");
         return CALLER_ACCESS_DENIED
     }
 
-    let memory_view = env.memory_view(&ctx);
+    if !env.charge_gas(
+        &mut store,
+        host_call_cost(len as usize).saturating_add(db_write_cost(len as usize)),
+    ) {
+        return DB_SET_FAILED
+    }
+
+    let memory_view = env.memory_view(&store);
     let contract_id = &env.contract_id;
 
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
@@ -570,7 +782,7 @@ pub(crate) fn zkas_db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32)
     // Check if there is existing bincode and compare it. Return DB_SUCCESS if
     // they're the same. The assumption should be that VerifyingKey was generated
     // already so we can skip things after this guard.
-    match env
+    let replaced = match env
         .blockchain
         .lock()
         .unwrap()
@@ -580,16 +792,17 @@ pub(crate) fn zkas_db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32)
         .get(&db_handle.tree, &serialize(&zkbin.namespace))
     {
         Ok(v) => {
-            if let Some(bytes) = v {
+            if let Some(bytes) = &v {
                 // We allow a panic here because this db should never be corrupted in this way.
                 let (existing_zkbin, _): (Vec<u8>, Vec<u8>) =
-                    deserialize(&bytes).expect("deserialize tuple");
+                    deserialize(bytes).expect("deserialize tuple");
 
                 if existing_zkbin == zkas_bincode {
                     debug!(target: "runtime::db::zkas_db_set()", "Existing zkas bincode is the same. Skipping.");
                     return DB_SUCCESS
                 }
             }
+            v
         }
         Err(e) => {
             error!(target: "runtime::db::zkas_db_set()", "Internal error getting from tree: {}", e);
@@ -616,6 +829,38 @@ pub(crate) fn zkas_db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32)
         return DB_SET_FAILED
     }
 
+    // When the contract gets upgraded, the circuit being replaced is kept under
+    // the version it was last used in, so the circuits historical transactions
+    // were proven against are kept. The wasm bincode is only replaced after
+    // `deploy()`, so here we still get the version being upgraded from.
+    if let Some(bytes) = replaced {
+        let version = match env.blockchain.lock().unwrap().wasm_bincode.version(*contract_id) {
+            Ok(v) => v.unwrap_or(0),
+            Err(e) => {
+                error!(target: "runtime::db::zkas_db_set()", "Internal error getting contract version: {}", e);
+                return DB_SET_FAILED
+            }
+        };
+
+        let key = zkas_version_key(&zkbin.namespace, version);
+        let lock = env.blockchain.lock().unwrap();
+        let mut overlay = lock.overlay.lock().unwrap();
+        match overlay.contains_key(&db_handle.tree, &key) {
+            // The circuit was already replaced during this deployment
+            Ok(true) => {}
+            Ok(false) => {
+                if overlay.insert(&db_handle.tree, &key, &bytes).is_err() {
+                    error!(target: "runtime::db::zkas_db_set()", "Couldn't archive replaced zkas circuit");
+                    return DB_SET_FAILED
+                }
+            }
+            Err(e) => {
+                error!(target: "runtime::db::zkas_db_set()", "Internal error checking tree: {}", e);
+                return DB_SET_FAILED
+            }
+        }
+    }
+
     let key = serialize(&zkbin.namespace);
     let value = serialize(&(zkas_bincode, vk_buf));
     if env
@@ -634,3 +879,22 @@ pub(crate) fn zkas_db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32)
 
     DB_SUCCESS
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_bounds_contain_prefixed_keys() {
+        assert_eq!(
+            prefix_bounds(vec![1, 2]),
+            (Bound::Included(vec![1, 2]), Bound::Excluded(vec![1, 3]))
+        );
+        assert_eq!(
+            prefix_bounds(vec![1, 0xff, 0xff]),
+            (Bound::Included(vec![1, 0xff, 0xff]), Bound::Excluded(vec![2]))
+        );
+        assert_eq!(prefix_bounds(vec![0xff]), (Bound::Included(vec![0xff]), Bound::Unbounded));
+        assert_eq!(prefix_bounds(vec![]), (Bound::Included(vec![]), Bound::Unbounded));
+    }
+}
@@ -23,13 +23,20 @@ use darkfi_serial::{serialize, Decodable, Encodable, WriteExt};
 use log::{debug, error};
 use wasmer::{FunctionEnvMut, WasmPtr};
 
-use crate::runtime::vm_runtime::{ContractSection, Env};
+use crate::runtime::{
+    gas::{db_read_cost, db_write_cost, host_call_cost},
+    vm_runtime::{ContractSection, Env},
+};
 
-pub(crate) fn merkle_add(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
-    let env = ctx.data();
+pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    let (env, mut store) = ctx.data_and_store_mut();
     match env.contract_section {
         ContractSection::Update => {
-            let memory_view = env.memory_view(&ctx);
+            if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+                return -2
+            }
+
+            let memory_view = env.memory_view(&store);
 
             let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
                 error!(target: "runtime::merkle", "Failed to make slice from ptr");
@@ -188,6 +195,14 @@ pub(crate) fn merkle_add(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -
                 return -2
             }
 
+            // Charge for reading the tree and writing it back, along with the new roots
+            let gas = db_read_cost(return_data.len())
+                .saturating_add(db_write_cost(tree_data.len()))
+                .saturating_add(db_write_cost(32).saturating_mul(new_roots.len() as u64 + 1));
+            if !env.charge_gas(&mut store, gas) {
+                return -2
+            }
+
             // Apply changes to overlay
             let lock = env.blockchain.lock().unwrap();
             let mut overlay = lock.overlay.lock().unwrap();
@@ -16,6 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+/// Host functions for cross-contract calls
+pub(crate) mod call;
+
+/// Host functions for cryptographic primitives
+pub(crate) mod crypto;
+
 /// Host functions for interacting with db backend
 pub(crate) mod db;
 
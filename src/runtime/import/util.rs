@@ -16,17 +16,30 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::io::Cursor;
+
 use darkfi_sdk::db::{CALLER_ACCESS_DENIED, DB_GET_FAILED};
+use darkfi_serial::Decodable;
 use log::error;
 use wasmer::{FunctionEnvMut, WasmPtr};
 
-use crate::runtime::vm_runtime::{ContractSection, Env};
+use crate::{
+    blockchain::ContractEvent,
+    runtime::{
+        gas::{db_read_cost, db_write_cost, host_call_cost, GAS_HOST_CALL},
+        vm_runtime::{ContractSection, Env},
+    },
+};
 
 /// Host function for logging strings.
 /// This is injected into the runtime with wasmer's `imports!` macro.
-pub(crate) fn drk_log(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
-    let env = ctx.data();
-    let memory_view = env.memory_view(&ctx);
+pub(crate) fn drk_log(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
+    let (env, mut store) = ctx.data_and_store_mut();
+    if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+        return
+    }
+
+    let memory_view = env.memory_view(&store);
 
     match ptr.read_utf8_string(&memory_view, len) {
         Ok(msg) => {
@@ -40,11 +53,15 @@ pub(crate) fn drk_log(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
     }
 }
 
-pub(crate) fn set_return_data(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
-    let env = ctx.data();
+pub(crate) fn set_return_data(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
     match env.contract_section {
         ContractSection::Exec | ContractSection::Metadata => {
-            let memory_view = env.memory_view(&ctx);
+            if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+                return darkfi_sdk::error::INTERNAL_ERROR
+            }
+
+            let memory_view = env.memory_view(&store);
 
             let Ok(slice) = ptr.slice(&memory_view, len) else {
                 return darkfi_sdk::error::INTERNAL_ERROR
@@ -65,9 +82,64 @@ pub(crate) fn set_return_data(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u
     }
 }
 
-pub(crate) fn put_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
-    let env = ctx.data();
-    let memory_view = env.memory_view(&ctx);
+/// Only `exec()` and `update()` can call this. Reads a serialized topic
+/// and data pair from VM memory and records it as a [`ContractEvent`]
+/// emitted by the executing contract.
+pub(crate) fn emit_event(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    match env.contract_section {
+        ContractSection::Exec | ContractSection::Update => {
+            // Events get stored by the node, so they are charged as a write
+            let cost = host_call_cost(len as usize) + db_write_cost(len as usize);
+            if !env.charge_gas(&mut store, cost) {
+                return darkfi_sdk::error::INTERNAL_ERROR
+            }
+
+            let memory_view = env.memory_view(&store);
+
+            let Ok(slice) = ptr.slice(&memory_view, len) else {
+                error!(target: "runtime::util", "Failed to make slice from ptr");
+                return darkfi_sdk::error::INTERNAL_ERROR
+            };
+
+            let Ok(buf) = slice.read_to_vec() else {
+                error!(target: "runtime::util", "Failed to read from memory slice");
+                return darkfi_sdk::error::INTERNAL_ERROR
+            };
+
+            let mut buf_reader = Cursor::new(buf);
+
+            let topic: String = match Decodable::decode(&mut buf_reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "runtime::util", "Failed to decode event topic: {}", e);
+                    return darkfi_sdk::error::INTERNAL_ERROR
+                }
+            };
+
+            let data: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "runtime::util", "Failed to decode event data: {}", e);
+                    return darkfi_sdk::error::INTERNAL_ERROR
+                }
+            };
+
+            let event = ContractEvent { contract_id: env.contract_id, topic, data };
+            env.events.borrow_mut().push(event);
+            0
+        }
+        _ => darkfi_sdk::error::CALLER_ACCESS_DENIED,
+    }
+}
+
+pub(crate) fn put_object_bytes(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    if !env.charge_gas(&mut store, host_call_cost(len as usize)) {
+        return -1
+    }
+
+    let memory_view = env.memory_view(&store);
 
     //debug!(target: "runtime::util", "diagnostic:");
     //let pages = memory_view.size().0;
@@ -100,11 +172,10 @@ pub(crate) fn put_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len:
     obj_idx as i64
 }
 
-pub(crate) fn get_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx: u32) -> i64 {
+pub(crate) fn get_object_bytes(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx: u32) -> i64 {
     // Get the slice, where we will read the size of the buffer
 
-    let env = ctx.data();
-    let memory_view = env.memory_view(&ctx);
+    let (env, mut store) = ctx.data_and_store_mut();
 
     // Get the object from env
 
@@ -115,6 +186,12 @@ pub(crate) fn get_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx:
     }
     let obj = &objects[idx as usize];
 
+    if !env.charge_gas(&mut store, host_call_cost(obj.len())) {
+        return -1
+    }
+
+    let memory_view = env.memory_view(&store);
+
     // Read N bytes from the object and write onto the ptr.
 
     // We need to re-read the slice, since in the first run, we just read n
@@ -132,10 +209,13 @@ pub(crate) fn get_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx:
     0
 }
 
-pub(crate) fn get_object_size(ctx: FunctionEnvMut<Env>, idx: u32) -> i64 {
+pub(crate) fn get_object_size(mut ctx: FunctionEnvMut<Env>, idx: u32) -> i64 {
     // Get the slice, where we will read the size of the buffer
 
-    let env = ctx.data();
+    let (env, mut store) = ctx.data_and_store_mut();
+    if !env.charge_gas(&mut store, GAS_HOST_CALL) {
+        return -1
+    }
     //let memory_view = env.memory_view(&ctx);
 
     // Get the object from env
@@ -151,28 +231,37 @@ pub(crate) fn get_object_size(ctx: FunctionEnvMut<Env>, idx: u32) -> i64 {
 }
 
 /// Will return current epoch number.
-pub(crate) fn get_current_epoch(ctx: FunctionEnvMut<Env>) -> u64 {
-    ctx.data().time_keeper.current_epoch()
+pub(crate) fn get_current_epoch(mut ctx: FunctionEnvMut<Env>) -> u64 {
+    // On gas exhaustion, the contract traps right after returning
+    let (env, mut store) = ctx.data_and_store_mut();
+    env.charge_gas(&mut store, GAS_HOST_CALL);
+    env.time_keeper.current_epoch()
 }
 
 /// Will return current slot number.
-pub(crate) fn get_current_slot(ctx: FunctionEnvMut<Env>) -> u64 {
-    ctx.data().time_keeper.current_slot()
+pub(crate) fn get_current_slot(mut ctx: FunctionEnvMut<Env>) -> u64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    env.charge_gas(&mut store, GAS_HOST_CALL);
+    env.time_keeper.current_slot()
 }
 
 /// Will return current runtime configured verifying slot number.
-pub(crate) fn get_verifying_slot(ctx: FunctionEnvMut<Env>) -> u64 {
-    ctx.data().time_keeper.verifying_slot
+pub(crate) fn get_verifying_slot(mut ctx: FunctionEnvMut<Env>) -> u64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    env.charge_gas(&mut store, GAS_HOST_CALL);
+    env.time_keeper.verifying_slot
 }
 
 /// Will return current runtime configured verifying slot epoch number.
-pub(crate) fn get_verifying_slot_epoch(ctx: FunctionEnvMut<Env>) -> u64 {
-    ctx.data().time_keeper.verifying_slot_epoch()
+pub(crate) fn get_verifying_slot_epoch(mut ctx: FunctionEnvMut<Env>) -> u64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    env.charge_gas(&mut store, GAS_HOST_CALL);
+    env.time_keeper.verifying_slot_epoch()
 }
 
 /// Will return requested slot from `SlotStore`.
-pub(crate) fn get_slot(ctx: FunctionEnvMut<Env>, slot: u64) -> i64 {
-    let env = ctx.data();
+pub(crate) fn get_slot(mut ctx: FunctionEnvMut<Env>, slot: u64) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
 
     if env.contract_section != ContractSection::Deploy &&
         env.contract_section != ContractSection::Exec &&
@@ -190,13 +279,20 @@ pub(crate) fn get_slot(ctx: FunctionEnvMut<Env>, slot: u64) -> i64 {
         }
     };
 
+    let ret = ret.to_vec();
+    if !env.charge_gas(&mut store, host_call_cost(0).saturating_add(db_read_cost(ret.len()))) {
+        return DB_GET_FAILED.into()
+    }
+
     // Copy Vec<u8> to the VM
     let mut objects = env.objects.borrow_mut();
-    objects.push(ret.to_vec());
+    objects.push(ret);
     (objects.len() - 1) as i64
 }
 
 /// Will return current blockchain timestamp.
-pub(crate) fn get_blockchain_time(ctx: FunctionEnvMut<Env>) -> u64 {
-    ctx.data().time_keeper.blockchain_timestamp()
+pub(crate) fn get_blockchain_time(mut ctx: FunctionEnvMut<Env>) -> u64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    env.charge_gas(&mut store, GAS_HOST_CALL);
+    env.time_keeper.blockchain_timestamp()
 }
@@ -24,3 +24,12 @@ pub(crate) mod memory;
 
 /// Imported host functions
 pub(crate) mod import;
+
+/// Gas schedule and metering
+pub mod gas;
+
+/// Compiled wasm module cache
+pub mod module_cache;
+
+/// WAT test contract builders
+pub mod wat;
@@ -0,0 +1,331 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use darkfi_sdk::crypto::ContractId;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use wasmer::{CompilerConfig, Engine, Module};
+use wasmer_compiler_singlepass::Singlepass;
+use wasmer_middlewares::Metering;
+
+use super::gas::{operator_cost, GAS_LIMIT, GAS_SCHEDULE_VERSION};
+use crate::Result;
+
+/// Maximum number of compiled modules kept in memory
+pub const MODULE_CACHE_CAPACITY: usize = 128;
+
+lazy_static! {
+    /// Global cache of compiled contract modules, shared by all runtimes
+    pub static ref MODULE_CACHE: ModuleCache = ModuleCache::new();
+}
+
+/// Cache key of a compiled module, in the form of (`contract_id`, `bincode_hash`)
+type ModuleKey = ([u8; 32], blake3::Hash);
+
+/// In-memory modules, along with the tick they were last used at,
+/// so the least recently used one can be evicted.
+#[derive(Default)]
+struct Modules {
+    entries: HashMap<ModuleKey, (CachedModule, u64)>,
+    tick: u64,
+}
+
+impl Modules {
+    fn get(&mut self, key: &ModuleKey) -> Option<CachedModule> {
+        self.tick += 1;
+        let (cached, used) = self.entries.get_mut(key)?;
+        *used = self.tick;
+        Some(cached.clone())
+    }
+
+    fn insert(&mut self, key: ModuleKey, cached: CachedModule, capacity: usize) {
+        self.tick += 1;
+        self.entries.insert(key, (cached, self.tick));
+
+        while self.entries.len() > capacity {
+            let oldest = *self.entries.iter().min_by_key(|(_, (_, used))| *used).unwrap().0;
+            debug!(target: "runtime::module_cache", "Evicting module {}", oldest.1);
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// A compiled wasm module, along with the engine it was compiled with,
+/// which runtime stores instantiating it must use.
+#[derive(Clone)]
+pub struct CachedModule {
+    pub engine: Engine,
+    pub module: Module,
+}
+
+/// Cache of compiled contract wasm modules, keyed by contract ID and the
+/// hash of the wasm bincode they were compiled from, so contracts don't get
+/// recompiled on every call. At most `capacity` modules are kept in memory,
+/// evicting the least recently used ones. If a path is set, compiled modules
+/// are also serialized to disk, so they survive restarts and evictions.
+pub struct ModuleCache {
+    /// Compiled modules in memory
+    modules: Mutex<Modules>,
+    /// Maximum number of modules kept in memory
+    capacity: usize,
+    /// Directory of serialized modules, if any
+    path: Mutex<Option<PathBuf>>,
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self::with_capacity(MODULE_CACHE_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { modules: Mutex::new(Modules::default()), capacity, path: Mutex::new(None) }
+    }
+
+    /// Set the directory compiled modules get serialized to, creating it
+    /// if it doesn't exist.
+    ///
+    /// Serialized modules are loaded back as native code without being
+    /// validated, and their checksum only detects corruption. Anyone able
+    /// to write to this directory can run arbitrary code in the node, so
+    /// it must only be writable by the node itself.
+    pub fn set_path(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path)?;
+        *self.path.lock().unwrap() = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Number of modules cached in memory
+    pub fn len(&self) -> usize {
+        self.modules.lock().unwrap().entries.len()
+    }
+
+    /// Check if there are no modules cached in memory
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Retrieve the compiled module of given contract wasm bincode, loading
+    /// it from disk or compiling it if it's not cached yet.
+    pub fn get_or_compile(
+        &self,
+        contract_id: &ContractId,
+        wasm_bytes: &[u8],
+    ) -> Result<CachedModule> {
+        let key = (contract_id.to_bytes(), blake3::hash(wasm_bytes));
+        if let Some(cached) = self.modules.lock().unwrap().get(&key) {
+            return Ok(cached)
+        }
+
+        // We don't hold the lock while compiling, as it might take a while
+        let file = self.path.lock().unwrap().as_ref().map(|path| module_file(path, &key));
+        let cached = match file.as_ref().and_then(|file| load_module(file)) {
+            Some(cached) => cached,
+            None => {
+                info!(target: "runtime::module_cache", "Compiling module of contract {}", contract_id);
+                let engine = engine();
+                let module = Module::new(&engine, wasm_bytes)?;
+                let cached = CachedModule { engine, module };
+                if let Some(file) = file {
+                    store_module(&file, &cached);
+                }
+                cached
+            }
+        };
+
+        self.modules.lock().unwrap().insert(key, cached.clone(), self.capacity);
+        Ok(cached)
+    }
+
+    /// Drop the cached modules of given contract, other than the one compiled
+    /// from its current wasm bincode. Used once a contract redeployment gets
+    /// finalized, since unfinalized forks might still use the previous one.
+    pub fn invalidate(&self, contract_id: &ContractId, wasm_bytes: &[u8]) {
+        let contract_id = contract_id.to_bytes();
+        let current = blake3::hash(wasm_bytes);
+        let path = self.path.lock().unwrap().clone();
+
+        self.modules.lock().unwrap().entries.retain(|key, _| {
+            if key.0 != contract_id || key.1 == current {
+                return true
+            }
+
+            debug!(target: "runtime::module_cache", "Invalidating module {}", key.1);
+            if let Some(path) = &path {
+                let _ = fs::remove_file(module_file(path, key));
+            }
+            false
+        });
+    }
+}
+
+impl Default for ModuleCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Create a new Singlepass engine with gas metering. Each compiled module
+/// needs its own, since the metering middleware can't be shared.
+fn engine() -> Engine {
+    // The metering middleware will call the cost function for each
+    // `Operator`, and subtract its cost from the remaining points.
+    let metering = Arc::new(Metering::new(GAS_LIMIT, operator_cost));
+
+    // Define the compiler and middleware, and engine
+    let mut compiler_config = Singlepass::new();
+    compiler_config.push_middleware(metering);
+    Engine::from(compiler_config)
+}
+
+/// Path of the serialized module file for given cache key.
+/// The gas schedule version is included, since modules compiled
+/// with a different schedule must not be reused.
+fn module_file(path: &Path, key: &ModuleKey) -> PathBuf {
+    let contract_id = ContractId::from_bytes(key.0).unwrap();
+    path.join(format!("{}-{}-v{}.wasmu", contract_id, key.1, GAS_SCHEDULE_VERSION))
+}
+
+/// Load a serialized module from given file. The file contains the
+/// hash of the serialized module, followed by the module itself.
+/// Returns `None` if the file doesn't exist or is invalid.
+fn load_module(file: &Path) -> Option<CachedModule> {
+    let bytes = fs::read(file).ok()?;
+    if bytes.len() < 32 || bytes[..32] != *blake3::hash(&bytes[32..]).as_bytes() {
+        warn!(target: "runtime::module_cache", "Corrupted serialized module {:?}", file);
+        return None
+    }
+
+    let engine = engine();
+    // SAFETY: Deserializing a module is unsafe, as its code gets executed
+    // without validation. The checksum above only guards against corruption,
+    // so we rely on the cache directory being writable only by the node, as
+    // documented in `ModuleCache::set_path`.
+    let module = match unsafe { Module::deserialize(&engine, &bytes[32..]) } {
+        Ok(module) => module,
+        Err(e) => {
+            warn!(target: "runtime::module_cache", "Failed deserializing module {:?}: {}", file, e);
+            return None
+        }
+    };
+
+    debug!(target: "runtime::module_cache", "Loaded serialized module {:?}", file);
+    Some(CachedModule { engine, module })
+}
+
+/// Serialize given module to given file, ignoring failures since
+/// the module is also cached in memory.
+fn store_module(file: &Path, cached: &CachedModule) {
+    let bytes = match cached.module.serialize() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(target: "runtime::module_cache", "Failed serializing module: {}", e);
+            return
+        }
+    };
+
+    let mut data = blake3::hash(&bytes).as_bytes().to_vec();
+    data.extend_from_slice(&bytes);
+    if let Err(e) = fs::write(file, data) {
+        warn!(target: "runtime::module_cache", "Failed writing module {:?}: {}", file, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi_sdk::pasta::pallas;
+    use wasmer::{imports, Instance, Store, TypedFunction};
+
+    const WASM_A: &[u8] = br#"(module (func (export "f") (result i32) i32.const 42))"#;
+    const WASM_B: &[u8] = br#"(module (func (export "f") (result i32) i32.const 69))"#;
+
+    /// Instantiate given cached module and call its function
+    fn call(cached: CachedModule) -> i32 {
+        let mut store = Store::new(cached.engine);
+        let instance = Instance::new(&mut store, &cached.module, &imports! {}).unwrap();
+        let f: TypedFunction<(), i32> = instance.exports.get_typed_function(&store, "f").unwrap();
+        f.call(&mut store).unwrap()
+    }
+
+    #[test]
+    fn module_cache() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("darkfi_module_cache_{}", rand::random::<u64>()));
+        let contract_id = ContractId::from(pallas::Base::from(42));
+        let other_id = ContractId::from(pallas::Base::from(69));
+
+        let cache = ModuleCache::new();
+        cache.set_path(&path)?;
+        assert_eq!(call(cache.get_or_compile(&contract_id, WASM_A)?), 42);
+        assert_eq!(call(cache.get_or_compile(&contract_id, WASM_A)?), 42);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(call(cache.get_or_compile(&contract_id, WASM_B)?), 69);
+        assert_eq!(call(cache.get_or_compile(&other_id, WASM_A)?), 42);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(fs::read_dir(&path)?.count(), 3);
+
+        // Serialized modules get loaded from disk
+        let restarted = ModuleCache::new();
+        restarted.set_path(&path)?;
+        assert_eq!(call(restarted.get_or_compile(&contract_id, WASM_B)?), 69);
+
+        // Redeploying drops the previous modules of the contract
+        cache.invalidate(&contract_id, WASM_B);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(fs::read_dir(&path)?.count(), 2);
+
+        // Corrupted files get recompiled
+        let key = (contract_id.to_bytes(), blake3::hash(WASM_B));
+        fs::write(module_file(&path, &key), b"corrupted")?;
+        assert_eq!(call(ModuleCache::new().get_or_compile(&contract_id, WASM_B)?), 69);
+        let restarted = ModuleCache::new();
+        restarted.set_path(&path)?;
+        assert_eq!(call(restarted.get_or_compile(&contract_id, WASM_B)?), 69);
+
+        fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn module_cache_eviction() -> Result<()> {
+        let ids: Vec<ContractId> =
+            (0..3_u64).map(|i| ContractId::from(pallas::Base::from(i))).collect();
+
+        let cache = ModuleCache::with_capacity(2);
+        cache.get_or_compile(&ids[0], WASM_A)?;
+        cache.get_or_compile(&ids[1], WASM_A)?;
+        // Using the first module makes the second one the least recently used
+        cache.get_or_compile(&ids[0], WASM_A)?;
+        cache.get_or_compile(&ids[2], WASM_A)?;
+        assert_eq!(cache.len(), 2);
+
+        let modules = cache.modules.lock().unwrap();
+        assert!(modules.entries.contains_key(&(ids[0].to_bytes(), blake3::hash(WASM_A))));
+        assert!(!modules.entries.contains_key(&(ids[1].to_bytes(), blake3::hash(WASM_A))));
+        assert!(modules.entries.contains_key(&(ids[2].to_bytes(), blake3::hash(WASM_A))));
+
+        Ok(())
+    }
+}
@@ -16,27 +16,22 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{
-    cell::{Cell, RefCell},
-    sync::Arc,
-};
+use std::cell::{Cell, RefCell};
 
 use darkfi_sdk::{crypto::ContractId, entrypoint};
 use darkfi_serial::serialize;
 use log::{debug, error, info};
 use wasmer::{
-    imports, wasmparser::Operator, AsStoreRef, CompilerConfig, Function, FunctionEnv, Instance,
-    Memory, MemoryView, Module, Pages, Store, Value, WASM_PAGE_SIZE,
-};
-use wasmer_compiler_singlepass::Singlepass;
-use wasmer_middlewares::{
-    metering::{get_remaining_points, MeteringPoints},
-    Metering,
+    imports, AsStoreMut, AsStoreRef, Function, FunctionEnv, Instance, Memory, MemoryView, Pages,
+    Store, Value, WASM_PAGE_SIZE,
 };
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
 
-use super::{import, import::db::DbHandle, memory::MemoryManipulation};
+use super::{import, import::db::DbHandle, memory::MemoryManipulation, module_cache::MODULE_CACHE};
 use crate::{
-    blockchain::{contract_store::SMART_CONTRACT_ZKAS_DB_NAME, BlockchainOverlayPtr},
+    blockchain::{
+        contract_store::SMART_CONTRACT_ZKAS_DB_NAME, BlockchainOverlayPtr, ContractEvent,
+    },
     util::time::TimeKeeper,
     Error, Result,
 };
@@ -44,9 +39,6 @@ use crate::{
 /// Name of the wasm linear memory in our guest module
 const MEMORY: &str = "memory";
 
-/// Gas limit for a contract
-const GAS_LIMIT: u64 = 400_000_000;
-
 #[derive(Clone, Copy, PartialEq)]
 pub enum ContractSection {
     /// Setup function of a contract
@@ -57,6 +49,8 @@ pub enum ContractSection {
     Update,
     /// Metadata
     Metadata,
+    /// State migration function of a contract, run once when upgraded
+    Migrate,
     /// Placeholder state before any initialization
     Null,
 }
@@ -68,6 +62,7 @@ impl ContractSection {
             Self::Exec => "__entrypoint",
             Self::Update => "__update",
             Self::Metadata => "__metadata",
+            Self::Migrate => "__migrate",
             Self::Null => unreachable!(),
         }
     }
@@ -89,12 +84,22 @@ pub struct Env {
     pub contract_return_data: Cell<Option<Vec<u8>>>,
     /// Logs produced by the contract
     pub logs: RefCell<Vec<String>>,
+    /// Events emitted by the contract and the contracts it invoked
+    pub events: RefCell<Vec<ContractEvent>>,
     /// Direct memory access to the VM
     pub memory: Option<Memory>,
     /// Object store for transferring memory from the host to VM
     pub objects: RefCell<Vec<Vec<u8>>>,
     /// Helper structure to calculate time related operations
     pub time_keeper: TimeKeeper,
+    /// Gas meter used by host functions to charge their cost
+    pub gas_meter: Option<GasMeter>,
+    /// The contract that invoked this one, if it's a nested call
+    pub caller: Option<ContractId>,
+    /// Depth of nested contract calls this runtime is executing at
+    pub call_depth: u32,
+    /// Set when a state-changing nested contract call failed
+    pub call_failed: Cell<bool>,
 }
 
 impl Env {
@@ -113,12 +118,28 @@ impl Env {
     pub fn memory(&self) -> &Memory {
         self.memory.as_ref().unwrap()
     }
+
+    /// Charge gas for a host function call. Returns `false` if there
+    /// wasn't enough gas left, in which case the host function must
+    /// abort. The gas meter needs to have been set first.
+    ///
+    ///     // ctx: FunctionEnvMut<Env>
+    ///     let (env, mut store) = ctx.data_and_store_mut();
+    ///     if !env.charge_gas(&mut store, gas::host_call_cost(len as usize)) {
+    ///         return ERROR
+    ///     }
+    ///
+    pub fn charge_gas(&self, store: &mut impl AsStoreMut, gas: u64) -> bool {
+        self.gas_meter.as_ref().unwrap().charge(store, gas)
+    }
 }
 
 pub struct Runtime {
     pub instance: Instance,
     pub store: Store,
     pub ctx: FunctionEnv<Env>,
+    /// Gas limit of the runtime
+    gas_limit: u64,
 }
 
 impl Runtime {
@@ -130,25 +151,13 @@ impl Runtime {
         time_keeper: TimeKeeper,
     ) -> Result<Self> {
         info!(target: "runtime::vm_runtime", "Instantiating a new runtime");
-        // This function will be called for each `Operator` encountered during
-        // the wasm module execution. It should return the cost of the operator
-        // that it received as its first argument. For now, every wasm opcode
-        // has a cost of `1`.
-        // https://docs.rs/wasmparser/latest/wasmparser/enum.Operator.html
-        let cost_function = |_operator: &Operator| -> u64 { 1 };
-
-        // `Metering` needs to be conigured with a limit and a cost function.
-        // For each `Operator`, the metering middleware will call the cost
-        // function and subtract the cost from the remaining points.
-        let metering = Arc::new(Metering::new(GAS_LIMIT, cost_function));
-
-        // Define the compiler and middleware, engine, and store
-        let mut compiler_config = Singlepass::new();
-        compiler_config.push_middleware(metering);
-        let mut store = Store::new(compiler_config);
-
-        debug!(target: "runtime::vm_runtime", "Compiling module");
-        let module = Module::new(&store, wasm_bytes)?;
+        // Retrieve the compiled module from the cache, or compile it.
+        // The module must be instantiated in a store using the engine
+        // it was compiled with, as that holds the gas metering.
+        debug!(target: "runtime::vm_runtime", "Retrieving module");
+        let cached = MODULE_CACHE.get_or_compile(&contract_id, wasm_bytes)?;
+        let mut store = Store::new(cached.engine);
+        let module = cached.module;
 
         // Initialize data
         let db_handles = RefCell::new(vec![]);
@@ -166,9 +175,14 @@ impl Runtime {
                 contract_section: ContractSection::Null,
                 contract_return_data: Cell::new(None),
                 logs,
+                events: RefCell::new(vec![]),
                 memory: None,
                 objects: RefCell::new(vec![]),
                 time_keeper,
+                gas_meter: None,
+                caller: None,
+                call_depth: 0,
+                call_failed: Cell::new(false),
             },
         );
 
@@ -222,12 +236,36 @@ impl Runtime {
                     import::db::db_del,
                 ),
 
+                "db_iter_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::db::db_iter,
+                ),
+
+                "db_range_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::db::db_range,
+                ),
+
+                "db_prefix_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::db::db_prefix,
+                ),
+
                 "zkas_db_set_" => Function::new_typed_with_env(
                     &mut store,
                     &ctx,
                     import::db::zkas_db_set,
                 ),
 
+                "emit_event_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::util::emit_event,
+                ),
+
                 "put_object_bytes_" => Function::new_typed_with_env(
                     &mut store,
                     &ctx,
@@ -252,6 +290,36 @@ impl Runtime {
                     import::merkle::merkle_add,
                 ),
 
+                "poseidon_hash_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::crypto::poseidon_hash,
+                ),
+
+                "ec_add_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::crypto::ec_add,
+                ),
+
+                "ec_mul_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::crypto::ec_mul,
+                ),
+
+                "schnorr_verify_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::crypto::schnorr_verify,
+                ),
+
+                "smt_verify_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::crypto::smt_verify,
+                ),
+
                 "get_current_epoch_" => Function::new_typed_with_env(
                     &mut store,
                     &ctx,
@@ -287,6 +355,24 @@ impl Runtime {
                     &ctx,
                     import::util::get_blockchain_time,
                 ),
+
+                "invoke_contract_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::call::invoke_contract,
+                ),
+
+                "get_caller_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::call::get_caller,
+                ),
+
+                "upgrade_contract_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::call::upgrade_contract,
+                ),
             }
         };
 
@@ -295,8 +381,9 @@ impl Runtime {
 
         let env_mut = ctx.as_mut(&mut store);
         env_mut.memory = Some(instance.exports.get_with_generics(MEMORY)?);
+        env_mut.gas_meter = Some(GasMeter::new(&instance)?);
 
-        Ok(Self { instance, store, ctx })
+        Ok(Self { instance, store, ctx, gas_limit: GAS_LIMIT })
     }
 
     /// Perform a sanity check of the WASM bincode
@@ -319,6 +406,7 @@ impl Runtime {
         env_mut.contract_section = section;
         assert!(env_mut.contract_return_data.take().is_none());
         env_mut.contract_return_data.set(None);
+        env_mut.call_failed.set(false);
         // Clear the logs
         let _ = env_mut.logs.take();
 
@@ -359,6 +447,12 @@ impl Runtime {
             None => Vec::new(),
         };
 
+        // The state might have been partially changed by a failed nested call
+        if env_mut.call_failed.get() {
+            error!(target: "runtime::vm_runtime", "State-changing contract call failed");
+            return Err(Error::ContractError(darkfi_sdk::error::ContractError::ContractCallFailed))
+        }
+
         let retval = match ret[0] {
             Value::I64(v) => v,
             _ => unreachable!("Got unexpected result from ret: {:?}", ret),
@@ -416,12 +510,11 @@ impl Runtime {
 
         // Update the wasm bincode in the WasmStore
         let env_mut = self.ctx.as_mut(&mut self.store);
-        env_mut
-            .blockchain
-            .lock()
-            .unwrap()
-            .wasm_bincode
-            .insert(env_mut.contract_id, &env_mut.contract_bincode)?;
+        env_mut.blockchain.lock().unwrap().wasm_bincode.insert(
+            env_mut.contract_id,
+            &env_mut.contract_bincode,
+            env_mut.time_keeper.verifying_slot,
+        )?;
 
         Ok(())
     }
@@ -430,9 +523,12 @@ impl Runtime {
     /// The runtime will look for an `ENTRYPOINT` symbol in the wasm code, and
     /// execute it if found. A payload is also passed as an instruction that can
     /// be used inside the vm by the runtime.
-    pub fn exec(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+    /// Along with the state update, the gas consumption of the runtime so far
+    /// is returned.
+    pub fn exec(&mut self, payload: &[u8]) -> Result<(Vec<u8>, GasReport)> {
         debug!(target: "runtime::vm_runtime", "exec: {:?}", payload);
-        self.call(ContractSection::Exec, payload)
+        let state_update = self.call(ContractSection::Exec, payload)?;
+        Ok((state_update, self.gas_report()))
     }
 
     /// This function runs after successful execution of `exec` and tries to
@@ -455,6 +551,28 @@ impl Runtime {
         self.call(ContractSection::Metadata, payload)
     }
 
+    /// This function runs once when a deployed smart contract gets upgraded, right
+    /// after the `INITIALIZE` symbol of the new wasm code. The runtime will look for
+    /// a `MIGRATE` symbol and execute it with the given payload if found, so the
+    /// new code can convert the state written by the previous version. Contracts
+    /// that don't need a migration simply don't export it.
+    pub fn migrate(&mut self, payload: &[u8]) -> Result<()> {
+        if self.instance.exports.get_function(ContractSection::Migrate.name()).is_err() {
+            debug!(target: "runtime::vm_runtime", "No {} symbol, skipping migration", ContractSection::Migrate.name());
+            return Ok(())
+        }
+
+        debug!(target: "runtime::vm_runtime", "migrate: {:?}", payload);
+        let _ = self.call(ContractSection::Migrate, payload)?;
+
+        Ok(())
+    }
+
+    /// Take the events emitted by all sections executed so far.
+    pub fn take_events(&mut self) -> Vec<ContractEvent> {
+        self.ctx.as_mut(&mut self.store).events.take()
+    }
+
     fn print_logs(&self) {
         let logs = self.ctx.as_ref(&self.store).logs.borrow();
         for msg in logs.iter() {
@@ -462,22 +580,42 @@ impl Runtime {
         }
     }
 
-    fn gas_used(&mut self) -> u64 {
+    /// Retrieve the amount of gas consumed by all sections executed so far.
+    pub fn gas_used(&mut self) -> u64 {
         let remaining_points = get_remaining_points(&mut self.store, &self.instance);
 
         match remaining_points {
-            MeteringPoints::Remaining(rem) => GAS_LIMIT - rem,
-            MeteringPoints::Exhausted => GAS_LIMIT + 1,
+            MeteringPoints::Remaining(rem) => self.gas_limit - rem,
+            MeteringPoints::Exhausted => self.gas_limit + 1,
         }
     }
 
+    /// Set up the runtime to be invoked by another contract, at given call
+    /// depth, with its gas limited to given amount, which should be the gas
+    /// the caller has remaining.
+    pub(crate) fn set_caller(&mut self, caller: ContractId, call_depth: u32, gas_limit: u64) {
+        let env_mut = self.ctx.as_mut(&mut self.store);
+        env_mut.caller = Some(caller);
+        env_mut.call_depth = call_depth;
+
+        let gas_limit = gas_limit.min(GAS_LIMIT);
+        set_remaining_points(&mut self.store, &self.instance, gas_limit);
+        self.gas_limit = gas_limit;
+    }
+
+    /// Retrieve the gas consumption report of all sections executed so far.
+    pub fn gas_report(&mut self) -> GasReport {
+        let used = self.gas_used();
+        GasReport { used, remaining: self.gas_limit.saturating_sub(used) }
+    }
+
     fn gas_info(&mut self) -> String {
         let gas_used = self.gas_used();
 
-        if gas_used > GAS_LIMIT {
-            format!("Gas fully exhausted: {}/{}", gas_used, GAS_LIMIT)
+        if gas_used > self.gas_limit {
+            format!("Gas fully exhausted: {}/{}", gas_used, self.gas_limit)
         } else {
-            format!("Gas used: {}/{}", gas_used, GAS_LIMIT)
+            format!("Gas used: {}/{}", gas_used, self.gas_limit)
         }
     }
 
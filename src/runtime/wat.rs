@@ -0,0 +1,115 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Builders of test contracts in the WebAssembly text format, which the
+//! runtime compiles as it does wasm bincode. These let tests exercise host
+//! functions without building a contract crate.
+
+use darkfi_sdk::crypto::ContractId;
+use darkfi_serial::serialize;
+
+use super::vm_runtime::ContractSection;
+
+/// Memory offset of the data given to [`contract`]
+pub const DATA_OFFSET: usize = 1024;
+
+/// Escape bytes for a WAT data segment string
+pub fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("\\{:02x}", b)).collect()
+}
+
+/// Build a test contract running `body` as given section. `imports` are
+/// declarations of the `env` host functions it uses, on top of
+/// `set_return_data_` which is always imported as `$ret`. `data` is
+/// placed at [`DATA_OFFSET`] and must not get overwritten by payloads.
+/// The body must leave an `i64` return code on the stack. Every other
+/// section succeeds, with `metadata` requesting no ZK proofs or signatures.
+pub fn contract(section: ContractSection, imports: &[&str], data: &[u8], body: &str) -> String {
+    let sections = [
+        ContractSection::Deploy,
+        ContractSection::Exec,
+        ContractSection::Update,
+        ContractSection::Metadata,
+    ];
+
+    let mut funcs = String::new();
+    for s in sections {
+        let s_body = if s == section {
+            body
+        } else if s == ContractSection::Metadata {
+            // Two empty vectors, written over the payload
+            "(i32.store16 (i32.const 0) (i32.const 0))
+             (drop (call $ret (i32.const 0) (i32.const 2)))
+             (i64.const 0)"
+        } else {
+            "(i64.const 0)"
+        };
+        funcs.push_str(&format!(
+            "(func (export \"{}\") (param i32) (result i64) {})\n",
+            s.name(),
+            s_body
+        ));
+    }
+
+    let imports: String = imports.iter().map(|i| format!("(import \"env\" {})\n", i)).collect();
+
+    format!(
+        r#"
+        (module
+            (import "env" "set_return_data_" (func $ret (param i32 i32) (result i64)))
+            {imports}
+            (memory (export "memory") 1)
+            (data (i32.const {DATA_OFFSET}) "{data}")
+            {funcs})
+        "#,
+        data = escape(data),
+    )
+}
+
+/// Build a test contract which, in given section, invokes `callee` with
+/// `data` as its payload, applying the state update if `apply` is set.
+/// The invocation error code is returned if it fails, otherwise the data
+/// the callee returned is set as the contract's return data.
+pub fn invoker(section: ContractSection, callee: &ContractId, data: &[u8], apply: bool) -> String {
+    let mut input = serialize(callee);
+    input.extend_from_slice(&serialize(&data.to_vec()));
+    input.extend_from_slice(&serialize(&apply));
+
+    let imports = [
+        r#""invoke_contract_" (func $invoke (param i32 i32) (result i64))"#,
+        r#""get_object_size_" (func $size (param i32) (result i64))"#,
+        r#""get_object_bytes_" (func $bytes (param i32 i32) (result i64))"#,
+    ];
+
+    let body = format!(
+        r#"
+        (local $obj i64)
+        (local.set $obj (call $invoke (i32.const {DATA_OFFSET}) (i32.const {len})))
+        (if (i64.lt_s (local.get $obj) (i64.const 0))
+            (then (return (local.get $obj))))
+        (drop (call $bytes (i32.const {out}) (i32.wrap_i64 (local.get $obj))))
+        (drop (call $ret (i32.const {out})
+            (i32.wrap_i64 (call $size (i32.wrap_i64 (local.get $obj))))))
+        (i64.const 0)
+        "#,
+        len = input.len(),
+        out = DATA_OFFSET + input.len(),
+    );
+
+    contract(section, &imports, &input, &body)
+}
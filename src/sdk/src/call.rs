@@ -0,0 +1,110 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_serial::{deserialize, Encodable};
+
+use super::{
+    crypto::ContractId,
+    entrypoint::SUCCESS,
+    error::{ContractError, GenericResult},
+    util::read_object,
+};
+
+/// Invoke the `exec` entrypoint of another contract, passing it `data` as
+/// its payload, and return the data it set with `set_return_data`.
+/// Everyone can call this. The invoked contract can't change any state.
+/// Nested calls can't carry ZK proofs or signatures, so if the invoked
+/// contract's `metadata()` requests any for `data`, `CallerAccessDenied`
+/// is returned.
+///
+/// ```
+/// ret = call_contract(contract_id, &data)?;
+/// ```
+pub fn call_contract(contract_id: ContractId, data: &[u8]) -> GenericResult<Vec<u8>> {
+    invoke_contract(contract_id, data, false)
+}
+
+/// Invoke the `exec` entrypoint of another contract, passing it `data` as
+/// its payload, and then its `apply` entrypoint with the returned state
+/// update, which is also returned to the caller.
+/// Only `deploy()`, `update()` and `migrate()` can call this. If the `apply`
+/// part fails, the calling contract fails too, as the state might have been
+/// partially changed.
+/// As with [`call_contract`], the invoked contract's `metadata()` must not
+/// request any ZK proof or signature for `data`.
+///
+/// ```
+/// update = call_contract_mut(contract_id, &data)?;
+/// ```
+pub fn call_contract_mut(contract_id: ContractId, data: &[u8]) -> GenericResult<Vec<u8>> {
+    invoke_contract(contract_id, data, true)
+}
+
+/// Everyone can call this. Will return the ID of the contract that invoked the
+/// current one, or `None` if it was invoked directly by a transaction call.
+///
+/// ```
+/// caller = get_caller()?;
+/// ```
+pub fn get_caller() -> GenericResult<Option<ContractId>> {
+    let ret = unsafe { get_caller_() };
+    let bytes = read_object(ret)?;
+    Ok(deserialize(&bytes)?)
+}
+
+/// Only the deployooor contract's `update()` can call this. Deploys new wasm
+/// bincode for an existing contract, keeping the previous one as an older
+/// version, and runs its `migrate()` once with given payload if the new
+/// bincode exports it.
+///
+/// ```
+/// upgrade_contract(contract_id, &wasm_bincode, &migrate_payload)?;
+/// ```
+pub fn upgrade_contract(
+    contract_id: ContractId,
+    wasm_bincode: &[u8],
+    migrate_payload: &[u8],
+) -> GenericResult<()> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += contract_id.encode(&mut buf)?;
+    len += wasm_bincode.to_vec().encode(&mut buf)?;
+    len += migrate_payload.to_vec().encode(&mut buf)?;
+
+    match unsafe { upgrade_contract_(buf.as_ptr(), len as u32) } {
+        SUCCESS => Ok(()),
+        e => Err(ContractError::from(e)),
+    }
+}
+
+fn invoke_contract(contract_id: ContractId, data: &[u8], apply: bool) -> GenericResult<Vec<u8>> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += contract_id.encode(&mut buf)?;
+    len += data.to_vec().encode(&mut buf)?;
+    len += apply.encode(&mut buf)?;
+
+    let ret = unsafe { invoke_contract_(buf.as_ptr(), len as u32) };
+    read_object(ret)
+}
+
+extern "C" {
+    fn invoke_contract_(ptr: *const u8, len: u32) -> i64;
+    fn get_caller_() -> i64;
+    fn upgrade_contract_(ptr: *const u8, len: u32) -> i64;
+}
@@ -109,20 +109,7 @@ impl<F: WithSmallOrderMulGroup<3> + Ord, H: FieldHasher<F, 2>, const N: usize> P
     /// Assumes leaf contains leaf-level data, i.e. hashes of secrets stored on
     /// leaf-level.
     pub fn calculate_root(&self, leaf: &F, hasher: &H) -> GenericResult<F> {
-        if *leaf != self.path[0].0 && *leaf != self.path[0].1 {
-            return Err(ContractError::SmtInvalidLeaf)
-        }
-
-        let mut prev = *leaf;
-        // Check levels between leaf level and root
-        for (left_hash, right_hash) in &self.path {
-            if &prev != left_hash && &prev != right_hash {
-                return Err(ContractError::SmtInvalidPathNodes)
-            }
-            prev = hasher.hash([*left_hash, *right_hash])?;
-        }
-
-        Ok(prev)
+        calculate_root_from_path(&self.path, leaf, hasher)
     }
 
     /// Takes in an expected `root_hash` and leaf-level data (i.e. hashes of secrets)
@@ -158,6 +145,33 @@ impl<F: WithSmallOrderMulGroup<3> + Ord, H: FieldHasher<F, 2>, const N: usize> P
     }
 }
 
+/// Calculate the root of a tree from leaf-level data and its path of sibling
+/// pairs, like [`Path::calculate_root`] does, for a path of any height.
+pub fn calculate_root_from_path<F: WithSmallOrderMulGroup<3> + Ord, H: FieldHasher<F, 2>>(
+    path: &[(F, F)],
+    leaf: &F,
+    hasher: &H,
+) -> GenericResult<F> {
+    let Some((first_left, first_right)) = path.first() else {
+        return Err(ContractError::SmtInvalidPathNodes)
+    };
+
+    if leaf != first_left && leaf != first_right {
+        return Err(ContractError::SmtInvalidLeaf)
+    }
+
+    let mut prev = *leaf;
+    // Check levels between leaf level and root
+    for (left_hash, right_hash) in path {
+        if &prev != left_hash && &prev != right_hash {
+            return Err(ContractError::SmtInvalidPathNodes)
+        }
+        prev = hasher.hash([*left_hash, *right_hash])?;
+    }
+
+    Ok(prev)
+}
+
 /// The Sparse Merkle Tree struct.
 ///
 /// SMT stores a set of leaves represented in a map and a set of empty
@@ -449,6 +463,12 @@ mod tests {
 
         let proof = smt.generate_membership_proof(0);
         let res = proof.check_membership(&smt.root(), &leaves[0], &poseidon).unwrap();
-        assert!(res)
+        assert!(res);
+
+        // Paths of any height can be verified as a slice
+        let root = calculate_root_from_path(&proof.path, &leaves[0], &poseidon).unwrap();
+        assert_eq!(root, smt.root());
+        assert!(calculate_root_from_path(&proof.path, &leaves[1], &poseidon).is_err());
+        assert!(calculate_root_from_path(&[], &leaves[0], &poseidon).is_err());
     }
 }
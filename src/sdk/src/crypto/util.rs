@@ -45,3 +45,21 @@ pub fn poseidon_hash<const N: usize>(messages: [pallas::Base; N]) -> pallas::Bas
     poseidon::Hash::<_, poseidon::P128Pow5T3, poseidon::ConstantLength<N>, 3, 2>::init()
         .hash(messages)
 }
+
+/// Maximum number of messages [`poseidon_hash_slice`] can hash
+pub const MAX_POSEIDON_MESSAGES: usize = 16;
+
+/// Wrapper around [`poseidon_hash`] for a number of messages only known at
+/// runtime, which must be from 1 up to [`MAX_POSEIDON_MESSAGES`].
+pub fn poseidon_hash_slice(messages: &[pallas::Base]) -> Option<pallas::Base> {
+    macro_rules! hash_len {
+        ($($n:literal),*) => {
+            match messages.len() {
+                $($n => Some(poseidon_hash::<$n>(messages.try_into().unwrap())),)*
+                _ => None,
+            }
+        };
+    }
+
+    hash_len!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16)
+}
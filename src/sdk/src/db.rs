@@ -16,12 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi_serial::Encodable;
+use darkfi_serial::{deserialize, Encodable};
 
 use super::{
     crypto::ContractId,
     error::{ContractError, GenericResult},
-    util::parse_ret,
+    util::{get_object_bytes, get_object_size, parse_ret},
 };
 
 pub type DbHandle = u32;
@@ -34,6 +34,7 @@ pub const DB_GET_FAILED: i32 = -4;
 pub const DB_CONTAINS_KEY_FAILED: i32 = -5;
 pub const DB_SET_FAILED: i32 = -6;
 pub const DB_DEL_FAILED: i32 = -7;
+pub const DB_ITER_FAILED: i32 = -8;
 
 /// Only deploy() can call this. Creates a new database instance for this contract.
 ///
@@ -123,6 +124,86 @@ pub fn db_contains_key(db_handle: DbHandle, key: &[u8]) -> GenericResult<bool> {
     }
 }
 
+/// Everyone can call this. Will read up to `limit` records of the key-value
+/// store, in ascending key order.
+///
+/// ```
+/// records = db_iter(db_handle, limit)?;
+/// ```
+pub fn db_iter(db_handle: DbHandle, limit: u32) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += db_handle.encode(&mut buf)?;
+    len += limit.encode(&mut buf)?;
+
+    let ret = unsafe { db_iter_(buf.as_ptr(), len as u32) };
+    parse_records(ret)
+}
+
+/// Everyone can call this. Will read up to `limit` records of the key-value
+/// store with keys from `start`, inclusive, to `end`, exclusive, in ascending
+/// key order. The next page of records starts at the last key read with a
+/// zero byte appended.
+///
+/// ```
+/// records = db_range(db_handle, start, end, limit)?;
+/// ```
+pub fn db_range(
+    db_handle: DbHandle,
+    start: &[u8],
+    end: &[u8],
+    limit: u32,
+) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += db_handle.encode(&mut buf)?;
+    len += start.to_vec().encode(&mut buf)?;
+    len += end.to_vec().encode(&mut buf)?;
+    len += limit.encode(&mut buf)?;
+
+    let ret = unsafe { db_range_(buf.as_ptr(), len as u32) };
+    parse_records(ret)
+}
+
+/// Everyone can call this. Will read up to `limit` records of the key-value
+/// store with keys starting with `prefix`, in ascending key order.
+///
+/// ```
+/// records = db_prefix(db_handle, prefix, limit)?;
+/// ```
+pub fn db_prefix(
+    db_handle: DbHandle,
+    prefix: &[u8],
+    limit: u32,
+) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += db_handle.encode(&mut buf)?;
+    len += prefix.to_vec().encode(&mut buf)?;
+    len += limit.encode(&mut buf)?;
+
+    let ret = unsafe { db_prefix_(buf.as_ptr(), len as u32) };
+    parse_records(ret)
+}
+
+/// Read the records returned by a database scan, or its error
+fn parse_records(ret: i64) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    if ret < 0 {
+        match ret as i32 {
+            CALLER_ACCESS_DENIED => return Err(ContractError::CallerAccessDenied),
+            DB_ITER_FAILED => return Err(ContractError::DbIterFailed),
+            _ => unimplemented!(),
+        }
+    }
+
+    let obj = ret as u32;
+    let obj_size = get_object_size(obj);
+    let mut buf = vec![0u8; obj_size as usize];
+    get_object_bytes(&mut buf, obj);
+
+    Ok(deserialize(&buf)?)
+}
+
 /// Only update() can call this. Set a value within the transaction.
 ///
 /// ```
@@ -189,6 +270,9 @@ extern "C" {
     fn db_lookup_(ptr: *const u8, len: u32) -> i32;
     fn db_get_(ptr: *const u8, len: u32) -> i64;
     fn db_contains_key_(ptr: *const u8, len: u32) -> i32;
+    fn db_iter_(ptr: *const u8, len: u32) -> i64;
+    fn db_range_(ptr: *const u8, len: u32) -> i64;
+    fn db_prefix_(ptr: *const u8, len: u32) -> i64;
     fn db_set_(ptr: *const u8, len: u32) -> i32;
     fn db_del_(ptr: *const u8, len: u32) -> i32;
 
@@ -69,6 +69,34 @@ macro_rules! define_contract {
             }
         }
     };
+
+    // Contracts that have to migrate their state when upgraded additionally
+    // export a `__migrate` function, which is run once after `__initialize`.
+    (
+        init: $init_func:ident,
+        exec: $exec_func:ident,
+        apply: $apply_func:ident,
+        metadata: $metadata_func:ident,
+        migrate: $migrate_func:ident
+    ) => {
+        $crate::define_contract!(
+            init: $init_func,
+            exec: $exec_func,
+            apply: $apply_func,
+            metadata: $metadata_func
+        );
+
+        /// # Safety
+        #[no_mangle]
+        pub unsafe extern "C" fn __migrate(input: *mut u8) -> i64 {
+            let (contract_id, migrate_data) = $crate::entrypoint::deserialize(input);
+
+            match $migrate_func(contract_id, &migrate_data) {
+                Ok(()) => $crate::entrypoint::SUCCESS,
+                Err(e) => e.into(),
+            }
+        }
+    };
 }
 
 /// Deserialize a given payload in `entrypoint`
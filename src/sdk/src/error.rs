@@ -72,6 +72,12 @@ pub enum ContractError {
     #[error("Db contains_key failed")]
     DbContainsKeyFailed,
 
+    #[error("Db iter failed")]
+    DbIterFailed,
+
+    #[error("Host cryptographic operation failed")]
+    HostCryptoFailed,
+
     #[error("Invalid function call")]
     InvalidFunction,
 
@@ -83,6 +89,15 @@ pub enum ContractError {
 
     #[error("Error retrieving system time")]
     GetSystemTimeFailed,
+
+    #[error("Contract call depth limit exceeded")]
+    CallDepthExceeded,
+
+    #[error("Contract call failed")]
+    ContractCallFailed,
+
+    #[error("Contract upgrade failed")]
+    ContractUpgradeFailed,
 }
 
 /// Builtin return values occupy the upper 32 bits
@@ -111,6 +126,11 @@ pub const DB_DEL_FAILED: i64 = to_builtin!(16);
 pub const SMT_INVALID_LEAF: i64 = to_builtin!(17);
 pub const SMT_INVALID_PATH_NODES: i64 = to_builtin!(18);
 pub const GET_SYSTEM_TIME_FAILED: i64 = to_builtin!(19);
+pub const CALL_DEPTH_EXCEEDED: i64 = to_builtin!(20);
+pub const CONTRACT_CALL_FAILED: i64 = to_builtin!(21);
+pub const DB_ITER_FAILED: i64 = to_builtin!(22);
+pub const HOST_CRYPTO_FAILED: i64 = to_builtin!(23);
+pub const CONTRACT_UPGRADE_FAILED: i64 = to_builtin!(24);
 
 impl From<ContractError> for i64 {
     fn from(err: ContractError) -> Self {
@@ -133,6 +153,11 @@ impl From<ContractError> for i64 {
             ContractError::SmtInvalidLeaf => SMT_INVALID_LEAF,
             ContractError::SmtInvalidPathNodes => SMT_INVALID_PATH_NODES,
             ContractError::GetSystemTimeFailed => GET_SYSTEM_TIME_FAILED,
+            ContractError::CallDepthExceeded => CALL_DEPTH_EXCEEDED,
+            ContractError::ContractCallFailed => CONTRACT_CALL_FAILED,
+            ContractError::DbIterFailed => DB_ITER_FAILED,
+            ContractError::HostCryptoFailed => HOST_CRYPTO_FAILED,
+            ContractError::ContractUpgradeFailed => CONTRACT_UPGRADE_FAILED,
             ContractError::Custom(error) => {
                 if error == 0 {
                     CUSTOM_ZERO
@@ -166,6 +191,11 @@ impl From<i64> for ContractError {
             SMT_INVALID_LEAF => Self::SmtInvalidLeaf,
             SMT_INVALID_PATH_NODES => Self::SmtInvalidPathNodes,
             GET_SYSTEM_TIME_FAILED => Self::GetSystemTimeFailed,
+            CALL_DEPTH_EXCEEDED => Self::CallDepthExceeded,
+            CONTRACT_CALL_FAILED => Self::ContractCallFailed,
+            DB_ITER_FAILED => Self::DbIterFailed,
+            HOST_CRYPTO_FAILED => Self::HostCryptoFailed,
+            CONTRACT_UPGRADE_FAILED => Self::ContractUpgradeFailed,
             _ => Self::Custom(error as u32),
         }
     }
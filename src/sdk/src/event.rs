@@ -0,0 +1,45 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_serial::Encodable;
+
+use super::error::{ContractError, GenericResult};
+
+/// Emit a structured event with given topic and data, which the node stores
+/// along with the transaction once it gets included in a block, so it can be
+/// followed without re-executing the transaction.
+/// Only `exec()` and `update()` can call this.
+///
+/// ```
+/// emit_event("transfer", &serialize(&params))?;
+/// ```
+pub fn emit_event(topic: &str, data: &[u8]) -> GenericResult<()> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += topic.to_string().encode(&mut buf)?;
+    len += data.to_vec().encode(&mut buf)?;
+
+    match unsafe { emit_event_(buf.as_ptr(), len as u32) } {
+        0 => Ok(()),
+        e => Err(ContractError::from(e)),
+    }
+}
+
+extern "C" {
+    fn emit_event_(ptr: *const u8, len: u32) -> i64;
+}
@@ -0,0 +1,149 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_serial::{deserialize, Encodable};
+use pasta_curves::pallas;
+
+use super::{
+    crypto::{schnorr::Signature, PublicKey},
+    error::{ContractError, GenericResult},
+    util::read_object,
+};
+
+/// Everyone can call this. Will return the Poseidon hash of given messages,
+/// computed by the host. Up to `MAX_POSEIDON_MESSAGES` can be hashed.
+///
+/// ```
+/// nullifier = poseidon_hash([secret.inner(), coin.inner()])?;
+/// ```
+pub fn poseidon_hash<const N: usize>(messages: [pallas::Base; N]) -> GenericResult<pallas::Base> {
+    let mut buf = vec![];
+    let len = messages.to_vec().encode(&mut buf)?;
+
+    let ret = unsafe { poseidon_hash_(buf.as_ptr(), len as u32) };
+    Ok(deserialize(&read_object(ret)?)?)
+}
+
+/// Everyone can call this. Will return the sum of given Pallas points,
+/// computed by the host.
+///
+/// ```
+/// sum = ec_add(a, b)?;
+/// ```
+pub fn ec_add(a: pallas::Point, b: pallas::Point) -> GenericResult<pallas::Point> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += a.encode(&mut buf)?;
+    len += b.encode(&mut buf)?;
+
+    let ret = unsafe { ec_add_(buf.as_ptr(), len as u32) };
+    Ok(deserialize(&read_object(ret)?)?)
+}
+
+/// Everyone can call this. Will return the given Pallas point multiplied
+/// by given scalar, computed by the host.
+///
+/// ```
+/// product = ec_mul(point, scalar)?;
+/// ```
+pub fn ec_mul(point: pallas::Point, scalar: pallas::Scalar) -> GenericResult<pallas::Point> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += point.encode(&mut buf)?;
+    len += scalar.encode(&mut buf)?;
+
+    let ret = unsafe { ec_mul_(buf.as_ptr(), len as u32) };
+    Ok(deserialize(&read_object(ret)?)?)
+}
+
+/// Everyone can call this. Will verify a Schnorr signature over given
+/// message with given public key in the host.
+///
+/// ```
+/// if schnorr_verify(public_key, message, &signature)? {
+///     println!("valid");
+/// }
+/// ```
+pub fn schnorr_verify(
+    public_key: PublicKey,
+    message: &[u8],
+    signature: &Signature,
+) -> GenericResult<bool> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += public_key.encode(&mut buf)?;
+    len += message.to_vec().encode(&mut buf)?;
+    len += signature.encode(&mut buf)?;
+
+    let ret = unsafe { schnorr_verify_(buf.as_ptr(), len as u32) };
+    parse_bool(ret)
+}
+
+/// Everyone can call this. Will verify in the host that given leaf belongs
+/// to a Poseidon sparse Merkle tree with given root, using its path of
+/// sibling pairs from the leaf level up to the root.
+///
+/// ```
+/// if smt_verify(root, leaf, &path.path)? {
+///     println!("member");
+/// }
+/// ```
+pub fn smt_verify(
+    root: pallas::Base,
+    leaf: pallas::Base,
+    path: &[(pallas::Base, pallas::Base)],
+) -> GenericResult<bool> {
+    let mut buf = vec![];
+    let mut len = 0;
+    len += root.encode(&mut buf)?;
+    len += leaf.encode(&mut buf)?;
+    len += path.to_vec().encode(&mut buf)?;
+
+    let ret = unsafe { smt_verify_(buf.as_ptr(), len as u32) };
+    parse_bool(ret)
+}
+
+/// Parse the boolean returned by a verification host function, or its error
+fn parse_bool(ret: i64) -> GenericResult<bool> {
+    match ret {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ContractError::from(ret)),
+    }
+}
+
+extern "C" {
+    fn poseidon_hash_(ptr: *const u8, len: u32) -> i64;
+    fn ec_add_(ptr: *const u8, len: u32) -> i64;
+    fn ec_mul_(ptr: *const u8, len: u32) -> i64;
+    fn schnorr_verify_(ptr: *const u8, len: u32) -> i64;
+    fn smt_verify_(ptr: *const u8, len: u32) -> i64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::HOST_CRYPTO_FAILED;
+
+    #[test]
+    fn verification_results() {
+        assert!(matches!(parse_bool(0), Ok(false)));
+        assert!(matches!(parse_bool(1), Ok(true)));
+        assert!(matches!(parse_bool(HOST_CRYPTO_FAILED), Err(ContractError::HostCryptoFailed)));
+    }
+}
@@ -24,6 +24,9 @@ pub use pasta_curves as pasta;
 /// Blockchain structures
 pub mod blockchain;
 
+/// Cross-contract calls
+pub mod call;
+
 /// Database functions
 pub mod db;
 
@@ -33,6 +36,12 @@ pub mod entrypoint;
 /// Error handling
 pub mod error;
 
+/// Structured contract events
+pub mod event;
+
+/// Cryptographic primitives computed by the host
+pub mod host_crypto;
+
 /// Logging infrastructure
 pub mod log;
 
@@ -42,6 +42,20 @@ pub fn get_object_size(object_index: u32) -> i64 {
     unsafe { get_object_size_(object_index) }
 }
 
+/// Read the object returned by a host function, or its error
+pub(crate) fn read_object(ret: i64) -> GenericResult<Vec<u8>> {
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    let obj = ret as u32;
+    let obj_size = get_object_size(obj);
+    let mut buf = vec![0u8; obj_size as usize];
+    get_object_bytes(&mut buf, obj);
+
+    Ok(buf)
+}
+
 /// Auxiliary function to parse db_get and get_slot return value.
 pub(crate) fn parse_ret(ret: i64) -> GenericResult<Option<Vec<u8>>> {
     if ret < 0 {
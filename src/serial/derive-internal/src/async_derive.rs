@@ -163,9 +163,7 @@ pub fn async_enum_ser(input: &ItemEnum, cratename: Ident) -> syn::Result<TokenSt
             };
 
             let mut len = 0;
-            let bytes = variant_idx.to_le_bytes();
-            s.write_all(&bytes).await?;
-            len += bytes.len();
+            len += #cratename::AsyncEncodable::encode_async(&variant_idx, s).await?;
 
             match self {
                 #fields_body
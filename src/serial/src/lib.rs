@@ -982,3 +982,33 @@ mod tests {
         assert_eq!(ts1_n, TestStruct1(baz));
     }
 }
+
+#[cfg(all(test, feature = "async", feature = "derive"))]
+mod async_derive_tests {
+    // `AsyncWriteExt` is deliberately not imported here: the derived async
+    // encoders must not depend on it being in scope at the derive site.
+    use super::{
+        async_trait, deserialize, deserialize_async, serialize, serialize_async, SerialDecodable,
+        SerialEncodable,
+    };
+    use futures_lite::future::block_on;
+
+    #[derive(Debug, PartialEq, SerialEncodable, SerialDecodable)]
+    enum TestEnum {
+        First,
+        Second(u32),
+        Third { foo: String },
+    }
+
+    #[test]
+    fn derive_async_enum_matches_sync_encoding() {
+        let values = [TestEnum::First, TestEnum::Second(42), TestEnum::Third { foo: "bar".into() }];
+
+        for value in values {
+            let bytes = block_on(serialize_async(&value));
+            assert_eq!(bytes, serialize(&value));
+            assert_eq!(block_on(deserialize_async::<TestEnum>(&bytes)).unwrap(), value);
+            assert_eq!(deserialize::<TestEnum>(&bytes).unwrap(), value);
+        }
+    }
+}
@@ -29,8 +29,11 @@ use crate::{
     tx::Transaction,
     util::time::{TimeKeeper, Timestamp},
     validator::{
-        pid::slot_pid_output, pow::PoWModule, utils::block_rank, verify_block, verify_proposal,
-        verify_transactions,
+        mempool::{Mempool, MempoolConfig},
+        pid::slot_pid_output,
+        pow::PoWModule,
+        utils::block_rank,
+        verify_block, verify_proposal, verify_transactions,
     },
     Error, Result,
 };
@@ -55,6 +58,8 @@ pub struct Consensus {
     pub forks: Vec<Fork>,
     /// Canonical blockchain PoW module state
     pub module: PoWModule,
+    /// Pending transactions metadata, used for their prioritisation
+    pub mempool: Mempool,
     /// Flag to enable testing mode
     pub testing_mode: bool,
 }
@@ -67,6 +72,7 @@ impl Consensus {
         finalization_threshold: usize,
         pow_threads: usize,
         pow_target: usize,
+        mempool_config: MempoolConfig,
         testing_mode: bool,
     ) -> Result<Self> {
         let module = PoWModule::new(blockchain.clone(), pow_threads, pow_target)?;
@@ -78,6 +84,7 @@ impl Consensus {
             checked_finalization: 0,
             forks: vec![],
             module,
+            mempool: Mempool::new(mempool_config),
             testing_mode,
         })
     }
@@ -135,29 +142,46 @@ impl Consensus {
         Ok((producers, last_hashes, second_to_last_hashes))
     }
 
-    /// Generate an unsigned block for provided fork, containing all
-    /// pending transactions. This should only be called after generating
-    /// next/current slot.
+    /// Auxiliary function to generate a time keeper for provided
+    /// fork next/current slot.
+    fn fork_time_keeper(&self, fork: &Fork) -> TimeKeeper {
+        // Grab fork's last slot
+        let slot = fork.slots.last().unwrap();
+
+        if slot.id < POS_START {
+            let mut t = self.time_keeper.current();
+            t.verifying_slot = slot.id;
+            t
+        } else {
+            self.time_keeper.current()
+        }
+    }
+
+    /// Retrieve provided fork unproposed transactions, in mempool priority
+    /// order, to be included in its next block. This should only be called
+    /// after generating next/current slot.
+    pub async fn unproposed_txs(&self, fork: &Fork) -> Result<Vec<Transaction>> {
+        let time_keeper = self.fork_time_keeper(fork);
+        fork.unproposed_txs(&self.blockchain, &self.mempool, &time_keeper).await
+    }
+
+    /// Generate an unsigned block for provided fork, containing provided
+    /// transactions, followed by the producer one. This should only be
+    /// called after generating next/current slot.
     pub async fn generate_unsigned_block(
         &self,
         fork: &Fork,
+        mut txs: Vec<Transaction>,
         producer_tx: Transaction,
     ) -> Result<BlockInfo> {
         // Grab fork's last slot
         let slot = fork.slots.last().unwrap();
 
         // Generate a time keeper for next/current slot
-        let time_keeper = if slot.id < POS_START {
-            let mut t = self.time_keeper.current();
-            t.verifying_slot = slot.id;
-            t
-        } else {
-            self.time_keeper.current()
-        };
+        let time_keeper = self.fork_time_keeper(fork);
 
-        // Grab forks' unproposed transactions
-        let mut unproposed_txs = fork.unproposed_txs(&self.blockchain, &time_keeper).await?;
-        unproposed_txs.push(producer_tx);
+        // Append the producer transaction
+        txs.push(producer_tx);
 
         // Grab forks' last block proposal(previous)
         let previous = fork.last_proposal()?;
@@ -176,7 +200,7 @@ impl Consensus {
         let mut block = BlockInfo::new_empty(header, fork.slots.clone());
 
         // Add transactions to the block
-        block.append_txs(unproposed_txs)?;
+        block.append_txs(txs)?;
 
         Ok(block)
     }
@@ -191,7 +215,8 @@ impl Consensus {
         producer_tx: Transaction,
         secret_key: &SecretKey,
     ) -> Result<Proposal> {
-        let mut block = self.generate_unsigned_block(fork, producer_tx).await?;
+        let txs = self.unproposed_txs(fork).await?;
+        let mut block = self.generate_unsigned_block(fork, txs, producer_tx).await?;
 
         // Sign block
         block.sign(secret_key)?;
@@ -522,19 +547,20 @@ impl Fork {
         }
     }
 
-    /// Auxiliary function to retrieve unproposed valid transactions.
+    /// Auxiliary function to retrieve unproposed valid transactions,
+    /// in mempool priority order. Transactions not paying their
+    /// required fee are never proposed.
     pub async fn unproposed_txs(
         &self,
         blockchain: &Blockchain,
+        mempool: &Mempool,
         time_keeper: &TimeKeeper,
     ) -> Result<Vec<Transaction>> {
-        // Retrieve all mempool transactions
-        let mut unproposed_txs: Vec<Transaction> = blockchain
-            .pending_txs
-            .get(&self.mempool, true)?
-            .iter()
-            .map(|x| x.clone().unwrap())
-            .collect();
+        // Retrieve all mempool transactions, highest priority first
+        let mut hashes = self.mempool.clone();
+        mempool.sort_hashes(&mut hashes);
+        let mut unproposed_txs: Vec<Transaction> =
+            blockchain.pending_txs.get(&hashes, true)?.iter().map(|x| x.clone().unwrap()).collect();
 
         // Iterate over fork proposals to find already proposed transactions
         // and remove them from the unproposed_txs vector.
@@ -554,7 +580,8 @@ impl Fork {
         let overlay = self.overlay.lock().unwrap().full_clone()?;
 
         // Verify transactions
-        let erroneous_txs = verify_transactions(&overlay, time_keeper, &unproposed_txs).await?;
+        let erroneous_txs =
+            verify_transactions(&overlay, time_keeper, &unproposed_txs, true).await?;
         if !erroneous_txs.is_empty() {
             unproposed_txs.retain(|x| !erroneous_txs.contains(x));
         }
@@ -0,0 +1,131 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Cursor;
+
+use darkfi_sdk::crypto::MONEY_CONTRACT_ID;
+use darkfi_serial::Decodable;
+
+use crate::{error::TxVerifyFailed, tx::Transaction, Result};
+
+/// Fee charged per serialized transaction byte
+pub const FEE_PER_BYTE: u64 = 10;
+
+/// Fee charged per verified ZK proof
+pub const FEE_PER_ZK_PROOF: u64 = 100_000;
+
+/// Amount of WASM gas covered by a single fee unit
+pub const GAS_PER_FEE_UNIT: u64 = 1_000;
+
+/// Compute the fee a transaction is required to pay, based on its
+/// serialized size, the number of ZK proofs it contains, and the
+/// gas consumed by executing its contract calls.
+pub fn compute_fee(size: u64, zk_proofs: u64, gas_used: u64) -> u64 {
+    let gas_fee = gas_used / GAS_PER_FEE_UNIT + (gas_used % GAS_PER_FEE_UNIT != 0) as u64;
+    size.saturating_mul(FEE_PER_BYTE)
+        .saturating_add(zk_proofs.saturating_mul(FEE_PER_ZK_PROOF))
+        .saturating_add(gas_fee)
+}
+
+/// Retrieve the fee value paid by a transaction in its `Money::Fee` call,
+/// if it has one. A transaction must not contain more than one fee call.
+pub fn tx_fee(tx: &Transaction) -> Result<Option<u64>> {
+    let mut fee = None;
+    for call in &tx.calls {
+        // Money::Fee(0x00) calls params start with the clear fee value
+        if call.contract_id != *MONEY_CONTRACT_ID || call.data.first() != Some(&0x00) {
+            continue
+        }
+
+        if fee.is_some() {
+            return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
+        }
+
+        let mut decoder = Cursor::new(&call.data[1..]);
+        fee = Some(u64::decode(&mut decoder)?);
+    }
+
+    Ok(fee)
+}
+
+/// Compute the total fees paid by the provided transactions.
+pub fn txs_fees(txs: &[Transaction]) -> Result<u64> {
+    let mut total: u64 = 0;
+    for tx in txs {
+        let Some(fee) = tx_fee(tx)? else { continue };
+        total = total.checked_add(fee).ok_or(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]))?;
+    }
+
+    Ok(total)
+}
+
+/// Retrieve the collected fees claimed by a `Money::PoWReward` producer
+/// transaction, which are the last field of its call params.
+pub fn pow_reward_fees(tx: &Transaction) -> Result<u64> {
+    let data = &tx.calls[0].data;
+    if data.len() < 9 {
+        return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
+    }
+
+    let mut decoder = Cursor::new(&data);
+    decoder.set_position((data.len() - 8) as u64);
+    Ok(u64::decode(&mut decoder)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi_sdk::{crypto::DAO_CONTRACT_ID, ContractCall};
+    use darkfi_serial::serialize;
+
+    #[test]
+    fn fee_computation() -> Result<()> {
+        assert_eq!(compute_fee(0, 0, 0), 0);
+        assert_eq!(compute_fee(100, 2, 1_001), 100 * FEE_PER_BYTE + 2 * FEE_PER_ZK_PROOF + 2);
+        assert_eq!(compute_fee(u64::MAX, 1, 0), u64::MAX);
+
+        let fee_call = |value: u64| {
+            let mut data = vec![0x00];
+            data.extend(serialize(&value));
+            ContractCall { contract_id: *MONEY_CONTRACT_ID, data }
+        };
+        let other_call = ContractCall { contract_id: *DAO_CONTRACT_ID, data: vec![0x00, 1, 2] };
+
+        let mut tx = Transaction { calls: vec![other_call], ..Default::default() };
+        assert_eq!(tx_fee(&tx)?, None);
+
+        tx.calls.push(fee_call(42));
+        assert_eq!(tx_fee(&tx)?, Some(42));
+        assert_eq!(txs_fees(&[tx.clone(), tx.clone(), Transaction::default()])?, 84);
+
+        // Multiple fee calls are not allowed
+        tx.calls.push(fee_call(1));
+        assert!(tx_fee(&tx).is_err());
+
+        // Reward fees are the last params field
+        let mut data = vec![0x08, 1, 2, 3];
+        data.extend(serialize(&1337u64));
+        let reward = Transaction {
+            calls: vec![ContractCall { contract_id: *MONEY_CONTRACT_ID, data }],
+            ..Default::default()
+        };
+        assert_eq!(pow_reward_fees(&reward)?, 1337);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use darkfi_serial::serialize;
+
+use crate::{
+    blockchain::BlockchainOverlayPtr,
+    tx::Transaction,
+    util::time::{TimeKeeper, Timestamp},
+    validator::verification::verify_transactions,
+    Error, Result,
+};
+
+/// Default maximum number of pending transactions
+pub const MEMPOOL_MAX_TXS: usize = 1000;
+
+/// Default maximum total size of pending transactions, in bytes
+pub const MEMPOOL_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// Default time after which pending transactions expire, in seconds
+pub const MEMPOOL_EXPIRY: u64 = 60 * 60;
+
+/// Configuration for the [`Mempool`] caps
+#[derive(Clone, Debug)]
+pub struct MempoolConfig {
+    /// Maximum number of pending transactions
+    pub max_txs: usize,
+    /// Maximum total size of pending transactions, in bytes
+    pub max_size: usize,
+    /// Time after which pending transactions expire, in seconds
+    pub expiry: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self { max_txs: MEMPOOL_MAX_TXS, max_size: MEMPOOL_MAX_SIZE, expiry: MEMPOOL_EXPIRY }
+    }
+}
+
+/// Metadata of a pending transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MempoolEntry {
+    /// Transaction hash
+    pub hash: blake3::Hash,
+    /// Serialized transaction size, in bytes
+    pub size: usize,
+    /// Fee paid by the transaction
+    pub fee: u64,
+    /// Time the transaction was received
+    pub received: Timestamp,
+    /// State keys created by the transaction, in the form of a tuple
+    /// (`tree`, `key`), such as its revealed nullifiers. Two transactions
+    /// creating the same key conflict with each other.
+    pub spends: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl MempoolEntry {
+    pub fn new(tx: &Transaction, fee: u64, spends: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        let serialized = serialize(tx);
+        Self {
+            hash: blake3::hash(&serialized),
+            size: serialized.len(),
+            fee,
+            received: Timestamp::current_time(),
+            spends,
+        }
+    }
+
+    /// Compare entries by priority, highest first: by fee density (fee per
+    /// byte), then by age, oldest first, and then by hash.
+    pub fn priority_cmp(&self, other: &Self) -> Ordering {
+        let density = self.fee as u128 * other.size as u128;
+        let other_density = other.fee as u128 * self.size as u128;
+
+        other_density
+            .cmp(&density)
+            .then(self.received.0.cmp(&other.received.0))
+            .then(self.hash.as_bytes().cmp(other.hash.as_bytes()))
+    }
+}
+
+/// Pending transactions pool, tracking their metadata and the state keys
+/// they create, so conflicting transactions (double-spends) are rejected
+/// upfront. The pool is kept within its configured caps by evicting the
+/// lowest priority transactions.
+#[derive(Clone, Debug)]
+pub struct Mempool {
+    /// Mempool caps configuration
+    pub config: MempoolConfig,
+    /// Pending transactions metadata, keyed by their hash
+    entries: HashMap<blake3::Hash, MempoolEntry>,
+    /// Created state keys, mapped to the pending transaction creating them
+    spends: HashMap<(Vec<u8>, Vec<u8>), blake3::Hash>,
+    /// Total size of pending transactions
+    size: usize,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Self { config, entries: HashMap::new(), spends: HashMap::new(), size: 0 }
+    }
+
+    /// Retrieve the metadata of a pending transaction.
+    pub fn get(&self, tx_hash: &blake3::Hash) -> Option<&MempoolEntry> {
+        self.entries.get(tx_hash)
+    }
+
+    /// Retrieve all pending transactions metadata, in priority order.
+    pub fn entries(&self) -> Vec<&MempoolEntry> {
+        let mut entries: Vec<&MempoolEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| a.priority_cmp(b));
+        entries
+    }
+
+    /// Sort given transaction hashes in priority order. Hashes not
+    /// in the pool are kept last, in their original order.
+    pub fn sort_hashes(&self, hashes: &mut [blake3::Hash]) {
+        hashes.sort_by(|a, b| match (self.entries.get(a), self.entries.get(b)) {
+            (Some(a), Some(b)) => a.priority_cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    }
+
+    /// Find the pending transaction conflicting with given entry, if any.
+    pub fn conflict(&self, entry: &MempoolEntry) -> Option<blake3::Hash> {
+        entry.spends.iter().find_map(|spend| self.spends.get(spend).copied())
+    }
+
+    /// Insert a transaction entry in the pool. Returns the hashes of the
+    /// transactions evicted to keep the pool within its caps. Fails if the
+    /// transaction conflicts with a pending one, or if the pool is full
+    /// and it has the lowest priority.
+    pub fn insert(&mut self, entry: MempoolEntry) -> Result<Vec<blake3::Hash>> {
+        if let Some(conflict) = self.conflict(&entry) {
+            return Err(Error::MempoolConflict(conflict.to_string()))
+        }
+
+        let hash = entry.hash;
+        self.insert_unchecked(entry);
+
+        let mut evicted = vec![];
+        while self.entries.len() > self.config.max_txs || self.size > self.config.max_size {
+            let lowest = self.entries.values().max_by(|a, b| a.priority_cmp(b)).unwrap().hash;
+            let removed = self.remove(&lowest).unwrap();
+
+            // If there is no room for the new entry, restore the evicted ones
+            if lowest == hash {
+                for entry in evicted {
+                    self.insert_unchecked(entry);
+                }
+                return Err(Error::MempoolFull)
+            }
+            evicted.push(removed);
+        }
+
+        Ok(evicted.iter().map(|entry| entry.hash).collect())
+    }
+
+    /// Insert a transaction entry in the pool, without checking for
+    /// conflicts or caps.
+    fn insert_unchecked(&mut self, entry: MempoolEntry) {
+        self.size += entry.size;
+        for spend in &entry.spends {
+            self.spends.insert(spend.clone(), entry.hash);
+        }
+        self.entries.insert(entry.hash, entry);
+    }
+
+    /// Remove a transaction from the pool, returning its entry if it existed.
+    pub fn remove(&mut self, tx_hash: &blake3::Hash) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(tx_hash)?;
+        self.size -= entry.size;
+        for spend in &entry.spends {
+            self.spends.remove(spend);
+        }
+
+        Some(entry)
+    }
+
+    /// Retrieve the hashes of the pending transactions received before
+    /// the configured expiry, relative to given timestamp.
+    pub fn expired(&self, now: &Timestamp) -> Vec<blake3::Hash> {
+        self.entries
+            .values()
+            .filter(|entry| now.0.saturating_sub(entry.received.0) > self.config.expiry)
+            .map(|entry| entry.hash)
+            .collect()
+    }
+
+    /// Retrieve pending transactions count
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Retrieve pending transactions total size, in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Verify a pending transaction, including its fee, against provided overlay,
+/// returning the state keys it creates if it is valid. The overlay is left modified by the
+/// transaction, so callers should provide a throwaway one.
+pub async fn verify_mempool_tx(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    tx: &Transaction,
+) -> Result<Option<Vec<(Vec<u8>, Vec<u8>)>>> {
+    overlay.lock().unwrap().overlay.lock().unwrap().track_created_keys();
+
+    let erroneous_txs = verify_transactions(overlay, time_keeper, &[tx.clone()], true).await?;
+    if !erroneous_txs.is_empty() {
+        return Ok(None)
+    }
+
+    Ok(Some(overlay.lock().unwrap().overlay.lock().unwrap().created_keys()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u8, fee: u64, size: usize, received: u64, spends: &[u8]) -> MempoolEntry {
+        MempoolEntry {
+            hash: blake3::hash(&[id]),
+            size,
+            fee,
+            received: Timestamp(received),
+            spends: spends.iter().map(|s| (b"nullifiers".to_vec(), vec![*s])).collect(),
+        }
+    }
+
+    #[test]
+    fn mempool_conflicts_and_eviction() -> Result<()> {
+        let config = MempoolConfig { max_txs: 3, max_size: 1000, expiry: 100 };
+        let mut mempool = Mempool::new(config);
+
+        let a = entry(0, 0, 100, 10, &[1]);
+        let b = entry(1, 50, 100, 20, &[2, 3]);
+        let c = entry(2, 50, 200, 30, &[4]);
+        assert!(mempool.insert(a.clone())?.is_empty());
+        assert!(mempool.insert(b.clone())?.is_empty());
+        assert!(mempool.insert(c.clone())?.is_empty());
+        assert_eq!(mempool.size(), 400);
+
+        // Highest fee density first, then oldest
+        let order: Vec<_> = mempool.entries().iter().map(|e| e.hash).collect();
+        assert_eq!(order, vec![b.hash, c.hash, a.hash]);
+
+        // Double-spends are rejected
+        assert!(mempool.insert(entry(3, 1000, 100, 40, &[3])).is_err());
+        assert_eq!(mempool.conflict(&entry(3, 0, 1, 0, &[9, 2])), Some(b.hash));
+
+        // Lowest priority transactions get evicted when full
+        let d = entry(4, 10, 100, 50, &[5]);
+        assert_eq!(mempool.insert(d.clone())?, vec![a.hash]);
+        assert!(mempool.get(&a.hash).is_none());
+        assert!(mempool.conflict(&entry(5, 0, 1, 0, &[1])).is_none());
+
+        // A transaction with the lowest priority can't get in
+        assert!(mempool.insert(entry(6, 0, 100, 60, &[6])).is_err());
+        assert_eq!(mempool.len(), 3);
+
+        // Size cap is enforced too
+        let e = entry(7, 1000, 500, 70, &[7]);
+        assert_eq!(mempool.insert(e.clone())?, vec![d.hash]);
+        assert_eq!(mempool.size(), 800);
+
+        // Entries evicted for a transaction that doesn't fit are restored
+        assert!(mempool.insert(entry(9, 600, 990, 90, &[9])).is_err());
+        assert_eq!(mempool.len(), 3);
+        assert_eq!(mempool.size(), 800);
+        assert!(mempool.get(&b.hash).is_some());
+
+        // Expiry
+        assert_eq!(mempool.expired(&Timestamp(125)), vec![b.hash]);
+        let mut hashes = vec![blake3::hash(b"unknown"), c.hash, e.hash, b.hash];
+        mempool.sort_hashes(&mut hashes);
+        assert_eq!(hashes, vec![e.hash, b.hash, c.hash, blake3::hash(b"unknown")]);
+
+        assert!(mempool.remove(&b.hash).is_some());
+        assert!(mempool.insert(entry(8, 0, 1, 80, &[3])).is_ok());
+
+        Ok(())
+    }
+}
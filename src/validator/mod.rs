@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use darkfi_sdk::{
     blockchain::{expected_reward, Slot},
@@ -29,11 +29,12 @@ use smol::lock::RwLock;
 use crate::{
     blockchain::{
         block_store::{BlockDifficulty, BlockInfo},
-        Blockchain, BlockchainOverlay,
+        Blockchain, BlockchainOverlay, Header, WasmStore,
     },
     error::TxVerifyFailed,
+    runtime::module_cache::MODULE_CACHE,
     tx::Transaction,
-    util::time::TimeKeeper,
+    util::time::{TimeKeeper, Timestamp},
     Error, Result,
 };
 
@@ -41,6 +42,14 @@ use crate::{
 pub mod consensus;
 use consensus::{Consensus, Proposal};
 
+/// Transaction fees computation
+pub mod fees;
+use fees::{compute_fee, tx_fee};
+
+/// Pending transactions pool
+pub mod mempool;
+use mempool::{verify_mempool_tx, Mempool, MempoolConfig, MempoolEntry};
+
 /// DarkFi PoW module
 pub mod pow;
 use pow::PoWModule;
@@ -50,9 +59,10 @@ pub mod pid;
 
 /// Verification functions
 pub mod verification;
+use validation::validate_pow_header;
 use verification::{
     verify_block, verify_genesis_block, verify_producer_transaction, verify_proposal,
-    verify_transactions,
+    verify_transaction, verify_transactions,
 };
 
 /// Validation functions
@@ -82,6 +92,15 @@ pub struct ValidatorConfig {
     pub genesis_txs_total: u64,
     /// Whitelisted faucet pubkeys (testnet stuff)
     pub faucet_pubkeys: Vec<PublicKey>,
+    /// Pending transactions pool caps
+    pub mempool: MempoolConfig,
+    /// Flag to enable fees verification of transactions added through
+    /// [`Validator::add_transactions`]. Blocks, proposals and pending
+    /// transactions always have their fees verified.
+    pub verify_fees: bool,
+    /// Number of most recent finalized blocks whose state diffs are kept,
+    /// so they can be rolled back
+    pub journal_depth: u64,
     /// Flag to enable testing mode
     pub testing_mode: bool,
 }
@@ -106,6 +125,9 @@ impl ValidatorConfig {
             genesis_block,
             genesis_txs_total,
             faucet_pubkeys,
+            mempool: MempoolConfig::default(),
+            verify_fees: true,
+            journal_depth: 1000,
             testing_mode,
         }
     }
@@ -122,6 +144,12 @@ pub struct Validator {
     pub consensus: Consensus,
     /// Flag signalling node has finished initial sync
     pub synced: bool,
+    /// Flag signalling node only syncs and verifies block headers
+    pub light: bool,
+    /// Flag to enable fees verification in [`Validator::add_transactions`]
+    pub verify_fees: bool,
+    /// Number of most recent finalized blocks whose state diffs are kept
+    pub journal_depth: u64,
     /// Flag to enable testing mode
     pub testing_mode: bool,
 }
@@ -162,12 +190,20 @@ impl Validator {
             config.finalization_threshold,
             config.pow_threads,
             config.pow_target,
+            config.mempool,
             testing_mode,
         )?;
 
         // Create the actual state
-        let state =
-            Arc::new(RwLock::new(Self { blockchain, consensus, synced: false, testing_mode }));
+        let state = Arc::new(RwLock::new(Self {
+            blockchain,
+            consensus,
+            synced: false,
+            light: false,
+            verify_fees: config.verify_fees,
+            journal_depth: config.journal_depth,
+            testing_mode,
+        }));
         info!(target: "validator::new", "Finished initializing validator");
 
         Ok(state)
@@ -189,52 +225,52 @@ impl Validator {
 
         // Verify state transition
         info!(target: "validator::append_tx", "Starting state transition validation");
-        let tx_vec = [tx.clone()];
-        let mut valid = false;
 
         // Generate a time keeper for current slot
         let time_keeper = self.consensus.time_keeper.current();
 
-        // If node participates in consensus and holds any forks, iterate over them
-        // to verify transaction validity in their overlays
-        for fork in self.consensus.forks.iter_mut() {
-            // Clone forks' overlay
-            let overlay = fork.overlay.lock().unwrap().full_clone()?;
-
-            // Verify transaction
-            let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec).await?;
-            if !erroneous_txs.is_empty() {
-                continue
-            }
-            valid = true;
+        // Verify transaction against forks and canonical state
+        let (valid_forks, spends) = self.verify_pending_tx(tx, &time_keeper).await?;
 
-            // Store transaction hash in forks' mempool
-            fork.mempool.push(tx_hash);
-        }
+        // Return error if transaction is not valid for canonical or any fork
+        let Some(spends) = spends else {
+            return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
+        };
 
-        // Verify transaction against canonical state
-        let overlay = BlockchainOverlay::new(&self.blockchain)?;
-        let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec).await?;
-        if erroneous_txs.is_empty() {
-            valid = true
-        }
+        // Insert transaction in the mempool, rejecting it if it conflicts
+        // with a pending one or if there is no room for it
+        let fee = tx_fee(tx)?.unwrap_or_default();
+        let evicted = self.consensus.mempool.insert(MempoolEntry::new(tx, fee, spends))?;
 
-        // Return error if transaction is not valid for canonical or any fork
-        if !valid {
-            return Err(TxVerifyFailed::ErroneousTxs(erroneous_txs).into())
+        // Store transaction hash in valid forks' mempool
+        for index in valid_forks {
+            self.consensus.forks[index].mempool.push(tx_hash);
         }
 
         // Add transaction to pending txs store
-        self.blockchain.add_pending_txs(&tx_vec)?;
+        self.blockchain.add_pending_txs(&[tx.clone()])?;
         info!(target: "validator::append_tx", "Appended tx to pending txs store");
 
+        // Remove evicted transactions
+        if !evicted.is_empty() {
+            info!(target: "validator::append_tx", "Evicting {} pending transactions", evicted.len());
+            self.remove_pending_txs(&evicted)?;
+        }
+
         Ok(())
     }
 
-    /// The node removes invalid transactions from the pending txs store.
+    /// The node removes invalid, conflicting and expired transactions from
+    /// the pending txs store, and rebuilds the mempool to keep it within its caps.
     pub async fn purge_pending_txs(&mut self) -> Result<()> {
         info!(target: "validator::purge_pending_txs", "Removing invalid transactions from pending transactions store...");
 
+        // Reset the mempool, keeping its previous state to preserve entries metadata
+        let previous = std::mem::replace(
+            &mut self.consensus.mempool,
+            Mempool::new(self.consensus.mempool.config.clone()),
+        );
+
         // Check if any pending transactions exist
         let pending_txs = self.blockchain.get_pending_txs()?;
         if pending_txs.is_empty() {
@@ -244,49 +280,117 @@ impl Validator {
 
         // Generate a time keeper for current slot
         let time_keeper = self.consensus.time_keeper.current();
+        let now = Timestamp::current_time();
 
+        let mut mempool = Mempool::new(previous.config.clone());
         let mut removed_txs = vec![];
         for tx in pending_txs {
-            let tx_hash = &blake3::hash(&serialize(&tx));
-            let tx_vec = [tx.clone()];
-            let mut valid = false;
-
-            // If node participates in consensus and holds any forks, iterate over them
-            // to verify transaction validity in their overlays
-            for fork in self.consensus.forks.iter_mut() {
-                // Clone forks' overlay
-                let overlay = fork.overlay.lock().unwrap().full_clone()?;
-
-                // Verify transaction
-                let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec).await?;
-                if erroneous_txs.is_empty() {
-                    valid = true;
-                    continue
+            let tx_hash = blake3::hash(&serialize(&tx));
+
+            // Verify transaction against forks and canonical state
+            let (valid_forks, spends) = self.verify_pending_tx(&tx, &time_keeper).await?;
+
+            // Remove erroneous transaction from forks' mempool
+            for (index, fork) in self.consensus.forks.iter_mut().enumerate() {
+                if !valid_forks.contains(&index) {
+                    fork.mempool.retain(|x| x != &tx_hash);
                 }
+            }
+
+            // Remove pending transaction if it's not valid for canonical or any fork
+            let Some(spends) = spends else {
+                removed_txs.push(tx_hash);
+                continue
+            };
 
-                // Remove erroneous transaction from forks' mempool
-                fork.mempool.retain(|x| x != tx_hash);
+            let mut entry = MempoolEntry::new(&tx, tx_fee(&tx)?.unwrap_or_default(), spends);
+            if let Some(previous) = previous.get(&tx_hash) {
+                entry.received = previous.received;
             }
 
-            // Verify transaction against canonical state
-            let overlay = BlockchainOverlay::new(&self.blockchain)?;
-            let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec).await?;
-            if erroneous_txs.is_empty() {
-                valid = true
+            // Remove pending transaction if it has expired
+            if now.0.saturating_sub(entry.received.0) > mempool.config.expiry {
+                removed_txs.push(tx_hash);
+                continue
             }
 
-            // Remove pending transaction if it's not valid for canonical or any fork
-            if !valid {
-                removed_txs.push(tx)
+            // Remove pending transaction if it conflicts with an older one,
+            // along with the ones evicted to make room for it
+            match mempool.insert(entry) {
+                Ok(evicted) => removed_txs.extend(evicted),
+                Err(_) => removed_txs.push(tx_hash),
             }
         }
+        self.consensus.mempool = mempool;
 
         if removed_txs.is_empty() {
             info!(target: "validator::purge_pending_txs", "No erroneous transactions found");
             return Ok(())
         }
         info!(target: "validator::purge_pending_txs", "Removing {} erroneous transactions...", removed_txs.len());
-        self.blockchain.remove_pending_txs(&removed_txs)?;
+        self.remove_pending_txs(&removed_txs)?;
+
+        Ok(())
+    }
+
+    /// Auxiliary function to verify a pending transaction against all forks
+    /// and the canonical state. Returns the indexes of the forks it is valid
+    /// for, along with the state keys it creates, if it is valid for any of them.
+    async fn verify_pending_tx(
+        &self,
+        tx: &Transaction,
+        time_keeper: &TimeKeeper,
+    ) -> Result<(Vec<usize>, Option<Vec<(Vec<u8>, Vec<u8>)>>)> {
+        let mut valid_forks = vec![];
+        let mut spends: Option<Vec<(Vec<u8>, Vec<u8>)>> = None;
+
+        // If node participates in consensus and holds any forks, iterate over them
+        // to verify transaction validity in their overlays
+        for (index, fork) in self.consensus.forks.iter().enumerate() {
+            // Clone forks' overlay
+            let overlay = fork.overlay.lock().unwrap().full_clone()?;
+
+            // Verify transaction
+            let Some(created) = verify_mempool_tx(&overlay, time_keeper, tx).await? else {
+                continue
+            };
+            valid_forks.push(index);
+
+            let spends = spends.get_or_insert_with(Vec::new);
+            for key in created {
+                if !spends.contains(&key) {
+                    spends.push(key);
+                }
+            }
+        }
+
+        // Verify transaction against canonical state
+        let overlay = BlockchainOverlay::new(&self.blockchain)?;
+        if let Some(created) = verify_mempool_tx(&overlay, time_keeper, tx).await? {
+            let spends = spends.get_or_insert_with(Vec::new);
+            for key in created {
+                if !spends.contains(&key) {
+                    spends.push(key);
+                }
+            }
+        }
+
+        Ok((valid_forks, spends))
+    }
+
+    /// Remove given transactions from the pending txs store,
+    /// the mempool and all forks' mempools.
+    pub fn remove_pending_txs(&mut self, tx_hashes: &[blake3::Hash]) -> Result<()> {
+        let txs: Vec<Transaction> =
+            self.blockchain.pending_txs.get(tx_hashes, false)?.into_iter().flatten().collect();
+        self.blockchain.remove_pending_txs(&txs)?;
+
+        for tx_hash in tx_hashes {
+            self.consensus.mempool.remove(tx_hash);
+        }
+        for fork in self.consensus.forks.iter_mut() {
+            fork.mempool.retain(|x| !tx_hashes.contains(x));
+        }
 
         Ok(())
     }
@@ -419,6 +523,23 @@ impl Validator {
         debug!(target: "validator::add_blocks", "Applying overlay changes");
         overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
 
+        // Drop the compiled modules of contracts redeployed by the added blocks,
+        // now that their previous bincode can no longer be used.
+        let heights: Vec<u64> = blocks.iter().map(|block| block.header.height).collect();
+        for diff in self.blockchain.journal.get(&heights, false)?.into_iter().flatten() {
+            for contract_id in WasmStore::redeployed(&diff)? {
+                let bincode = self.blockchain.wasm_bincode.get(contract_id)?;
+                MODULE_CACHE.invalidate(&contract_id, &bincode);
+            }
+        }
+
+        // Added blocks are finalized, so only the state diffs of the most
+        // recent ones have to be kept for rollbacks.
+        let (last, _) = self.blockchain.last()?;
+        if let Some(height) = last.checked_sub(self.journal_depth) {
+            self.blockchain.journal.prune(height)?;
+        }
+
         // Purge pending erroneous txs since canonical state has been changed
         self.blockchain.remove_pending_txs(&removed_txs)?;
         self.purge_pending_txs().await?;
@@ -429,6 +550,60 @@ impl Validator {
         Ok(())
     }
 
+    /// Validate a set of [`Header`] in sequence and apply them if all are valid.
+    /// Used by light nodes, which only keep track of the headers chain and its
+    /// proof of work, without verifying or storing block bodies.
+    pub async fn add_headers(&mut self, headers: &[Header]) -> Result<()> {
+        debug!(target: "validator::add_headers", "Instantiating BlockchainOverlay");
+        let overlay = BlockchainOverlay::new(&self.blockchain)?;
+
+        // Retrieve last header
+        let (_, last) = overlay.lock().unwrap().last()?;
+        let mut previous = overlay.lock().unwrap().headers.get(&[last], true)?[0].clone().unwrap();
+
+        // Create a PoW module to validate each header
+        let mut module = self.consensus.module.clone();
+
+        // Validate and insert each header
+        for header in headers {
+            overlay.lock().unwrap().overlay.lock().unwrap().begin_block(header.height);
+
+            if validate_pow_header(header, &previous, &module).is_err() {
+                error!(target: "validator::add_headers", "Erroneous header found in set");
+                overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
+                return Err(Error::BlockIsInvalid(header.hash()?.to_string()))
+            }
+
+            // Store header and its order
+            let lock = overlay.lock().unwrap();
+            let hashes = lock.headers.insert(&[header.clone()])?;
+            lock.order.insert(&[header.height], &hashes)?;
+            drop(lock);
+
+            // Update PoW module
+            let difficulty = module.next_difficulty()?;
+            let cummulative_difficulty = module.cummulative_difficulty.clone() + difficulty.clone();
+            let block_difficulty = BlockDifficulty::new(
+                header.height,
+                header.timestamp.0,
+                difficulty,
+                cummulative_difficulty,
+            );
+            module.append_difficulty(&overlay, block_difficulty)?;
+
+            // Use last inserted header as next iteration previous
+            previous = header.clone();
+        }
+
+        debug!(target: "validator::add_headers", "Applying overlay changes");
+        overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
+
+        // Update PoW module
+        self.consensus.module = module;
+
+        Ok(())
+    }
+
     /// Validate a set of [`Transaction`] in sequence and apply them if all are valid.
     /// In case any of the transactions fail, they will be returned to the caller.
     /// The function takes a boolean called `write` which tells it to actually write
@@ -451,7 +626,8 @@ impl Validator {
         );
 
         // Verify all transactions and get erroneous ones
-        let erroneous_txs = verify_transactions(&overlay, &time_keeper, txs).await?;
+        let erroneous_txs =
+            verify_transactions(&overlay, &time_keeper, txs, self.verify_fees).await?;
 
         let lock = overlay.lock().unwrap();
         let mut overlay = lock.overlay.lock().unwrap();
@@ -472,6 +648,43 @@ impl Validator {
         Ok(())
     }
 
+    /// Simulate the execution of a [`Transaction`] on top of the canonical state,
+    /// without applying it. Since the transaction is usually simulated in order
+    /// to figure out its fee, it is not required to pay one. On success, the total
+    /// gas consumed by its contract calls is returned, along with the fee it must pay.
+    pub async fn simulate_transaction(
+        &self,
+        tx: &Transaction,
+        verifying_slot: u64,
+    ) -> Result<(u64, u64)> {
+        debug!(target: "validator::simulate_transaction", "Instantiating BlockchainOverlay");
+        let overlay = BlockchainOverlay::new(&self.blockchain)?;
+
+        // Generate a time keeper using transaction verifying slot
+        let time_keeper = TimeKeeper::new(
+            self.consensus.time_keeper.genesis_ts,
+            self.consensus.time_keeper.epoch_length,
+            self.consensus.time_keeper.slot_time,
+            verifying_slot,
+        );
+
+        // Map of ZK proof verifying keys for the transaction
+        let mut vks = HashMap::new();
+        for call in &tx.calls {
+            vks.insert(call.contract_id.to_bytes(), HashMap::new());
+        }
+
+        let result = verify_transaction(&overlay, &time_keeper, tx, &mut vks, false).await;
+
+        overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
+        let gas_used = result?;
+
+        let zk_proofs = tx.proofs.iter().map(|x| x.len() as u64).sum();
+        let fee = compute_fee(serialize(tx).len() as u64, zk_proofs, gas_used);
+
+        Ok((gas_used, fee))
+    }
+
     /// Append to canonical state received slot.
     /// This should be only used for test purposes.
     pub async fn receive_test_slot(&mut self, slot: &Slot) -> Result<()> {
@@ -37,7 +37,7 @@ use smol::channel::Receiver;
 use crate::{
     blockchain::{
         block_store::{BlockDifficulty, BlockInfo},
-        Blockchain, BlockchainOverlayPtr,
+        Blockchain, BlockchainOverlayPtr, Header,
     },
     util::{ringbuffer::RingBuffer, time::Timestamp},
     validator::utils::median,
@@ -216,21 +216,35 @@ impl PoWModule {
 
     /// Verify provided block corresponds to next mine target
     pub fn verify_block_hash(&self, block: &BlockInfo) -> Result<()> {
+        self.verify_header_hash(&block.header)
+    }
+
+    /// Verify provided header corresponds to next mine target
+    pub fn verify_header_hash(&self, header: &Header) -> Result<()> {
         // Then we verify the proof of work:
         let verifier_setup = Instant::now();
 
         // Grab the next mine target
         let target = self.next_mine_target()?;
 
+        // Merge mined headers are verified against the Monero block
+        // committing to them, otherwise we hash the header itself.
+        // Merge mined blocks are hashed with the miner provided key,
+        // see `MoneroPowData::seed_hash` on why that is sound.
+        let (key, input) = match header.verify_pow_data()? {
+            Some(blob) => (header.pow_data.as_ref().unwrap().seed_hash.to_vec(), blob),
+            None => (header.previous.as_bytes().to_vec(), header.hash()?.as_bytes().to_vec()),
+        };
+
         // Setup verifier
         let flags = RandomXFlags::default();
-        let cache = RandomXCache::new(flags, block.header.previous.as_bytes()).unwrap();
+        let cache = RandomXCache::new(flags, &key).unwrap();
         let vm = RandomXVM::new(flags, &cache).unwrap();
         debug!(target: "validator::pow::verify_block", "[VERIFIER] Setup time: {:?}", verifier_setup.elapsed());
 
         // Compute the output hash
         let verification_time = Instant::now();
-        let out_hash = vm.hash(block.hash()?.as_bytes());
+        let out_hash = vm.hash(&input);
         let out_hash = BigUint::from_bytes_be(&out_hash);
 
         // Verify hash is less than the expected mine target
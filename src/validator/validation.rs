@@ -22,7 +22,7 @@ use darkfi_sdk::{
 };
 
 use crate::{
-    blockchain::{BlockInfo, Blockchain},
+    blockchain::{BlockInfo, Blockchain, Header},
     validator::{pid::slot_pid_output, pow::PoWModule},
     Error, Result,
 };
@@ -45,13 +45,9 @@ pub fn validate_block(
 }
 
 /// A PoW block is considered valid when the following rules apply:
-///     1. Block version is equal to 1
-///     2. Parent hash is equal to the hash of the previous block
-///     3. Block heigh increments previous block height by 1
-///     4. Timestamp is valid based on PoWModule validation
-///     5. Block hash is valid based on PoWModule validation
-///     6. Slots vector contains a single valid slot
-///     7. Block height is the same as the slots vector last slot id
+///     1. Its header is valid based on [`validate_pow_header`]
+///     2. Slots vector contains a single valid slot
+///     3. Block height is the same as the slots vector last slot id
 /// Additional validity rules can be applied.
 pub fn validate_pow_block(
     block: &BlockInfo,
@@ -61,31 +57,11 @@ pub fn validate_pow_block(
 ) -> Result<()> {
     let error = Err(Error::BlockIsInvalid(block.hash()?.to_string()));
 
-    // Check block version (1)
-    if block.header.version != 1 {
-        return error
-    }
-
-    // Check previous hash (2)
+    // Check block header (1)
+    validate_pow_header(&block.header, &previous.header, module)?;
     let previous_hash = previous.hash()?;
-    if block.header.previous != previous_hash {
-        return error
-    }
 
-    // Check heights are incremental (3)
-    if block.header.height != previous.header.height + 1 {
-        return error
-    }
-
-    // Check timestamp validity (4)
-    if !module.verify_timestamp_by_median(block.header.timestamp.0) {
-        return error
-    }
-
-    // Check block hash corresponds to next one (5)
-    module.verify_block_hash(block)?;
-
-    // Verify slots vector contains single slot (6)
+    // Verify slots vector contains single slot (2)
     if block.slots.len() != 1 {
         return error
     }
@@ -104,7 +80,7 @@ pub fn validate_pow_block(
         expected_reward,
     )?;
 
-    // Check block height is the last slot id (7)
+    // Check block height is the last slot id (3)
     if last_slot.id != block.header.height {
         return error
     }
@@ -112,6 +88,40 @@ pub fn validate_pow_block(
     Ok(())
 }
 
+/// A PoW header is considered valid when the following rules apply:
+///     1. Header version is equal to 1
+///     2. Previous hash is equal to the hash of the previous header
+///     3. Header height increments previous header height by 1
+///     4. Timestamp is valid based on PoWModule validation
+///     5. Header hash is valid based on PoWModule validation
+/// Additional validity rules can be applied.
+pub fn validate_pow_header(header: &Header, previous: &Header, module: &PoWModule) -> Result<()> {
+    let error = Err(Error::BlockIsInvalid(header.hash()?.to_string()));
+
+    // Check header version (1)
+    if header.version != 1 {
+        return error
+    }
+
+    // Check previous hash (2)
+    if header.previous != previous.hash()? {
+        return error
+    }
+
+    // Check heights are incremental (3)
+    if header.height != previous.height + 1 {
+        return error
+    }
+
+    // Check timestamp validity (4)
+    if !module.verify_timestamp_by_median(header.timestamp.0) {
+        return error
+    }
+
+    // Check header hash corresponds to next one (5)
+    module.verify_header_hash(header)
+}
+
 /// A PoW slot is considered valid when the following rules apply:
 ///     1. Id increments previous slot id by 1
 ///     2. Forks extend previous block hash
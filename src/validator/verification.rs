@@ -16,14 +16,17 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
 
 use darkfi_sdk::{
     blockchain::{block_version, expected_reward},
     crypto::{schnorr::SchnorrPublic, PublicKey, CONSENSUS_CONTRACT_ID, MONEY_CONTRACT_ID},
     pasta::pallas,
 };
-use darkfi_serial::{Decodable, Encodable, WriteExt};
+use darkfi_serial::{serialize, Decodable, Encodable, WriteExt};
 use log::{debug, error, warn};
 
 use crate::{
@@ -34,10 +37,11 @@ use crate::{
     util::time::TimeKeeper,
     validator::{
         consensus::{Consensus, Fork, Proposal, TXS_CAP},
+        fees::{compute_fee, pow_reward_fees, tx_fee, txs_fees},
         pow::PoWModule,
         validation::validate_block,
     },
-    zk::VerifyingKey,
+    zk::{Proof, VerifyingKey},
     Error, Result,
 };
 
@@ -90,6 +94,9 @@ pub async fn verify_genesis_block(
         return Err(Error::BlockContainsNoTransactions(block_hash))
     }
 
+    // Journal the block state changes, so it can be rolled back
+    overlay.lock().unwrap().overlay.lock().unwrap().begin_block(block.header.height);
+
     // Insert genesis slot so transactions can be validated against.
     // Since an overlay is used, original database is not affected.
     overlay.lock().unwrap().slots.insert(&[genesis_slot.clone()])?;
@@ -101,9 +108,10 @@ pub async fn verify_genesis_block(
         return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
     }
 
-    // Verify transactions, exluding producer(last) one
+    // Verify transactions, exluding producer(last) one.
+    // Genesis transactions don't pay fees.
     let txs = &block.txs[..block.txs.len() - 1];
-    let erroneous_txs = verify_transactions(overlay, time_keeper, txs).await?;
+    let erroneous_txs = verify_transactions(overlay, time_keeper, txs, false).await?;
     if !erroneous_txs.is_empty() {
         warn!(target: "validator::verification::verify_genesis_block", "Erroneous transactions found in set");
         overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
@@ -153,22 +161,32 @@ pub async fn verify_block(
         return Err(Error::BlockContainsNoTransactions(block_hash))
     }
 
+    // Journal the block state changes, so it can be rolled back
+    overlay.lock().unwrap().overlay.lock().unwrap().begin_block(block.header.height);
+
     // Insert last block slot so transactions can be validated against.
     // Rest (empty) slots will be inserted along with the block.
     // Since an overlay is used, original database is not affected.
     overlay.lock().unwrap().slots.insert(&[block.slots.last().unwrap().clone()])?;
 
     // Verify proposal transaction if not in testing mode
+    let txs = &block.txs[..block.txs.len() - 1];
     if !testing_mode {
         let tx = block.txs.last().unwrap();
         let public_key =
             verify_producer_transaction(overlay, time_keeper, tx, block.header.version).await?;
         verify_producer_signature(block, &public_key)?;
+
+        // PoW producers must claim exactly the fees paid by the block transactions
+        if block.header.version == 1 && pow_reward_fees(tx)? != txs_fees(txs)? {
+            warn!(target: "validator::verification::verify_block", "Producer transaction claims wrong fees");
+            return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
+        }
     }
 
-    // Verify transactions, exluding producer(last) one
-    let txs = &block.txs[..block.txs.len() - 1];
-    let erroneous_txs = verify_transactions(overlay, time_keeper, txs).await?;
+    // Verify transactions, exluding producer(last) one. Fees are always
+    // verified, since block validity can't depend on node configuration.
+    let erroneous_txs = verify_transactions(overlay, time_keeper, txs, true).await?;
     if !erroneous_txs.is_empty() {
         warn!(target: "validator::verification::verify_block", "Erroneous transactions found in set");
         overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
@@ -246,7 +264,15 @@ pub async fn verify_producer_transaction(
     tx.calls.encode(&mut payload)?; // Actual call data
 
     debug!(target: "validator::verification::verify_producer_transaction", "Instantiating WASM runtime");
-    let wasm = overlay.lock().unwrap().wasm_bincode.get(call.contract_id)?;
+    let Some(version) = overlay
+        .lock()
+        .unwrap()
+        .wasm_bincode
+        .version_at(call.contract_id, time_keeper.verifying_slot)?
+    else {
+        return Err(Error::WasmBincodeNotFound)
+    };
+    let wasm = overlay.lock().unwrap().wasm_bincode.get_version(call.contract_id, version)?;
 
     let mut runtime = Runtime::new(&wasm, overlay.clone(), call.contract_id, time_keeper.clone())?;
 
@@ -277,7 +303,11 @@ pub async fn verify_producer_transaction(
         if inner_vk_map.contains_key(zkas_ns.as_str()) {
             continue
         }
-        let (_, vk) = overlay.lock().unwrap().contracts.get_zkas(&call.contract_id, zkas_ns)?;
+        let (_, vk) = overlay.lock().unwrap().contracts.get_zkas_version(
+            &call.contract_id,
+            zkas_ns,
+            version,
+        )?;
         inner_vk_map.insert(zkas_ns.to_string(), vk);
     }
 
@@ -288,7 +318,7 @@ pub async fn verify_producer_transaction(
     // After getting the metadata, we run the "exec" function with the same runtime
     // and the same payload.
     debug!(target: "validator::verification::verify_producer_transaction", "Executing \"exec\" call");
-    let state_update = runtime.exec(&payload)?;
+    let (state_update, _) = runtime.exec(&payload)?;
     debug!(target: "validator::verification::verify_producer_transaction", "Successfully executed \"exec\" call");
 
     // If that was successful, we apply the state update in the ephemeral overlay.
@@ -322,24 +352,63 @@ pub async fn verify_producer_transaction(
     debug!(target: "validator::verification::verify_producer_transaction", "ZK proof verification successful");
     debug!(target: "validator::verification::verify_producer_transaction", "Proposal transaction {} verified successfully", tx_hash);
 
+    // Store the emitted events along with the applied state
+    overlay.lock().unwrap().events.insert(&tx_hash, &runtime.take_events())?;
+
     Ok(signature_public_key)
 }
 
 /// Verify WASM execution, signatures, and ZK proofs for a given [`Transaction`],
-/// and apply it to the provided overlay.
+/// and apply it to the provided overlay. If `verify_fee` is set, the transaction
+/// must also pay at least its required fee, computed from its size, ZK proofs
+/// count and gas consumption.
+/// On success, the total gas consumed by the transaction calls is returned.
 pub async fn verify_transaction(
     overlay: &BlockchainOverlayPtr,
     time_keeper: &TimeKeeper,
     tx: &Transaction,
     verifying_keys: &mut HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
-) -> Result<()> {
+    verify_fee: bool,
+) -> Result<u64> {
+    let (zkp_table, gas_used) =
+        execute_transaction(overlay, time_keeper, tx, verifying_keys, verify_fee).await?;
+
     let tx_hash = tx.hash()?;
-    debug!(target: "validator::verification::verify_transaction", "Validating transaction {}", tx_hash);
+    debug!(target: "validator::verification::verify_transaction", "Verifying ZK proofs for transaction {}", tx_hash);
+    if let Err(e) = tx.verify_zkps(verifying_keys, zkp_table).await {
+        error!(target: "validator::verification::verify_transaction", "ZK proof verification for tx {} failed: {}", tx_hash, e);
+        return Err(TxVerifyFailed::InvalidZkProof.into())
+    }
+
+    debug!(target: "validator::verification::verify_transaction", "ZK proof verification successful");
+    debug!(target: "validator::verification::verify_transaction", "Transaction {} verified successfully", tx_hash);
+
+    Ok(gas_used)
+}
+
+/// Verify WASM execution and signatures for a given [`Transaction`], and apply it
+/// to the provided overlay, like [`verify_transaction`] does, but leave its ZK
+/// proofs unverified. On success, the table of the public inputs of its ZK proofs
+/// is returned, so the caller can verify them, usually in a batch, along with the
+/// total gas consumed by the transaction calls.
+async fn execute_transaction(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    tx: &Transaction,
+    verifying_keys: &mut HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
+    verify_fee: bool,
+) -> Result<(ZkpTable, u64)> {
+    let tx_hash = tx.hash()?;
+    debug!(target: "validator::verification::execute_transaction", "Executing transaction {}", tx_hash);
 
     // Table of public inputs used for ZK proof verification
     let mut zkp_table = vec![];
     // Table of public keys used for signature verification
     let mut sig_table = vec![];
+    // Total gas consumed by the transaction calls
+    let mut gas_used = 0;
+    // Events emitted by the transaction calls
+    let mut events = vec![];
 
     // Iterate over all calls to get the metadata
     for (idx, call) in tx.calls.iter().enumerate() {
@@ -347,24 +416,35 @@ pub async fn verify_transaction(
         if (call.contract_id == *MONEY_CONTRACT_ID && call.data[0] == 0x08) ||
             (call.contract_id == *CONSENSUS_CONTRACT_ID && call.data[0] == 0x02)
         {
-            error!(target: "validator::verification::verify_transaction", "Reward transaction detected");
+            error!(target: "validator::verification::execute_transaction", "Reward transaction detected");
             return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
         }
 
-        debug!(target: "validator::verification::verify_transaction", "Executing contract call {}", idx);
+        debug!(target: "validator::verification::execute_transaction", "Executing contract call {}", idx);
 
         // Write the actual payload data
         let mut payload = vec![];
         payload.write_u32(idx as u32)?; // Call index
         tx.calls.encode(&mut payload)?; // Actual call data
 
-        debug!(target: "validator::verification::verify_transaction", "Instantiating WASM runtime");
-        let wasm = overlay.lock().unwrap().wasm_bincode.get(call.contract_id)?;
+        // Calls run the contract version in effect at the verifying slot, so
+        // transactions are verified against the bincode and circuits they were
+        // created for, even after the contract gets upgraded.
+        debug!(target: "validator::verification::execute_transaction", "Instantiating WASM runtime");
+        let Some(version) = overlay
+            .lock()
+            .unwrap()
+            .wasm_bincode
+            .version_at(call.contract_id, time_keeper.verifying_slot)?
+        else {
+            return Err(Error::WasmBincodeNotFound)
+        };
+        let wasm = overlay.lock().unwrap().wasm_bincode.get_version(call.contract_id, version)?;
 
         let mut runtime =
             Runtime::new(&wasm, overlay.clone(), call.contract_id, time_keeper.clone())?;
 
-        debug!(target: "validator::verification::verify_transaction", "Executing \"metadata\" call");
+        debug!(target: "validator::verification::execute_transaction", "Executing \"metadata\" call");
         let metadata = runtime.metadata(&payload)?;
 
         // Decode the metadata retrieved from the execution
@@ -374,21 +454,25 @@ pub async fn verify_transaction(
         let zkp_pub: Vec<(String, Vec<pallas::Base>)> = Decodable::decode(&mut decoder)?;
         let sig_pub: Vec<PublicKey> = Decodable::decode(&mut decoder)?;
         // TODO: Make sure we've read all the bytes above.
-        debug!(target: "validator::verification::verify_transaction", "Successfully executed \"metadata\" call");
+        debug!(target: "validator::verification::execute_transaction", "Successfully executed \"metadata\" call");
 
         // Here we'll look up verifying keys and insert them into the per-contract map.
-        debug!(target: "validator::verification::verify_transaction", "Performing VerifyingKey lookups from the sled db");
+        debug!(target: "validator::verification::execute_transaction", "Performing VerifyingKey lookups from the sled db");
         for (zkas_ns, _) in &zkp_pub {
             let inner_vk_map = verifying_keys.get_mut(&call.contract_id.to_bytes()).unwrap();
 
-            // TODO: This will be a problem in case of ::deploy, unless we force a different
-            // namespace and disable updating existing circuit. Might be a smart idea to do
-            // so in order to have to care less about being able to verify historical txs.
+            // The map is shared by the transactions verified along with this one,
+            // which all run the same contract version, since an upgrade only takes
+            // effect from the block after the one deploying it.
             if inner_vk_map.contains_key(zkas_ns.as_str()) {
                 continue
             }
 
-            let (_, vk) = overlay.lock().unwrap().contracts.get_zkas(&call.contract_id, zkas_ns)?;
+            let (_, vk) = overlay.lock().unwrap().contracts.get_zkas_version(
+                &call.contract_id,
+                zkas_ns,
+                version,
+            )?;
 
             inner_vk_map.insert(zkas_ns.to_string(), vk);
         }
@@ -398,61 +482,78 @@ pub async fn verify_transaction(
 
         // After getting the metadata, we run the "exec" function with the same runtime
         // and the same payload.
-        debug!(target: "validator::verification::verify_transaction", "Executing \"exec\" call");
-        let state_update = runtime.exec(&payload)?;
-        debug!(target: "validator::verification::verify_transaction", "Successfully executed \"exec\" call");
+        debug!(target: "validator::verification::execute_transaction", "Executing \"exec\" call");
+        let (state_update, _) = runtime.exec(&payload)?;
+        debug!(target: "validator::verification::execute_transaction", "Successfully executed \"exec\" call");
 
         // If that was successful, we apply the state update in the ephemeral overlay.
-        debug!(target: "validator::verification::verify_transaction", "Executing \"apply\" call");
+        debug!(target: "validator::verification::execute_transaction", "Executing \"apply\" call");
         runtime.apply(&state_update)?;
-        debug!(target: "validator::verification::verify_transaction", "Successfully executed \"apply\" call");
+        debug!(target: "validator::verification::execute_transaction", "Successfully executed \"apply\" call");
+
+        gas_used += runtime.gas_used();
+        events.extend(runtime.take_events());
 
         // At this point we're done with the call and move on to the next one.
     }
 
+    // Verify the transaction pays its required fee, before the expensive checks
+    if verify_fee {
+        let Some(paid) = tx_fee(tx)? else {
+            error!(target: "validator::verification::execute_transaction", "Transaction {} doesn't pay a fee", tx_hash);
+            return Err(TxVerifyFailed::MissingFee.into())
+        };
+
+        let zk_proofs = zkp_table.iter().map(|x| x.len() as u64).sum();
+        let required = compute_fee(serialize(tx).len() as u64, zk_proofs, gas_used);
+        if paid < required {
+            error!(target: "validator::verification::execute_transaction", "Transaction {} pays fee {}, required {}", tx_hash, paid, required);
+            return Err(TxVerifyFailed::InsufficientFee(paid, required).into())
+        }
+    }
+
     // When we're done looping and executing over the tx's contract calls, we now
     // move on with verification. First we verify the signatures as that's cheaper,
     // and then finally we verify the ZK proofs.
-    debug!(target: "validator::verification::verify_transaction", "Verifying signatures for transaction {}", tx_hash);
+    debug!(target: "validator::verification::execute_transaction", "Verifying signatures for transaction {}", tx_hash);
     if sig_table.len() != tx.signatures.len() {
-        error!(target: "validator::verification::verify_transaction", "Incorrect number of signatures in tx {}", tx_hash);
+        error!(target: "validator::verification::execute_transaction", "Incorrect number of signatures in tx {}", tx_hash);
         return Err(TxVerifyFailed::MissingSignatures.into())
     }
 
     // TODO: Go through the ZK circuits that have to be verified and account for the opcodes.
 
     if let Err(e) = tx.verify_sigs(sig_table) {
-        error!(target: "validator::verification::verify_transaction", "Signature verification for tx {} failed: {}", tx_hash, e);
+        error!(target: "validator::verification::execute_transaction", "Signature verification for tx {} failed: {}", tx_hash, e);
         return Err(TxVerifyFailed::InvalidSignature.into())
     }
 
-    debug!(target: "validator::verification::verify_transaction", "Signature verification successful");
-
-    debug!(target: "validator::verification::verify_transaction", "Verifying ZK proofs for transaction {}", tx_hash);
-    if let Err(e) = tx.verify_zkps(verifying_keys, zkp_table).await {
-        error!(target: "validator::verification::verify_transaction", "ZK proof verification for tx {} failed: {}", tx_hash, e);
-        return Err(TxVerifyFailed::InvalidZkProof.into())
-    }
+    debug!(target: "validator::verification::execute_transaction", "Signature verification successful");
 
-    debug!(target: "validator::verification::verify_transaction", "ZK proof verification successful");
-    debug!(target: "validator::verification::verify_transaction", "Transaction {} verified successfully", tx_hash);
+    // Store the emitted events along with the applied state
+    overlay.lock().unwrap().events.insert(&tx_hash, &events)?;
 
-    Ok(())
+    Ok((zkp_table, gas_used))
 }
 
 /// Verify a set of [`Transaction`] in sequence and apply them if all are valid.
 /// In case any of the transactions fail, they will be returned to the caller.
-/// The function takes a boolean called `write` which tells it to actually write
-/// the state transitions to the database.
+/// The function takes a boolean called `verify_fees` which tells it to verify
+/// each transaction pays its required fee.
+/// ZK proofs are verified after executing all transactions, in batches, using
+/// [`verify_zkps_batched`]. If any of them is invalid, the transactions are
+/// executed again from scratch without the ones containing invalid proofs,
+/// since the rest of them might depend on their state changes.
 pub async fn verify_transactions(
     overlay: &BlockchainOverlayPtr,
     time_keeper: &TimeKeeper,
     txs: &[Transaction],
+    verify_fees: bool,
 ) -> Result<Vec<Transaction>> {
     debug!(target: "validator::verification::verify_transactions", "Verifying {} transactions", txs.len());
 
-    // Tracker for failed txs
-    let mut erroneous_txs = vec![];
+    // Tracker for failed txs indexes
+    let mut erroneous = HashSet::new();
 
     // Map of ZK proof verifying keys for the current transaction batch
     let mut vks: HashMap<[u8; 32], HashMap<String, VerifyingKey>> = HashMap::new();
@@ -464,18 +565,139 @@ pub async fn verify_transactions(
         }
     }
 
-    // Iterate over transactions and attempt to verify them
-    for tx in txs {
+    // Keep the overlay state before the transactions, in case we have to execute them again
+    let initial_state = overlay.lock().unwrap().overlay.lock().unwrap().clone();
+
+    // Iterate over transactions and attempt to execute them, deferring ZK proofs verification
+    let mut executed = vec![];
+    let mut zkp_tables = vec![];
+    for (index, tx) in txs.iter().enumerate() {
         overlay.lock().unwrap().checkpoint();
-        if let Err(e) = verify_transaction(overlay, time_keeper, tx, &mut vks).await {
-            warn!(target: "validator::verification::verify_transactions", "Transaction verification failed: {}", e);
-            erroneous_txs.push(tx.clone());
-            // TODO: verify this works as expected
-            overlay.lock().unwrap().revert_to_checkpoint()?;
+        match execute_transaction(overlay, time_keeper, tx, &mut vks, verify_fees).await {
+            Ok((zkp_table, _)) => {
+                executed.push(index);
+                zkp_tables.push((tx, zkp_table));
+            }
+            Err(e) => {
+                warn!(target: "validator::verification::verify_transactions", "Transaction verification failed: {}", e);
+                erroneous.insert(index);
+                // TODO: verify this works as expected
+                overlay.lock().unwrap().revert_to_checkpoint()?;
+            }
+        }
+    }
+
+    // Verify all the executed transactions ZK proofs
+    let invalid: HashSet<usize> =
+        verify_zkps_batched(&zkp_tables, &vks).into_iter().map(|i| executed[i]).collect();
+
+    if !invalid.is_empty() {
+        warn!(target: "validator::verification::verify_transactions", "{} transactions contain invalid ZK proofs", invalid.len());
+        erroneous.extend(invalid);
+
+        // Execute the rest of the transactions again, from the initial state
+        *overlay.lock().unwrap().overlay.lock().unwrap() = initial_state;
+        for (index, tx) in txs.iter().enumerate() {
+            if erroneous.contains(&index) {
+                continue
+            }
+
+            overlay.lock().unwrap().checkpoint();
+            if let Err(e) =
+                verify_transaction(overlay, time_keeper, tx, &mut vks, verify_fees).await
+            {
+                warn!(target: "validator::verification::verify_transactions", "Transaction verification failed: {}", e);
+                erroneous.insert(index);
+                overlay.lock().unwrap().revert_to_checkpoint()?;
+            }
+        }
+    }
+
+    let erroneous_txs =
+        txs.iter().enumerate().filter(|(i, _)| erroneous.contains(i)).map(|(_, tx)| tx.clone());
+
+    Ok(erroneous_txs.collect())
+}
+
+/// Verify the ZK proofs of given transactions, along with their public inputs
+/// tables. The proofs of each circuit across all transactions are grouped into
+/// a single batch, and the groups are verified in parallel. If a batch fails,
+/// its proofs get verified one by one to find the invalid ones.
+/// Returns the indexes of the transactions containing invalid proofs.
+pub fn verify_zkps_batched(
+    txs: &[(&Transaction, ZkpTable)],
+    verifying_keys: &HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
+) -> HashSet<usize> {
+    let mut invalid = HashSet::new();
+
+    // Group the proofs by their circuit
+    let mut groups: HashMap<([u8; 32], &str), Vec<ZkpItem>> = HashMap::new();
+    for (index, (tx, zkp_table)) in txs.iter().enumerate() {
+        if tx.calls.len() != tx.proofs.len() || tx.calls.len() != zkp_table.len() {
+            invalid.insert(index);
+            continue
+        }
+
+        for ((call, proofs), pubvals) in tx.calls.iter().zip(&tx.proofs).zip(zkp_table) {
+            if proofs.len() != pubvals.len() {
+                invalid.insert(index);
+                continue
+            }
+
+            for (proof, (zk_ns, public_vals)) in proofs.iter().zip(pubvals) {
+                let circuit = (call.contract_id.to_bytes(), zk_ns.as_str());
+                groups.entry(circuit).or_default().push((index, proof, &public_vals[..]));
+            }
+        }
+    }
+
+    debug!(target: "validator::verification::verify_zkps_batched", "Verifying {} ZK proof batches", groups.len());
+
+    // Verify each group in its own thread
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = groups
+            .iter()
+            .map(|((contract_id, zk_ns), items)| {
+                let vk = verifying_keys.get(contract_id).and_then(|vks| vks.get(*zk_ns));
+                scope.spawn(move || verify_zkp_group(vk, zk_ns, items))
+            })
+            .collect();
+
+        for handle in handles {
+            invalid.extend(handle.join().unwrap());
         }
+    });
+
+    invalid
+}
+
+/// Table of a transaction ZK proofs public inputs, per call, in the form
+/// of a tuple (`zkas_ns`, `public_inputs`)
+pub type ZkpTable = Vec<Vec<(String, Vec<pallas::Base>)>>;
+
+/// A ZK proof along with its public inputs, and the index of its transaction
+type ZkpItem<'a> = (usize, &'a Proof, &'a [pallas::Base]);
+
+/// Auxiliary function to verify a batch of proofs of the same circuit,
+/// returning the transactions indexes of the invalid ones.
+fn verify_zkp_group(vk: Option<&VerifyingKey>, zk_ns: &str, items: &[ZkpItem]) -> Vec<usize> {
+    let Some(vk) = vk else {
+        error!(target: "validator::verification::verify_zkp_group", "{} circuit VK nonexistent", zk_ns);
+        return items.iter().map(|(index, _, _)| *index).collect()
+    };
+
+    let batch: Vec<_> =
+        items.iter().map(|(_, proof, public_vals)| (*proof, *public_vals)).collect();
+    if Proof::verify_batch(vk, &batch) {
+        return vec![]
     }
 
-    Ok(erroneous_txs)
+    warn!(target: "validator::verification::verify_zkp_group", "Batch verification of {} ZK proofs failed", zk_ns);
+    items
+        .iter()
+        .filter(|(_, proof, public_vals)| proof.verify(vk, public_vals).is_err())
+        .map(|(index, _, _)| *index)
+        .collect()
 }
 
 /// Verify given [`Proposal`] against provided consensus state
@@ -493,7 +715,8 @@ pub async fn verify_proposal(
 
 /// Verify given PoW [`Proposal`] against provided consensus state,
 /// A proposal is considered valid when the following rules apply:
-///     1. Proposal hash matches the actual block one
+///     1. Proposal hash matches the actual block one, and any merge
+///        mining data commits to it
 ///     2. Block transactions don't exceed set limit
 ///     3. If proposal extends a known fork, verify block's slot
 ///        correspond to the fork hot/live/next one
@@ -513,6 +736,10 @@ pub async fn verify_pow_proposal(
         return Err(Error::ProposalHashesMissmatchError)
     }
 
+    // Merge mining data is not part of the hash, so it must be checked
+    // before looking up forks by it (1)
+    proposal.block.header.verify_pow_data()?;
+
     // Check that proposal transactions don't exceed limit (2)
     if proposal.block.txs.len() > TXS_CAP {
         warn!(
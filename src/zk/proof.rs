@@ -25,7 +25,7 @@ use darkfi_serial::{SerialDecodable, SerialEncodable};
 use halo2_proofs::{
     helpers::SerdeFormat,
     plonk,
-    plonk::{Circuit, SingleVerifier},
+    plonk::{BatchVerifier, Circuit, SingleVerifier},
     poly::commitment::Params,
     transcript::{Blake2bRead, Blake2bWrite},
 };
@@ -211,6 +211,19 @@ impl Proof {
         plonk::verify_proof(&vk.params, &vk.vk, strategy, &[&[instances]], &mut transcript)
     }
 
+    /// Verify a batch of proofs of the same circuit, along with their public
+    /// inputs, at once. This is cheaper than verifying them one by one, but
+    /// only tells if all of them are valid, so on failure callers should fall
+    /// back to [`Proof::verify`] to find the invalid ones.
+    pub fn verify_batch(vk: &VerifyingKey, proofs: &[(&Proof, &[pallas::Base])]) -> bool {
+        let mut batch = BatchVerifier::new();
+        for (proof, instances) in proofs {
+            batch.add_proof(vec![vec![instances.to_vec()]], proof.0.clone());
+        }
+
+        batch.finalize(&vk.params, &vk.vk)
+    }
+
     pub fn new(bytes: Vec<u8>) -> Self {
         Proof(bytes)
     }
@@ -130,5 +130,17 @@ fn zkvm_opcodes() -> Result<()> {
     let verifying_key = VerifyingKey::build(zkbin.k, &circuit);
     proof.verify(&verifying_key, &public_inputs)?;
 
+    // Batch verification fails if any of the proofs is invalid
+    let mut wrong_inputs = public_inputs.clone();
+    wrong_inputs[9] = b;
+    assert!(Proof::verify_batch(
+        &verifying_key,
+        &[(&proof, &public_inputs), (&proof, &public_inputs)]
+    ));
+    assert!(!Proof::verify_batch(
+        &verifying_key,
+        &[(&proof, &public_inputs), (&proof, &wrong_inputs)]
+    ));
+
     Ok(())
 }